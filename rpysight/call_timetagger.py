@@ -7,6 +7,7 @@ without any PYTHONPATH manipulation - someone has already added the
 """
 from typing import Optional, List, Tuple
 import socket
+import time
 
 import numpy as np
 import pyarrow as pa
@@ -40,6 +41,7 @@ class RealTimeRendering(TimeTagger.CustomMeasurement):
 
     def __init__(self, tagger, channels: Optional[list], fname: Optional[str] = None):
         super().__init__(tagger)
+        self.disconnected = False
         if channels:  # during replay we skip it
             [self.register_channel(channel=channel) for channel in channels]
         self.init_stream_and_schema()
@@ -148,9 +150,26 @@ class RealTimeRendering(TimeTagger.CustomMeasurement):
         end_time
             End timestamp of the of the current data block.
         """
-        if len(incoming_tags) > 0:
+        if len(incoming_tags) > 0 and not self.disconnected:
             batch = self.convert_tags_to_recordbatch(incoming_tags)
-            self.stream.write(batch)
+            try:
+                self.stream.write(batch)
+            except OSError:
+                # rPySight closes the connection once its acquisition ends
+                self.disconnected = True
+
+
+CHANNEL_NAMES = [
+    'pmt1_ch',
+    'pmt2_ch',
+    'pmt3_ch',
+    'pmt4_ch',
+    'laser_ch',
+    'frame_ch',
+    'line_ch',
+    'taglens_ch',
+    'trigger_ch',
+]
 
 
 def infer_channel_list_from_cfg(config):
@@ -165,10 +184,34 @@ def infer_channel_list_from_cfg(config):
         config['frame_ch'],
         config['line_ch'],
         config['taglens_ch'],
+        config.get('trigger_ch', {"channel": 0}),
     ]
     channels = [ch for ch in relevant_channels if ch["channel"] != 0]
+    if config.get('gated_frame', False) and config['frame_ch']['channel'] != 0:
+        channels.append({
+            "channel": -config['frame_ch']['channel'],
+            "threshold": config['frame_ch']['threshold'],
+        })
     return channels
 
+
+def applied_channel_config(tagger, config) -> str:
+    """The configuration of the connected input channels as applied by the
+    TimeTagger, which may differ from the requested one, e.g. when it rejected
+    a threshold. It's returned as a TOML string with the same keys as the
+    configuration, so that the Rust side can reconcile the two."""
+    applied = {}
+    for name in CHANNEL_NAMES:
+        channel = config.get(name, {"channel": 0})["channel"]
+        if channel == 0:
+            continue
+        applied[name] = {
+            "channel": channel,
+            "threshold": float(tagger.getTriggerLevel(abs(channel))),
+        }
+    return toml.dumps(applied)
+
+
 class MockDelayedChannel:
     def __init__(self, ch):
         self.channel = ch
@@ -204,7 +247,7 @@ def set_tt_for_demuxing(tagger, config) -> Tuple[List, List]:
     return delayed_channels, new_channels
     
 
-def run_tagger(cfg: str):
+def run_tagger(cfg: str, report_applied):
     """Run a TimeTagger acquisition with the given parameters.
 
     This function starts an acquisition using parameters from the rPySight GUI.
@@ -215,12 +258,16 @@ def run_tagger(cfg: str):
     ----------
     cfg : str
         A TOML string to be parsed into a dictionary
+    report_applied : Callable[[str], None]
+        Called with the channel configuration applied by the TimeTagger, as
+        TOML, before the acquisition starts
     """
     config = toml.loads(cfg)
     tagger = TimeTagger.createTimeTagger()
     tagger.reset()
     channels = infer_channel_list_from_cfg(config)
     [tagger.setTriggerLevel(ch['channel'], ch['threshold']) for ch in channels]
+    report_applied(applied_channel_config(tagger, config))
     int_channels = [channel['channel'] for channel in channels]
     if config['demux']['demultiplex']:
         delayed_channels, demux_channels = set_tt_for_demuxing(tagger, config)
@@ -229,7 +276,10 @@ def run_tagger(cfg: str):
         _rt = RealTimeRendering(measure_group.getTagger(), int_channels, config['filename'])
         _fw = FileWriter(measure_group.getTagger(), config['filename'], int_channels)
         measure_group.startFor(int(1_000_000e12))
-        measure_group.waitUntilFinished()
+        while not measure_group.waitUntilFinished(timeout=100):
+            if _rt.disconnected:
+                measure_group.stop()
+                break
 
 
 def replay_existing(cfg: str):
@@ -241,6 +291,110 @@ def replay_existing(cfg: str):
     tagger.waitForCompletion(timeout=-1)
 
 
+def probe_tagger(cfg: str) -> str:
+    """Check that a TimeTagger is reachable without starting an acquisition.
+
+    Parameters
+    ----------
+    cfg : str
+        A TOML string to be parsed into a dictionary
+
+    Returns
+    -------
+    str
+        A short description of the connected TimeTagger
+    """
+    tagger = TimeTagger.createTimeTagger()
+    try:
+        return f"Connected to TimeTagger {tagger.getSerial()}"
+    finally:
+        TimeTagger.freeTimeTagger(tagger)
+
+
+def line_period_of(config) -> int:
+    """The duration of a single line in picoseconds"""
+    if config['bidir'] == "Bidir":
+        return config['scan_period']['period'] // 2
+    return config['scan_period']['period']
+
+
+def generate_test_pattern_batch(config, frame_start: int, schema) -> pa.RecordBatch:
+    """Generate the events of a single synthetic frame.
+
+    Each frame starts with a frame event followed by an event per line, if
+    these channels are connected, and photons of the first PMT channel whose
+    rate grows linearly along each line. The frame is returned as a
+    RecordBatch with the schema of the TimeTagger's stream.
+    """
+    rows = config['rows']
+    line_period = line_period_of(config)
+    rng = np.random.default_rng()
+    times = [np.array([frame_start], dtype=np.int64)]
+    channels = [np.array([config['frame_ch']['channel']], dtype=np.int32)]
+    line_starts = frame_start + np.arange(rows, dtype=np.int64) * line_period
+    if config['line_ch']['channel'] != 0:
+        times.append(line_starts)
+        channels.append(np.full(rows, config['line_ch']['channel'], dtype=np.int32))
+    photons_per_line = 100
+    offsets = np.sqrt(rng.random((rows, photons_per_line))) * line_period
+    photons = (line_starts[:, np.newaxis] + offsets.astype(np.int64)).ravel()
+    times.append(photons)
+    channels.append(np.full(len(photons), config['pmt1_ch']['channel'], dtype=np.int32))
+    times = np.concatenate(times)
+    channels = np.concatenate(channels)
+    order = np.argsort(times, kind="stable")
+    return pa.record_batch(
+        [
+            pa.array(np.zeros(len(times), dtype=np.uint8)),
+            pa.array(np.zeros(len(times), dtype=np.uint16)),
+            pa.array(channels[order]),
+            pa.array(times[order]),
+        ],
+        schema=schema,
+    )
+
+
+def run_test_pattern(cfg: str):
+    """Stream a synthetic test pattern instead of the TimeTagger data.
+
+    This allows running rPySight without the hardware, e.g. for demos. The
+    frames are streamed in real time until rPySight closes the connection.
+
+    Parameters
+    ----------
+    cfg : str
+        A TOML string to be parsed into a dictionary
+    """
+    config = toml.loads(cfg)
+    schema = pa.schema(
+        [
+            ("type_", pa.uint8()),
+            ("missed_events", pa.uint16()),
+            ("channel", pa.int32()),
+            ("time", pa.int64()),
+        ]
+    )
+    sock = socket.socket()
+    sock.bind((HOST, PORT))
+    sock.listen()
+    conn, _ = sock.accept()
+    socketfile = conn.makefile('wb')
+    opts = pa.ipc.IpcWriteOptions(allow_64bit=True)
+    stream = pa.ipc.new_stream(socketfile, schema, options=opts)
+    line_period = line_period_of(config)
+    frame_period = config['rows'] * line_period + config['frame_dead_time']
+    frame_start = 0
+    try:
+        while True:
+            stream.write(generate_test_pattern_batch(config, frame_start, schema))
+            frame_start += frame_period
+            time.sleep(frame_period / 1e12)
+    except (BrokenPipeError, ConnectionResetError, OSError):
+        pass
+    finally:
+        sock.close()
+
+
 if __name__ == '__main__':
     with open(r"E:\Hagai\21-09-29\fov1\calcium_m1_fov1.toml") as f:
         config = toml.load(f)
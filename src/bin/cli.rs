@@ -11,7 +11,7 @@ use anyhow::Result;
 use futures::executor::block_on;
 use thiserror::Error;
 
-use librpysight::configuration::AppConfig;
+use librpysight::configuration::{AppConfig, Inputs};
 use librpysight::{
     make_config_dir, reload_cfg_or_use_default, setup_logger, start_acquisition,
     DEFAULT_CONFIG_FNAME,
@@ -102,6 +102,10 @@ fn main() -> Result<()> {
         },
         _ => panic!("Wrong number of arguments received, pass no args to initialize a new default configuration."),
     };
-    block_on(start_acquisition(config_path, config));
+    config.validate()?;
+    for (channel, role) in Inputs::from_config(&config).describe() {
+        info!("Channel {} is routed to {:?}", channel, role);
+    }
+    block_on(start_acquisition(config_path, config))?;
     Ok(())
 }
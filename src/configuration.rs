@@ -4,13 +4,17 @@ use std::fs::read_to_string;
 use std::num::ParseFloatError;
 use std::ops::{Deref, Index};
 use std::path::Path;
+use std::time::Duration;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::gui::{ChannelNumber, EdgeDetected, MainAppGui};
+use crate::point_cloud_renderer::ChannelNames;
+use crate::serialize_and_render::serialized_row_size;
 use crate::snakes::Picosecond;
-use crate::UserInputError;
+use crate::{UserInputError, GRAYSCALE_START, SUPPORTED_SPECTRAL_CHANNELS};
 
 /// Physical number of the input SMA ports on the time tagger.
 ///
@@ -29,6 +33,11 @@ const VIRTUAL_CHANNELS_MAX_NUM: usize = 8;
 const TOTAL_INPUTS_WITH_VIRTUAL: usize =
     TOTAL_INPUTS_WITHOUT_VIRTUAL + VIRTUAL_INPUTS_OFFSET + VIRTUAL_CHANNELS_MAX_NUM;
 
+/// The laser should pulse at least this many times during the dwell time of
+/// each pixel for the photon arrival times relative to the pulses to be
+/// meaningful
+const MIN_LASER_PULSES_PER_PIXEL: Picosecond = 2;
+
 /// Picosecond and Hz aware period
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Period {
@@ -57,6 +66,83 @@ impl Deref for Period {
     }
 }
 
+/// Raised when a fill fraction is outside of its valid range
+#[derive(Debug, Error, PartialEq)]
+pub enum FillFractionError {
+    #[error("The fill fraction should be above 0% and at most 100% (got {0}%)")]
+    OutOfRange(f32),
+}
+
+/// Raised when a configuration can't produce a meaningful acquisition
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ConfigValidationError {
+    #[error("None of the PMT channels is connected, so there's nothing to render")]
+    NoPmtChannels,
+    #[error("Collapsed bidirectional rows can't be split by their scan direction, as the reverse rows share the output rows of the forward ones")]
+    CollapsedSplitBidir,
+    #[error("The retrace of a sawtooth scanner should take at least 0 and less than 1 of the scan period (got {0})")]
+    RetraceFractionOutOfRange(f32),
+}
+
+/// The fraction of each line during which the scanner is inside the imaged
+/// field of view.
+///
+/// It's stored, and written to configuration files, in percent. The explicit
+/// constructors prevent mixing up percents and fractions, e.g. passing 0.713
+/// instead of 71.3.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(try_from = "f32", into = "f32")]
+pub struct FillFraction(f32);
+
+impl FillFraction {
+    /// A fill fraction given in percent, e.g. 71.3
+    pub fn from_percent(percent: f32) -> Result<FillFraction, FillFractionError> {
+        if percent > 0.0 && percent <= 100.0 {
+            Ok(FillFraction(percent))
+        } else {
+            Err(FillFractionError::OutOfRange(percent))
+        }
+    }
+
+    /// A fill fraction given as a fraction of the line, e.g. 0.713
+    pub fn from_fraction(fraction: f32) -> Result<FillFraction, FillFractionError> {
+        FillFraction::from_percent(fraction * 100.0)
+    }
+
+    pub fn percent(&self) -> f32 {
+        self.0
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.0 / 100.0
+    }
+}
+
+impl TryFrom<f32> for FillFraction {
+    type Error = FillFractionError;
+
+    fn try_from(percent: f32) -> Result<Self, Self::Error> {
+        FillFraction::from_percent(percent)
+    }
+}
+
+impl From<FillFraction> for f32 {
+    fn from(fill_fraction: FillFraction) -> f32 {
+        fill_fraction.0
+    }
+}
+
+/// A rate of data, e.g. of the serialized photons
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct BytesPerSecond(pub f64);
+
+impl BytesPerSecond {
+    /// The number of bytes written at this rate during the given duration
+    pub fn total_bytes(&self, duration: Duration) -> u64 {
+        (self.0 * duration.as_secs_f64()).ceil() as u64
+    }
+}
+
 /// Determines whether the scan was bidirectional or unidirectional
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum Bidirectionality {
@@ -73,6 +159,363 @@ impl From<bool> for Bidirectionality {
     }
 }
 
+/// Determines how the photons of the different channels are colored in the
+/// merged channel
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ColorMode {
+    /// Each channel has its own color from a fixed palette
+    PerChannelPalette,
+    /// The first three channels are red, green and blue, and their
+    /// intensities are summed
+    DirectRGB,
+}
+
+/// Determines whether the voxels of volumetric acquisitions are colored by
+/// their depth, using one of the given colormaps, rather than by channel
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DepthColorMode {
+    /// Voxels are colored by their channel, following the color mode
+    Off,
+    /// Perceptually uniform, from dark purple at the bottom plane to yellow
+    /// at the top one
+    Viridis,
+    /// From dark blue at the bottom plane, through cyan, green and yellow, to
+    /// dark red at the top one
+    Jet,
+}
+
+impl DepthColorMode {
+    /// The colormap of the mode, as evenly spaced anchors from the bottom
+    /// plane to the top one
+    fn anchors(&self) -> Option<&'static [[f32; 3]; 5]> {
+        match self {
+            DepthColorMode::Off => None,
+            DepthColorMode::Viridis => Some(&[
+                [0.267, 0.005, 0.329],
+                [0.229, 0.322, 0.546],
+                [0.128, 0.567, 0.551],
+                [0.369, 0.789, 0.383],
+                [0.993, 0.906, 0.144],
+            ]),
+            DepthColorMode::Jet => Some(&[
+                [0.0, 0.0, 0.5],
+                [0.0, 0.5, 1.0],
+                [0.5, 1.0, 0.5],
+                [1.0, 0.5, 0.0],
+                [0.5, 0.0, 0.0],
+            ]),
+        }
+    }
+
+    /// The color of the given depth, between 0 at the bottom plane and 1 at
+    /// the top one, interpolated linearly between the anchors of the
+    /// colormap. `None` when voxels aren't colored by depth
+    pub fn color_at(&self, depth: f32) -> Option<[f32; 3]> {
+        let anchors = self.anchors()?;
+        let position = depth.clamp(0.0, 1.0) * (anchors.len() - 1) as f32;
+        let low = (position.floor() as usize).min(anchors.len() - 2);
+        let weight = position - low as f32;
+        let mut color = [0.0; 3];
+        for (comp, (start, end)) in color
+            .iter_mut()
+            .zip(anchors[low].iter().zip(anchors[low + 1].iter()))
+        {
+            *comp = start + weight * (end - start);
+        }
+        Some(color)
+    }
+}
+
+/// Determines how the number of photons in a voxel is mapped to its brightness
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum IntensityScale {
+    /// Each photon brightens the voxel by the color increment
+    Linear,
+    /// The photon count is normalized by `ln(1 + count)` before it's mapped
+    /// to a brightness, revealing dim structures
+    Log,
+}
+
+/// Determines how the rendered frames are drawn in the window
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DisplayMode {
+    /// Each voxel is drawn as a point of a point cloud
+    PointCloud,
+    /// The frame is drawn as a textured image, which is crisper and faster
+    /// for planar imaging. Only supported when a single plane is imaged
+    Image2D,
+}
+
+/// Determines when the accumulated photons are flushed to the screen and the
+/// buffers are cleared
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "mode", content = "window")]
+pub enum IntegrationWindow {
+    /// Each scanned frame is integrated separately
+    PerFrame,
+    /// Photons are integrated over windows of the given length, in
+    /// picoseconds of experiment time, regardless of the frame and line
+    /// signals
+    Time(Picosecond),
+}
+
+/// The kind of scanner moving the beam along each row
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind")]
+pub enum XScanner {
+    /// A resonant mirror, whose sinusoidal motion is only used during the
+    /// fill fraction of each half period
+    Resonant,
+    /// A galvo driven by a sawtooth, which sweeps linearly along each row and
+    /// then flies back to its start during the given fraction of the scan
+    /// period. Sawtooth scans are always unidirectional
+    Sawtooth { retrace_fraction: f32 },
+}
+
+/// The compression of the serialized data stream.
+///
+/// The IPC writer of arrow2 doesn't compress the buffers themselves, so the
+/// whole stream is compressed instead, and has to be decompressed before it's
+/// read as an Arrow stream.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "codec")]
+pub enum OutputCompression {
+    Uncompressed,
+    /// Levels range from 1 to 22, where higher levels compress better but
+    /// slower
+    Zstd {
+        level: i32,
+    },
+    /// Levels range from 0 to 16, where higher levels compress better but
+    /// slower
+    Lz4 {
+        level: u32,
+    },
+}
+
+impl OutputCompression {
+    /// The extension of the file containing the serialized stream
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputCompression::Uncompressed => "arrow_stream",
+            OutputCompression::Zstd { .. } => "arrow_stream.zst",
+            OutputCompression::Lz4 { .. } => "arrow_stream.lz4",
+        }
+    }
+}
+
+/// Determines how the time of arrival of photons is mapped to rows
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum LineTiming {
+    /// Every line lasts exactly half of the scan period
+    Nominal,
+    /// Each row starts at its measured line signal, and lasts as long as the
+    /// previous line did, which follows scanners with line-to-line jitter.
+    /// Only supported in 2D imaging
+    Measured,
+}
+
+/// Determines how line events are matched to the rows of the frame
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum LineMatching {
+    /// Each line event starts the row following the previous one
+    Counted,
+    /// Each line event is matched to the row whose expected start time is
+    /// nearest to it, so that missing or spurious line events don't shift the
+    /// following rows
+    NearestExpected,
+}
+
+/// Determines how a batch whose first event predates the events of the
+/// previous batches is handled, e.g. when the transport reorders them
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum OutOfOrderBatches {
+    /// The snake is rewound to the first event of the batch, so that its
+    /// photons are still placed in their voxels
+    Rewind,
+    /// The batch is logged and discarded
+    Skip,
+}
+
+/// The phase shift, in picoseconds, of the odd rows in bidirectional scans.
+///
+/// In 3D imaging the optimal shift may differ between planes, so it can be
+/// given per plane. A single value is used for all planes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum LineShift {
+    Global(Picosecond),
+    PerPlane(Vec<Picosecond>),
+}
+
+impl LineShift {
+    /// The shift of the given plane. Planes missing from a per-plane shift
+    /// use the shift of the last plane given.
+    pub fn for_plane(&self, plane: usize) -> Picosecond {
+        match self {
+            LineShift::Global(shift) => *shift,
+            LineShift::PerPlane(shifts) => shifts
+                .get(plane)
+                .or_else(|| shifts.last())
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// An elliptical mask of the field of view, excluding the photons arriving
+/// outside of it from rendering and serialization.
+///
+/// The radii are given relative to half of the field of view, so a circle with
+/// a radius of 1.0 is the largest circle fitting inside it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "shape")]
+pub enum FieldMask {
+    Circle { radius: f32 },
+    Ellipse { row_radius: f32, column_radius: f32 },
+}
+
+impl FieldMask {
+    /// Whether the given point is inside the mask. The row and column are
+    /// normalized to [-1.0, 1.0], with the center of the field of view at 0.
+    pub fn contains(&self, row: f32, column: f32) -> bool {
+        let (row_radius, column_radius) = match *self {
+            FieldMask::Circle { radius } => (radius, radius),
+            FieldMask::Ellipse {
+                row_radius,
+                column_radius,
+            } => (row_radius, column_radius),
+        };
+        (row / row_radius).powi(2) + (column / column_radius).powi(2) <= 1.0
+    }
+}
+
+/// A rectangular region of interest of a single plane in 3D imaging.
+///
+/// Like the field mask, its bounds are given relative to half of the field of
+/// view, between -1.0 and 1.0, with the center of the field of view at 0.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PlaneRoi {
+    pub row_start: f32,
+    pub row_end: f32,
+    pub column_start: f32,
+    pub column_end: f32,
+}
+
+impl PlaneRoi {
+    /// Whether the given point, normalized like the bounds, is inside the ROI
+    pub fn contains(&self, row: f32, column: f32) -> bool {
+        (self.row_start..=self.row_end).contains(&row)
+            && (self.column_start..=self.column_end).contains(&column)
+    }
+}
+
+/// Renders the photons of one channel only when they coincide with an event
+/// of a reference channel, e.g. for correlated photon measurements.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Coincidence {
+    /// The channel whose photons are filtered
+    pub channel: DataType,
+    /// The channel whose events the filtered photons have to coincide with
+    pub reference: DataType,
+    /// The longest time (in ps) between a reference event and a photon of
+    /// the filtered channel, before or after it, in which the photon is still
+    /// rendered
+    pub window: Picosecond,
+}
+
+/// Models used to estimate the true photon count of a voxel whose detections
+/// piled up, i.e. when several photons arrived too close to each other to be
+/// registered separately.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "model")]
+pub enum PileupCorrection {
+    /// The classic Poisson correction, `-max * ln(1 - count / max)`, where
+    /// `max_count` is the number of photons at which the voxel saturates
+    Poisson { max_count: f32 },
+}
+
+impl PileupCorrection {
+    /// The estimated number of photons that arrived in a voxel in which
+    /// `count` photons were detected. The correction diverges as the count
+    /// reaches the maximum, so saturated voxels are estimated as if they had
+    /// detected one photon less than the maximum.
+    pub fn correct(&self, count: f32) -> f32 {
+        match self {
+            PileupCorrection::Poisson { max_count } => {
+                let count = count.min((max_count - 1.0).max(0.0));
+                -max_count * (1.0 - count / max_count).ln()
+            }
+        }
+    }
+}
+
+/// The columns of the serialized table whose order may be configured. The
+/// optional pile-up and lifetime columns always follow them
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SerializedColumn {
+    Channel,
+    X,
+    Y,
+    Z,
+    Value,
+}
+
+impl SerializedColumn {
+    /// The order of the columns unless another one is configured
+    pub const DEFAULT_ORDER: [SerializedColumn; 5] = [
+        SerializedColumn::Channel,
+        SerializedColumn::X,
+        SerializedColumn::Y,
+        SerializedColumn::Z,
+        SerializedColumn::Value,
+    ];
+
+    /// The name of the column in the serialized schema
+    pub fn name(&self) -> &'static str {
+        match self {
+            SerializedColumn::Channel => "channel",
+            SerializedColumn::X => "x",
+            SerializedColumn::Y => "y",
+            SerializedColumn::Z => "z",
+            SerializedColumn::Value => "value",
+        }
+    }
+}
+
+/// Adjusts the color increment after each rendered frame, so that the mean
+/// brightness of the displayed voxels approaches a target, e.g. when the
+/// count rate changes during an experiment.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct AutoExposure {
+    /// The desired mean brightness of the displayed voxels, taking the
+    /// brightest color component of each, between 0 and 1
+    pub target: f32,
+    /// Fraction of the deviation from the target, in logarithmic terms,
+    /// corrected after each frame, between 0 and 1
+    pub rate: f32,
+}
+
+impl AutoExposure {
+    /// The color increment for the next frame, given the current one and the
+    /// mean brightness it resulted in.
+    ///
+    /// The brightness of a voxel grows geometrically with its photons from
+    /// `start_level`, so the increment is raised to the ratio between the
+    /// log-brightness of the target and that of the frame, which is limited
+    /// to [0.5, 2.0] so that a single odd frame can't derail the exposure.
+    /// Frames whose voxels are no brighter than a single photon leave the
+    /// increment as is, since it can't change their brightness.
+    pub fn adjust(&self, increment: f32, mean_brightness: f32, start_level: f32) -> f32 {
+        if increment <= 1.0 || mean_brightness <= start_level || self.target <= start_level {
+            return increment;
+        }
+        let ratio = ((self.target / start_level).ln() / (mean_brightness / start_level).ln())
+            .clamp(0.5, 2.0);
+        increment.powf(ratio.powf(self.rate))
+    }
+}
+
 impl From<Bidirectionality> for bool {
     fn from(bidir: Bidirectionality) -> bool {
         match bidir {
@@ -91,9 +534,13 @@ pub enum DataType {
     Pmt3,
     Pmt4,
     Frame,
+    /// The falling edge of a gated frame channel
+    FrameEnd,
     Line,
     TagLens,
     Laser,
+    /// An external trigger which has to arrive before each frame starts
+    Trigger,
     Invalid,
 }
 
@@ -108,6 +555,7 @@ impl DataType {
             "line_ch" => Self::Line,
             "taglens_ch" => Self::TagLens,
             "laser_ch" => Self::Laser,
+            "trigger_ch" => Self::Trigger,
             "invalid" => Self::Invalid,
             _ => panic!("Wrong DataType entered (received {})", name),
         }
@@ -128,6 +576,19 @@ impl InputChannel {
     }
 }
 
+/// Thresholds reported by the TimeTagger may differ from the requested ones
+/// by this many volts due to the resolution of its trigger levels
+const APPLIED_THRESHOLD_TOLERANCE: f32 = 1e-3;
+
+/// An input channel which the TimeTagger configured differently from the
+/// request, or not at all
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMismatch {
+    pub name: &'static str,
+    pub requested: InputChannel,
+    pub applied: Option<InputChannel>,
+}
+
 /// A data structure which maps the input channel to the data type it relays.
 ///
 /// The underlying storage is an array, and due to the way the Index trait is
@@ -153,6 +614,7 @@ impl Inputs {
             config.line_ch,
             config.taglens_ch,
             config.laser_ch,
+            config.trigger_ch,
         ];
         let datatypes = vec![
             DataType::Pmt1,
@@ -163,6 +625,7 @@ impl Inputs {
             DataType::Line,
             DataType::TagLens,
             DataType::Laser,
+            DataType::Trigger,
         ];
 
         assert!(needed_channels.len() == datatypes.len());
@@ -175,6 +638,15 @@ impl Inputs {
                 used_channels += 1;
             }
         }
+        // In gated mode the opposite edge of the frame channel closes the
+        // frame
+        if config.gated_frame && config.frame_ch.channel != 0 {
+            let frame_end_ch = -config.frame_ch.channel;
+            set.insert(frame_end_ch);
+            physical_to_logical_map[(MAX_TIMETAGGER_INPUTS + frame_end_ch) as usize] =
+                DataType::FrameEnd;
+            used_channels += 1;
+        }
         assert_eq!(
             set.len(),
             used_channels,
@@ -185,12 +657,26 @@ impl Inputs {
         }
         let inps = Inputs(physical_to_logical_map);
         debug!(
-            "The inputs struct was constructed successfully: {:#?}",
-            inps
+            "The inputs struct was constructed successfully: {:?}",
+            inps.describe()
         );
         inps
     }
 
+    /// Lists the channels which are assigned a role, including virtual
+    /// channels, together with that role.
+    ///
+    /// Channels are given in their TimeTagger representation, i.e. negative
+    /// for falling edges.
+    pub fn describe(&self) -> Vec<(i32, DataType)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, dt)| **dt != DataType::Invalid)
+            .map(|(idx, dt)| (idx as i32 - MAX_TIMETAGGER_INPUTS, *dt))
+            .collect()
+    }
+
     pub fn get(&self, channel: i32) -> &DataType {
         let actual_idx = (MAX_TIMETAGGER_INPUTS + channel) as usize;
         if actual_idx >= self.0.len() {
@@ -257,19 +743,81 @@ impl Index<i32> for Inputs {
 /// It can be serialized so that it can be saved on disk as a configuration
 /// file, and it can also be sent from Rust to Python so that the TimeTagger
 /// will be aware of the different channels in use.
+///
+/// Fields missing from a configuration file are populated with their default
+/// values, so older configuration files remain valid.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct AppConfig {
     pub(crate) filename: String,
     pub(crate) rows: u32,
     pub(crate) columns: u32,
     pub(crate) planes: u32,
-    pub(crate) fill_fraction: f32, // (0..100)
+    pub(crate) fill_fraction: FillFraction,
     pub(crate) frame_dead_time: Picosecond,
     pub(crate) replay_existing: bool,
+    pub(crate) stop_at_replay_end: bool,
+    pub(crate) use_test_pattern: bool,
     pub(crate) rolling_avg: u16,
-    pub(crate) line_shift: Picosecond,
+    pub(crate) line_shift: LineShift,
+    pub(crate) forward_line_shift: Picosecond,
+    pub(crate) mirror_reverse_rows: bool,
+    pub(crate) shift_reverse_rows: bool,
+    pub(crate) collapse_bidir: bool,
     pub(crate) increment_color_by: f32,
     pub(crate) bidir: Bidirectionality,
+    pub(crate) batch_relevance_tolerance: Picosecond,
+    pub(crate) gated_frame: bool,
+    pub(crate) max_extra_lines: u32,
+    pub(crate) min_frame_interval: Picosecond,
+    pub(crate) sync_loss_lines: u32,
+    pub(crate) blank_on_sync_loss: bool,
+    pub(crate) skip_duplicate_batches: bool,
+    pub(crate) out_of_order_batches: OutOfOrderBatches,
+    pub(crate) mark_missed_events: bool,
+    pub(crate) color_mode: ColorMode,
+    pub(crate) depth_color_mode: DepthColorMode,
+    pub(crate) min_frame_display_ms: u64,
+    pub(crate) lifetime_bins: u16,
+    pub(crate) strobe_laser_marker: bool,
+    pub(crate) intensity_scale: IntensityScale,
+    pub(crate) display_mode: DisplayMode,
+    pub(crate) control_window: ChannelNames,
+    pub(crate) affine: Option<[[f32; 3]; 4]>,
+    pub(crate) draw_centroid: bool,
+    pub(crate) draw_scan_path: bool,
+    pub(crate) display_decimation: u32,
+    pub(crate) max_render_points: usize,
+    pub(crate) display_min_count: u16,
+    pub(crate) sparse_image_clear: bool,
+    pub(crate) frame_table: bool,
+    pub(crate) mip_preview_interval_frames: u32,
+    pub(crate) thumbnail_size: u32,
+    pub(crate) record_raw_events: bool,
+    pub(crate) stream_to_stdout: bool,
+    pub(crate) skip_initial_frames: u32,
+    pub(crate) serialization_threads: usize,
+    pub(crate) flush_partial_frame: bool,
+    pub(crate) render_core: Option<usize>,
+    pub(crate) snake_length_tolerance: Option<usize>,
+    pub(crate) snake_path: Option<String>,
+    pub(crate) snake_dump_path: Option<String>,
+    pub(crate) line_clock_script: Option<String>,
+    pub(crate) dead_time_warning_fraction: Option<f32>,
+    pub(crate) line_timing: LineTiming,
+    pub(crate) line_matching: LineMatching,
+    pub(crate) live_feed_decimation: u32,
+    pub(crate) channel_enabled: [bool; SUPPORTED_SPECTRAL_CHANNELS],
+    pub(crate) serialize_channels: Vec<usize>,
+    pub(crate) column_order: Vec<SerializedColumn>,
+    pub(crate) split_bidir_direction: bool,
+    pub(crate) carry_voxel_indices: bool,
+    pub(crate) planar_channels: Vec<usize>,
+    pub(crate) frame_rate_smoothing: f32,
+    pub(crate) background_level: f32,
+    pub(crate) color_start_level: f32,
+    pub(crate) max_color_level: f32,
+    pub(crate) display_gamma: f32,
     pub(crate) laser_period: Period,
     pub(crate) scan_period: Period,
     pub(crate) tag_period: Period,
@@ -281,16 +829,63 @@ pub struct AppConfig {
     pub(crate) frame_ch: InputChannel,
     pub(crate) line_ch: InputChannel,
     pub(crate) taglens_ch: InputChannel,
+    pub(crate) trigger_ch: InputChannel,
     pub(crate) demux: Demux,
+    pub(crate) integration_window: IntegrationWindow,
+    pub(crate) output_compression: OutputCompression,
+    pub(crate) x_scanner: XScanner,
+    pub(crate) field_mask: Option<FieldMask>,
+    pub(crate) coincidence: Option<Coincidence>,
+    pub(crate) pileup_correction: Option<PileupCorrection>,
+    pub(crate) auto_exposure: Option<AutoExposure>,
+    pub(crate) plane_rois: Option<Vec<PlaneRoi>>,
 }
 
 impl AppConfig {
+    /// A planar acquisition of a single PMT channel, with the default values
+    /// of [`AppConfigBuilder`] for all other parameters.
+    ///
+    /// Useful for scripts and tests, which would otherwise need a long chain
+    /// of builder calls.
+    pub fn basic_2d(rows: u32, columns: u32, scan_freq_hz: f64, pmt_ch: InputChannel) -> AppConfig {
+        AppConfigBuilder::default()
+            .with_rows(rows)
+            .with_columns(columns)
+            .with_planes(1)
+            .with_scan_period(Period::from_freq(scan_freq_hz))
+            .with_pmt1_ch(pmt_ch)
+            .with_taglens_ch(InputChannel::new(0, 0.0))
+            .build()
+    }
+
+    /// A volumetric acquisition of a single PMT channel using a TAG lens, with
+    /// the default values of [`AppConfigBuilder`] for all other parameters
+    pub fn basic_3d(
+        rows: u32,
+        columns: u32,
+        planes: u32,
+        scan_freq_hz: f64,
+        tag_freq_hz: f64,
+        pmt_ch: InputChannel,
+        taglens_ch: InputChannel,
+    ) -> AppConfig {
+        AppConfigBuilder::default()
+            .with_rows(rows)
+            .with_columns(columns)
+            .with_planes(planes)
+            .with_scan_period(Period::from_freq(scan_freq_hz))
+            .with_tag_period(Period::from_freq(tag_freq_hz))
+            .with_pmt1_ch(pmt_ch)
+            .with_taglens_ch(taglens_ch)
+            .build()
+    }
+
     /// Parse the supplied user parameters, returning errors if illegal.
     ///
     /// Each field is parsed using either simple string to number parsing or more
     /// elaborate special functions for some designated special types.
     pub fn from_user_input(user_input: &MainAppGui) -> anyhow::Result<AppConfig, UserInputError> {
-        Ok(AppConfigBuilder::default()
+        let config = AppConfigBuilder::default()
             .with_filename(user_input.get_filename().to_string())
             .with_rows(
                 user_input
@@ -323,42 +918,56 @@ impl AppConfig {
                     .parse::<f64>()
                     .map_err(UserInputError::InvalidScanPeriod)?,
             ))
-            .with_fill_fraction(user_input.get_fill_fraction().parse::<f32>()?)
+            .with_fill_fraction(
+                FillFraction::from_percent(user_input.get_fill_fraction().parse::<f32>()?)
+                    .map_err(UserInputError::InvalidFillFraction)?,
+            )
             .with_frame_dead_time(
                 string_ms_to_ps(user_input.get_frame_dead_time())
                     .map_err(UserInputError::InvalidFrameDeadTime)?,
             )
             .with_pmt1_ch(convert_user_channel_input_to_num(
                 user_input.get_pmt1_channel(),
-            ))
+            )?)
             .with_pmt2_ch(convert_user_channel_input_to_num(
                 user_input.get_pmt2_channel(),
-            ))
+            )?)
             .with_pmt3_ch(convert_user_channel_input_to_num(
                 user_input.get_pmt3_channel(),
-            ))
+            )?)
             .with_pmt4_ch(convert_user_channel_input_to_num(
                 user_input.get_pmt4_channel(),
-            ))
+            )?)
+            .with_channel_enabled(user_input.get_channel_enabled())
             .with_laser_ch(convert_user_channel_input_to_num(
                 user_input.get_laser_channel(),
-            ))
+            )?)
             .with_frame_ch(convert_user_channel_input_to_num(
                 user_input.get_frame_channel(),
-            ))
+            )?)
             .with_line_ch(convert_user_channel_input_to_num(
                 user_input.get_line_channel(),
-            ))
+            )?)
             .with_taglens_ch(convert_user_channel_input_to_num(
                 user_input.get_tag_channel(),
-            ))
+            )?)
             .with_replay_existing(user_input.get_replay_existing())
             .with_rolling_avg(user_input.get_rolling_avg())
-            .with_line_shift(user_input.get_line_shift().parse::<Picosecond>().unwrap())
-            .build())
+            .with_color_increment(string_to_color_increment(user_input.get_color_increment())?)
+            .with_line_shifts(
+                user_input
+                    .get_line_shift()
+                    .map_err(UserInputError::InvalidLineShift)?,
+            )
+            .build();
+        config
+            .validate()
+            .map_err(UserInputError::InvalidConfiguration)?;
+        Ok(config)
     }
 
-    /// Create an [`AppConfig`] from an existing config file
+    /// Create an [`AppConfig`] from an existing config file, rejecting it if
+    /// it's invalid, see [`AppConfig::validate`]
     pub fn try_from_config_path(config_path: &Path) -> Result<Self> {
         let raw_cfg: AppConfig = toml::from_str(&read_to_string(config_path)?)?;
         let cfg = AppConfigBuilder::default()
@@ -372,10 +981,16 @@ impl AppConfig {
             .with_tag_period(raw_cfg.tag_period)
             .with_bidir(raw_cfg.bidir)
             .with_replay_existing(raw_cfg.replay_existing)
+            .with_stop_at_replay_end(raw_cfg.stop_at_replay_end)
+            .with_use_test_pattern(raw_cfg.use_test_pattern)
             .with_rolling_avg(raw_cfg.rolling_avg)
             .with_fill_fraction(raw_cfg.fill_fraction)
             .with_frame_dead_time(raw_cfg.frame_dead_time)
-            .with_line_shift(raw_cfg.line_shift)
+            .with_line_shifts(raw_cfg.line_shift)
+            .with_forward_line_shift(raw_cfg.forward_line_shift)
+            .with_mirror_reverse_rows(raw_cfg.mirror_reverse_rows)
+            .with_shift_reverse_rows(raw_cfg.shift_reverse_rows)
+            .with_collapse_bidir(raw_cfg.collapse_bidir)
             .with_pmt1_ch(raw_cfg.pmt1_ch)
             .with_pmt2_ch(raw_cfg.pmt2_ch)
             .with_pmt3_ch(raw_cfg.pmt3_ch)
@@ -384,21 +999,244 @@ impl AppConfig {
             .with_frame_ch(raw_cfg.frame_ch)
             .with_line_ch(raw_cfg.line_ch)
             .with_taglens_ch(raw_cfg.taglens_ch)
+            .with_trigger_ch(raw_cfg.trigger_ch)
             .with_demux(raw_cfg.demux)
+            .with_field_mask(raw_cfg.field_mask)
+            .with_coincidence(raw_cfg.coincidence)
+            .with_pileup_correction(raw_cfg.pileup_correction)
+            .with_auto_exposure(raw_cfg.auto_exposure)
+            .with_plane_rois(raw_cfg.plane_rois)
+            .with_batch_relevance_tolerance(raw_cfg.batch_relevance_tolerance)
+            .with_gated_frame(raw_cfg.gated_frame)
+            .with_max_extra_lines(raw_cfg.max_extra_lines)
+            .with_min_frame_interval(raw_cfg.min_frame_interval)
+            .with_sync_loss_lines(raw_cfg.sync_loss_lines)
+            .with_blank_on_sync_loss(raw_cfg.blank_on_sync_loss)
+            .with_skip_duplicate_batches(raw_cfg.skip_duplicate_batches)
+            .with_out_of_order_batches(raw_cfg.out_of_order_batches)
+            .with_mark_missed_events(raw_cfg.mark_missed_events)
+            .with_color_mode(raw_cfg.color_mode)
+            .with_depth_color_mode(raw_cfg.depth_color_mode)
+            .with_min_frame_display_ms(raw_cfg.min_frame_display_ms)
+            .with_lifetime_bins(raw_cfg.lifetime_bins)
+            .with_strobe_laser_marker(raw_cfg.strobe_laser_marker)
+            .with_intensity_scale(raw_cfg.intensity_scale)
+            .with_display_mode(raw_cfg.display_mode)
+            .with_control_window(raw_cfg.control_window)
+            .with_affine(raw_cfg.affine)
+            .with_draw_centroid(raw_cfg.draw_centroid)
+            .with_draw_scan_path(raw_cfg.draw_scan_path)
+            .with_display_decimation(raw_cfg.display_decimation)
+            .with_max_render_points(raw_cfg.max_render_points)
+            .with_display_min_count(raw_cfg.display_min_count)
+            .with_sparse_image_clear(raw_cfg.sparse_image_clear)
+            .with_frame_table(raw_cfg.frame_table)
+            .with_mip_preview_interval_frames(raw_cfg.mip_preview_interval_frames)
+            .with_thumbnail_size(raw_cfg.thumbnail_size)
+            .with_record_raw_events(raw_cfg.record_raw_events)
+            .with_stream_to_stdout(raw_cfg.stream_to_stdout)
+            .with_skip_initial_frames(raw_cfg.skip_initial_frames)
+            .with_serialization_threads(raw_cfg.serialization_threads)
+            .with_flush_partial_frame(raw_cfg.flush_partial_frame)
+            .with_render_core(raw_cfg.render_core)
+            .with_snake_length_tolerance(raw_cfg.snake_length_tolerance)
+            .with_snake_path(raw_cfg.snake_path)
+            .with_snake_dump_path(raw_cfg.snake_dump_path)
+            .with_line_clock_script(raw_cfg.line_clock_script)
+            .with_dead_time_warning_fraction(raw_cfg.dead_time_warning_fraction)
+            .with_line_timing(raw_cfg.line_timing)
+            .with_line_matching(raw_cfg.line_matching)
+            .with_live_feed_decimation(raw_cfg.live_feed_decimation)
+            .with_channel_enabled(raw_cfg.channel_enabled)
+            .with_serialize_channels(raw_cfg.serialize_channels)
+            .with_column_order(raw_cfg.column_order)
+            .with_split_bidir_direction(raw_cfg.split_bidir_direction)
+            .with_carry_voxel_indices(raw_cfg.carry_voxel_indices)
+            .with_planar_channels(raw_cfg.planar_channels)
+            .with_frame_rate_smoothing(raw_cfg.frame_rate_smoothing)
+            .with_background_level(raw_cfg.background_level)
+            .with_color_start_level(raw_cfg.color_start_level)
+            .with_max_color_level(raw_cfg.max_color_level)
+            .with_display_gamma(raw_cfg.display_gamma)
+            .with_integration_window(raw_cfg.integration_window)
+            .with_output_compression(raw_cfg.output_compression)
+            .with_x_scanner(raw_cfg.x_scanner)
             .build();
-
+        cfg.validate()?;
         Ok(cfg)
     }
 
     /// The time in ps it takes for a frame to complete. Not including the dead
     /// time between frames.
     pub fn calc_frame_duration(&self) -> Picosecond {
-        match self.bidir {
+        match self.scan_direction() {
             Bidirectionality::Bidir => (*self.scan_period / 2) * (self.rows as Picosecond),
             Bidirectionality::Unidir => *self.scan_period * (self.rows as Picosecond),
         }
     }
 
+    /// Describes why the frame dead time looks like a mistake, if the
+    /// configuration asks for this check.
+    ///
+    /// A dead time longer than the given fraction of the frame duration is
+    /// suspicious, as is a zero dead time in bidirectional scans, which
+    /// usually need some time to settle between frames.
+    pub fn frame_dead_time_warning(&self) -> Option<String> {
+        let fraction = self.dead_time_warning_fraction?;
+        let frame_duration = self.calc_frame_duration();
+        if self.frame_dead_time == 0 && self.scan_direction() == Bidirectionality::Bidir {
+            return Some(
+                "The frame dead time is 0 in a bidirectional scan, which usually needs some \
+                time to settle between frames"
+                    .to_string(),
+            );
+        }
+        if self.frame_dead_time as f64 > fraction as f64 * frame_duration as f64 {
+            return Some(format!(
+                "The frame dead time of {} ps is more than {} of the frame duration, {} ps, \
+                so it may be wrong",
+                self.frame_dead_time, fraction, frame_duration
+            ));
+        }
+        None
+    }
+
+    /// Describes why the laser period looks like a mistake, which would
+    /// silently corrupt the photon arrival times relative to the pulses.
+    ///
+    /// The period must be nonzero, and the laser should pulse a few times
+    /// during the dwell time of each pixel.
+    pub fn laser_period_warning(&self) -> Option<String> {
+        let laser_period = *self.laser_period;
+        let dwell_time = *self.pixel_dwell_time();
+        if laser_period <= 0 {
+            return Some(format!(
+                "The laser period is {} ps, so the photon arrival times can't be related to \
+                the laser pulses",
+                laser_period
+            ));
+        }
+        if laser_period * MIN_LASER_PULSES_PER_PIXEL > dwell_time {
+            return Some(format!(
+                "The laser period of {} ps isn't much shorter than the pixel dwell time of {} \
+                ps, so the laser frequency may be wrong",
+                laser_period, dwell_time
+            ));
+        }
+        None
+    }
+
+    /// Describes why the line and frame channels can't place the photons in
+    /// their rows as configured.
+    ///
+    /// Without a line channel the rows are derived from the time since the
+    /// start of each frame, so a frame channel is needed to start the frames,
+    /// and the rows can't follow the measured line timing.
+    pub fn sync_channels_warning(&self) -> Option<String> {
+        if self.line_ch.channel != 0 {
+            return None;
+        }
+        if self.frame_ch.channel == 0 {
+            return Some(
+                "Neither a line nor a frame channel is connected, so the frames will never start"
+                    .to_string(),
+            );
+        }
+        if self.line_timing == LineTiming::Measured {
+            return Some(
+                "Measured line timing requires a line channel, the rows follow the nominal \
+                timing within each frame instead"
+                    .to_string(),
+            );
+        }
+        None
+    }
+
+    /// Checks that the configuration describes an acquisition worth running.
+    ///
+    /// Unlike the [`AppConfig::validation_warnings`], these errors mean that
+    /// nothing would be rendered, e.g. when all PMT channels are disconnected
+    /// or a sawtooth scanner spends its whole period in retrace, or that part
+    /// of the output would silently stay empty.
+    pub fn validate(&self) -> Result<(), ConfigValidationError> {
+        let pmt_channels = [self.pmt1_ch, self.pmt2_ch, self.pmt3_ch, self.pmt4_ch];
+        if pmt_channels.iter().all(|ch| ch.channel == 0) {
+            return Err(ConfigValidationError::NoPmtChannels);
+        }
+        if self.collapse_bidir && self.splits_bidir_direction() {
+            return Err(ConfigValidationError::CollapsedSplitBidir);
+        }
+        if let XScanner::Sawtooth { retrace_fraction } = self.x_scanner {
+            if !(0.0..1.0).contains(&retrace_fraction) {
+                return Err(ConfigValidationError::RetraceFractionOutOfRange(
+                    retrace_fraction,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes each of the configuration values which look like a mistake,
+    /// even though the acquisition may run with them
+    pub fn validation_warnings(&self) -> Vec<String> {
+        [
+            self.frame_dead_time_warning(),
+            self.laser_period_warning(),
+            self.sync_channels_warning(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// The input channels of the configuration, named as in its TOML
+    /// representation
+    pub fn named_input_channels(&self) -> [(&'static str, InputChannel); 9] {
+        [
+            ("pmt1_ch", self.pmt1_ch),
+            ("pmt2_ch", self.pmt2_ch),
+            ("pmt3_ch", self.pmt3_ch),
+            ("pmt4_ch", self.pmt4_ch),
+            ("laser_ch", self.laser_ch),
+            ("frame_ch", self.frame_ch),
+            ("line_ch", self.line_ch),
+            ("taglens_ch", self.taglens_ch),
+            ("trigger_ch", self.trigger_ch),
+        ]
+    }
+
+    /// Compares the channel configuration which the TimeTagger reported as
+    /// applied, a TOML table of input channels keyed by their names, to the
+    /// requested one.
+    ///
+    /// Disconnected channels are ignored, while connected channels missing
+    /// from the report or having a different channel or threshold are
+    /// returned.
+    pub fn reconcile_applied_channels(
+        &self,
+        applied: &str,
+    ) -> Result<Vec<ChannelMismatch>, toml::de::Error> {
+        let applied: std::collections::HashMap<String, InputChannel> = toml::from_str(applied)?;
+        Ok(self
+            .named_input_channels()
+            .iter()
+            .filter(|(_, requested)| requested.channel != 0)
+            .filter_map(|(name, requested)| {
+                let applied = applied.get(*name).copied();
+                let matches = applied.is_some_and(|applied| {
+                    applied.channel == requested.channel
+                        && (applied.threshold - requested.threshold).abs()
+                            <= APPLIED_THRESHOLD_TOLERANCE
+                });
+                (!matches).then_some(ChannelMismatch {
+                    name,
+                    requested: *requested,
+                    applied,
+                })
+            })
+            .collect())
+    }
+
     /// Return the frame rate in Hz
     pub fn frame_rate(&self) -> f32 {
         Period {
@@ -407,6 +1245,99 @@ impl AppConfig {
         .to_hz()
     }
 
+    /// Return the rate in Hz at which lines are scanned, i.e. twice the scan
+    /// frequency in bidirectional scans
+    pub fn line_rate(&self) -> f32 {
+        self.line_period().to_hz()
+    }
+
+    /// Estimates the rate at which data is serialized when photons arrive at
+    /// the given rate.
+    ///
+    /// Voxels hit by several photons during a frame are serialized as a single
+    /// row, so each photon adds at most one row, and each frame at most one
+    /// row per voxel and serialized channel. The estimate is thus an upper
+    /// bound, which is reached for sparse images. Compression of the output
+    /// isn't taken into account.
+    pub fn estimate_output_rate(&self, expected_count_rate_hz: f64) -> BytesPerSecond {
+        let max_row_rate = (self.get_num_pixels() * self.serialize_channels.len()) as f64
+            * self.frame_rate() as f64;
+        let row_rate = expected_count_rate_hz.max(0.0).min(max_row_rate);
+        BytesPerSecond(row_rate * serialized_row_size(self) as f64)
+    }
+
+    /// Whether the photons of reverse rows are serialized in channels of their
+    /// own, which only applies to bidirectional scans
+    pub fn splits_bidir_direction(&self) -> bool {
+        self.split_bidir_direction && self.scan_direction() == Bidirectionality::Bidir
+    }
+
+    /// The time between consecutive line events, i.e. half of the scan period
+    /// in bidirectional scans
+    pub fn line_period(&self) -> Period {
+        match self.scan_direction() {
+            Bidirectionality::Bidir => Period {
+                period: *self.scan_period / 2,
+            },
+            Bidirectionality::Unidir => self.scan_period,
+        }
+    }
+
+    /// The display mode used for rendering. Volumetric acquisitions are always
+    /// drawn as point clouds, since they can't be shown as a single image
+    pub fn display_mode(&self) -> DisplayMode {
+        if self.planes > 1 {
+            DisplayMode::PointCloud
+        } else {
+            self.display_mode
+        }
+    }
+
+    /// The shift applied to the reverse rows of a bidirectional scan, which is
+    /// zero when they shouldn't be shifted
+    pub(crate) fn reverse_line_shift(&self) -> LineShift {
+        if self.shift_reverse_rows {
+            self.line_shift.clone()
+        } else {
+            LineShift::Global(0)
+        }
+    }
+
+    /// The time the scanner is effectively inside the image space. This time
+    /// is different than the scan period due to the fill fraction
+    pub(crate) fn calc_effective_line_period(&self) -> Picosecond {
+        match self.x_scanner {
+            XScanner::Resonant => ((*self.scan_period / 2) as f64
+                * self.fill_fraction.fraction() as f64)
+                .round() as Picosecond,
+            XScanner::Sawtooth { retrace_fraction } => {
+                (*self.scan_period as f64 * (1.0 - retrace_fraction as f64)).round() as Picosecond
+            }
+        }
+    }
+
+    /// The direction in which rows are scanned. Sawtooth scanners only scan
+    /// in one direction, regardless of the configured one
+    pub fn scan_direction(&self) -> Bidirectionality {
+        match self.x_scanner {
+            XScanner::Resonant => self.bidir,
+            XScanner::Sawtooth { .. } => Bidirectionality::Unidir,
+        }
+    }
+
+    /// Whether the rows are scanned by a sawtooth galvo
+    pub(crate) fn is_sawtooth(&self) -> bool {
+        matches!(self.x_scanner, XScanner::Sawtooth { .. })
+    }
+
+    /// The time the scanner spends on each pixel of a line, barring any
+    /// TAG-related scanning
+    pub fn pixel_dwell_time(&self) -> Period {
+        Period {
+            period: self.calc_effective_line_period() / (self.columns.max(1) as Picosecond),
+        }
+    }
+
     pub fn get_num_pixels(&self) -> usize {
         let planes = self.planes.max(1);
         let rows = self.rows.max(1);
@@ -419,12 +1350,31 @@ impl AppConfig {
     }
 }
 
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfigBuilder::default().build()
+    }
+}
+
 /// Converts a miliseconds number (a string) into its equivalent in ps.
 fn string_ms_to_ps(ms_as_string: &str) -> anyhow::Result<Picosecond, ParseFloatError> {
     let ms = ms_as_string.parse::<f64>()?;
     Ok((ms * 1_000_000_000f64) as Picosecond)
 }
 
+/// Parses the color increment typed by the user, which has to be above 1 for
+/// the brightness of the voxels to actually increase with each photon.
+pub(crate) fn string_to_color_increment(increment: &str) -> Result<f32, UserInputError> {
+    let increment = increment
+        .parse::<f32>()
+        .map_err(UserInputError::InvalidColorIncrement)?;
+    if increment > 1.0 {
+        Ok(increment)
+    } else {
+        Err(UserInputError::ColorIncrementTooLow(increment))
+    }
+}
+
 /// Converts a chosen user channel to its TT representation in the time tag
 /// stream.
 ///
@@ -434,8 +1384,23 @@ fn string_ms_to_ps(ms_as_string: &str) -> anyhow::Result<Picosecond, ParseFloatE
 /// has a threshold value.
 ///
 /// This function converts the user's choice into the internal representation
-/// detailed above. An empty channel is given the value 0.
-fn convert_user_channel_input_to_num(channel: (ChannelNumber, EdgeDetected, f32)) -> InputChannel {
+/// detailed above. An empty channel is given the value 0. Rising edges are
+/// detected with positive thresholds and falling edges with negative ones, so
+/// thresholds whose sign doesn't match the edge are rejected. A zero threshold
+/// fits both edges.
+fn convert_user_channel_input_to_num(
+    channel: (ChannelNumber, EdgeDetected, f32),
+) -> Result<InputChannel, UserInputError> {
+    let consistent = match channel.1 {
+        EdgeDetected::Rising => channel.2 >= 0.0,
+        EdgeDetected::Falling => channel.2 <= 0.0,
+    };
+    if !consistent && channel.0 != ChannelNumber::Disconnected {
+        return Err(UserInputError::InconsistentThreshold {
+            edge: channel.1,
+            threshold: channel.2,
+        });
+    }
     let edge: i32 = match channel.1 {
         EdgeDetected::Rising => 1,
         EdgeDetected::Falling => -1,
@@ -462,7 +1427,7 @@ fn convert_user_channel_input_to_num(channel: (ChannelNumber, EdgeDetected, f32)
             ChannelNumber::Channel18 => 18,
             ChannelNumber::Disconnected => 0,
         };
-    InputChannel::new(ch, channel.2)
+    Ok(InputChannel::new(ch, channel.2))
 }
 
 /// Demultiplexing configuration.
@@ -511,13 +1476,19 @@ pub struct AppConfigBuilder {
     scan_period: Period,
     tag_period: Period,
     bidir: Bidirectionality,
-    fill_fraction: f32, // (0..100)
+    fill_fraction: FillFraction,
     frame_dead_time: Picosecond,
     replay_existing: bool,
+    stop_at_replay_end: bool,
+    use_test_pattern: bool,
     rolling_avg: u16,
     increment_color_by: f32,
     laser_period: Period,
-    line_shift: Picosecond,
+    line_shift: LineShift,
+    forward_line_shift: Picosecond,
+    mirror_reverse_rows: bool,
+    shift_reverse_rows: bool,
+    collapse_bidir: bool,
     pmt1_ch: InputChannel,
     pmt2_ch: InputChannel,
     pmt3_ch: InputChannel,
@@ -526,7 +1497,68 @@ pub struct AppConfigBuilder {
     frame_ch: InputChannel,
     line_ch: InputChannel,
     taglens_ch: InputChannel,
+    trigger_ch: InputChannel,
     demux: Demux,
+    batch_relevance_tolerance: Picosecond,
+    gated_frame: bool,
+    max_extra_lines: u32,
+    min_frame_interval: Picosecond,
+    sync_loss_lines: u32,
+    blank_on_sync_loss: bool,
+    skip_duplicate_batches: bool,
+    out_of_order_batches: OutOfOrderBatches,
+    mark_missed_events: bool,
+    color_mode: ColorMode,
+    depth_color_mode: DepthColorMode,
+    min_frame_display_ms: u64,
+    lifetime_bins: u16,
+    strobe_laser_marker: bool,
+    intensity_scale: IntensityScale,
+    display_mode: DisplayMode,
+    control_window: ChannelNames,
+    affine: Option<[[f32; 3]; 4]>,
+    draw_centroid: bool,
+    draw_scan_path: bool,
+    display_decimation: u32,
+    max_render_points: usize,
+    display_min_count: u16,
+    sparse_image_clear: bool,
+    frame_table: bool,
+    mip_preview_interval_frames: u32,
+    thumbnail_size: u32,
+    record_raw_events: bool,
+    stream_to_stdout: bool,
+    skip_initial_frames: u32,
+    serialization_threads: usize,
+    flush_partial_frame: bool,
+    render_core: Option<usize>,
+    snake_length_tolerance: Option<usize>,
+    snake_path: Option<String>,
+    snake_dump_path: Option<String>,
+    line_clock_script: Option<String>,
+    dead_time_warning_fraction: Option<f32>,
+    line_timing: LineTiming,
+    line_matching: LineMatching,
+    live_feed_decimation: u32,
+    channel_enabled: [bool; SUPPORTED_SPECTRAL_CHANNELS],
+    serialize_channels: Vec<usize>,
+    column_order: Vec<SerializedColumn>,
+    split_bidir_direction: bool,
+    carry_voxel_indices: bool,
+    planar_channels: Vec<usize>,
+    frame_rate_smoothing: f32,
+    background_level: f32,
+    color_start_level: f32,
+    max_color_level: f32,
+    display_gamma: f32,
+    integration_window: IntegrationWindow,
+    output_compression: OutputCompression,
+    x_scanner: XScanner,
+    field_mask: Option<FieldMask>,
+    coincidence: Option<Coincidence>,
+    pileup_correction: Option<PileupCorrection>,
+    auto_exposure: Option<AutoExposure>,
+    plane_rois: Option<Vec<PlaneRoi>>,
 }
 
 impl AppConfigBuilder {
@@ -544,10 +1576,16 @@ impl AppConfigBuilder {
             tag_period: Period::from_freq(189800.0),
             bidir: Bidirectionality::Bidir,
             replay_existing: false,
+            stop_at_replay_end: true,
+            use_test_pattern: false,
             rolling_avg: 1,
-            fill_fraction: 71.0,
+            fill_fraction: FillFraction(71.0),
             frame_dead_time: 1_310_000_000,
-            line_shift: 0,
+            line_shift: LineShift::Global(0),
+            forward_line_shift: 0,
+            mirror_reverse_rows: true,
+            shift_reverse_rows: true,
+            collapse_bidir: false,
             pmt1_ch: InputChannel::new(1, 0.0),
             pmt2_ch: InputChannel::new(0, 0.0),
             pmt3_ch: InputChannel::new(0, 0.0),
@@ -556,7 +1594,68 @@ impl AppConfigBuilder {
             frame_ch: InputChannel::new(0, 0.0),
             line_ch: InputChannel::new(-2, 0.0),
             taglens_ch: InputChannel::new(3, 0.0),
+            trigger_ch: InputChannel::new(0, 0.0),
             demux: Demux::default(),
+            batch_relevance_tolerance: 0,
+            gated_frame: false,
+            max_extra_lines: 0,
+            min_frame_interval: 0,
+            sync_loss_lines: 10,
+            blank_on_sync_loss: false,
+            skip_duplicate_batches: true,
+            out_of_order_batches: OutOfOrderBatches::Rewind,
+            mark_missed_events: false,
+            color_mode: ColorMode::PerChannelPalette,
+            depth_color_mode: DepthColorMode::Off,
+            min_frame_display_ms: 0,
+            lifetime_bins: 0,
+            strobe_laser_marker: false,
+            intensity_scale: IntensityScale::Linear,
+            display_mode: DisplayMode::PointCloud,
+            control_window: ChannelNames::ChannelMerge,
+            affine: None,
+            draw_centroid: false,
+            draw_scan_path: false,
+            display_decimation: 1,
+            max_render_points: 0,
+            display_min_count: 0,
+            sparse_image_clear: false,
+            frame_table: false,
+            mip_preview_interval_frames: 0,
+            thumbnail_size: 0,
+            record_raw_events: false,
+            stream_to_stdout: false,
+            skip_initial_frames: 0,
+            serialization_threads: 1,
+            flush_partial_frame: false,
+            render_core: None,
+            snake_length_tolerance: None,
+            snake_path: None,
+            snake_dump_path: None,
+            line_clock_script: None,
+            dead_time_warning_fraction: Some(0.5),
+            line_timing: LineTiming::Nominal,
+            line_matching: LineMatching::Counted,
+            live_feed_decimation: 1,
+            channel_enabled: [true; SUPPORTED_SPECTRAL_CHANNELS],
+            serialize_channels: (0..SUPPORTED_SPECTRAL_CHANNELS).collect(),
+            column_order: SerializedColumn::DEFAULT_ORDER.to_vec(),
+            split_bidir_direction: false,
+            carry_voxel_indices: false,
+            planar_channels: Vec::new(),
+            frame_rate_smoothing: 0.1,
+            background_level: 0.0,
+            color_start_level: GRAYSCALE_START,
+            max_color_level: 1.0,
+            display_gamma: 1.0,
+            integration_window: IntegrationWindow::PerFrame,
+            output_compression: OutputCompression::Uncompressed,
+            x_scanner: XScanner::Resonant,
+            field_mask: None,
+            coincidence: None,
+            pileup_correction: None,
+            auto_exposure: None,
+            plane_rois: None,
         }
     }
 
@@ -583,9 +1682,76 @@ impl AppConfigBuilder {
             frame_ch: self.frame_ch,
             line_ch: self.line_ch,
             taglens_ch: self.taglens_ch,
+            trigger_ch: self.trigger_ch,
             replay_existing: self.replay_existing,
-            line_shift: self.line_shift,
+            stop_at_replay_end: self.stop_at_replay_end,
+            use_test_pattern: self.use_test_pattern,
+            line_shift: self.line_shift.clone(),
+            forward_line_shift: self.forward_line_shift,
+            mirror_reverse_rows: self.mirror_reverse_rows,
+            shift_reverse_rows: self.shift_reverse_rows,
+            collapse_bidir: self.collapse_bidir,
             demux: self.demux.clone(),
+            batch_relevance_tolerance: self.batch_relevance_tolerance,
+            gated_frame: self.gated_frame,
+            max_extra_lines: self.max_extra_lines,
+            min_frame_interval: self.min_frame_interval,
+            sync_loss_lines: self.sync_loss_lines,
+            blank_on_sync_loss: self.blank_on_sync_loss,
+            skip_duplicate_batches: self.skip_duplicate_batches,
+            out_of_order_batches: self.out_of_order_batches,
+            mark_missed_events: self.mark_missed_events,
+            color_mode: self.color_mode,
+            depth_color_mode: self.depth_color_mode,
+            min_frame_display_ms: self.min_frame_display_ms,
+            lifetime_bins: self.lifetime_bins,
+            strobe_laser_marker: self.strobe_laser_marker,
+            intensity_scale: self.intensity_scale,
+            display_mode: self.display_mode,
+            control_window: self.control_window,
+            affine: self.affine,
+            draw_centroid: self.draw_centroid,
+            draw_scan_path: self.draw_scan_path,
+            display_decimation: self.display_decimation,
+            max_render_points: self.max_render_points,
+            display_min_count: self.display_min_count,
+            sparse_image_clear: self.sparse_image_clear,
+            frame_table: self.frame_table,
+            mip_preview_interval_frames: self.mip_preview_interval_frames,
+            thumbnail_size: self.thumbnail_size,
+            record_raw_events: self.record_raw_events,
+            stream_to_stdout: self.stream_to_stdout,
+            skip_initial_frames: self.skip_initial_frames,
+            serialization_threads: self.serialization_threads,
+            flush_partial_frame: self.flush_partial_frame,
+            render_core: self.render_core,
+            snake_length_tolerance: self.snake_length_tolerance,
+            snake_path: self.snake_path.clone(),
+            snake_dump_path: self.snake_dump_path.clone(),
+            line_clock_script: self.line_clock_script.clone(),
+            dead_time_warning_fraction: self.dead_time_warning_fraction,
+            line_timing: self.line_timing,
+            line_matching: self.line_matching,
+            live_feed_decimation: self.live_feed_decimation,
+            channel_enabled: self.channel_enabled,
+            serialize_channels: self.serialize_channels.clone(),
+            column_order: self.column_order.clone(),
+            split_bidir_direction: self.split_bidir_direction,
+            carry_voxel_indices: self.carry_voxel_indices,
+            planar_channels: self.planar_channels.clone(),
+            frame_rate_smoothing: self.frame_rate_smoothing,
+            background_level: self.background_level,
+            color_start_level: self.color_start_level,
+            max_color_level: self.max_color_level,
+            display_gamma: self.display_gamma,
+            integration_window: self.integration_window,
+            output_compression: self.output_compression,
+            x_scanner: self.x_scanner,
+            field_mask: self.field_mask,
+            coincidence: self.coincidence,
+            pileup_correction: self.pileup_correction,
+            auto_exposure: self.auto_exposure,
+            plane_rois: self.plane_rois.clone(),
         };
         Self::assert_cfg_valid(&cfg);
         cfg
@@ -598,10 +1764,12 @@ impl AppConfigBuilder {
         if (cfg.demux.demultiplex) && (cfg.demux.periods < 2) {
             panic!("Demultiplexing with a single period doesn't make sense.")
         }
-        match cfg.demux.demux_ch.as_str() {
-            "pmt1_ch" => assert!(cfg.pmt1_ch.channel != 0),
-            "pmt2_ch" => assert!(cfg.pmt2_ch.channel != 0),
-            _ => unreachable!(),
+        if cfg.demux.demultiplex {
+            match cfg.demux.demux_ch.as_str() {
+                "pmt1_ch" => assert!(cfg.pmt1_ch.channel != 0),
+                "pmt2_ch" => assert!(cfg.pmt2_ch.channel != 0),
+                _ => unreachable!(),
+            }
         }
         if cfg.increment_color_by <= 1.0 {
             panic!(
@@ -640,9 +1808,6 @@ impl AppConfigBuilder {
     /// Change the number of rendered planes
     pub fn with_planes(&mut self, planes: u32) -> &mut Self {
         assert!(planes < 100_000);
-        if planes > 1 {
-            assert!(planes % 2 == 0, "Number of planes must be even");
-        };
         self.planes = planes;
         self
     }
@@ -674,9 +1839,7 @@ impl AppConfigBuilder {
     }
 
     /// Change the fill fraction value
-    pub fn with_fill_fraction<T: Into<f32>>(&mut self, fill_fraction: T) -> &mut Self {
-        let fill_fraction = fill_fraction.into();
-        assert!((0.0..=100.0).contains(&fill_fraction));
+    pub fn with_fill_fraction(&mut self, fill_fraction: FillFraction) -> &mut Self {
         self.fill_fraction = fill_fraction;
         self
     }
@@ -744,35 +1907,548 @@ impl AppConfigBuilder {
         self
     }
 
+    /// Specify the external trigger channel. When it's connected each frame
+    /// starts only after a trigger event arrives
+    pub fn with_trigger_ch(&mut self, trigger_ch: InputChannel) -> &mut Self {
+        assert!(trigger_ch.channel.abs() <= MAX_TIMETAGGER_INPUTS);
+        self.trigger_ch = trigger_ch;
+        self
+    }
+
     /// Whether the filename points at an existing file which should be replayed
     pub fn with_replay_existing(&mut self, replay_existing: bool) -> &mut Self {
         self.replay_existing = replay_existing;
         self
     }
 
+    /// Specify whether a replay stops once its stream runs out of events, or
+    /// keeps waiting for more of them
+    pub fn with_stop_at_replay_end(&mut self, stop_at_replay_end: bool) -> &mut Self {
+        self.stop_at_replay_end = stop_at_replay_end;
+        self
+    }
+
+    /// Whether a synthetic test pattern should be streamed instead of the
+    /// data of a TimeTagger
+    pub fn with_use_test_pattern(&mut self, use_test_pattern: bool) -> &mut Self {
+        self.use_test_pattern = use_test_pattern;
+        self
+    }
+
     /// Specify the value for the line shift
     pub fn with_line_shift(&mut self, line_shift: Picosecond) -> &mut Self {
+        self.line_shift = LineShift::Global(line_shift);
+        self
+    }
+
+    /// Specify the line shift, either for all planes or per plane
+    pub fn with_line_shifts(&mut self, line_shift: LineShift) -> &mut Self {
         self.line_shift = line_shift;
         self
     }
 
+    /// Specify the shift of the forward rows of a bidirectional scan, while
+    /// the line shift applies to its reverse rows
+    pub fn with_forward_line_shift(&mut self, forward_line_shift: Picosecond) -> &mut Self {
+        self.forward_line_shift = forward_line_shift;
+        self
+    }
+
+    /// Specify whether the reverse rows of a bidirectional scan are mirrored
+    /// in image space, i.e. whether their columns are rendered right to left
+    pub fn with_mirror_reverse_rows(&mut self, mirror_reverse_rows: bool) -> &mut Self {
+        self.mirror_reverse_rows = mirror_reverse_rows;
+        self
+    }
+
+    /// Specify whether the line shift is applied to the reverse rows of a
+    /// bidirectional scan
+    pub fn with_shift_reverse_rows(&mut self, shift_reverse_rows: bool) -> &mut Self {
+        self.shift_reverse_rows = shift_reverse_rows;
+        self
+    }
+
+    /// Specify whether the reverse rows of a bidirectional scan are rendered
+    /// into the rows of their paired forward rows, averaging the two sweeps
+    pub fn with_collapse_bidir(&mut self, collapse_bidir: bool) -> &mut Self {
+        self.collapse_bidir = collapse_bidir;
+        self
+    }
+
     /// Specify whether we're demultiplexing a channel or not
     pub fn with_demux(&mut self, demux: Demux) -> &mut Self {
         self.demux = demux;
         self
     }
 
-    /// Specify the multiplicative factor in the rendered display.
-    /// Enter a higher number under low light conditions
-    pub fn with_color_increment(&mut self, val: f32) -> &mut Self {
-        self.increment_color_by = val;
+    /// Specify a mask of the field of view, outside of which photons are
+    /// discarded
+    pub fn with_field_mask(&mut self, field_mask: Option<FieldMask>) -> &mut Self {
+        self.field_mask = field_mask;
         self
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+
+    /// Specify a channel whose photons are rendered only if they arrive
+    /// shortly before or after an event of a reference channel
+    pub fn with_coincidence(&mut self, coincidence: Option<Coincidence>) -> &mut Self {
+        self.coincidence = coincidence;
+        self
+    }
+
+    /// Specify a model that corrects the serialized photon counts of each
+    /// voxel for pile-up
+    pub fn with_pileup_correction(
+        &mut self,
+        pileup_correction: Option<PileupCorrection>,
+    ) -> &mut Self {
+        self.pileup_correction = pileup_correction;
+        self
+    }
+
+    /// Adjust the color increment after each rendered frame so that the mean
+    /// brightness of the displayed voxels approaches the target of the given
+    /// auto-exposure. The configured increment is used for the first frame
+    pub fn with_auto_exposure(&mut self, auto_exposure: Option<AutoExposure>) -> &mut Self {
+        if let Some(auto) = auto_exposure {
+            assert!(auto.target > 0.0 && auto.target <= 1.0);
+            assert!(auto.rate > 0.0 && auto.rate <= 1.0);
+        }
+        self.auto_exposure = auto_exposure;
+        self
+    }
+
+    /// Specify a region of interest for each plane of a 3D acquisition, by
+    /// plane index. Photons outside of the ROI of their plane are discarded
+    pub fn with_plane_rois(&mut self, plane_rois: Option<Vec<PlaneRoi>>) -> &mut Self {
+        self.plane_rois = plane_rois;
+        self
+    }
+
+    /// Specify the multiplicative factor in the rendered display.
+    /// Enter a higher number under low light conditions
+    pub fn with_color_increment(&mut self, val: f32) -> &mut Self {
+        self.increment_color_by = val;
+        self
+    }
+
+    /// Specify how much earlier than the start of the current frame an event
+    /// may arrive and still be processed as part of it
+    pub fn with_batch_relevance_tolerance(&mut self, tolerance: Picosecond) -> &mut Self {
+        assert!(tolerance >= 0);
+        self.batch_relevance_tolerance = tolerance;
+        self
+    }
+
+    /// Specify whether the frame channel is a gate, i.e. its leading edge
+    /// starts the frame and its opposite edge ends it
+    pub fn with_gated_frame(&mut self, gated_frame: bool) -> &mut Self {
+        self.gated_frame = gated_frame;
+        self
+    }
+
+    /// Specify how many lines beyond the expected number of rows are
+    /// tolerated before a new frame is forced. Only lines arriving before the
+    /// end of the current frame count as extra ones
+    pub fn with_max_extra_lines(&mut self, max_extra_lines: u32) -> &mut Self {
+        self.max_extra_lines = max_extra_lines;
+        self
+    }
+
+    /// Specify the minimal time in ps between two frame signals, below which
+    /// the later signal is considered a glitch and ignored
+    pub fn with_min_frame_interval(&mut self, min_frame_interval: Picosecond) -> &mut Self {
+        assert!(min_frame_interval >= 0);
+        self.min_frame_interval = min_frame_interval;
+        self
+    }
+
+    /// Specify after how many line periods without a line event, on top of
+    /// the frame dead time, the sync with the scanner is considered lost. 0
+    /// disables the check
+    pub fn with_sync_loss_lines(&mut self, sync_loss_lines: u32) -> &mut Self {
+        self.sync_loss_lines = sync_loss_lines;
+        self
+    }
+
+    /// Specify whether photons arriving after the sync with the scanner was
+    /// lost are discarded instead of being displayed
+    pub fn with_blank_on_sync_loss(&mut self, blank_on_sync_loss: bool) -> &mut Self {
+        self.blank_on_sync_loss = blank_on_sync_loss;
+        self
+    }
+
+    /// Specify whether batches whose events exactly duplicate the previous
+    /// batch are skipped instead of being counted twice
+    pub fn with_skip_duplicate_batches(&mut self, skip_duplicate_batches: bool) -> &mut Self {
+        self.skip_duplicate_batches = skip_duplicate_batches;
+        self
+    }
+
+    /// Specify how batches arriving out of time order are handled
+    pub fn with_out_of_order_batches(
+        &mut self,
+        out_of_order_batches: OutOfOrderBatches,
+    ) -> &mut Self {
+        self.out_of_order_batches = out_of_order_batches;
+        self
+    }
+
+    /// Specify whether the voxels swept while the TimeTagger missed photons
+    /// are rendered as missing data
+    pub fn with_mark_missed_events(&mut self, mark_missed_events: bool) -> &mut Self {
+        self.mark_missed_events = mark_missed_events;
+        self
+    }
+
+    /// Specify how the channels are colored in the merged channel
+    pub fn with_color_mode(&mut self, color_mode: ColorMode) -> &mut Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// Specify whether the voxels of volumetric acquisitions are colored by
+    /// their depth rather than by channel
+    pub fn with_depth_color_mode(&mut self, depth_color_mode: DepthColorMode) -> &mut Self {
+        self.depth_color_mode = depth_color_mode;
+        self
+    }
+
+    /// Specify the minimal duration, in milliseconds, each rendered frame is
+    /// kept on screen
+    pub fn with_min_frame_display_ms(&mut self, min_frame_display_ms: u64) -> &mut Self {
+        self.min_frame_display_ms = min_frame_display_ms;
+        self
+    }
+
+    /// Specify the number of bins in the per-voxel histogram of photon
+    /// arrival times relative to the laser pulse. 0 disables the histograms.
+    pub fn with_lifetime_bins(&mut self, lifetime_bins: u16) -> &mut Self {
+        self.lifetime_bins = lifetime_bins;
+        self
+    }
+
+    /// Specify whether the laser channel carries a single marker per
+    /// stroboscopic cycle rather than an event per pulse, in which case the
+    /// phase of each photon is measured relative to the pulse train that
+    /// starts at the marker
+    pub fn with_strobe_laser_marker(&mut self, strobe_laser_marker: bool) -> &mut Self {
+        self.strobe_laser_marker = strobe_laser_marker;
+        self
+    }
+
+    /// Specify how photon counts are mapped to brightness
+    pub fn with_intensity_scale(&mut self, intensity_scale: IntensityScale) -> &mut Self {
+        self.intensity_scale = intensity_scale;
+        self
+    }
+
+    /// Specify whether the frames are drawn as point clouds or as images
+    pub fn with_display_mode(&mut self, display_mode: DisplayMode) -> &mut Self {
+        self.display_mode = display_mode;
+        self
+    }
+
+    /// Specify the window whose keyboard controls, e.g. closing it, steer the
+    /// acquisition
+    pub fn with_control_window(&mut self, control_window: ChannelNames) -> &mut Self {
+        self.control_window = control_window;
+        self
+    }
+
+    /// Specify the affine transform applied to the coordinates of the
+    /// photons, both displayed and serialized. The first three rows multiply
+    /// each coordinate and the last one is added to it. Photons moved outside
+    /// of the volume are dropped. None keeps the coordinates as they are
+    pub fn with_affine(&mut self, affine: Option<[[f32; 3]; 4]>) -> &mut Self {
+        self.affine = affine;
+        self
+    }
+
+    /// Specify whether the count-weighted centroid of each frame is marked
+    /// on screen
+    pub fn with_draw_centroid(&mut self, draw_centroid: bool) -> &mut Self {
+        self.draw_centroid = draw_centroid;
+        self
+    }
+
+    /// Specify whether the path of the scanner, including its flybacks, is
+    /// drawn over the rendered frames
+    pub fn with_draw_scan_path(&mut self, draw_scan_path: bool) -> &mut Self {
+        self.draw_scan_path = draw_scan_path;
+        self
+    }
+
+    /// Specify that only every Nth photon is displayed, while all of them are
+    /// still serialized
+    pub fn with_display_decimation(&mut self, display_decimation: u32) -> &mut Self {
+        self.display_decimation = display_decimation;
+        self
+    }
+
+    /// Specify the maximal number of points rendered in each frame, beyond
+    /// which photons of new voxels are only serialized. 0 renders all points
+    pub fn with_max_render_points(&mut self, max_render_points: usize) -> &mut Self {
+        self.max_render_points = max_render_points;
+        self
+    }
+
+    /// Specify the minimal number of photons a voxel must collect in a frame
+    /// before it's displayed. All voxels are still serialized. 0 displays all
+    /// voxels
+    pub fn with_display_min_count(&mut self, display_min_count: u16) -> &mut Self {
+        self.display_min_count = display_min_count;
+        self
+    }
+
+    /// Specify whether an image display only resets the voxels drawn in the
+    /// previous frame instead of the whole frame
+    pub fn with_sparse_image_clear(&mut self, sparse_image_clear: bool) -> &mut Self {
+        self.sparse_image_clear = sparse_image_clear;
+        self
+    }
+
+    /// Specify whether a table with the timing and photon count of each frame
+    /// is written next to the data
+    pub fn with_frame_table(&mut self, frame_table: bool) -> &mut Self {
+        self.frame_table = frame_table;
+        self
+    }
+
+    /// Specify every how many frames a maximum intensity projection of the
+    /// last frame is written as a PNG next to the data. 0 disables the
+    /// previews
+    pub fn with_mip_preview_interval_frames(
+        &mut self,
+        mip_preview_interval_frames: u32,
+    ) -> &mut Self {
+        self.mip_preview_interval_frames = mip_preview_interval_frames;
+        self
+    }
+
+    /// Specify the width and height of the thumbnail of each frame which is
+    /// appended to a stream next to the data. 0 disables the thumbnails
+    pub fn with_thumbnail_size(&mut self, thumbnail_size: u32) -> &mut Self {
+        self.thumbnail_size = thumbnail_size;
+        self
+    }
+
+    /// Specify whether the raw events are archived in a stream file next to
+    /// the data, for replaying them later
+    pub fn with_record_raw_events(&mut self, record_raw_events: bool) -> &mut Self {
+        self.record_raw_events = record_raw_events;
+        self
+    }
+
+    /// Specify whether the serialized table is streamed to stdout instead of
+    /// being written to disk, e.g. for piping it into analysis scripts
+    pub fn with_stream_to_stdout(&mut self, stream_to_stdout: bool) -> &mut Self {
+        self.stream_to_stdout = stream_to_stdout;
+        self
+    }
+
+    /// Specify the number of frames at the start of the acquisition whose
+    /// photons are discarded instead of serialized, e.g. while the scanner
+    /// settles. They're still displayed
+    pub fn with_skip_initial_frames(&mut self, skip_initial_frames: u32) -> &mut Self {
+        self.skip_initial_frames = skip_initial_frames;
+        self
+    }
+
+    /// Specify the number of threads which map the frames to the serialized
+    /// batches. The batches are always written in order. Must be at least 1
+    pub fn with_serialization_threads(&mut self, serialization_threads: usize) -> &mut Self {
+        assert!(serialization_threads >= 1);
+        self.serialization_threads = serialization_threads;
+        self
+    }
+
+    /// Specify whether the photons which weren't serialized yet when the
+    /// acquisition stops, e.g. those of an interrupted frame, are serialized
+    /// before shutting down
+    pub fn with_flush_partial_frame(&mut self, flush_partial_frame: bool) -> &mut Self {
+        self.flush_partial_frame = flush_partial_frame;
+        self
+    }
+
+    /// Specify the CPU core to which the rendering thread is pinned. Pinning
+    /// requires the `core_affinity` feature
+    pub fn with_render_core(&mut self, render_core: Option<usize>) -> &mut Self {
+        self.render_core = render_core;
+        self
+    }
+
+    /// Specify by how many cells the length of each generated or loaded snake
+    /// may diverge from the calculated one before a warning is logged. None
+    /// skips this check
+    pub fn with_snake_length_tolerance(&mut self, tolerance: Option<usize>) -> &mut Self {
+        self.snake_length_tolerance = tolerance;
+        self
+    }
+
+    /// Specify a snake previously dumped to disk which is used instead of
+    /// the one built from the rest of the configuration
+    pub fn with_snake_path(&mut self, path: Option<String>) -> &mut Self {
+        self.snake_path = path;
+        self
+    }
+
+    /// Specify a path to which the snake built from the rest of the
+    /// configuration is dumped, so that it can later be loaded with
+    /// [`AppConfigBuilder::with_snake_path`]. Only planar snakes are dumped
+    pub fn with_snake_dump_path(&mut self, path: Option<String>) -> &mut Self {
+        self.snake_dump_path = path;
+        self
+    }
+
+    /// Specify a Python script whose `line_clock` function is called with the
+    /// index of each line within its frame and the time of its line signal
+    pub fn with_line_clock_script(&mut self, path: Option<String>) -> &mut Self {
+        self.line_clock_script = path;
+        self
+    }
+
+    /// Specify the fraction of the frame duration above which the frame dead
+    /// time is considered implausible and a warning is logged. None skips this
+    /// check
+    pub fn with_dead_time_warning_fraction(&mut self, fraction: Option<f32>) -> &mut Self {
+        if let Some(fraction) = fraction {
+            assert!(fraction >= 0.0);
+        }
+        self.dead_time_warning_fraction = fraction;
+        self
+    }
+
+    /// Specify whether rows follow the measured line signals or the nominal
+    /// scan period
+    pub fn with_line_timing(&mut self, line_timing: LineTiming) -> &mut Self {
+        self.line_timing = line_timing;
+        self
+    }
+
+    /// Specify whether line events are counted or matched to the nearest
+    /// expected row
+    pub fn with_line_matching(&mut self, line_matching: LineMatching) -> &mut Self {
+        self.line_matching = line_matching;
+        self
+    }
+
+    /// Specify that only every Nth photon is sent to the live feed of
+    /// processed photons
+    pub fn with_live_feed_decimation(&mut self, live_feed_decimation: u32) -> &mut Self {
+        self.live_feed_decimation = live_feed_decimation;
+        self
+    }
+
+    /// Specify which of the PMT channels are rendered and serialized when the
+    /// acquisition starts. They may also be toggled during the acquisition
+    pub fn with_channel_enabled(
+        &mut self,
+        channel_enabled: [bool; SUPPORTED_SPECTRAL_CHANNELS],
+    ) -> &mut Self {
+        self.channel_enabled = channel_enabled;
+        self
+    }
+
+    /// Specify the indices of the PMT channels which are written to disk.
+    /// Photons of the other channels are still rendered
+    pub fn with_serialize_channels(&mut self, serialize_channels: Vec<usize>) -> &mut Self {
+        self.serialize_channels = serialize_channels;
+        self
+    }
+
+    /// Specify the order of the columns of the serialized table. Each of the
+    /// columns must appear exactly once
+    pub fn with_column_order(&mut self, column_order: Vec<SerializedColumn>) -> &mut Self {
+        assert!(column_order.len() == SerializedColumn::DEFAULT_ORDER.len());
+        assert!(SerializedColumn::DEFAULT_ORDER
+            .iter()
+            .all(|column| column_order.contains(column)));
+        self.column_order = column_order;
+        self
+    }
+
+    /// Serialize the photons of the reverse rows of bidirectional scans in
+    /// channels of their own, numbered after the regular ones, e.g. for
+    /// measuring the offset between the two scan directions
+    pub fn with_split_bidir_direction(&mut self, split_bidir_direction: bool) -> &mut Self {
+        self.split_bidir_direction = split_bidir_direction;
+        self
+    }
+
+    /// Specify whether the cells of planar snakes carry the array indices of
+    /// their voxels, so that photons are serialized without mapping their
+    /// coordinates back to indices. Ignored in 3D imaging
+    pub fn with_carry_voxel_indices(&mut self, carry_voxel_indices: bool) -> &mut Self {
+        self.carry_voxel_indices = carry_voxel_indices;
+        self
+    }
+
+    /// Specify the indices of the PMT channels which are imaged in 2D during
+    /// volumetric acquisitions, starting from 0
+    pub fn with_planar_channels(&mut self, planar_channels: Vec<usize>) -> &mut Self {
+        self.planar_channels = planar_channels;
+        self
+    }
+
+    /// Specify the weight of the newest frame in the moving average of the
+    /// measured frame rate, between 0 (exclusive) and 1
+    pub fn with_frame_rate_smoothing(&mut self, frame_rate_smoothing: f32) -> &mut Self {
+        self.frame_rate_smoothing = frame_rate_smoothing;
+        self
+    }
+
+    /// Specify the gray level, between 0 and 1, of voxels without photons
+    pub fn with_background_level(&mut self, background_level: f32) -> &mut Self {
+        self.background_level = background_level;
+        self
+    }
+
+    /// Specify the brightness of a voxel after its first photon, between 0
+    /// (exclusive) and 1
+    pub fn with_color_start_level(&mut self, color_start_level: f32) -> &mut Self {
+        self.color_start_level = color_start_level;
+        self
+    }
+
+    /// Specify the level at which the color components of bright voxels
+    /// saturate
+    pub fn with_max_color_level(&mut self, max_color_level: f32) -> &mut Self {
+        self.max_color_level = max_color_level;
+        self
+    }
+
+    /// Specify the gamma applied to the brightness of the displayed voxels,
+    /// relative to the maximal color level. Must be positive, and 1.0 keeps
+    /// the brightness linear
+    pub fn with_display_gamma(&mut self, display_gamma: f32) -> &mut Self {
+        assert!(display_gamma > 0.0);
+        self.display_gamma = display_gamma;
+        self
+    }
+
+    /// Specify whether photons are integrated per frame or over a fixed time
+    /// window
+    pub fn with_integration_window(&mut self, integration_window: IntegrationWindow) -> &mut Self {
+        self.integration_window = integration_window;
+        self
+    }
+
+    /// Specify the compression of the serialized data stream
+    pub fn with_output_compression(&mut self, output_compression: OutputCompression) -> &mut Self {
+        self.output_compression = output_compression;
+        self
+    }
+
+    /// Specify the kind of scanner moving the beam along each row
+    pub fn with_x_scanner(&mut self, x_scanner: XScanner) -> &mut Self {
+        self.x_scanner = x_scanner;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     /// Helper method to test config-dependent things without actually caring
     /// about the different config values
@@ -786,7 +2462,7 @@ mod tests {
             .with_tag_period(Period::from_freq(189800))
             .with_bidir(Bidirectionality::Bidir)
             .with_rolling_avg(1)
-            .with_fill_fraction(71.3)
+            .with_fill_fraction(FillFraction::from_percent(71.3).unwrap())
             .with_frame_dead_time(8 * *Period::from_freq(7926.17))
             .with_pmt1_ch(InputChannel::new(-1, 0.0))
             .with_pmt2_ch(InputChannel::new(0, 0.0))
@@ -902,6 +2578,64 @@ mod tests {
         assert_eq!(inps[1002], DataType::Pmt3);
     }
 
+    #[test]
+    fn inputs_gated_frame_maps_opposite_edge() {
+        let config = setup_default_config()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_gated_frame(true)
+            .build();
+        let inps = Inputs::from_config(&config);
+        assert_eq!(inps[4], DataType::Frame);
+        assert_eq!(inps[-4], DataType::FrameEnd);
+    }
+
+    #[test]
+    fn inputs_ungated_frame_ignores_opposite_edge() {
+        let config = setup_default_config()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .build();
+        let inps = Inputs::from_config(&config);
+        assert_eq!(inps[-4], DataType::Invalid);
+    }
+
+    #[test]
+    fn inputs_describe_lists_only_used_channels() {
+        let config = setup_default_config()
+            .with_pmt1_ch(InputChannel::new(-1, 0.0))
+            .with_frame_ch(InputChannel::new(5, 0.0))
+            .build();
+        let inps = Inputs::from_config(&config);
+        assert_eq!(
+            inps.describe(),
+            vec![
+                (-1, DataType::Pmt1),
+                (2, DataType::Line),
+                (3, DataType::TagLens),
+                (5, DataType::Frame)
+            ]
+        );
+    }
+
+    #[test]
+    fn inputs_describe_includes_virtual_channels() {
+        let config = setup_default_config()
+            .with_pmt1_ch(InputChannel::new(-1, 0.0))
+            .with_pmt2_ch(InputChannel::new(-4, 0.0))
+            .with_demux(Demux::new(true, String::from("pmt1_ch"), 2, 0))
+            .build();
+        let inps = Inputs::from_config(&config);
+        assert_eq!(
+            inps.describe(),
+            vec![
+                (-4, DataType::Pmt2),
+                (2, DataType::Line),
+                (3, DataType::TagLens),
+                (1001, DataType::Pmt1),
+                (1002, DataType::Pmt3)
+            ]
+        );
+    }
+
     #[test]
     fn frame_time_bidir() {
         let config = setup_default_config().with_bidir(true).build();
@@ -926,6 +2660,184 @@ mod tests {
         assert_eq!(config.frame_rate(), 61.923203f32);
     }
 
+    #[test]
+    fn line_rate_unidir() {
+        let config = setup_default_config().with_bidir(false).build();
+        assert_eq!(config.line_rate(), 7926.17f32);
+    }
+
+    #[test]
+    fn line_rate_bidir() {
+        let config = setup_default_config().with_bidir(true).build();
+        assert_eq!(config.line_rate(), 15852.34f32);
+    }
+
+    #[test]
+    fn pixel_dwell_time_standard() {
+        let config = setup_default_config().build();
+        assert_eq!(config.pixel_dwell_time(), Period { period: 175_693 });
+    }
+
+    #[test]
+    fn line_shift_accepts_scalar_or_per_plane_values() {
+        let scalar: AppConfig = toml::from_str("line_shift = 1000").unwrap();
+        assert_eq!(scalar.line_shift, LineShift::Global(1000));
+        let per_plane: AppConfig = toml::from_str("line_shift = [1000, 1200]").unwrap();
+        assert_eq!(per_plane.line_shift.for_plane(1), 1200);
+        assert_eq!(per_plane.line_shift.for_plane(5), 1200);
+    }
+
+    #[test]
+    fn elliptical_field_mask_follows_its_radii() {
+        let mask = FieldMask::Ellipse {
+            row_radius: 1.0,
+            column_radius: 0.5,
+        };
+        assert!(mask.contains(0.0, 0.0));
+        assert!(mask.contains(0.9, 0.0));
+        assert!(!mask.contains(0.0, 0.9));
+        assert!(!mask.contains(0.8, 0.4));
+    }
+
+    #[test]
+    fn field_mask_round_trips_through_toml() {
+        let unmasked = setup_default_config().build();
+        let masked = setup_default_config()
+            .with_field_mask(Some(FieldMask::Circle { radius: 0.8 }))
+            .build();
+        for config in [unmasked, masked] {
+            let serialized = toml::to_string(&config).unwrap();
+            assert_eq!(toml::from_str::<AppConfig>(&serialized).unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn coincidence_round_trips_through_toml() {
+        let config = setup_default_config()
+            .with_coincidence(Some(Coincidence {
+                channel: DataType::Pmt2,
+                reference: DataType::Pmt1,
+                window: 2_000,
+            }))
+            .build();
+        let serialized = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<AppConfig>(&serialized).unwrap(), config);
+    }
+
+    #[test]
+    fn plane_rois_round_trip_through_toml() {
+        let roi = PlaneRoi {
+            row_start: -1.0,
+            row_end: 0.5,
+            column_start: -0.5,
+            column_end: 1.0,
+        };
+        let config = setup_default_config()
+            .with_plane_rois(Some(vec![roi, roi]))
+            .build();
+        let serialized = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<AppConfig>(&serialized).unwrap(), config);
+    }
+
+    #[test]
+    fn fill_fraction_in_percent_equals_fraction() {
+        assert_eq!(
+            FillFraction::from_percent(50.0).unwrap(),
+            FillFraction::from_fraction(0.5).unwrap()
+        );
+        assert_eq!(FillFraction::from_fraction(0.5).unwrap().percent(), 50.0);
+    }
+
+    #[test]
+    fn out_of_range_fill_fraction_errors() {
+        for percent in [0.0, -10.0, 100.5, 713.0] {
+            assert_eq!(
+                FillFraction::from_percent(percent),
+                Err(FillFractionError::OutOfRange(percent))
+            );
+        }
+        assert!(FillFraction::from_fraction(1.5).is_err());
+        assert!(toml::from_str::<FillFraction>("0.0").is_err());
+    }
+
+    #[test]
+    fn basic_2d_config_matches_builder() {
+        let config = AppConfig::basic_2d(512, 256, 7923.0, InputChannel::new(-1, -0.1));
+        let built = AppConfigBuilder::default()
+            .with_rows(512)
+            .with_columns(256)
+            .with_planes(1)
+            .with_scan_period(Period::from_freq(7923.0))
+            .with_pmt1_ch(InputChannel::new(-1, -0.1))
+            .with_taglens_ch(InputChannel::new(0, 0.0))
+            .build();
+        assert_eq!(config, built);
+    }
+
+    #[test]
+    fn basic_3d_config_matches_builder() {
+        let config = AppConfig::basic_3d(
+            256,
+            256,
+            10,
+            7923.0,
+            189_800.0,
+            InputChannel::new(1, 0.0),
+            InputChannel::new(3, 0.0),
+        );
+        let built = AppConfigBuilder::default()
+            .with_rows(256)
+            .with_columns(256)
+            .with_planes(10)
+            .with_scan_period(Period::from_freq(7923.0))
+            .with_tag_period(Period::from_freq(189_800.0))
+            .with_pmt1_ch(InputChannel::new(1, 0.0))
+            .with_taglens_ch(InputChannel::new(3, 0.0))
+            .build();
+        assert_eq!(config, built);
+    }
+
+    #[test]
+    fn output_rate_is_the_row_size_times_the_count_rate() {
+        let config = setup_default_config().with_lifetime_bins(4).build();
+        let row_size = 1 + 4 + 4 + 4 + 1 + 4 * 2;
+        let rate = config.estimate_output_rate(1_000_000.0);
+        assert_eq!(rate, BytesPerSecond(row_size as f64 * 1_000_000.0));
+        assert_eq!(
+            rate.total_bytes(Duration::from_secs(60)),
+            60 * 1_000_000 * row_size
+        );
+    }
+
+    #[test]
+    fn output_compression_round_trips_through_toml() {
+        for compression in [
+            OutputCompression::Uncompressed,
+            OutputCompression::Zstd { level: 3 },
+            OutputCompression::Lz4 { level: 4 },
+        ] {
+            let config = setup_default_config()
+                .with_output_compression(compression)
+                .build();
+            let serialized = toml::to_string(&config).unwrap();
+            assert_eq!(toml::from_str::<AppConfig>(&serialized).unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn integration_window_round_trips_through_toml() {
+        for window in [
+            IntegrationWindow::PerFrame,
+            IntegrationWindow::Time(100_000_000_000),
+        ] {
+            let config = setup_default_config()
+                .with_integration_window(window)
+                .build();
+            let serialized = toml::to_string(&config).unwrap();
+            assert_eq!(toml::from_str::<AppConfig>(&serialized).unwrap(), config);
+        }
+    }
+
     #[test]
     fn string_ms_to_ps_simple() {
         let deadtime = "1.0";
@@ -945,7 +2857,7 @@ mod tests {
             EdgeDetected::Rising,
             0.0,
         ));
-        assert_eq!(result, InputChannel::new(0, 0.0));
+        assert_eq!(result, Ok(InputChannel::new(0, 0.0)));
     }
 
     #[test]
@@ -955,7 +2867,7 @@ mod tests {
             EdgeDetected::Falling,
             0.0,
         ));
-        assert_eq!(result, InputChannel::new(0, 0.0));
+        assert_eq!(result, Ok(InputChannel::new(0, 0.0)));
     }
 
     #[test]
@@ -965,13 +2877,207 @@ mod tests {
             EdgeDetected::Falling,
             -1.0,
         ));
-        assert_eq!(result, InputChannel::new(-3, -1.0));
+        assert_eq!(result, Ok(InputChannel::new(-3, -1.0)));
     }
 
     #[test]
     fn channel_inp_to_num_standard_rising() {
         let result =
             convert_user_channel_input_to_num((ChannelNumber::Channel3, EdgeDetected::Rising, 1.0));
-        assert_eq!(result, InputChannel::new(3, 1.0));
+        assert_eq!(result, Ok(InputChannel::new(3, 1.0)));
+    }
+
+    #[test]
+    fn channel_inp_to_num_falling_with_positive_threshold() {
+        let result = convert_user_channel_input_to_num((
+            ChannelNumber::Channel3,
+            EdgeDetected::Falling,
+            0.5,
+        ));
+        assert_eq!(
+            result,
+            Err(UserInputError::InconsistentThreshold {
+                edge: EdgeDetected::Falling,
+                threshold: 0.5
+            })
+        );
+    }
+
+    #[test]
+    fn channel_inp_to_num_rising_with_negative_threshold() {
+        let result = convert_user_channel_input_to_num((
+            ChannelNumber::Channel3,
+            EdgeDetected::Rising,
+            -0.5,
+        ));
+        assert!(matches!(
+            result,
+            Err(UserInputError::InconsistentThreshold { .. })
+        ));
+    }
+
+    #[test]
+    fn channel_inp_to_num_zero_threshold_fits_both_edges() {
+        for edge in [EdgeDetected::Rising, EdgeDetected::Falling] {
+            let result = convert_user_channel_input_to_num((ChannelNumber::Channel3, edge, 0.0));
+            assert!(result.is_ok());
+        }
+    }
+
+    #[test]
+    fn dead_time_longer_than_the_frame_is_warned_about() {
+        let mut builder = setup_default_config();
+        let frame_duration = builder.build().calc_frame_duration();
+        let config = builder.with_frame_dead_time(frame_duration + 1).build();
+        let warning = config.frame_dead_time_warning().unwrap();
+        assert!(warning.contains(&frame_duration.to_string()));
+    }
+
+    #[test]
+    fn plausible_dead_time_isnt_warned_about() {
+        let config = setup_default_config().build();
+        assert_eq!(config.frame_dead_time_warning(), None);
+        let unchecked = setup_default_config()
+            .with_frame_dead_time(0)
+            .with_dead_time_warning_fraction(None)
+            .build();
+        assert_eq!(unchecked.frame_dead_time_warning(), None);
+    }
+
+    #[test]
+    fn zero_dead_time_is_warned_about_only_in_bidir_scans() {
+        let mut builder = setup_default_config();
+        builder.with_frame_dead_time(0);
+        assert!(builder.build().frame_dead_time_warning().is_some());
+        let unidir = builder.with_bidir(Bidirectionality::Unidir).build();
+        assert_eq!(unidir.frame_dead_time_warning(), None);
+    }
+
+    #[test]
+    fn default_dead_time_check_matches_the_default_config_file() {
+        let config = AppConfig::try_from_config_path(Path::new("resources/default.toml")).unwrap();
+        let builder = AppConfigBuilder::default().build();
+        assert_eq!(
+            config.dead_time_warning_fraction,
+            builder.dead_time_warning_fraction
+        );
+        assert_eq!(builder.dead_time_warning_fraction, Some(0.5));
+    }
+
+    #[test]
+    fn laser_period_longer_than_the_pixel_dwell_is_warned_about() {
+        let config = setup_default_config()
+            .with_laser_period(Period::from_freq(1_000_000.0))
+            .build();
+        assert!(*config.laser_period > *config.pixel_dwell_time());
+        let warning = config.laser_period_warning().unwrap();
+        assert!(warning.contains(&config.pixel_dwell_time().period.to_string()));
+        assert_eq!(config.validation_warnings(), vec![warning]);
+    }
+
+    #[test]
+    fn plausible_laser_period_isnt_warned_about() {
+        let config = setup_default_config().build();
+        assert_eq!(config.laser_period_warning(), None);
+        let stopped = setup_default_config()
+            .with_laser_period(Period { period: 0 })
+            .build();
+        assert!(stopped.laser_period_warning().is_some());
+    }
+
+    #[test]
+    fn frames_without_a_line_channel_are_accepted() {
+        let config = setup_default_config()
+            .with_line_ch(InputChannel::new(0, 0.0))
+            .with_frame_ch(InputChannel::new(3, 0.5))
+            .build();
+        assert_eq!(config.sync_channels_warning(), None);
+    }
+
+    #[test]
+    fn missing_sync_channels_are_warned_about() {
+        let mut builder = setup_default_config();
+        builder.with_line_ch(InputChannel::new(0, 0.0));
+        assert!(builder.build().sync_channels_warning().is_some());
+        let measured = builder
+            .with_frame_ch(InputChannel::new(3, 0.5))
+            .with_line_timing(LineTiming::Measured)
+            .build();
+        assert!(measured.sync_channels_warning().is_some());
+    }
+
+    #[test]
+    fn config_without_pmt_channels_is_invalid() {
+        let config = setup_default_config()
+            .with_pmt1_ch(InputChannel::new(0, 0.0))
+            .with_pmt2_ch(InputChannel::new(0, 0.0))
+            .with_pmt3_ch(InputChannel::new(0, 0.0))
+            .with_pmt4_ch(InputChannel::new(0, 0.0))
+            .build();
+        assert_eq!(config.validate(), Err(ConfigValidationError::NoPmtChannels));
+    }
+
+    #[test]
+    fn config_with_a_single_pmt_channel_is_valid() {
+        let config = setup_default_config()
+            .with_pmt1_ch(InputChannel::new(-1, -0.2))
+            .with_pmt2_ch(InputChannel::new(0, 0.0))
+            .with_pmt3_ch(InputChannel::new(0, 0.0))
+            .with_pmt4_ch(InputChannel::new(0, 0.0))
+            .build();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn collapsed_bidir_rows_cant_be_split_by_direction() {
+        let config = setup_default_config()
+            .with_bidir(Bidirectionality::Bidir)
+            .with_collapse_bidir(true)
+            .with_split_bidir_direction(true)
+            .build();
+        assert_eq!(
+            config.validate(),
+            Err(ConfigValidationError::CollapsedSplitBidir)
+        );
+    }
+
+    #[test]
+    fn sawtooth_retrace_fraction_outside_of_the_period_is_invalid() {
+        for retrace_fraction in [-0.1, 1.0, 1.5] {
+            let config = setup_default_config()
+                .with_x_scanner(XScanner::Sawtooth { retrace_fraction })
+                .build();
+            assert_eq!(
+                config.validate(),
+                Err(ConfigValidationError::RetraceFractionOutOfRange(
+                    retrace_fraction
+                ))
+            );
+        }
+        let config = setup_default_config()
+            .with_x_scanner(XScanner::Sawtooth {
+                retrace_fraction: 0.0,
+            })
+            .build();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn config_files_with_an_invalid_retrace_fraction_are_rejected() {
+        let config = setup_default_config()
+            .with_x_scanner(XScanner::Sawtooth {
+                retrace_fraction: 1.0,
+            })
+            .build();
+        let mut path = std::env::temp_dir();
+        path.push("config_files_with_an_invalid_retrace_fraction.toml");
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        let loaded = AppConfig::try_from_config_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        let error = loaded.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ConfigValidationError>(),
+            Some(&ConfigValidationError::RetraceFractionOutOfRange(1.0))
+        );
     }
 }
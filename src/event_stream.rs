@@ -4,10 +4,21 @@
 use std::fmt::Debug;
 
 use arrow2::array::{Array, Int32Array, Int64Array, UInt16Array, UInt8Array};
-use arrow2::datatypes::DataType;
+use arrow2::datatypes::{DataType, Schema};
 use arrow2::record_batch::RecordBatch;
 use lazy_static::lazy_static;
 use pyo3::prelude::*;
+use thiserror::Error;
+
+/// Names of the columns of each batch arriving from the TimeTagger, in order
+const COLUMN_NAMES: [&str; 4] = ["type_", "missed_events", "channel", "time"];
+/// Types of the columns of each batch arriving from the TimeTagger, in order
+const COLUMN_TYPES: [DataType; 4] = [
+    DataType::UInt8,
+    DataType::UInt16,
+    DataType::Int32,
+    DataType::Int64,
+];
 
 lazy_static! {
     static ref TYPE_: UInt8Array = UInt8Array::new_empty(DataType::UInt8);
@@ -22,6 +33,136 @@ lazy_static! {
     };
 }
 
+/// Errors due to a batch whose schema doesn't match the expected one, probably
+/// due to a misconfigured Python side.
+#[derive(Debug, Error, PartialEq)]
+pub enum EventStreamError {
+    #[error("Expected a batch with {expected} columns, found {found}")]
+    WrongColumnCount { expected: usize, found: usize },
+    #[error("Expected column {index} ('{name}') to be of type {expected}, found {found}")]
+    WrongColumnType {
+        index: usize,
+        name: &'static str,
+        expected: String,
+        found: String,
+    },
+    #[error("Expected column {index} to be named '{expected}', found '{found}'")]
+    WrongColumnName {
+        index: usize,
+        expected: &'static str,
+        found: String,
+    },
+}
+
+/// Checks that the names and types of the fields of a stream's schema match
+/// the columns of the TimeTagger's batches.
+///
+/// The batches are later read by the position of their columns, so a stream
+/// whose columns were reordered or renamed must be rejected before any of
+/// them is read, even if their types happen to match.
+pub fn validate_schema(schema: &Schema) -> Result<(), EventStreamError> {
+    let fields = schema.fields();
+    if fields.len() != COLUMN_NAMES.len() {
+        return Err(EventStreamError::WrongColumnCount {
+            expected: COLUMN_NAMES.len(),
+            found: fields.len(),
+        });
+    }
+    for (index, field) in fields.iter().enumerate() {
+        if field.name() != COLUMN_NAMES[index] {
+            return Err(EventStreamError::WrongColumnName {
+                index,
+                expected: COLUMN_NAMES[index],
+                found: field.name().clone(),
+            });
+        }
+        if field.data_type() != &COLUMN_TYPES[index] {
+            return Err(EventStreamError::WrongColumnType {
+                index,
+                name: COLUMN_NAMES[index],
+                expected: format!("{:?}", COLUMN_TYPES[index]),
+                found: format!("{:?}", field.data_type()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Errors due to a raw buffer of tags that can't be parsed
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseError {
+    #[error("Expected at least {expected} bytes for the tag at offset {offset}, found {found}")]
+    Truncated {
+        offset: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+/// A single tag in the binary layout used by the TimeTagger, which lets raw
+/// buffers be parsed without going through Arrow.
+///
+/// Fields are stored in little-endian byte order, with a padding byte after
+/// the type.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tag {
+    pub type_: u8,
+    pub reserved: u8,
+    pub missed_events: u16,
+    pub channel: i32,
+    pub time: i64,
+}
+
+impl Tag {
+    /// Number of bytes each tag occupies in a raw buffer
+    pub const SIZE: usize = std::mem::size_of::<Tag>();
+
+    /// Parses the tag at the start of the buffer, returning an error if it's
+    /// too short
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tag, ParseError> {
+        Tag::from_bytes_at(bytes, 0)
+    }
+
+    /// Parses the tag found `offset` bytes into the buffer
+    fn from_bytes_at(buffer: &[u8], offset: usize) -> Result<Tag, ParseError> {
+        let bytes = buffer
+            .get(offset..offset + Tag::SIZE)
+            .ok_or(ParseError::Truncated {
+                offset,
+                expected: Tag::SIZE,
+                found: buffer.len().saturating_sub(offset),
+            })?;
+        Ok(Tag {
+            type_: bytes[0],
+            reserved: bytes[1],
+            missed_events: u16::from_le_bytes([bytes[2], bytes[3]]),
+            channel: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            time: i64::from_le_bytes([
+                bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14],
+                bytes[15],
+            ]),
+        })
+    }
+}
+
+impl From<Tag> for Event {
+    fn from(tag: Tag) -> Self {
+        Event::new(tag.type_, tag.missed_events, tag.channel, tag.time)
+    }
+}
+
+/// Parses a raw buffer of consecutive tags into events.
+///
+/// A buffer whose length isn't a multiple of [`Tag::SIZE`] ends with a
+/// truncated tag, which is reported as an error.
+pub fn process_tags(buffer: &[u8]) -> Result<Vec<Event>, ParseError> {
+    (0..buffer.len())
+        .step_by(Tag::SIZE)
+        .map(|offset| Tag::from_bytes_at(buffer, offset).map(Event::from))
+        .collect()
+}
+
 /// A single tag\event that arrives from the Time Tagger.
 #[pyclass]
 #[derive(Debug, Copy, Clone)]
@@ -150,28 +291,22 @@ impl<'a> EventStream<'a> {
         EMPTY_EVENT_STREAM.clone()
     }
 
-    pub fn from_streamed_batch(batch: &'a RecordBatch) -> EventStream<'a> {
-        let type_ = batch
-            .column(0)
-            .as_any()
-            .downcast_ref::<UInt8Array>()
-            .expect("Type field conversion failed");
-        let missed_events = batch
-            .column(1)
-            .as_any()
-            .downcast_ref::<UInt16Array>()
-            .expect("Missed events field conversion failed");
-        let channel = batch
-            .column(2)
-            .as_any()
-            .downcast_ref::<Int32Array>()
-            .expect("Channel field conversion failed");
-        let time = batch
-            .column(3)
-            .as_any()
-            .downcast_ref::<Int64Array>()
-            .expect("Time field conversion failed");
-        EventStream::new(type_, missed_events, channel, time)
+    /// Creates a stream with views over the columns of the batch, returning
+    /// an error if they don't match the expected schema.
+    pub fn from_streamed_batch(
+        batch: &'a RecordBatch,
+    ) -> Result<EventStream<'a>, EventStreamError> {
+        if batch.num_columns() != COLUMN_NAMES.len() {
+            return Err(EventStreamError::WrongColumnCount {
+                expected: COLUMN_NAMES.len(),
+                found: batch.num_columns(),
+            });
+        }
+        let type_ = downcast_column::<UInt8Array>(batch, 0, DataType::UInt8)?;
+        let missed_events = downcast_column::<UInt16Array>(batch, 1, DataType::UInt16)?;
+        let channel = downcast_column::<Int32Array>(batch, 2, DataType::Int32)?;
+        let time = downcast_column::<Int64Array>(batch, 3, DataType::Int64)?;
+        Ok(EventStream::new(type_, missed_events, channel, time))
     }
 
     pub fn iter(&'a self) -> RefEventStreamIter<'a> {
@@ -183,6 +318,24 @@ impl<'a> EventStream<'a> {
     }
 }
 
+/// Downcasts the column at the given index of the batch to its concrete type
+fn downcast_column<T: 'static>(
+    batch: &RecordBatch,
+    index: usize,
+    expected: DataType,
+) -> Result<&T, EventStreamError> {
+    let column = batch.column(index);
+    column
+        .as_any()
+        .downcast_ref::<T>()
+        .ok_or_else(|| EventStreamError::WrongColumnType {
+            index,
+            name: COLUMN_NAMES[index],
+            expected: format!("{:?}", expected),
+            found: format!("{:?}", column.data_type()),
+        })
+}
+
 impl<'a> IntoIterator for EventStream<'a> {
     type Item = Event;
     type IntoIter = EventStreamIter<'a>;
@@ -208,3 +361,103 @@ impl<'a> IntoIterator for &'a EventStream<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow2::datatypes::Field;
+    use std::sync::Arc;
+
+    fn batch_with_time_column(time: Arc<dyn Array>) -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("type_", DataType::UInt8, false),
+            Field::new("missed_events", DataType::UInt16, false),
+            Field::new("channel", DataType::Int32, false),
+            Field::new("time", time.data_type().clone(), false),
+        ]);
+        let columns: Vec<Arc<dyn Array>> = vec![
+            Arc::new(UInt8Array::from_slice(vec![0])),
+            Arc::new(UInt16Array::from_slice(vec![0])),
+            Arc::new(Int32Array::from_slice(vec![1])),
+            time,
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns).unwrap()
+    }
+
+    #[test]
+    fn single_row_batch_yields_one_event() {
+        let batch = batch_with_time_column(Arc::new(Int64Array::from_slice(vec![100])));
+        let stream = EventStream::from_streamed_batch(&batch).unwrap();
+        assert_eq!(stream.num_rows(), 1);
+        let events: Vec<Event> = stream.into_iter().collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].channel, 1);
+        assert_eq!(events[0].time, 100);
+    }
+
+    /// Two tags, the second on a negative channel with missed events
+    const RAW_TAGS: [u8; 32] = [
+        0, 0, 0, 0, 1, 0, 0, 0, 0xe8, 0x03, 0, 0, 0, 0, 0, 0, // first
+        4, 0, 2, 1, 0xfe, 0xff, 0xff, 0xff, 0, 0xca, 0x9a, 0x3b, 0, 0, 0, 0, // second
+    ];
+
+    #[test]
+    fn tag_fields_are_little_endian() {
+        assert_eq!(Tag::SIZE, 16);
+        let tag = Tag::from_bytes(&RAW_TAGS[16..]).unwrap();
+        assert_eq!(
+            tag,
+            Tag {
+                type_: 4,
+                reserved: 0,
+                missed_events: 258,
+                channel: -2,
+                time: 1_000_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn raw_tags_are_processed_into_events() {
+        let events = process_tags(&RAW_TAGS).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            (events[0].type_, events[0].channel, events[0].time),
+            (0, 1, 1_000)
+        );
+        assert_eq!(
+            (events[1].missed_event, events[1].channel, events[1].time),
+            (258, -2, 1_000_000_000)
+        );
+    }
+
+    #[test]
+    fn truncated_tag_buffer_is_an_error() {
+        assert_eq!(
+            process_tags(&RAW_TAGS[..20]).unwrap_err(),
+            ParseError::Truncated {
+                offset: 16,
+                expected: 16,
+                found: 4,
+            }
+        );
+        assert!(Tag::from_bytes(&RAW_TAGS[..15]).is_err());
+        assert!(process_tags(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn wrong_column_type_names_the_column() {
+        let batch = batch_with_time_column(Arc::new(Int32Array::from_slice(vec![100])));
+        let err = EventStream::from_streamed_batch(&batch).unwrap_err();
+        assert_eq!(
+            err,
+            EventStreamError::WrongColumnType {
+                index: 3,
+                name: "time",
+                expected: "Int64".to_string(),
+                found: "Int32".to_string(),
+            }
+        );
+        assert!(err.to_string().contains("'time'"));
+    }
+}
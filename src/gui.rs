@@ -1,13 +1,21 @@
+use std::num::ParseIntError;
 use std::path::PathBuf;
 
+use crossbeam::channel::Sender;
+use futures::channel::oneshot;
 use iced::{
     button, pick_list, text_input, Align, Application, Button, Checkbox, Clipboard, Column,
     Command, Container, Element, Image, Length, PickList, Row, Text, TextInput,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{channel_value_to_pair, start_acquisition, DEFAULT_CONFIG_FNAME};
-use crate::{configuration::AppConfig, snakes::Picosecond};
+use crate::configuration::{string_to_color_increment, AppConfig, LineShift};
+use crate::point_cloud_renderer::AcquisitionControl;
+use crate::snakes::Picosecond;
+use crate::{
+    channel_value_to_pair, check_timetagger_connection, spawn_acquisition, AcquisitionFailure,
+    AcquisitionHandle, PythonStartupError, DEFAULT_CONFIG_FNAME, SUPPORTED_SPECTRAL_CHANNELS,
+};
 
 #[derive(Default)]
 pub struct MainAppGui {
@@ -26,6 +34,8 @@ pub struct MainAppGui {
     bidirectional: bool,
     rolling_avg_input: text_input::State,
     rolling_avg_value: String,
+    color_increment_input: text_input::State,
+    color_increment_value: String,
     fill_fraction_input: text_input::State,
     fill_fraction_value: String,
     frame_dead_time_input: text_input::State,
@@ -81,7 +91,16 @@ pub struct MainAppGui {
     replay_existing: bool,
     line_shift_input: text_input::State,
     line_shift_value: String,
+    /// The line shift of the loaded configuration, kept until the user edits
+    /// the field since it may hold a shift per plane
+    loaded_line_shift: Option<LineShift>,
     run_button: button::State,
+    test_connection_button: button::State,
+    display_paused: bool,
+    channel_enabled: [bool; SUPPORTED_SPECTRAL_CHANNELS],
+    acquisition: Option<Sender<AcquisitionControl>>,
+    python_error: Option<String>,
+    connection_status: Option<String>,
 }
 
 impl MainAppGui {
@@ -153,6 +172,10 @@ impl MainAppGui {
         )
     }
 
+    pub(crate) fn get_channel_enabled(&self) -> [bool; SUPPORTED_SPECTRAL_CHANNELS] {
+        self.channel_enabled
+    }
+
     pub(crate) fn get_laser_channel(&self) -> (ChannelNumber, EdgeDetected, f32) {
         (
             self.laser_selected,
@@ -189,13 +212,286 @@ impl MainAppGui {
         self.replay_existing
     }
 
-    pub(crate) fn get_line_shift(&self) -> &str {
-        &self.line_shift_value
+    pub(crate) fn get_line_shift(&self) -> Result<LineShift, ParseIntError> {
+        match &self.loaded_line_shift {
+            Some(line_shift) => Ok(line_shift.clone()),
+            None => self.line_shift_value.parse().map(LineShift::Global),
+        }
     }
 
     pub(crate) fn get_rolling_avg(&self) -> u16 {
         self.rolling_avg_value.parse::<u16>().unwrap_or(1)
     }
+
+    pub(crate) fn get_color_increment(&self) -> &str {
+        &self.color_increment_value
+    }
+
+    /// Summarizes the timing of the scan described by the current inputs, or
+    /// explains that it can't be computed from them.
+    fn scan_timing_summary(&self) -> String {
+        match AppConfig::from_user_input(self) {
+            Ok(config) => format!(
+                "Frame rate: {:.2} Hz, line rate: {:.2} Hz, pixel dwell time: {:.1} ns",
+                config.frame_rate(),
+                config.line_rate(),
+                *config.pixel_dwell_time() as f64 / 1_000.0,
+            ),
+            Err(_) => "Frame rate: N/A, line rate: N/A, pixel dwell time: N/A".to_string(),
+        }
+    }
+
+    /// Keep handling messages while the acquisition runs on its own thread,
+    /// and report back once it ends
+    fn track_acquisition(&mut self, acquisition: AcquisitionHandle) -> Command<Message> {
+        if self.display_paused {
+            let _ = acquisition.control.send(AcquisitionControl::PauseDisplay);
+        }
+        self.acquisition = Some(acquisition.control);
+        Command::perform(
+            acquisition_outcome(acquisition.finished),
+            Message::StartedAcquistion,
+        )
+    }
+
+    /// Send the command to the running acquisition, if there is one
+    fn send_control(&self, command: AcquisitionControl) {
+        if let Some(control) = self.acquisition.as_ref() {
+            // A closed channel means the acquisition is already over
+            let _ = control.send(command);
+        }
+    }
+
+    /// Toggle a PMT channel, also in the running acquisition
+    fn set_channel_enabled(&mut self, channel: usize, enabled: bool) -> Command<Message> {
+        self.channel_enabled[channel] = enabled;
+        self.send_control(AcquisitionControl::SetChannelEnabled { channel, enabled });
+        Command::none()
+    }
+
+    /// Updates the GUI's state following a user interaction
+    fn handle_message(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::FilenameChanged(filename) => {
+                self.filename_value = filename;
+                Command::none()
+            }
+            Message::RowsChanged(rows) => {
+                self.rows_value = rows;
+                Command::none()
+            }
+            Message::ColumnsChanged(columns) => {
+                self.columns_value = columns;
+                Command::none()
+            }
+            Message::PlanesChanged(planes) => {
+                self.planes_value = planes;
+                Command::none()
+            }
+            Message::ScanPeriodChanged(period) => {
+                self.scan_period_value = period;
+                Command::none()
+            }
+            Message::TagLensPeriodChanged(period) => {
+                self.tag_period_value = period;
+                Command::none()
+            }
+            Message::BidirectionalityChanged(bidir) => {
+                self.bidirectional = bidir;
+                Command::none()
+            }
+            Message::FillFractionChanged(fillfrac) => {
+                self.fill_fraction_value = fillfrac;
+                Command::none()
+            }
+            Message::FrameDeadTimeChanged(deadtime) => {
+                self.frame_dead_time_value = deadtime;
+                Command::none()
+            }
+            Message::Pmt1Changed(pmt1) => {
+                self.pmt1_selected = pmt1;
+                Command::none()
+            }
+            Message::Pmt1EdgeChanged(pmt1_edge) => {
+                self.pmt1_edge_selected = pmt1_edge;
+                Command::none()
+            }
+            Message::Pmt1ThresholdChanged(pmt1_thresh) => {
+                self.pmt1_threshold_value = pmt1_thresh;
+                Command::none()
+            }
+            Message::Pmt1EnabledChanged(enabled) => self.set_channel_enabled(0, enabled),
+            Message::Pmt2Changed(pmt2) => {
+                self.pmt2_selected = pmt2;
+                Command::none()
+            }
+            Message::Pmt2EdgeChanged(pmt2_edge) => {
+                self.pmt2_edge_selected = pmt2_edge;
+                Command::none()
+            }
+            Message::Pmt2ThresholdChanged(pmt2_thresh) => {
+                self.pmt2_threshold_value = pmt2_thresh;
+                Command::none()
+            }
+            Message::Pmt2EnabledChanged(enabled) => self.set_channel_enabled(1, enabled),
+            Message::Pmt3Changed(pmt3) => {
+                self.pmt3_selected = pmt3;
+                Command::none()
+            }
+            Message::Pmt3EdgeChanged(pmt3_edge) => {
+                self.pmt3_edge_selected = pmt3_edge;
+                Command::none()
+            }
+            Message::Pmt3ThresholdChanged(pmt3_thresh) => {
+                self.pmt3_threshold_value = pmt3_thresh;
+                Command::none()
+            }
+            Message::Pmt3EnabledChanged(enabled) => self.set_channel_enabled(2, enabled),
+            Message::Pmt4Changed(pmt4) => {
+                self.pmt4_selected = pmt4;
+                Command::none()
+            }
+            Message::Pmt4EdgeChanged(pmt4_edge) => {
+                self.pmt4_edge_selected = pmt4_edge;
+                Command::none()
+            }
+            Message::Pmt4ThresholdChanged(pmt4_thresh) => {
+                self.pmt4_threshold_value = pmt4_thresh;
+                Command::none()
+            }
+            Message::Pmt4EnabledChanged(enabled) => self.set_channel_enabled(3, enabled),
+            Message::LaserChanged(laser) => {
+                self.laser_selected = laser;
+                Command::none()
+            }
+            Message::LaserEdgeChanged(laser_edge) => {
+                self.laser_edge_selected = laser_edge;
+                Command::none()
+            }
+            Message::LaserThresholdChanged(laser_thresh) => {
+                self.laser_threshold_value = laser_thresh;
+                Command::none()
+            }
+            Message::FrameChanged(frame) => {
+                self.frame_selected = frame;
+                Command::none()
+            }
+            Message::FrameEdgeChanged(frame_edge) => {
+                self.frame_edge_selected = frame_edge;
+                Command::none()
+            }
+            Message::FrameThresholdChanged(frame_thresh) => {
+                self.frame_threshold_value = frame_thresh;
+                Command::none()
+            }
+            Message::LineChanged(line) => {
+                self.line_selected = line;
+                Command::none()
+            }
+            Message::LineEdgeChanged(line_edge) => {
+                self.line_edge_selected = line_edge;
+                Command::none()
+            }
+            Message::LineThresholdChanged(line_thresh) => {
+                self.line_threshold_value = line_thresh;
+                Command::none()
+            }
+            Message::TagLensChanged(taglens) => {
+                self.taglens_selected = taglens;
+                Command::none()
+            }
+            Message::TagLensEdgeChanged(taglens_edge) => {
+                self.taglens_edge_selected = taglens_edge;
+                Command::none()
+            }
+            Message::TagLensThresholdChanged(taglens_thresh) => {
+                self.taglens_threshold_value = taglens_thresh;
+                Command::none()
+            }
+            Message::ReplayExistingChanged(replay_existing) => {
+                self.replay_existing = replay_existing;
+                Command::none()
+            }
+            Message::LineShiftChanged(line_shift) => {
+                self.line_shift_value = line_shift;
+                self.loaded_line_shift = None;
+                Command::none()
+            }
+            Message::RollingAvgChanged(rolling_avg) => {
+                self.rolling_avg_value = rolling_avg;
+                Command::none()
+            }
+            Message::ColorIncrementChanged(color_increment) => {
+                self.color_increment_value = color_increment;
+                Command::none()
+            }
+            Message::ButtonPressed => {
+                self.python_error = None;
+                match AppConfig::from_user_input(self) {
+                    Ok(config) => {
+                        let acquisition =
+                            spawn_acquisition(PathBuf::from(DEFAULT_CONFIG_FNAME), config);
+                        self.track_acquisition(acquisition)
+                    }
+                    Err(e) => {
+                        self.python_error = Some(e.to_string());
+                        Command::none()
+                    }
+                }
+            }
+            Message::TestConnectionPressed => {
+                self.python_error = None;
+                self.connection_status = None;
+                match AppConfig::from_user_input(self) {
+                    Ok(config) => Command::perform(
+                        async move { check_timetagger_connection(&config) },
+                        Message::ConnectionChecked,
+                    ),
+                    Err(e) => {
+                        self.python_error = Some(e.to_string());
+                        Command::none()
+                    }
+                }
+            }
+            Message::ConnectionChecked(Ok(description)) => {
+                self.connection_status = Some(description);
+                Command::none()
+            }
+            Message::ConnectionChecked(Err(e)) => {
+                self.python_error = Some(e.to_string());
+                Command::none()
+            }
+            Message::DisplayPausedChanged(display_paused) => {
+                self.display_paused = display_paused;
+                self.send_control(if display_paused {
+                    AcquisitionControl::PauseDisplay
+                } else {
+                    AcquisitionControl::ResumeDisplay
+                });
+                Command::none()
+            }
+            Message::StartedAcquistion(Ok(())) => {
+                self.acquisition = None;
+                Command::none()
+            }
+            Message::StartedAcquistion(Err(e)) => {
+                self.acquisition = None;
+                self.python_error = Some(e.to_string());
+                Command::none()
+            }
+        }
+    }
+}
+
+/// Waits for the acquisition to end. An acquisition thread which never
+/// reported its result crashed, e.g. due to a panic
+async fn acquisition_outcome(
+    finished: oneshot::Receiver<Result<(), PythonStartupError>>,
+) -> Result<(), AcquisitionFailure> {
+    match finished.await {
+        Ok(result) => result.map_err(AcquisitionFailure::from),
+        Err(oneshot::Canceled) => Err(AcquisitionFailure::Crashed),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -212,15 +508,19 @@ pub enum Message {
     Pmt1Changed(ChannelNumber),
     Pmt1EdgeChanged(EdgeDetected),
     Pmt1ThresholdChanged(String),
+    Pmt1EnabledChanged(bool),
     Pmt2Changed(ChannelNumber),
     Pmt2EdgeChanged(EdgeDetected),
     Pmt2ThresholdChanged(String),
+    Pmt2EnabledChanged(bool),
     Pmt3Changed(ChannelNumber),
     Pmt3EdgeChanged(EdgeDetected),
     Pmt3ThresholdChanged(String),
+    Pmt3EnabledChanged(bool),
     Pmt4Changed(ChannelNumber),
     Pmt4EdgeChanged(EdgeDetected),
     Pmt4ThresholdChanged(String),
+    Pmt4EnabledChanged(bool),
     LaserChanged(ChannelNumber),
     LaserEdgeChanged(EdgeDetected),
     LaserThresholdChanged(String),
@@ -236,8 +536,12 @@ pub enum Message {
     ReplayExistingChanged(bool),
     LineShiftChanged(String),
     RollingAvgChanged(String),
+    ColorIncrementChanged(String),
     ButtonPressed,
-    StartedAcquistion(()),
+    TestConnectionPressed,
+    ConnectionChecked(Result<String, PythonStartupError>),
+    DisplayPausedChanged(bool),
+    StartedAcquistion(Result<(), AcquisitionFailure>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -379,11 +683,14 @@ impl Application for MainAppGui {
             scan_period_value: prev_config.scan_period.to_hz().to_string(),
             tag_period_value: prev_config.tag_period.to_hz().to_string(),
             bidirectional: prev_config.bidir.into(),
-            fill_fraction_value: prev_config.fill_fraction.to_string(),
+            fill_fraction_value: prev_config.fill_fraction.percent().to_string(),
             frame_dead_time_value: ps_to_ms(prev_config.frame_dead_time).to_string(),
             replay_existing: prev_config.replay_existing,
-            line_shift_value: prev_config.line_shift.to_string(),
+            line_shift_value: prev_config.line_shift.for_plane(0).to_string(),
+            loaded_line_shift: Some(prev_config.line_shift.clone()),
             rolling_avg_value: prev_config.rolling_avg.to_string(),
+            color_increment_value: prev_config.increment_color_by.to_string(),
+            channel_enabled: prev_config.channel_enabled,
             ..Default::default()
         };
         let pmt1 = channel_value_to_pair(prev_config.pmt1_ch);
@@ -427,163 +734,12 @@ impl Application for MainAppGui {
     }
 
     fn update(&mut self, message: Message, _clip: &mut Clipboard) -> Command<Self::Message> {
-        match message {
-            Message::FilenameChanged(filename) => {
-                self.filename_value = filename;
-                Command::none()
-            }
-            Message::RowsChanged(rows) => {
-                self.rows_value = rows;
-                Command::none()
-            }
-            Message::ColumnsChanged(columns) => {
-                self.columns_value = columns;
-                Command::none()
-            }
-            Message::PlanesChanged(planes) => {
-                self.planes_value = planes;
-                Command::none()
-            }
-            Message::ScanPeriodChanged(period) => {
-                self.scan_period_value = period;
-                Command::none()
-            }
-            Message::TagLensPeriodChanged(period) => {
-                self.tag_period_value = period;
-                Command::none()
-            }
-            Message::BidirectionalityChanged(bidir) => {
-                self.bidirectional = bidir;
-                Command::none()
-            }
-            Message::FillFractionChanged(fillfrac) => {
-                self.fill_fraction_value = fillfrac;
-                Command::none()
-            }
-            Message::FrameDeadTimeChanged(deadtime) => {
-                self.frame_dead_time_value = deadtime;
-                Command::none()
-            }
-            Message::Pmt1Changed(pmt1) => {
-                self.pmt1_selected = pmt1;
-                Command::none()
-            }
-            Message::Pmt1EdgeChanged(pmt1_edge) => {
-                self.pmt1_edge_selected = pmt1_edge;
-                Command::none()
-            }
-            Message::Pmt1ThresholdChanged(pmt1_thresh) => {
-                self.pmt1_threshold_value = pmt1_thresh;
-                Command::none()
-            }
-            Message::Pmt2Changed(pmt2) => {
-                self.pmt2_selected = pmt2;
-                Command::none()
-            }
-            Message::Pmt2EdgeChanged(pmt2_edge) => {
-                self.pmt2_edge_selected = pmt2_edge;
-                Command::none()
-            }
-            Message::Pmt2ThresholdChanged(pmt2_thresh) => {
-                self.pmt2_threshold_value = pmt2_thresh;
-                Command::none()
-            }
-            Message::Pmt3Changed(pmt3) => {
-                self.pmt3_selected = pmt3;
-                Command::none()
-            }
-            Message::Pmt3EdgeChanged(pmt3_edge) => {
-                self.pmt3_edge_selected = pmt3_edge;
-                Command::none()
-            }
-            Message::Pmt3ThresholdChanged(pmt3_thresh) => {
-                self.pmt3_threshold_value = pmt3_thresh;
-                Command::none()
-            }
-            Message::Pmt4Changed(pmt4) => {
-                self.pmt4_selected = pmt4;
-                Command::none()
-            }
-            Message::Pmt4EdgeChanged(pmt4_edge) => {
-                self.pmt4_edge_selected = pmt4_edge;
-                Command::none()
-            }
-            Message::Pmt4ThresholdChanged(pmt4_thresh) => {
-                self.pmt4_threshold_value = pmt4_thresh;
-                Command::none()
-            }
-            Message::LaserChanged(laser) => {
-                self.laser_selected = laser;
-                Command::none()
-            }
-            Message::LaserEdgeChanged(laser_edge) => {
-                self.laser_edge_selected = laser_edge;
-                Command::none()
-            }
-            Message::LaserThresholdChanged(laser_thresh) => {
-                self.laser_threshold_value = laser_thresh;
-                Command::none()
-            }
-            Message::FrameChanged(frame) => {
-                self.frame_selected = frame;
-                Command::none()
-            }
-            Message::FrameEdgeChanged(frame_edge) => {
-                self.frame_edge_selected = frame_edge;
-                Command::none()
-            }
-            Message::FrameThresholdChanged(frame_thresh) => {
-                self.frame_threshold_value = frame_thresh;
-                Command::none()
-            }
-            Message::LineChanged(line) => {
-                self.line_selected = line;
-                Command::none()
-            }
-            Message::LineEdgeChanged(line_edge) => {
-                self.line_edge_selected = line_edge;
-                Command::none()
-            }
-            Message::LineThresholdChanged(line_thresh) => {
-                self.line_threshold_value = line_thresh;
-                Command::none()
-            }
-            Message::TagLensChanged(taglens) => {
-                self.taglens_selected = taglens;
-                Command::none()
-            }
-            Message::TagLensEdgeChanged(taglens_edge) => {
-                self.taglens_edge_selected = taglens_edge;
-                Command::none()
-            }
-            Message::TagLensThresholdChanged(taglens_thresh) => {
-                self.taglens_threshold_value = taglens_thresh;
-                Command::none()
-            }
-            Message::ReplayExistingChanged(replay_existing) => {
-                self.replay_existing = replay_existing;
-                Command::none()
-            }
-            Message::LineShiftChanged(line_shift) => {
-                self.line_shift_value = line_shift;
-                Command::none()
-            }
-            Message::RollingAvgChanged(rolling_avg) => {
-                self.rolling_avg_value = rolling_avg;
-                Command::none()
-            }
-            Message::ButtonPressed => Command::perform(
-                start_acquisition(
-                    PathBuf::from(DEFAULT_CONFIG_FNAME),
-                    AppConfig::from_user_input(self).expect(""),
-                ),
-                Message::StartedAcquistion,
-            ),
-            Message::StartedAcquistion(()) => Command::none(),
-        }
+        self.handle_message(message)
     }
 
     fn view(&mut self) -> Element<Message> {
+        let scan_timing = Text::new(self.scan_timing_summary());
+
         let filename = TextInput::new(
             &mut self.filename_input,
             "Save to",
@@ -712,6 +868,25 @@ impl Application for MainAppGui {
             .push(rolling_avg_label)
             .push(rolling_avg);
 
+        let color_increment = TextInput::new(
+            &mut self.color_increment_input,
+            "Color Increment [> 1]",
+            &self.color_increment_value,
+            Message::ColorIncrementChanged,
+        )
+        .padding(10)
+        .size(20);
+        let color_increment_label = Text::new("Color Increment");
+        let color_increment_row = Row::new()
+            .spacing(10)
+            .align_items(Align::Center)
+            .push(color_increment_label)
+            .push(color_increment);
+        let color_increment_row = match string_to_color_increment(&self.color_increment_value) {
+            Ok(_) => color_increment_row,
+            Err(e) => color_increment_row.push(Text::new(e.to_string()).color([0.8, 0.0, 0.0])),
+        };
+
         let deadtime = TextInput::new(
             &mut self.frame_dead_time_input,
             "Frame Dead Time [ms]",
@@ -765,13 +940,21 @@ impl Application for MainAppGui {
         .padding(10)
         .size(20);
 
+        let pmt1_enabled = Checkbox::new(
+            self.channel_enabled[0],
+            "Enabled",
+            Message::Pmt1EnabledChanged,
+        )
+        .size(20);
+
         let pmt1_row = Row::new()
             .spacing(10)
             .align_items(Align::Center)
             .push(Text::new("PMT 1"))
             .push(pmt1)
             .push(pmt1_edge)
-            .push(pmt1_thresh);
+            .push(pmt1_thresh)
+            .push(pmt1_enabled);
 
         let pmt2 = PickList::new(
             &mut self.pmt2_pick_list,
@@ -796,13 +979,21 @@ impl Application for MainAppGui {
         .padding(10)
         .size(20);
 
+        let pmt2_enabled = Checkbox::new(
+            self.channel_enabled[1],
+            "Enabled",
+            Message::Pmt2EnabledChanged,
+        )
+        .size(20);
+
         let pmt2_row = Row::new()
             .spacing(10)
             .align_items(Align::Center)
             .push(Text::new("PMT 2"))
             .push(pmt2)
             .push(pmt2_edge)
-            .push(pmt2_thresh);
+            .push(pmt2_thresh)
+            .push(pmt2_enabled);
 
         let pmt3 = PickList::new(
             &mut self.pmt3_pick_list,
@@ -827,13 +1018,21 @@ impl Application for MainAppGui {
         .padding(10)
         .size(20);
 
+        let pmt3_enabled = Checkbox::new(
+            self.channel_enabled[2],
+            "Enabled",
+            Message::Pmt3EnabledChanged,
+        )
+        .size(20);
+
         let pmt3_row = Row::new()
             .spacing(10)
             .align_items(Align::Center)
             .push(Text::new("PMT 3"))
             .push(pmt3)
             .push(pmt3_edge)
-            .push(pmt3_thresh);
+            .push(pmt3_thresh)
+            .push(pmt3_enabled);
 
         let pmt4 = PickList::new(
             &mut self.pmt4_pick_list,
@@ -858,13 +1057,21 @@ impl Application for MainAppGui {
         .padding(10)
         .size(20);
 
+        let pmt4_enabled = Checkbox::new(
+            self.channel_enabled[3],
+            "Enabled",
+            Message::Pmt4EnabledChanged,
+        )
+        .size(20);
+
         let pmt4_row = Row::new()
             .spacing(10)
             .align_items(Align::Center)
             .push(Text::new("PMT 4"))
             .push(pmt4)
             .push(pmt4_edge)
-            .push(pmt4_thresh);
+            .push(pmt4_thresh)
+            .push(pmt4_enabled);
 
         let laser = PickList::new(
             &mut self.laser_pick_list,
@@ -1001,6 +1208,27 @@ impl Application for MainAppGui {
             .on_press(Message::ButtonPressed)
             .padding(10);
 
+        let test_connection = Button::new(
+            &mut self.test_connection_button,
+            Text::new("Test Connection"),
+        )
+        .on_press(Message::TestConnectionPressed)
+        .padding(10);
+
+        let pause_display = Checkbox::new(
+            self.display_paused,
+            "Pause display",
+            Message::DisplayPausedChanged,
+        )
+        .size(20);
+
+        let run_row = Row::new()
+            .spacing(20)
+            .align_items(Align::Center)
+            .push(run_app)
+            .push(test_connection)
+            .push(pause_display);
+
         let first_column = Column::new()
             .spacing(20)
             .padding(20)
@@ -1014,7 +1242,8 @@ impl Application for MainAppGui {
             .push(fillfrac_row)
             .push(deadtime_row)
             .push(line_shift_row)
-            .push(bidir);
+            .push(bidir)
+            .push(scan_timing);
 
         let second_column = Column::new()
             .spacing(20)
@@ -1028,7 +1257,8 @@ impl Application for MainAppGui {
             .push(frame_row)
             .push(line_row)
             .push(taglens_row)
-            .push(rolling_avg_row);
+            .push(rolling_avg_row)
+            .push(color_increment_row);
 
         let content = Column::new()
             .spacing(20)
@@ -1038,7 +1268,15 @@ impl Application for MainAppGui {
             .align_items(Align::Center)
             .push(Image::new("resources/logo.png"))
             .push(Row::new().push(first_column).push(second_column))
-            .push(run_app);
+            .push(run_row);
+        let content = match &self.connection_status {
+            Some(status) => content.push(Text::new(status).color([0.0, 0.6, 0.0])),
+            None => content,
+        };
+        let content = match &self.python_error {
+            Some(error) => content.push(Text::new(error).color([0.8, 0.0, 0.0])),
+            None => content,
+        };
 
         Container::new(content)
             .width(Length::Fill)
@@ -1047,4 +1285,124 @@ impl Application for MainAppGui {
             .center_y()
             .into()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crossbeam::channel::unbounded;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::configuration::{AppConfigBuilder, ConfigValidationError, InputChannel};
+    use crate::UserInputError;
+
+    #[test]
+    fn messages_are_handled_during_the_acquisition() {
+        let mut gui = MainAppGui::default();
+        let (control, commands) = unbounded();
+        let (done, finished) = oneshot::channel();
+        // Stands in for a running acquisition which only ends once told to
+        let acquisition = std::thread::spawn(move || {
+            let command = commands.recv_timeout(Duration::from_secs(5));
+            let _ = done.send(Ok(()));
+            command
+        });
+        let _ = gui.track_acquisition(AcquisitionHandle { control, finished });
+        let _ = gui.handle_message(Message::DisplayPausedChanged(true));
+        assert!(gui.display_paused);
+        assert_eq!(
+            acquisition.join().unwrap(),
+            Ok(AcquisitionControl::PauseDisplay)
+        );
+    }
+
+    #[test]
+    fn crashed_acquisitions_are_reported_to_the_user() {
+        let mut gui = MainAppGui::default();
+        let (done, finished) = oneshot::channel::<Result<(), PythonStartupError>>();
+        let acquisition = std::thread::spawn(move || {
+            let _done = done;
+            panic!("The acquisition crashed");
+        });
+        assert!(acquisition.join().is_err());
+        let outcome = block_on(acquisition_outcome(finished));
+        assert_eq!(outcome, Err(AcquisitionFailure::Crashed));
+        let _ = gui.handle_message(Message::StartedAcquistion(outcome));
+        assert_eq!(
+            gui.python_error,
+            Some(AcquisitionFailure::Crashed.to_string())
+        );
+    }
+
+    #[test]
+    fn scan_timing_follows_the_inputs() {
+        let (mut gui, _) = MainAppGui::new(AppConfigBuilder::default().build());
+        let _ = gui.handle_message(Message::ColumnsChanged("512".to_string()));
+        let summary = gui.scan_timing_summary();
+        let _ = gui.handle_message(Message::ColumnsChanged("256".to_string()));
+        assert_ne!(summary, gui.scan_timing_summary());
+        let _ = gui.handle_message(Message::ColumnsChanged("many".to_string()));
+        assert!(gui.scan_timing_summary().contains("N/A"));
+    }
+
+    #[test]
+    fn per_plane_line_shift_is_kept_until_edited() {
+        let line_shift = LineShift::PerPlane(vec![0, 100, 250]);
+        let (mut gui, _) = MainAppGui::new(
+            AppConfigBuilder::default()
+                .with_line_shifts(line_shift.clone())
+                .build(),
+        );
+        assert_eq!(
+            AppConfig::from_user_input(&gui).unwrap().line_shift,
+            line_shift
+        );
+        let _ = gui.handle_message(Message::LineShiftChanged("50".to_string()));
+        assert_eq!(
+            AppConfig::from_user_input(&gui).unwrap().line_shift,
+            LineShift::Global(50)
+        );
+    }
+
+    #[test]
+    fn color_increment_input_flows_into_the_config() {
+        let (mut gui, _) = MainAppGui::new(AppConfigBuilder::default().build());
+        let _ = gui.handle_message(Message::ColorIncrementChanged("1.5".to_string()));
+        let config = AppConfig::from_user_input(&gui).unwrap();
+        assert_eq!(config.increment_color_by, 1.5);
+    }
+
+    #[test]
+    fn color_increment_not_above_one_is_a_user_error() {
+        let (mut gui, _) = MainAppGui::new(AppConfigBuilder::default().build());
+        let _ = gui.handle_message(Message::ColorIncrementChanged("1.0".to_string()));
+        assert_eq!(
+            AppConfig::from_user_input(&gui),
+            Err(UserInputError::ColorIncrementTooLow(1.0))
+        );
+        let _ = gui.handle_message(Message::ColorIncrementChanged("bright".to_string()));
+        assert!(matches!(
+            AppConfig::from_user_input(&gui),
+            Err(UserInputError::InvalidColorIncrement(_))
+        ));
+    }
+
+    #[test]
+    fn disconnected_pmt_channels_are_a_user_error() {
+        let config = AppConfigBuilder::default()
+            .with_pmt1_ch(InputChannel::new(0, 0.0))
+            .with_pmt2_ch(InputChannel::new(0, 0.0))
+            .with_pmt3_ch(InputChannel::new(0, 0.0))
+            .with_pmt4_ch(InputChannel::new(0, 0.0))
+            .build();
+        let (gui, _) = MainAppGui::new(config);
+        assert_eq!(
+            AppConfig::from_user_input(&gui),
+            Err(UserInputError::InvalidConfiguration(
+                ConfigValidationError::NoPmtChannels
+            ))
+        );
+    }
+}
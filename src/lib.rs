@@ -25,7 +25,7 @@ pub mod serialize_and_render;
 pub mod snakes;
 
 use std::net::TcpStream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{
     fs::{create_dir_all, read_to_string, write, File},
     num::{ParseFloatError, ParseIntError},
@@ -36,15 +36,25 @@ extern crate log;
 #[macro_use]
 extern crate lazy_static;
 use anyhow::Result;
+use chrono::{DateTime, Local};
+use crossbeam::channel::{unbounded, Receiver, Sender};
 use directories::ProjectDirs;
+use futures::channel::oneshot;
 use iced::Settings;
 use nalgebra::Point3;
 use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
 use thiserror::Error;
 
-use crate::configuration::{AppConfig, AppConfigBuilder, InputChannel};
+use crate::configuration::{
+    AppConfig, AppConfigBuilder, ConfigValidationError, DisplayMode, FillFractionError,
+    InputChannel,
+};
 use crate::gui::{ChannelNumber, EdgeDetected};
-use crate::point_cloud_renderer::{AppState, Channels, DisplayChannel};
+use crate::point_cloud_renderer::{
+    window_roles, AcquisitionControl, AppState, Channels, DisplayChannel, LineClock, WindowRole,
+};
+use crate::serialize_and_render::FrameImage;
 
 /// The port we use to transfer data from the Python process controlling the TT
 /// to the renderer.
@@ -57,16 +67,31 @@ pub const DEFAULT_CONFIG_FNAME: &str = "default.toml";
 const TT_RUN_FUNCTION_NAME: &str = "run_tagger";
 /// The function name that runs the TT in replay mode
 const TT_REPLAY_FUNCTION_NAME: &str = "replay_existing";
+/// The function name that streams a synthetic test pattern without a TT
+const TT_TEST_PATTERN_FUNCTION_NAME: &str = "run_test_pattern";
+/// The function name that checks the TT is reachable without acquiring
+const TT_PROBE_FUNCTION_NAME: &str = "probe_tagger";
+/// The function name of the line clock script which is called with each line
+const LINE_CLOCK_FUNCTION_NAME: &str = "line_clock";
 /// Number of color channels rPySight can display, excluding the merged one.
 const SUPPORTED_SPECTRAL_CHANNELS: usize = 4;
+/// Default brightness of a voxel after its first photon.
+const GRAYSCALE_START: f32 = 0.05;
 
 lazy_static! {
     /// GRAY, GREEN, MAGENTA, CYAN
     static ref DISPLAY_COLORS: [Point3<f32>; SUPPORTED_SPECTRAL_CHANNELS] = [
-        Point3::<f32>::new(0.05, 0.05, 0.05),
-        Point3::<f32>::new(0.0, 0.05, 0.0),
-        Point3::<f32>::new(0.05, 0.0, 0.05),
-        Point3::<f32>::new(0.0, 0.05, 0.05)
+        Point3::<f32>::new(GRAYSCALE_START, GRAYSCALE_START, GRAYSCALE_START),
+        Point3::<f32>::new(0.0, GRAYSCALE_START, 0.0),
+        Point3::<f32>::new(GRAYSCALE_START, 0.0, GRAYSCALE_START),
+        Point3::<f32>::new(0.0, GRAYSCALE_START, GRAYSCALE_START)
+    ];
+    /// RED, GREEN, BLUE, WHITE
+    static ref DIRECT_RGB_COLORS: [Point3<f32>; SUPPORTED_SPECTRAL_CHANNELS] = [
+        Point3::<f32>::new(GRAYSCALE_START, 0.0, 0.0),
+        Point3::<f32>::new(0.0, GRAYSCALE_START, 0.0),
+        Point3::<f32>::new(0.0, 0.0, GRAYSCALE_START),
+        Point3::<f32>::new(GRAYSCALE_START, GRAYSCALE_START, GRAYSCALE_START)
     ];
 }
 
@@ -148,19 +173,101 @@ fn create_dir_and_populate_with_default(path: PathBuf) -> Result<AppConfig> {
 pub fn load_timetagger_run_function(
     module_filename: PathBuf,
     replay_existing: bool,
-) -> PyResult<PyObject> {
+    use_test_pattern: bool,
+) -> Result<PyObject, PythonStartupError> {
+    let python_code = read_to_string(&module_filename).map_err(|e| {
+        PythonStartupError::new(format!("Couldn't read {:?}: {}", module_filename, e))
+    })?;
+    load_python_function(
+        &python_code,
+        select_timetagger_function(replay_existing, use_test_pattern),
+    )
+}
+
+/// The name of the Python function which starts the acquisition. The test
+/// pattern takes precedence since it doesn't require a TimeTagger at all.
+fn select_timetagger_function(replay_existing: bool, use_test_pattern: bool) -> &'static str {
+    if use_test_pattern {
+        TT_TEST_PATTERN_FUNCTION_NAME
+    } else if replay_existing {
+        TT_REPLAY_FUNCTION_NAME
+    } else {
+        TT_RUN_FUNCTION_NAME
+    }
+}
+
+/// Loads the given Python code as a module and returns one of its functions.
+///
+/// Exceptions raised while the module is executed, e.g. due to a missing
+/// import, are returned with their traceback. Python caches modules by name,
+/// so any previously loaded module is discarded first, so that functions it
+/// defined don't linger in the new one.
+fn load_python_function(
+    python_code: &str,
+    function_name: &str,
+) -> Result<PyObject, PythonStartupError> {
+    load_python_module_function(python_code, "run_tt", function_name)
+}
+
+/// Loads the given Python code as the module of the given name and returns
+/// one of its functions, like [`load_python_function`]
+fn load_python_module_function(
+    python_code: &str,
+    module_name: &str,
+    function_name: &str,
+) -> Result<PyObject, PythonStartupError> {
     // Generate an owned object to be returned by value
-    return Python::with_gil(|py| -> PyResult<PyObject> {
-        let python_code = read_to_string(module_filename)?;
-        let run_tt = PyModule::from_code_bound(py, &python_code, "run_tt.py", "run_tt")?;
-        let tt_starter;
-        if replay_existing {
-            tt_starter = run_tt.getattr(TT_REPLAY_FUNCTION_NAME)?;
-        } else {
-            tt_starter = run_tt.getattr(TT_RUN_FUNCTION_NAME)?;
-        };
-        Ok(tt_starter.to_object(py))
-    });
+    Python::with_gil(|py| {
+        let file_name = format!("{}.py", module_name);
+        py.import_bound("sys")
+            .and_then(|sys| sys.getattr("modules"))
+            .and_then(|modules| modules.call_method1("pop", (module_name, py.None())))
+            .and_then(|_| PyModule::from_code_bound(py, python_code, &file_name, module_name))
+            .and_then(|module| module.getattr(function_name))
+            .map(|function| function.to_object(py))
+            .map_err(|e| PythonStartupError::from_pyerr(py, e))
+    })
+}
+
+/// Calls the Python function with the configuration string as its argument,
+/// returning the string it returned, if any
+fn call_python_function(
+    function: &PyObject,
+    config: String,
+) -> Result<Option<String>, PythonStartupError> {
+    Python::with_gil(|py| {
+        function
+            .call1(py, (config,))
+            .map(|returned| returned.extract::<String>(py).ok())
+            .map_err(|e| PythonStartupError::from_pyerr(py, e))
+    })
+}
+
+/// Loads the line clock of the given Python script, which calls its
+/// `line_clock` function with the index and time of each line.
+///
+/// An exception raised by the function is logged and the line clock stops, so
+/// that a faulty script can't flood the log with a message per line.
+fn load_line_clock(script: &Path) -> Result<LineClock, PythonStartupError> {
+    let python_code = read_to_string(script)
+        .map_err(|e| PythonStartupError::new(format!("Couldn't read {:?}: {}", script, e)))?;
+    let function =
+        load_python_module_function(&python_code, "line_clock", LINE_CLOCK_FUNCTION_NAME)?;
+    let mut stopped = false;
+    Ok(Box::new(move |line, time| {
+        if stopped {
+            return;
+        }
+        let result = Python::with_gil(|py| {
+            function
+                .call1(py, (line, time))
+                .map_err(|e| PythonStartupError::from_pyerr(py, e))
+        });
+        if let Err(e) = result {
+            error!("The line clock stopped: {}", e.message);
+            stopped = true;
+        }
+    }))
 }
 
 /// Call the TimeTagger library in Python and run the device.
@@ -170,21 +277,146 @@ pub fn load_timetagger_run_function(
 /// TT is done, so this function will be running in parallel to the rest of rPySight
 /// and once its done it can also serve as a signal to the broader app that the TT
 /// has finished its role for now.
-pub fn start_timetagger_with_python(app_config: &AppConfig) -> PyResult<()> {
+///
+/// Before acquiring, the Python method reports the channel configuration the
+/// TimeTagger actually applied, and a warning is logged for each channel which
+/// differs from the requested one, e.g. due to a threshold the TT rejected.
+pub fn start_timetagger_with_python(app_config: &AppConfig) -> Result<(), PythonStartupError> {
     debug!("Starting timetagger");
     let module_filename = PathBuf::from(CALL_TIMETAGGER_SCRIPT_NAME);
-    let tt_module = load_timetagger_run_function(module_filename, app_config.replay_existing)?;
+    let tt_module = load_timetagger_run_function(
+        module_filename,
+        app_config.replay_existing,
+        app_config.use_test_pattern,
+    )?;
     debug!("Calling Python to start the TT business");
+    let config = toml::to_string(app_config).map_err(|e| {
+        PythonStartupError::new(format!("Unable to convert configuration to string: {}", e))
+    })?;
+    let function_name =
+        select_timetagger_function(app_config.replay_existing, app_config.use_test_pattern);
+    if function_name == TT_RUN_FUNCTION_NAME {
+        let requested = app_config.clone();
+        call_python_function_reporting_channels(&tt_module, config, move |applied| {
+            warn_on_misapplied_channels(&requested, applied)
+        })
+    } else {
+        call_python_function(&tt_module, config).map(|_| ())
+    }
+}
 
-    Python::with_gil(|py| -> PyResult<PyObject> {
-        return tt_module.call1(
+/// Calls the Python function with the configuration string and a callback,
+/// through which the function reports the channel configuration applied by
+/// the TimeTagger while the acquisition is still running
+fn call_python_function_reporting_channels<F>(
+    function: &PyObject,
+    config: String,
+    report: F,
+) -> Result<(), PythonStartupError>
+where
+    F: Fn(&str) + Send + 'static,
+{
+    Python::with_gil(|py| {
+        let callback = PyCFunction::new_closure_bound(
             py,
-            (toml::to_string(app_config).expect("Unable to convert configuration to string"),),
-        );
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _kwargs: Option<&Bound<'_, PyDict>>| -> PyResult<()> {
+                let applied: String = args.get_item(0)?.extract()?;
+                report(&applied);
+                Ok(())
+            },
+        )
+        .map_err(|e| PythonStartupError::from_pyerr(py, e))?;
+        function
+            .call1(py, (config, callback))
+            .map(|_| ())
+            .map_err(|e| PythonStartupError::from_pyerr(py, e))
     })
-    .expect("Starting the TimeTagger failed, aborting!");
+}
 
-    Ok(())
+/// Checks that Python and the TimeTagger are reachable before an acquisition.
+///
+/// The TimeTagger script is loaded and its probe function is called with the
+/// given configuration, which connects to the TT without starting an
+/// acquisition. On success, the description of the TT returned by the probe
+/// is returned.
+pub fn check_timetagger_connection(app_config: &AppConfig) -> Result<String, PythonStartupError> {
+    let module_filename = PathBuf::from(CALL_TIMETAGGER_SCRIPT_NAME);
+    let python_code = read_to_string(&module_filename).map_err(|e| {
+        PythonStartupError::new(format!("Couldn't read {:?}: {}", module_filename, e))
+    })?;
+    let config = toml::to_string(app_config).map_err(|e| {
+        PythonStartupError::new(format!("Unable to convert configuration to string: {}", e))
+    })?;
+    probe_timetagger(&python_code, config)
+}
+
+/// Calls the probe function of the given Python code, falling back to a
+/// generic description when it doesn't return one
+fn probe_timetagger(python_code: &str, config: String) -> Result<String, PythonStartupError> {
+    let probe = load_python_function(python_code, TT_PROBE_FUNCTION_NAME)?;
+    Ok(call_python_function(&probe, config)?
+        .unwrap_or_else(|| "The TimeTagger is reachable".to_string()))
+}
+
+/// Logs a warning for each channel which the TimeTagger configured differently
+/// from the request
+fn warn_on_misapplied_channels(app_config: &AppConfig, applied: &str) {
+    match app_config.reconcile_applied_channels(applied) {
+        Ok(mismatches) => {
+            for mismatch in mismatches {
+                warn!(
+                    "The TimeTagger applied {:?} instead of the requested {:?} to {}",
+                    mismatch.applied, mismatch.requested, mismatch.name
+                );
+            }
+        }
+        Err(e) => warn!(
+            "Couldn't parse the channels applied by the TimeTagger: {}",
+            e
+        ),
+    }
+}
+
+/// An error raised while starting the TimeTagger through Python.
+///
+/// It carries the Python exception message and, when available, its
+/// formatted traceback, so that they can be shown to the user.
+#[derive(Debug, Error, Clone, PartialEq)]
+#[error("Starting the TimeTagger failed: {message}")]
+pub struct PythonStartupError {
+    pub message: String,
+    pub traceback: Option<String>,
+}
+
+impl PythonStartupError {
+    fn new(message: String) -> Self {
+        PythonStartupError {
+            message,
+            traceback: None,
+        }
+    }
+
+    fn from_pyerr(py: Python, err: PyErr) -> Self {
+        let traceback = err
+            .traceback_bound(py)
+            .and_then(|traceback| traceback.format().ok());
+        PythonStartupError {
+            message: err.to_string(),
+            traceback,
+        }
+    }
+}
+
+/// The reasons an acquisition started by [`spawn_acquisition`] ended without
+/// finishing successfully
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum AcquisitionFailure {
+    #[error(transparent)]
+    PythonStartup(#[from] PythonStartupError),
+    #[error("The acquisition crashed before it finished, see the log for its error")]
+    Crashed,
 }
 
 /// A custom error returned when the user supplies incorrect values.
@@ -202,6 +434,18 @@ pub enum UserInputError {
     InvalidScanPeriod(ParseFloatError),
     #[error("Wrong frame dead time value (got `{0}`)")]
     InvalidFrameDeadTime(ParseFloatError),
+    #[error("Wrong fill fraction value ({0})")]
+    InvalidFillFraction(FillFractionError),
+    #[error("Wrong line shift value (got `{0}`)")]
+    InvalidLineShift(ParseIntError),
+    #[error("Wrong color increment value (got `{0}`)")]
+    InvalidColorIncrement(ParseFloatError),
+    #[error("The color increment has to be above 1 (got {0})")]
+    ColorIncrementTooLow(f32),
+    #[error("The threshold of a channel detecting the {edge:?} edge can't be {threshold}, as its sign has to match the edge")]
+    InconsistentThreshold { edge: EdgeDetected, threshold: f32 },
+    #[error("Invalid configuration ({0})")]
+    InvalidConfiguration(ConfigValidationError),
     #[error("Unknown user input error")]
     Unknown,
 }
@@ -255,7 +499,7 @@ fn channel_value_to_pair(ch: InputChannel) -> (ChannelNumber, EdgeDetected, f32)
     (chnum, edge, ch.threshold)
 }
 
-fn generate_windows(width: u32, height: u32, fr: u64) -> Channels<DisplayChannel> {
+fn generate_windows(config: &AppConfig, fr: u64) -> Channels<DisplayChannel> {
     let channel_names = [
         "Channel 1",
         "Channel 2",
@@ -263,32 +507,161 @@ fn generate_windows(width: u32, height: u32, fr: u64) -> Channels<DisplayChannel
         "Channel 4",
         "Channel Merge",
     ];
+    let roles = window_roles(config.control_window);
     let mut channels = Vec::new();
-    for name in channel_names.iter() {
-        channels.push(DisplayChannel::new(*name, width, height, fr));
+    for (name, role) in channel_names.iter().zip(roles) {
+        let title = match role {
+            WindowRole::Control => format!("{} (controls)", name),
+            WindowRole::Display => name.to_string(),
+        };
+        let channel = DisplayChannel::new(
+            &title,
+            config.rows,
+            config.columns,
+            fr,
+            config.background_level,
+            config.draw_scan_path,
+        );
+        channels.push(match config.display_mode() {
+            DisplayMode::PointCloud => channel,
+            DisplayMode::Image2D => channel.with_image_display(FrameImage::from_config(config)),
+        });
     }
-    Channels::new(channels)
+    Channels::new(channels).with_control_window(config.control_window)
 }
 
 /// Initializes things on the Python side and starts the acquisition.
 ///
-/// This method is called once the user clicks the "Run Application" button or
-/// from the CLI.
-pub async fn start_acquisition(config_name: PathBuf, cfg: AppConfig) {
+/// This method is called from the CLI and blocks until the acquisition ends.
+/// The GUI uses [`spawn_acquisition`] instead.
+///
+/// If the Python side failed to start the TimeTagger, its error is returned
+/// once the acquisition stops.
+pub async fn start_acquisition(
+    config_name: PathBuf,
+    cfg: AppConfig,
+) -> Result<(), PythonStartupError> {
+    run_acquisition(config_name, cfg, None)
+}
+
+/// A running acquisition started by [`spawn_acquisition`].
+pub struct AcquisitionHandle {
+    pub(crate) control: Sender<AcquisitionControl>,
+    pub(crate) finished: oneshot::Receiver<Result<(), PythonStartupError>>,
+}
+
+/// Starts an acquisition on a dedicated thread and returns immediately.
+///
+/// Unlike [`start_acquisition`], the calling thread - usually the one running
+/// the GUI - is free to keep handling user input, and steers the acquisition
+/// by sending commands through the returned handle.
+pub fn spawn_acquisition(config_name: PathBuf, cfg: AppConfig) -> AcquisitionHandle {
+    let (control, commands) = unbounded();
+    let (done, finished) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = done.send(run_acquisition(config_name, cfg, Some(commands)));
+    });
+    AcquisitionHandle { control, finished }
+}
+
+/// Runs the acquisition on the current thread until its window is closed
+fn run_acquisition(
+    config_name: PathBuf,
+    cfg: AppConfig,
+    commands: Option<Receiver<AcquisitionControl>>,
+) -> Result<(), PythonStartupError> {
     let _ = save_cfg(Some(config_name), &cfg).ok(); // errors are logged and quite irrelevant
+    let _ = save_acquisition_cfg(&cfg, Local::now()).ok();
+    for warning in cfg.validation_warnings() {
+        warn!("{}", warning);
+    }
     let fr = (&cfg).frame_rate().round() as u64;
-    let channels = generate_windows(cfg.rows, cfg.columns, fr);
+    let channels = generate_windows(&cfg, fr);
     let mut app = AppState::<DisplayChannel, TcpStream>::new(
         channels,
         TT_DATA_STREAM.to_string(),
         cfg.clone(),
     );
+    if let Some(commands) = commands {
+        app = app.with_control(commands);
+    }
+    if let Some(script) = &cfg.line_clock_script {
+        app = app.with_line_clock(load_line_clock(Path::new(script))?);
+    }
     debug!("Renderer set up correctly");
+    pin_render_thread(cfg.render_core);
     let cloned_cfg = cfg.clone();
-    std::thread::spawn(move || {
-        start_timetagger_with_python(&cloned_cfg).expect("Failed to start TimeTagger, aborting")
-    });
-    app.start_inf_acq_loop(cfg).expect("Some error during acq");
+    let python_handle = std::thread::spawn(move || start_timetagger_with_python(&cloned_cfg));
+    if let Err(e) = app.start_inf_acq_loop(cfg) {
+        error!("The acquisition stopped due to an error: {:?}", e);
+    }
+    // The TimeTagger keeps running until the end of the acquisition, so a
+    // finished Python thread at this point most likely means it failed
+    if python_handle.is_finished() {
+        if let Ok(Err(e)) = python_handle.join() {
+            error!("{}\n{}", e, e.traceback.as_deref().unwrap_or_default());
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Validates the requested core index against the number of available cores.
+///
+/// Unavailable cores are logged and ignored, leaving the thread unpinned.
+fn select_render_core(requested: Option<usize>, available: usize) -> Option<usize> {
+    match requested {
+        Some(core) if core < available => Some(core),
+        Some(core) => {
+            warn!(
+                "Core {} was requested for rendering but only {} cores are available, not pinning",
+                core, available
+            );
+            None
+        }
+        None => None,
+    }
+}
+
+/// Pins the current thread, which runs the acquisition and rendering loop,
+/// to the requested core.
+///
+/// The renderer owns the windows, so it stays on the thread that started the
+/// acquisition, and that thread is the one being pinned.
+fn pin_render_thread(requested: Option<usize>) {
+    if requested.is_none() {
+        return;
+    }
+    let cores = available_cores();
+    if let Some(core) = select_render_core(requested, cores.len()) {
+        if pin_current_thread(cores[core]) {
+            info!("Rendering thread pinned to core {}", core);
+        } else {
+            warn!("Couldn't pin the rendering thread to core {}", core);
+        }
+    }
+}
+
+#[cfg(feature = "core_affinity")]
+fn available_cores() -> Vec<core_affinity::CoreId> {
+    core_affinity::get_core_ids().unwrap_or_default()
+}
+
+#[cfg(feature = "core_affinity")]
+fn pin_current_thread(core: core_affinity::CoreId) -> bool {
+    core_affinity::set_for_current(core)
+}
+
+/// Without the `core_affinity` feature no cores are available for pinning
+#[cfg(not(feature = "core_affinity"))]
+fn available_cores() -> Vec<usize> {
+    warn!("rPySight was built without the core_affinity feature, so threads can't be pinned");
+    Vec::new()
+}
+
+#[cfg(not(feature = "core_affinity"))]
+fn pin_current_thread(_core: usize) -> bool {
+    false
 }
 
 /// Saves the current configuration to disk.
@@ -323,6 +696,43 @@ fn save_cfg(config_name: Option<PathBuf>, app_config: &AppConfig) -> anyhow::Res
     Ok(())
 }
 
+/// The path of the config copy that accompanies the given output filename,
+/// which is placed next to it and named after it and the acquisition's start
+fn acquisition_cfg_path(filename: &str, started: DateTime<Local>) -> PathBuf {
+    let output = Path::new(filename);
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    output.with_file_name(format!(
+        "{}_{}.toml",
+        stem,
+        started.format("%Y-%m-%d_%H-%M-%S")
+    ))
+}
+
+/// Writes a timestamped copy of the configuration next to the output file.
+///
+/// Unlike the default configuration, which is overwritten by every
+/// acquisition, this copy records the exact settings that produced the
+/// dataset. Like [`save_cfg`], its errors are logged and otherwise ignored.
+fn save_acquisition_cfg(app_config: &AppConfig, started: DateTime<Local>) -> Result<PathBuf> {
+    let config_path = acquisition_cfg_path(&app_config.filename, started);
+    let serialized_cfg = toml::to_string(app_config).map_err(|e| {
+        warn!("Couldn't serialize the acquisition's config: {}", e);
+        e
+    })?;
+    write(&config_path, serialized_cfg).map_err(|e| {
+        warn!(
+            "Couldn't write the acquisition's config to {:?}: {}",
+            config_path, e
+        );
+        e
+    })?;
+    debug!("Acquisition config saved to {:?}", config_path);
+    Ok(config_path)
+}
+
 /// Setup the logger. We're not using color here because terminals are either
 /// slow in rendering them, or their simply not supported.
 pub fn setup_logger(fname: Option<PathBuf>) {
@@ -348,3 +758,164 @@ pub fn setup_logger(fname: Option<PathBuf>) {
         .apply()
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::ChannelMismatch;
+
+    #[test]
+    fn python_exception_is_returned_with_traceback() {
+        let code = "def run_tagger(cfg):\n    raise RuntimeError('TimeTagger not found')\n";
+        let function = load_python_function(code, TT_RUN_FUNCTION_NAME).unwrap();
+        let error = call_python_function(&function, String::new()).unwrap_err();
+        assert!(error.message.contains("TimeTagger not found"));
+        assert!(error.traceback.is_some());
+    }
+
+    #[test]
+    fn misapplied_channels_are_reported_before_the_acquisition_ends() {
+        // The acquisition fails after reporting the applied channels, which
+        // are still received
+        let code = "def run_tagger(cfg, report_applied):\n    report_applied('''
+pmt1_ch = { channel = -1, threshold = -0.5 }
+frame_ch = { channel = 3, threshold = 0.5 }
+''')\n    raise RuntimeError('Acquisition interrupted')\n";
+        let function = load_python_function(code, TT_RUN_FUNCTION_NAME).unwrap();
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = reports.clone();
+        let error = call_python_function_reporting_channels(&function, String::new(), move |a| {
+            received.lock().unwrap().push(a.to_string())
+        })
+        .unwrap_err();
+        assert!(error.message.contains("Acquisition interrupted"));
+        let applied = reports.lock().unwrap().pop().unwrap();
+        let cfg = AppConfigBuilder::default()
+            .with_pmt1_ch(InputChannel::new(-1, -0.3))
+            .with_frame_ch(InputChannel::new(3, 0.5))
+            .with_line_ch(InputChannel::new(2, 0.1))
+            .with_taglens_ch(InputChannel::new(0, 0.0))
+            .build();
+        let mismatches = cfg.reconcile_applied_channels(&applied).unwrap();
+        assert_eq!(
+            mismatches,
+            vec![
+                ChannelMismatch {
+                    name: "pmt1_ch",
+                    requested: InputChannel::new(-1, -0.3),
+                    applied: Some(InputChannel::new(-1, -0.5)),
+                },
+                ChannelMismatch {
+                    name: "line_ch",
+                    requested: InputChannel::new(2, 0.1),
+                    applied: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_python_import_is_returned_as_error() {
+        let code = "import a_module_that_does_not_exist\n";
+        let error = load_python_function(code, TT_RUN_FUNCTION_NAME).unwrap_err();
+        assert!(error.message.contains("a_module_that_does_not_exist"));
+    }
+
+    #[test]
+    fn acquisition_config_is_saved_next_to_the_output() {
+        let mut output = std::env::temp_dir();
+        output.push("test_acquisition_cfg.ttbin");
+        let cfg = AppConfigBuilder::default()
+            .with_filename(output.to_string_lossy().into_owned())
+            .build();
+        let config_path = save_acquisition_cfg(&cfg, Local::now()).unwrap();
+        assert_eq!(config_path.parent(), output.parent());
+        let file_name = config_path.file_name().unwrap().to_string_lossy();
+        assert!(file_name.starts_with("test_acquisition_cfg_"));
+        assert!(file_name.ends_with(".toml"));
+        let saved = AppConfig::try_from_config_path(&config_path).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        assert_eq!(saved, cfg);
+    }
+
+    #[test]
+    fn available_render_core_is_selected() {
+        assert_eq!(select_render_core(Some(2), 4), Some(2));
+        assert_eq!(select_render_core(Some(0), 1), Some(0));
+    }
+
+    #[test]
+    fn unavailable_render_core_falls_back_to_no_pinning() {
+        assert_eq!(select_render_core(Some(4), 4), None);
+        assert_eq!(select_render_core(Some(0), 0), None);
+        assert_eq!(select_render_core(None, 4), None);
+    }
+
+    #[test]
+    fn test_pattern_function_is_selected_when_requested() {
+        assert_eq!(
+            select_timetagger_function(false, true),
+            TT_TEST_PATTERN_FUNCTION_NAME
+        );
+        assert_eq!(
+            select_timetagger_function(true, true),
+            TT_TEST_PATTERN_FUNCTION_NAME
+        );
+        assert_eq!(
+            select_timetagger_function(true, false),
+            TT_REPLAY_FUNCTION_NAME
+        );
+        assert_eq!(
+            select_timetagger_function(false, false),
+            TT_RUN_FUNCTION_NAME
+        );
+    }
+
+    #[test]
+    fn successful_probe_reports_the_connected_timetagger() {
+        let code = "def probe_tagger(cfg):\n    return 'Connected to TimeTagger 1234'\n";
+        let description = probe_timetagger(code, String::new()).unwrap();
+        assert_eq!(description, "Connected to TimeTagger 1234");
+    }
+
+    #[test]
+    fn failed_probe_is_returned_as_error() {
+        let code = "def probe_tagger(cfg):\n    raise RuntimeError('No TimeTagger detected')\n";
+        let error = probe_timetagger(code, String::new()).unwrap_err();
+        assert!(error.message.contains("No TimeTagger detected"));
+    }
+
+    #[test]
+    fn missing_python_function_is_returned_as_error() {
+        let code = "def replay_existing(cfg):\n    pass\n";
+        assert!(load_python_function(code, TT_RUN_FUNCTION_NAME).is_err());
+    }
+
+    #[test]
+    fn line_clock_script_is_called_with_each_line_until_it_fails() {
+        let mut script = std::env::temp_dir();
+        script.push("line_clock_script_is_called_with_each_line.py");
+        let code = "ticks = []\ndef line_clock(line, time):\n    if line > 1:\n        raise RuntimeError('GPIO unavailable')\n    ticks.append((line, time))\n";
+        write(&script, code).unwrap();
+        let mut line_clock = load_line_clock(&script).unwrap();
+        std::fs::remove_file(&script).unwrap();
+        for (line, time) in [(0, 1_000), (1, 2_000), (2, 3_000), (1, 4_000)] {
+            line_clock(line, time);
+        }
+        let ticks: Vec<(u32, i64)> = Python::with_gil(|py| {
+            py.import_bound("line_clock")
+                .and_then(|module| module.getattr("ticks"))
+                .and_then(|ticks| ticks.extract())
+                .unwrap()
+        });
+        assert_eq!(ticks, vec![(0, 1_000), (1, 2_000)]);
+    }
+
+    #[test]
+    fn missing_line_clock_script_is_returned_as_error() {
+        let error = load_line_clock(Path::new("no_such_line_clock.py"))
+            .err()
+            .unwrap();
+        assert!(error.message.contains("no_such_line_clock.py"));
+    }
+}
@@ -2,37 +2,454 @@
 
 extern crate kiss3d;
 
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::Read;
 use std::net::TcpStream;
 use std::ops::{Index, IndexMut};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use arrow2::{
+    error::ArrowError,
     io::ipc::read::{read_stream_metadata, StreamReader, StreamState},
     record_batch::RecordBatch,
 };
-use crossbeam::channel::unbounded;
+use crossbeam::channel::{bounded, unbounded, Receiver, Sender, TrySendError};
 use hashbrown::HashMap;
-use kiss3d::window::Window;
-use nalgebra::Point3;
+use kiss3d::context::{Context as GlContext, Texture};
+use kiss3d::scene::PlanarSceneNode;
+use kiss3d::window::{CanvasSetup, NumSamples, Window};
+use nalgebra::{Matrix3, Point2, Point3, Vector3};
 use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::configuration::{AppConfig, DataType, Inputs};
-use crate::event_stream::{Event, EventStream};
-use crate::serialize_and_render::{serialize_data, FrameBuffers};
-use crate::snakes::{Coordinate, Picosecond, Snake, ThreeDimensionalSnake, TwoDimensionalSnake};
+use crate::configuration::{
+    AppConfig, Coincidence, DataType, Inputs, IntegrationWindow, LineMatching, OutOfOrderBatches,
+    Period,
+};
+use crate::event_stream::{validate_schema, Event, EventStream, EventStreamError};
+use crate::serialize_and_render::{
+    frame_table_path, raw_events_path, serialize_data, thumbnails_path, FrameBuffers, FrameImage,
+    FrameTable, MipPreview, RawEventRecorder, ThumbnailStream,
+};
+use crate::snakes::{
+    is_masked, Coordinate, Picosecond, Snake, ThreeDimensionalSnake, TwoDimensionalSnake,
+};
 use crate::SUPPORTED_SPECTRAL_CHANNELS;
 
+/// Half of the length of the arms of the centroid marker, relative to the
+/// size of the rendered volume
+const CENTROID_MARKER_SIZE: f32 = 0.02;
+
+/// Gray level of the line tracing the scan path, which is kept faint so that
+/// it doesn't hide the photons
+const SCAN_PATH_LEVEL: f32 = 0.25;
+
+/// Number of consecutive failed attempts to populate a frame after which the
+/// acquisition is aborted
+const MAX_CONSECUTIVE_FAILURES: u32 = 10;
+
+/// Errors that may occur while reading and processing the event stream.
+#[derive(Debug, Error)]
+pub enum AcquisitionError {
+    #[error("The data stream was read before it was acquired")]
+    StreamNotAcquired,
+    #[error("Couldn't extract batch number {batch_number} from the stream (`{source}`)")]
+    BatchRead {
+        batch_number: u64,
+        source: ArrowError,
+    },
+    #[error("Batch number {batch_number} doesn't match the expected schema (`{source}`)")]
+    InvalidBatch {
+        batch_number: u64,
+        source: EventStreamError,
+    },
+    #[error("The serialization thread panicked")]
+    SerializationPanicked,
+    #[error("The schema of the stream doesn't match the expected one (`{0}`)")]
+    InvalidSchema(#[source] EventStreamError),
+}
+
+impl AcquisitionError {
+    /// Whether the acquisition may carry on after encountering this error
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, AcquisitionError::BatchRead { .. })
+    }
+}
+
+/// Commands which steer a running acquisition from another thread, usually
+/// the GUI's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquisitionControl {
+    /// Keep acquiring and serializing the data, but stop updating the display
+    PauseDisplay,
+    /// Display the incoming frames again
+    ResumeDisplay,
+    /// Render and serialize the photons of the given PMT channel (0-based),
+    /// or count them without doing so
+    SetChannelEnabled { channel: usize, enabled: bool },
+}
+
+/// Decide whether the acquisition loop should retry after the given error or
+/// abort.
+///
+/// Recoverable errors are retried until too many of them occur in a row.
+fn retry_or_abort(
+    error: AcquisitionError,
+    failed_attempts: &mut u32,
+) -> Result<(), AcquisitionError> {
+    *failed_attempts += 1;
+    if error.is_recoverable() && *failed_attempts < MAX_CONSECUTIVE_FAILURES {
+        warn!(
+            "Retrying after a failed attempt ({}): {}",
+            failed_attempts, error
+        );
+        Ok(())
+    } else {
+        error!("Aborting the acquisition: {}", error);
+        Err(error)
+    }
+}
+
 /// A coordinate in image space, i.e. a float in the range [0, 1].
 /// Used for the rendering part of the code, since that's the type the renderer
 /// requires.
 pub type ImageCoor = Point3<Coordinate>;
 
+/// Applies the affine transform to the coordinate. The first three rows of
+/// the transform multiply the coordinate and its last row is added to it.
+///
+/// Coordinates with NaNs, which mark cells outside of the image, are returned
+/// unchanged so that they're still recognized as such. Coordinates moved
+/// outside of the rendered volume are returned as None.
+pub fn apply_affine(affine: &[[f32; 3]; 4], coord: &ImageCoor) -> Option<ImageCoor> {
+    if coord.iter().any(|c| c.is_nan()) {
+        return Some(*coord);
+    }
+    let linear = Matrix3::from_fn(|row, column| affine[row][column]);
+    let transformed =
+        linear * Vector3::new(*coord.x, *coord.y, *coord.z) + Vector3::from(affine[3]);
+    transformed
+        .iter()
+        .all(|c| (-0.5..=0.5).contains(c))
+        .then(|| {
+            ImageCoor::new(
+                OrderedFloat(transformed.x),
+                OrderedFloat(transformed.y),
+                OrderedFloat(transformed.z),
+            )
+        })
+}
+
+/// The number of photons of a single frame that were rendered, and of those
+/// that were dropped broken down by the reason for dropping them.
+///
+/// A high fraction of dropped photons usually means that the timing
+/// parameters or the input thresholds are off.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderDiagnostics {
+    /// Photons which were placed in a voxel
+    pub rendered: u64,
+    /// Photons arriving while the mirrors were turning around
+    pub flyback: u64,
+    /// Photons arriving at voxels outside of the field mask
+    pub masked: u64,
+    /// Photons arriving after the end of the frame, before the next one
+    /// started
+    pub out_of_frame: u64,
+    /// Photons arriving while the frame gate was closed
+    pub gated: u64,
+    /// Photons that arrived too long before the frame started
+    pub stale: u64,
+    /// Photons of the coincidence-filtered channel without a reference event
+    /// within the coincidence window
+    pub uncorrelated: u64,
+    /// Photons of channels which were disabled during the acquisition
+    pub disabled: u64,
+    /// Photons discarded since the sync with the scanner was lost
+    pub unsynced: u64,
+    /// Photons which the affine transform moved outside of the volume
+    pub outside: u64,
+}
+
+impl RenderDiagnostics {
+    /// The number of photons that were dropped for any reason
+    pub fn dropped(&self) -> u64 {
+        self.flyback
+            + self.masked
+            + self.out_of_frame
+            + self.gated
+            + self.stale
+            + self.uncorrelated
+            + self.disabled
+            + self.unsynced
+            + self.outside
+    }
+
+    /// The fraction of the frame's photons which were rendered, or None if
+    /// no photons arrived
+    pub fn rendered_fraction(&self) -> Option<f64> {
+        match self.rendered + self.dropped() {
+            0 => None,
+            total => Some(self.rendered as f64 / total as f64),
+        }
+    }
+}
+
+/// Whether the line events arrive as expected while the photons keep flowing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncStatus {
+    Synced,
+    /// No line event arrived for too long after the last one, e.g. since the
+    /// scanner stalled
+    SyncLost {
+        last_line: Picosecond,
+    },
+}
+
+/// Detects a loss of sync with the scanner, which happens when photons keep
+/// arriving long after the last line event.
+///
+/// This differs from a stalled stream, since the data keeps flowing and only
+/// the line signal is missing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyncMonitor {
+    timeout: Picosecond,
+    last_line: Option<Picosecond>,
+    lost: bool,
+}
+
+impl SyncMonitor {
+    /// Consider the sync lost once no line event arrived for `timeout`
+    pub fn new(timeout: Picosecond) -> Self {
+        Self {
+            timeout,
+            last_line: None,
+            lost: false,
+        }
+    }
+
+    /// Record the arrival of a line event, which restores a lost sync
+    pub fn line_arrived(&mut self, time: Picosecond) {
+        if self.lost {
+            info!("The line signal is back at {}", time);
+        }
+        self.last_line = Some(time);
+        self.lost = false;
+    }
+
+    /// Check the sync when a photon arrives, returning whether it's still
+    /// synced. The sync can't be lost before the first line event.
+    pub fn photon_arrived(&mut self, time: Picosecond) -> bool {
+        if let Some(last_line) = self.last_line {
+            if !self.lost && time - last_line > self.timeout {
+                warn!(
+                    "Sync with the scanner lost, no line event since {} while photons arrive at {}",
+                    last_line, time
+                );
+                self.lost = true;
+            }
+        }
+        !self.lost
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        match (self.lost, self.last_line) {
+            (true, Some(last_line)) => SyncStatus::SyncLost { last_line },
+            _ => SyncStatus::Synced,
+        }
+    }
+}
+
+/// The timing reference of the photons relative to the laser pulses, used to
+/// compute their lifetimes.
+///
+/// Usually each laser pulse arrives as an event and is the reference of the
+/// photons that follow it. In stroboscopic setups the laser channel carries a
+/// single marker per cycle instead, so the reference is the pulse of the train
+/// starting at the marker, spaced by the laser period, that preceded the
+/// photon.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LaserPhase {
+    laser_period: Picosecond,
+    strobe: bool,
+    reference: Option<Picosecond>,
+}
+
+impl LaserPhase {
+    pub fn new(laser_period: Period, strobe: bool) -> Self {
+        Self {
+            laser_period: (*laser_period).max(1),
+            strobe,
+            reference: None,
+        }
+    }
+
+    /// Record the arrival of a laser event, which resets the phase
+    pub fn laser_arrived(&mut self, time: Picosecond) {
+        self.reference = Some(time);
+    }
+
+    /// The time of the photon relative to the laser pulse preceding it
+    pub fn relative_time(&self, time: Picosecond) -> Picosecond {
+        let since_reference = time - self.reference.unwrap_or(0);
+        if self.strobe {
+            since_reference.rem_euclid(self.laser_period)
+        } else {
+            since_reference
+        }
+    }
+
+    /// The number of laser pulses since the last marker of a stroboscopic
+    /// cycle, or None when the laser channel isn't a marker or no marker
+    /// arrived yet
+    pub fn pulse_index(&self, time: Picosecond) -> Option<u64> {
+        match self.reference {
+            Some(reference) if self.strobe => {
+                Some((time - reference).div_euclid(self.laser_period) as u64)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Passes the photons of the filtered channel only if a reference event
+/// arrived up to a window before or after them.
+///
+/// The references of the last two windows are remembered, so photons which
+/// arrive up to a window late still coincide with them, while photons
+/// without a reference are held back until either a reference releases them
+/// or the window elapses.
+#[derive(Clone, Debug)]
+pub struct CoincidenceFilter {
+    coincidence: Coincidence,
+    references: VecDeque<Picosecond>,
+    pending: VecDeque<Event>,
+}
+
+impl CoincidenceFilter {
+    pub fn new(coincidence: Coincidence) -> Self {
+        Self {
+            coincidence,
+            references: VecDeque::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Whether an event of the given type should be processed now. Photons
+    /// of the filtered channel without a coincident reference are held back
+    /// instead.
+    pub fn admits(&mut self, data_type: DataType, event: &Event) -> bool {
+        let window = self.coincidence.window;
+        let horizon = event.time - 2 * window;
+        while matches!(self.references.front(), Some(&reference) if reference < horizon) {
+            self.references.pop_front();
+        }
+        if data_type == self.coincidence.reference {
+            self.references.push_back(event.time);
+            true
+        } else if data_type == self.coincidence.channel {
+            let coincident = self
+                .references
+                .iter()
+                .any(|reference| (event.time - reference).abs() <= window);
+            if !coincident {
+                self.pending.push_back(*event);
+            }
+            coincident
+        } else {
+            true
+        }
+    }
+
+    /// Drops the held back photons which arrived more than a window before
+    /// the given time, so no later reference can release them, and returns
+    /// their number
+    pub fn expire(&mut self, time: Picosecond) -> u64 {
+        let window = self.coincidence.window;
+        let before = self.pending.len();
+        while matches!(self.pending.front(), Some(photon) if time - photon.time > window) {
+            self.pending.pop_front();
+        }
+        (before - self.pending.len()) as u64
+    }
+
+    /// The held back photons which an event of the given type arriving at
+    /// the given time releases, in their order of arrival
+    pub fn release(&mut self, data_type: DataType, time: Picosecond) -> Vec<Event> {
+        if data_type != self.coincidence.reference {
+            return Vec::new();
+        }
+        let window = self.coincidence.window;
+        let mut released = Vec::new();
+        self.pending.retain(|photon| {
+            let coincident = (time - photon.time).abs() <= window;
+            if coincident {
+                released.push(*photon);
+            }
+            !coincident
+        });
+        released
+    }
+}
+
+/// The arrival time and input channel of a processed photon
+pub type LivePhoton = (Picosecond, i32);
+
+/// Called with the index of each line within its frame, starting from 0, and
+/// with the time of its line signal, e.g. to drive a line clock output which
+/// other hardware syncs to
+pub type LineClock = Box<dyn FnMut(u32, Picosecond)>;
+
+/// Sends a decimated subset of the processed photons to an external consumer.
+///
+/// The channel is bounded so that a slow consumer can't stall the
+/// acquisition - photons arriving while it's full are dropped from the feed.
+#[derive(Clone, Debug)]
+pub struct LiveFeed {
+    sender: Sender<LivePhoton>,
+    decimation: u32,
+    photons_until_sent: u32,
+}
+
+impl LiveFeed {
+    pub fn new(sender: Sender<LivePhoton>, decimation: u32) -> Self {
+        Self {
+            sender,
+            decimation: decimation.max(1),
+            photons_until_sent: 0,
+        }
+    }
+
+    /// Send the photon if it's the next one in the decimated subset. Returns
+    /// false once the consumer is gone.
+    pub fn push(&mut self, photon: LivePhoton) -> bool {
+        if self.photons_until_sent == 0 {
+            self.photons_until_sent = self.decimation;
+            match self.sender.try_send(photon) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    trace!("The live feed is full, dropping {:?}", photon)
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        self.photons_until_sent -= 1;
+        true
+    }
+}
+
 /// A handler of streaming time tagger data
 pub trait EventStreamHandler {
     fn event_to_coordinate(&mut self, event: Event) -> ProcessedEvent;
-    fn get_event_stream<'a>(&mut self, batch: &'a RecordBatch) -> Option<EventStream<'a>>;
+    fn get_event_stream<'a>(
+        &mut self,
+        batch: &'a RecordBatch,
+    ) -> Result<Option<EventStream<'a>>, AcquisitionError>;
 }
 
 /// The result of handling an event generated by the time tagger.
@@ -40,7 +457,7 @@ pub trait EventStreamHandler {
 /// Each event might arrive from different channels which require different
 /// handling, and this enum contains all possible actions we might want to do
 /// with these results.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProcessedEvent {
     /// Contains the coordinates in image space and the color
     Displayed(Point3<Coordinate>, usize),
@@ -55,16 +472,112 @@ pub enum ProcessedEvent {
     /// end of the current frame. Probably means that we didn't record all line
     /// signals that arrived during the frame
     PhotonNewFrame,
+    /// Start a new integration period since the event arrived after the end
+    /// of the current time window
+    TimeWindowNewFrame,
     /// Erroneuous event, usually for tests
     Error,
 }
 
+/// The span of a batch of events, used to detect batches which were sent
+/// twice
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BatchRange {
+    first: Picosecond,
+    last: Picosecond,
+    len: usize,
+}
+
+impl BatchRange {
+    /// The range of the batch, or `None` if it's empty
+    fn of(event_stream: &EventStream) -> Option<Self> {
+        let len = event_stream.num_rows();
+        let last = Event::from_stream_idx(event_stream, len.checked_sub(1)?)?;
+        let first = Event::from_stream_idx(event_stream, 0)?;
+        Some(BatchRange {
+            first: first.time,
+            last: last.time,
+            len,
+        })
+    }
+}
+
+/// A planar snake for the channels which are imaged in 2D during a volumetric
+/// acquisition.
+///
+/// Photons of these channels are placed in the plane of the volume which is
+/// nearest to its center instead of following the TAG lens.
+struct PlanarSnake {
+    snake: TwoDimensionalSnake,
+    channels: [bool; SUPPORTED_SPECTRAL_CHANNELS],
+    center_plane: Coordinate,
+}
+
+impl PlanarSnake {
+    /// The planar snake of the configured channels, or `None` if there are no
+    /// such channels or if the acquisition is planar anyway
+    fn from_config(config: &AppConfig, volume_snake: &dyn Snake) -> Option<PlanarSnake> {
+        if config.planes < 2 || config.planar_channels.is_empty() {
+            return None;
+        }
+        let mut channels = [false; SUPPORTED_SPECTRAL_CHANNELS];
+        config
+            .planar_channels
+            .iter()
+            .filter(|ch| **ch < SUPPORTED_SPECTRAL_CHANNELS)
+            .for_each(|ch| channels[*ch] = true);
+        let center_plane = volume_snake
+            .get_z_imagespace_planes()
+            .and_then(|planes| {
+                planes
+                    .iter()
+                    .copied()
+                    .min_by_key(|plane| OrderedFloat(plane.abs()))
+            })
+            .unwrap_or(OrderedFloat(0.0));
+        let mut planar_config = config.clone();
+        planar_config.planes = 1;
+        planar_config.carry_voxel_indices = false;
+        Some(PlanarSnake {
+            snake: TwoDimensionalSnake::from_acq_params(&planar_config, 0),
+            channels,
+            center_plane,
+        })
+    }
+
+    /// The coordinate of the photon, placed in the center plane of the volume
+    fn time_to_coord_linear(&mut self, time: Picosecond, ch: usize) -> ProcessedEvent {
+        match self.snake.time_to_coord_linear(time, ch) {
+            ProcessedEvent::Displayed(point, ch) if !is_masked(&point) => {
+                ProcessedEvent::Displayed(ImageCoor::new(point.x, point.y, self.center_plane), ch)
+            }
+            processed => processed,
+        }
+    }
+
+    /// The coordinates swept between the two times, placed in the center
+    /// plane of the volume
+    fn coords_between(&self, start: Picosecond, end: Picosecond) -> Vec<ImageCoor> {
+        self.snake
+            .coords_between(start, end)
+            .into_iter()
+            .map(|point| ImageCoor::new(point.x, point.y, self.center_plane))
+            .collect()
+    }
+}
+
 /// Implemented by Apps who wish to display points
 pub trait PointDisplay {
     /// Add the point to the renderer. This is where the ordered_float
     /// abstraction "leaks" and we have to use the native type that the
     /// underlying library expects.
     fn display_point(&mut self, p: &ImageCoor, c: &Point3<f32>, time: Picosecond);
+    /// Mark the given point, e.g. the centroid of the frame, in the next
+    /// rendered frame
+    fn display_marker(&mut self, _p: &ImageCoor) {}
+    /// Draw a line through the given points, e.g. the scan path, in the next
+    /// rendered frame
+    fn display_path(&mut self, _path: &[ImageCoor]) {}
     /// Start the GPU-based rendering process
     fn render(&mut self);
     /// Hide the rendering window
@@ -82,6 +595,7 @@ pub struct Channels<T: PointDisplay> {
     channel3: T,
     channel4: T,
     channel_merge: T,
+    control_window: ChannelNames,
 }
 
 impl<T: PointDisplay> Channels<T> {
@@ -93,9 +607,17 @@ impl<T: PointDisplay> Channels<T> {
             channel3: channels.remove(0),
             channel4: channels.remove(0),
             channel_merge: channels.remove(0),
+            control_window: ChannelNames::ChannelMerge,
         }
     }
 
+    /// Designate the window whose keyboard controls steer the acquisition.
+    /// Defaults to the merged channel
+    pub fn with_control_window(mut self, control_window: ChannelNames) -> Self {
+        self.control_window = control_window;
+        self
+    }
+
     pub fn hide_all(&mut self) {
         self.channel1.hide();
         self.channel2.hide();
@@ -109,14 +631,33 @@ impl<T: PointDisplay> Channels<T> {
     /// Due to issues with kiss3d we only render a single channel - the merged one -
     /// at this time.
     pub fn render(&mut self, frame_buffers: &mut FrameBuffers) {
+        frame_buffers.hide_low_count_voxels();
+        frame_buffers.adjust_exposure();
+        frame_buffers.apply_display_gamma();
         Channels::render_single_channel(
             &mut frame_buffers.merged_channel(),
             &mut self.channel_merge,
         );
+        // The events of a window are only handled when it's rendered
+        let control_window = self.control_window;
+        if control_window != ChannelNames::ChannelMerge {
+            self[control_window].render();
+        }
+        frame_buffers.clear_merged_counts();
         info!("Merged channel rendered");
         frame_buffers.clear_non_rendered_channels();
     }
 
+    /// Mark the point in the next rendered frame of the merged channel
+    pub fn display_marker(&mut self, p: &ImageCoor) {
+        self.channel_merge.display_marker(p);
+    }
+
+    /// Draw the path in the next rendered frame of the merged channel
+    pub fn display_path(&mut self, path: &[ImageCoor]) {
+        self.channel_merge.display_path(path);
+    }
+
     /// Populate the rendering list of a specific channel and render it.
     fn render_single_channel(
         frame_buffer: &mut HashMap<Point3<OrderedFloat<f32>>, Point3<f32>>,
@@ -128,16 +669,15 @@ impl<T: PointDisplay> Channels<T> {
         channel.render();
     }
 
-    /// Whether the user indicated to close the window
+    /// Whether the user indicated to close the control window
     pub fn should_close(&self) -> bool {
-        // We'll use channel_merge as the indicator because it will always be
-        // used
-        self.channel_merge.should_close()
+        self[self.control_window].should_close()
     }
 }
 
 /// Available channels to render. The length of this enum is always one more
 /// than [`crate::SUPPORTED_SPECTRAL_CHANNELS`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ChannelNames {
     Channel1,
     Channel2,
@@ -146,6 +686,36 @@ pub enum ChannelNames {
     ChannelMerge,
 }
 
+impl ChannelNames {
+    /// All channels, in the order of their windows
+    pub const ALL: [ChannelNames; SUPPORTED_SPECTRAL_CHANNELS + 1] = [
+        ChannelNames::Channel1,
+        ChannelNames::Channel2,
+        ChannelNames::Channel3,
+        ChannelNames::Channel4,
+        ChannelNames::ChannelMerge,
+    ];
+}
+
+/// Whether the keyboard controls of a window steer the acquisition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowRole {
+    Control,
+    Display,
+}
+
+/// The role of each window, in the order of [`ChannelNames::ALL`]. Only the
+/// given control window steers the acquisition
+pub fn window_roles(control_window: ChannelNames) -> [WindowRole; SUPPORTED_SPECTRAL_CHANNELS + 1] {
+    ChannelNames::ALL.map(|name| {
+        if name == control_window {
+            WindowRole::Control
+        } else {
+            WindowRole::Display
+        }
+    })
+}
+
 impl<T: PointDisplay> Index<ChannelNames> for Channels<T> {
     type Output = T;
 
@@ -173,20 +743,80 @@ impl<T: PointDisplay> IndexMut<ChannelNames> for Channels<T> {
 }
 
 /// Holds the custom renderer that will be used for rendering the
-/// point cloud
+/// point cloud, or the image of planar frames
 pub struct DisplayChannel {
     pub window: Window,
+    image: Option<ImageQuad>,
 }
 
 impl PointDisplay for DisplayChannel {
     #[inline]
     fn display_point(&mut self, p: &ImageCoor, c: &Point3<f32>, _time: Picosecond) {
-        // Convert the point to ScanImage's FOV (and to f32)
-        let p0: &Point3<f32> = &Point3::new(-*p.y, -*p.x, *p.z);
-        self.window.draw_point(p0, c)
+        match &mut self.image {
+            Some(image) => image.frame.set_voxel(p, c),
+            None => {
+                // Convert the point to ScanImage's FOV (and to f32)
+                let p0: &Point3<f32> = &Point3::new(-*p.y, -*p.x, *p.z);
+                self.window.draw_point(p0, c)
+            }
+        }
+    }
+
+    /// Draws a cross centered on the point
+    fn display_marker(&mut self, p: &ImageCoor) {
+        let color = Point3::new(1.0, 1.0, 1.0);
+        match &self.image {
+            Some(_) => {
+                let center = self.planar_position(p);
+                let arm = CENTROID_MARKER_SIZE * self.window.width() as f32;
+                for offset in [Point2::new(arm, 0.0), Point2::new(0.0, arm)] {
+                    self.window.draw_planar_line(
+                        &(center - offset.coords),
+                        &(center + offset.coords),
+                        &color,
+                    );
+                }
+            }
+            None => {
+                let center = Point3::new(-*p.y, -*p.x, *p.z);
+                for offset in [
+                    Point3::new(CENTROID_MARKER_SIZE, 0.0, 0.0),
+                    Point3::new(0.0, CENTROID_MARKER_SIZE, 0.0),
+                ] {
+                    self.window.draw_line(
+                        &(center - offset.coords),
+                        &(center + offset.coords),
+                        &color,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Connects consecutive points with faint lines
+    fn display_path(&mut self, path: &[ImageCoor]) {
+        let color = Point3::new(SCAN_PATH_LEVEL, SCAN_PATH_LEVEL, SCAN_PATH_LEVEL);
+        for segment in path.windows(2) {
+            match &self.image {
+                Some(_) => {
+                    let start = self.planar_position(&segment[0]);
+                    let end = self.planar_position(&segment[1]);
+                    self.window.draw_planar_line(&start, &end, &color);
+                }
+                None => {
+                    let start = Point3::new(-*segment[0].y, -*segment[0].x, *segment[0].z);
+                    let end = Point3::new(-*segment[1].y, -*segment[1].x, *segment[1].z);
+                    self.window.draw_line(&start, &end, &color);
+                }
+            }
+        }
     }
 
     fn render(&mut self) {
+        if let Some(image) = &mut self.image {
+            image.upload();
+            image.frame.clear();
+        }
         self.window.render();
     }
 
@@ -200,10 +830,189 @@ impl PointDisplay for DisplayChannel {
 }
 
 impl DisplayChannel {
-    pub fn new(title: &str, width: u32, height: u32, frame_rate: u64) -> Self {
-        let mut window = Window::new_with_size(title, width, height);
+    /// Opens a window whose background, the color of voxels without photons,
+    /// has the given gray level. Antialiased windows are multisampled, which
+    /// smooths the lines drawn over the frames
+    pub fn new(
+        title: &str,
+        width: u32,
+        height: u32,
+        frame_rate: u64,
+        background_level: f32,
+        antialiased: bool,
+    ) -> Self {
+        let mut window = if antialiased {
+            let setup = CanvasSetup {
+                vsync: true,
+                samples: NumSamples::Four,
+            };
+            Window::new_with_setup(title, width, height, setup)
+        } else {
+            Window::new_with_size(title, width, height)
+        };
         window.set_framerate_limit(Some(frame_rate));
-        Self { window }
+        window.set_background_color(background_level, background_level, background_level);
+        Self {
+            window,
+            image: None,
+        }
+    }
+
+    /// Draws the frames as a textured image filling the window rather than as
+    /// a point cloud
+    pub fn with_image_display(mut self, frame: FrameImage) -> Self {
+        let texture = Texture::new();
+        let mut node = self
+            .window
+            .add_rectangle(self.window.width() as f32, self.window.height() as f32);
+        node.set_texture(texture.clone());
+        self.image = Some(ImageQuad {
+            frame,
+            texture,
+            _node: node,
+        });
+        self
+    }
+
+    /// The position of the point on the image filling the window, whose first
+    /// row is at its top
+    fn planar_position(&self, p: &ImageCoor) -> Point2<f32> {
+        Point2::new(
+            *p.y * self.window.width() as f32,
+            -*p.x * self.window.height() as f32,
+        )
+    }
+}
+
+/// The rectangle covering the window whose texture shows the planar frame in
+/// [`crate::configuration::DisplayMode::Image2D`]
+struct ImageQuad {
+    frame: FrameImage,
+    texture: Rc<Texture>,
+    _node: PlanarSceneNode,
+}
+
+impl ImageQuad {
+    /// Copies the pixels of the frame to the texture on the GPU
+    fn upload(&self) {
+        let ctxt = GlContext::get();
+        ctxt.active_texture(GlContext::TEXTURE0);
+        ctxt.bind_texture(GlContext::TEXTURE_2D, Some(&*self.texture));
+        // Rows of RGB pixels aren't necessarily aligned to four bytes
+        ctxt.pixel_storei(GlContext::UNPACK_ALIGNMENT, 1);
+        ctxt.tex_image2d(
+            GlContext::TEXTURE_2D,
+            0,
+            GlContext::RGB as i32,
+            self.frame.columns() as i32,
+            self.frame.rows() as i32,
+            0,
+            GlContext::RGB,
+            Some(self.frame.pixels()),
+        );
+        // Each voxel should stay a sharp square rather than be interpolated
+        for filter in [GlContext::TEXTURE_MIN_FILTER, GlContext::TEXTURE_MAG_FILTER] {
+            ctxt.tex_parameteri(GlContext::TEXTURE_2D, filter, GlContext::NEAREST as i32);
+        }
+        for wrap in [GlContext::TEXTURE_WRAP_S, GlContext::TEXTURE_WRAP_T] {
+            ctxt.tex_parameteri(GlContext::TEXTURE_2D, wrap, GlContext::CLAMP_TO_EDGE as i32);
+        }
+    }
+}
+
+/// A display channel without a window, which ignores the points it receives.
+///
+/// Used when the frames are consumed as data rather than shown on screen.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeadlessChannel;
+
+impl PointDisplay for HeadlessChannel {
+    fn display_point(&mut self, _p: &ImageCoor, _c: &Point3<f32>, _time: Picosecond) {}
+
+    fn render(&mut self) {}
+
+    fn hide(&mut self) {}
+
+    fn should_close(&self) -> bool {
+        false
+    }
+}
+
+/// Decides when a completed frame may replace the one currently on screen.
+///
+/// Frames which complete before the current one was shown for the minimal
+/// duration are held back, and their photons are accumulated into the next
+/// rendered frame.
+#[derive(Clone, Debug)]
+pub struct FramePacer {
+    min_display: Duration,
+    last_shown: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(min_display: Duration) -> Self {
+        FramePacer {
+            min_display,
+            last_shown: None,
+        }
+    }
+
+    /// Whether a frame completed at `now` should be rendered, updating the
+    /// time the displayed frame was shown if it should
+    pub fn should_show(&mut self, now: Instant) -> bool {
+        match self.last_shown {
+            Some(last) if now.saturating_duration_since(last) < self.min_display => false,
+            _ => {
+                self.last_shown = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Measures the frame rate of the acquisition from the start times of its
+/// frames.
+///
+/// The instantaneous rate jitters from frame to frame, so the reported rate is
+/// an exponential moving average of it, where `smoothing` is the weight of the
+/// newest frame. A smoothing of 1 reports the instantaneous rate.
+#[derive(Clone, Debug)]
+pub struct FrameRateMeter {
+    smoothing: f32,
+    last_frame_start: Option<Picosecond>,
+    rate: Option<f32>,
+}
+
+impl FrameRateMeter {
+    pub fn new(smoothing: f32) -> Self {
+        FrameRateMeter {
+            smoothing: smoothing.clamp(f32::EPSILON, 1.0),
+            last_frame_start: None,
+            rate: None,
+        }
+    }
+
+    /// Registers the start time of a new frame, returning the smoothed rate
+    /// in Hz once at least two frames were seen
+    pub fn push(&mut self, frame_start: Picosecond) -> Option<f32> {
+        if let Some(last) = self.last_frame_start.replace(frame_start) {
+            if frame_start > last {
+                let instantaneous = Period {
+                    period: frame_start - last,
+                }
+                .to_hz();
+                self.rate = Some(match self.rate {
+                    Some(rate) => rate + self.smoothing * (instantaneous - rate),
+                    None => instantaneous,
+                });
+            }
+        }
+        self.rate
+    }
+
+    /// The latest smoothed rate in Hz
+    pub fn rate(&self) -> Option<f32> {
+        self.rate
     }
 }
 
@@ -220,78 +1029,681 @@ pub struct AppState<T: PointDisplay, R: Read> {
     data_stream_fh: String,
     pub data_stream: Option<StreamReader<R>>,
     snake: Box<dyn Snake>,
+    planar_snake: Option<PlanarSnake>,
     inputs: Inputs,
     rows_per_frame: u32,
+    max_extra_lines: u32,
+    min_frame_interval: Picosecond,
+    last_frame_signal: Option<Picosecond>,
+    line_matching: LineMatching,
+    line_period: Picosecond,
     line_count: u32,
     lines_vec: Vec<Picosecond>,
+    frames_started: u64,
     batch_readout_count: u64,
+    stop_at_stream_end: bool,
     frame_buffers: FrameBuffers,
+    batch_relevance_tolerance: Picosecond,
+    frame_gate_open: bool,
+    frame_pacer: FramePacer,
+    frame_rate_meter: FrameRateMeter,
+    laser_phase: LaserPhase,
+    integration_window: IntegrationWindow,
+    window_start: Option<Picosecond>,
+    uses_trigger: bool,
+    trigger_armed: bool,
+    frame_table: Option<FrameTable>,
+    mip_preview: Option<MipPreview>,
+    thumbnails: Option<ThumbnailStream>,
+    raw_recorder: Option<RawEventRecorder>,
+    diagnostics: RenderDiagnostics,
+    last_frame_diagnostics: RenderDiagnostics,
+    disconnected_events: u64,
+    control: Option<Receiver<AcquisitionControl>>,
+    display_paused: bool,
+    coincidence: Option<CoincidenceFilter>,
+    live_feed: Option<LiveFeed>,
+    live_feed_decimation: u32,
+    line_clock: Option<LineClock>,
+    channel_enabled: [bool; SUPPORTED_SPECTRAL_CHANNELS],
+    serializer: Option<Sender<FrameBuffers>>,
+    skip_initial_frames: u32,
+    flush_partial_frame: bool,
+    frame_in_progress: bool,
+    shut_down: bool,
+    sync_monitor: Option<SyncMonitor>,
+    blank_on_sync_loss: bool,
+    skip_duplicate_batches: bool,
+    last_batch_range: Option<BatchRange>,
+    out_of_order_batches: OutOfOrderBatches,
+    latest_batch_end: Option<Picosecond>,
+    mark_missed_events: bool,
+    last_photon_times: [Picosecond; SUPPORTED_SPECTRAL_CHANNELS],
+    frame_centroid: Option<ImageCoor>,
+    draw_centroid: bool,
+    scan_path: Option<Vec<ImageCoor>>,
+    affine: Option<[[f32; 3]; 4]>,
 }
 
 impl<T: PointDisplay, R: Read> AppState<T, R> {
     /// Generates a new app from a renderer and a receiving end of a channel
     pub fn new(channels: Channels<T>, data_stream_fh: String, appconfig: AppConfig) -> Self {
         let snake = AppState::<T, R>::choose_snake_variant(&appconfig);
+        let planar_snake = PlanarSnake::from_config(&appconfig, snake.as_ref());
+        let frame_table = if appconfig.frame_table {
+            let path = frame_table_path(&appconfig.filename);
+            FrameTable::create(&path)
+                .map_err(|e| warn!("Couldn't create the frame table at {:?}: {}", path, e))
+                .ok()
+        } else {
+            None
+        };
+        let thumbnails = if appconfig.thumbnail_size > 0 {
+            let path = thumbnails_path(&appconfig.filename);
+            ThumbnailStream::create(&path, &appconfig)
+                .map_err(|e| warn!("Couldn't create the thumbnail stream at {:?}: {}", path, e))
+                .ok()
+        } else {
+            None
+        };
+        let scan_path = appconfig.draw_scan_path.then(|| {
+            let path = snake.scan_path();
+            match &appconfig.affine {
+                Some(affine) => path
+                    .iter()
+                    .filter_map(|coord| apply_affine(affine, coord))
+                    .collect(),
+                None => path,
+            }
+        });
+        let raw_recorder = appconfig
+            .record_raw_events
+            .then(|| RawEventRecorder::new(raw_events_path(&appconfig.filename)));
+        // The sync can only be monitored with a line signal, and photons
+        // arriving during the frame's dead time don't indicate a sync loss
+        let sync_monitor = if appconfig.line_ch.channel != 0 && appconfig.sync_loss_lines > 0 {
+            Some(SyncMonitor::new(
+                appconfig.frame_dead_time
+                    + appconfig.sync_loss_lines as Picosecond * *appconfig.line_period(),
+            ))
+        } else {
+            None
+        };
         AppState {
             channels,
             data_stream_fh,
             data_stream: None,
             snake,
+            planar_snake,
             inputs: Inputs::from_config(&appconfig),
             rows_per_frame: appconfig.rows,
+            max_extra_lines: appconfig.max_extra_lines,
+            min_frame_interval: appconfig.min_frame_interval,
+            last_frame_signal: None,
+            line_matching: appconfig.line_matching,
+            line_period: *appconfig.line_period(),
             line_count: 0,
             lines_vec: Vec::<Picosecond>::with_capacity(3000),
+            frames_started: 0,
             batch_readout_count: 0,
-            frame_buffers: FrameBuffers::new(appconfig.increment_color_by),
+            stop_at_stream_end: appconfig.replay_existing && appconfig.stop_at_replay_end,
+            frame_buffers: FrameBuffers::from_config(&appconfig),
+            batch_relevance_tolerance: appconfig.batch_relevance_tolerance,
+            frame_gate_open: true,
+            frame_pacer: FramePacer::new(Duration::from_millis(appconfig.min_frame_display_ms)),
+            frame_rate_meter: FrameRateMeter::new(appconfig.frame_rate_smoothing),
+            laser_phase: LaserPhase::new(appconfig.laser_period, appconfig.strobe_laser_marker),
+            integration_window: appconfig.integration_window,
+            window_start: None,
+            uses_trigger: appconfig.trigger_ch.channel != 0,
+            trigger_armed: appconfig.trigger_ch.channel != 0,
+            frame_table,
+            mip_preview: MipPreview::from_config(&appconfig),
+            thumbnails,
+            raw_recorder,
+            diagnostics: RenderDiagnostics::default(),
+            last_frame_diagnostics: RenderDiagnostics::default(),
+            disconnected_events: 0,
+            control: None,
+            display_paused: false,
+            coincidence: appconfig.coincidence.map(CoincidenceFilter::new),
+            live_feed: None,
+            line_clock: None,
+            live_feed_decimation: appconfig.live_feed_decimation,
+            channel_enabled: appconfig.channel_enabled,
+            serializer: None,
+            skip_initial_frames: appconfig.skip_initial_frames,
+            flush_partial_frame: appconfig.flush_partial_frame,
+            frame_in_progress: false,
+            shut_down: false,
+            sync_monitor,
+            blank_on_sync_loss: appconfig.blank_on_sync_loss,
+            skip_duplicate_batches: appconfig.skip_duplicate_batches,
+            last_batch_range: None,
+            out_of_order_batches: appconfig.out_of_order_batches,
+            latest_batch_end: None,
+            mark_missed_events: appconfig.mark_missed_events,
+            last_photon_times: [0; SUPPORTED_SPECTRAL_CHANNELS],
+            frame_centroid: None,
+            draw_centroid: appconfig.draw_centroid,
+            scan_path,
+            affine: appconfig.affine,
         }
     }
 
     /// Decide on 2D or 3D rendering based on the configuration.
     fn choose_snake_variant(config: &AppConfig) -> Box<dyn Snake + 'static> {
+        if let Some(path) = &config.snake_path {
+            if config.planes > 1 {
+                warn!("Only planar snakes can be loaded, ignoring {}", path);
+            } else {
+                match TwoDimensionalSnake::from_serialized(path) {
+                    Ok(snake) => {
+                        snake.verify_length(config);
+                        return Box::new(snake);
+                    }
+                    Err(e) => warn!("Couldn't load the snake at {}: {}", path, e),
+                }
+            }
+        }
         match config.planes {
-            0 | 1 => Box::new(TwoDimensionalSnake::from_acq_params(config, 0)),
-            2..=u32::MAX => Box::new(ThreeDimensionalSnake::from_acq_params(config, 0)),
+            0 | 1 => {
+                let snake = TwoDimensionalSnake::from_acq_params(config, 0);
+                if let Some(path) = &config.snake_dump_path {
+                    match snake.dump_to(path) {
+                        Ok(()) => info!("Dumped the snake to {}", path),
+                        Err(e) => warn!("Couldn't dump the snake to {}: {}", path, e),
+                    }
+                }
+                Box::new(snake)
+            }
+            2..=u32::MAX => {
+                if let Some(path) = &config.snake_dump_path {
+                    warn!("Only planar snakes can be dumped, ignoring {}", path);
+                }
+                Box::new(ThreeDimensionalSnake::from_acq_params(config, 0))
+            }
         }
     }
 
-    /// Render the data to the screen
-    fn render(&mut self) {
-        self.channels.render(&mut self.frame_buffers);
+    /// Listen to commands arriving from the given channel during the
+    /// acquisition
+    pub fn with_control(mut self, control: Receiver<AcquisitionControl>) -> Self {
+        self.control = Some(control);
+        self
     }
 
-    /// Called when an event from the line channel arrives to the event stream.
-    ///
-    /// It handles the first line of the experiment, by returning a special
-    /// signal, a standard line in the middle of the frame or a line which
-    /// is the first in the next frame's line count.
-    fn handle_line_event(&mut self, time: Picosecond) -> ProcessedEvent {
-        // The new line that arrived is the first of the next frame
-        if self.line_count == self.rows_per_frame {
-            self.line_count = 0;
-            debug!("Here are the lines: {:#?}", self.lines_vec);
-            self.lines_vec.clear();
-            self.snake.update_snake_for_next_frame(time);
-            ProcessedEvent::LineNewFrame
-        } else {
-            self.line_count += 1;
-            self.lines_vec.push(time);
-            ProcessedEvent::NoOp
-        }
+    /// Invoke the callback with each line that starts during the processing,
+    /// see [`LineClock`]
+    pub fn with_line_clock(mut self, line_clock: impl FnMut(u32, Picosecond) + 'static) -> Self {
+        self.line_clock = Some(Box::new(line_clock));
+        self
     }
 
-    /// Called when an event from the frame channel arrives
-    fn handle_frame_event(&mut self, time: Picosecond) -> ProcessedEvent {
-        debug!("A new frame due to a frame signal");
-        self.line_count = 0;
-        self.lines_vec.clear();
-        self.snake.update_snake_for_next_frame(time);
-        ProcessedEvent::FrameNewFrame
+    /// Start sending the processed photons, decimated according to the
+    /// configuration, to the returned channel, which holds up to `capacity`
+    /// photons
+    pub fn live_feed(&mut self, capacity: usize) -> Receiver<LivePhoton> {
+        let (sender, receiver) = bounded(capacity);
+        self.live_feed = Some(LiveFeed::new(sender, self.live_feed_decimation));
+        receiver
     }
 
-    /// Process events in an existing stream of events.
-    ///
-    /// The method will iterate over each event and "act" on it by calling the
-    /// correct methods. If one of the events is a new frame-signaling event it
+    /// The frame rate of the acquisition in Hz, smoothed over the recent
+    /// frames, once at least two frames were acquired
+    pub fn measured_frame_rate(&self) -> Option<f32> {
+        self.frame_rate_meter.rate()
+    }
+
+    /// Whether the display is currently paused by an [`AcquisitionControl`]
+    pub fn is_display_paused(&self) -> bool {
+        self.display_paused
+    }
+
+    /// Cleans up once the acquisition ends.
+    ///
+    /// All channel windows are hidden and the serializer is signaled to
+    /// finish writing by dropping its channel. The recorded raw events are
+    /// finished as well. The data stream is closed, which signals the Python
+    /// side to stop the TimeTagger.
+    pub fn shutdown(&mut self) {
+        info!("Shutting the acquisition down");
+        self.channels.hide_all();
+        self.serializer = None;
+        if let Some(recorder) = self.raw_recorder.as_mut() {
+            if let Err(e) = recorder.finish() {
+                error!("Couldn't finish recording the raw events: {}", e);
+            }
+        }
+        self.data_stream = None;
+        self.shut_down = true;
+    }
+
+    /// Whether [`AppState::shutdown`] was called
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    /// Whether the line events still arrive as expected. Acquisitions without
+    /// a line signal are always considered synced.
+    pub fn sync_status(&self) -> SyncStatus {
+        self.sync_monitor
+            .map_or(SyncStatus::Synced, |monitor| monitor.status())
+    }
+
+    /// Apply all control commands that arrived since the last check, without
+    /// blocking
+    pub fn apply_control_commands(&mut self) {
+        if let Some(control) = self.control.as_ref() {
+            for command in control.try_iter() {
+                debug!("Received a control command: {:?}", command);
+                match command {
+                    AcquisitionControl::PauseDisplay => self.display_paused = true,
+                    AcquisitionControl::ResumeDisplay => self.display_paused = false,
+                    AcquisitionControl::SetChannelEnabled { channel, enabled } => {
+                        match self.channel_enabled.get_mut(channel) {
+                            Some(channel_enabled) => *channel_enabled = enabled,
+                            None => warn!("Can't toggle the nonexistent channel {}", channel),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The number of rendered and dropped photons in the last completed frame
+    pub fn frame_diagnostics(&self) -> RenderDiagnostics {
+        self.last_frame_diagnostics
+    }
+
+    /// Whether the event arrived from one of the PMT channels
+    fn is_photon(&self, event: &Event) -> bool {
+        matches!(
+            self.inputs[event.channel],
+            DataType::Pmt1 | DataType::Pmt2 | DataType::Pmt3 | DataType::Pmt4
+        )
+    }
+
+    /// Sends the photon to the live feed, closing the feed once its consumer
+    /// is gone
+    fn feed_live_photon(&mut self, event: &Event) {
+        if let Some(feed) = self.live_feed.as_mut() {
+            if !feed.push((event.time, event.channel)) {
+                info!("The live feed consumer disconnected");
+                self.live_feed = None;
+            }
+        }
+    }
+
+    /// Render the data to the screen
+    fn render(&mut self) {
+        if let (true, Some(centroid)) = (self.draw_centroid, self.frame_centroid) {
+            self.channels.display_marker(&centroid);
+        }
+        if let Some(path) = &self.scan_path {
+            self.channels.display_path(path);
+        }
+        self.channels.render(&mut self.frame_buffers);
+    }
+
+    /// The count-weighted centroid of the photons of the last completed
+    /// frame, or `None` if it had no photons
+    pub fn frame_centroid(&self) -> Option<ImageCoor> {
+        self.frame_centroid
+    }
+
+    /// Whether the frame, numbered from 1, is one of the frames at the start
+    /// of the acquisition whose photons aren't serialized
+    fn is_warm_up_frame(&self, frame_number: usize) -> bool {
+        frame_number <= self.skip_initial_frames as usize
+    }
+
+    /// Sends the photons aggregated since the last serialized frame to the
+    /// serializer, unless the frame is one of the warm-up frames
+    fn serialize_frame(&self, frame_number: usize) {
+        if self.is_warm_up_frame(frame_number) {
+            return;
+        }
+        if let Some(serializer) = &self.serializer {
+            if let Err(e) = serializer.send(self.frame_buffers.clone()) {
+                error!(
+                    "Couldn't send frame number {} due to an error: {:#?}",
+                    frame_number, e.0
+                );
+            }
+        }
+    }
+
+    /// Writes the previews of the frame that was just completed, numbered
+    /// from 1. They show the photons of this frame alone, rather than those
+    /// of all frames of the rolling average which the buffers accumulate
+    fn write_frame_previews(&mut self, frame_number: usize) {
+        if self.mip_preview.is_none() && self.thumbnails.is_none() {
+            return;
+        }
+        let counts = self.frame_buffers.take_frame_counts();
+        self.write_mip_preview(&counts, frame_number);
+        self.push_thumbnail(&counts);
+    }
+
+    /// Writes the projection of the frame's voxel counts if a preview is due
+    /// after it
+    fn write_mip_preview(&mut self, counts: &HashMap<ImageCoor, u32>, frame_number: usize) {
+        if let Some(preview) = self.mip_preview.as_mut() {
+            if !preview.frame_completed() {
+                return;
+            }
+            match preview.write(counts, frame_number) {
+                Ok(path) => debug!("Wrote the preview of frame {} to {:?}", frame_number, path),
+                Err(e) => warn!(
+                    "Couldn't write the preview of frame {}: {}",
+                    frame_number, e
+                ),
+            }
+        }
+    }
+
+    /// Appends the thumbnail of the frame that was just completed to the
+    /// thumbnail stream, if there's one
+    fn push_thumbnail(&mut self, counts: &HashMap<ImageCoor, u32>) {
+        if let Some(thumbnails) = self.thumbnails.as_mut() {
+            if let Err(e) = thumbnails.push(counts) {
+                warn!("Couldn't write to the thumbnail stream: {}", e);
+            }
+        }
+    }
+
+    /// Sends the photons which weren't serialized yet to the serializer when
+    /// the acquisition stops, if the configuration asks for it.
+    ///
+    /// These are the photons of a frame which was interrupted by an error,
+    /// which is then marked as partial in the frame table, and those of the
+    /// frames completed since the last serialized one.
+    pub fn flush_partial_frame(&mut self, frame_number: usize) {
+        if !self.flush_partial_frame || !self.frame_buffers.has_aggregated_photons() {
+            return;
+        }
+        if self.frame_in_progress {
+            if let Some(table) = self.frame_table.as_mut() {
+                let start_time = self.snake.get_earliest_frame_time();
+                let end_time = self.snake.get_max_frame_time();
+                if let Err(e) = table.push(start_time, end_time, self.diagnostics.rendered, true) {
+                    warn!("Couldn't write to the frame table: {}", e);
+                }
+            }
+        }
+        info!("Serializing the partial frame {}", frame_number);
+        self.serialize_frame(frame_number);
+        self.frame_buffers.clear_non_rendered_channels();
+    }
+
+    /// The number of events which arrived on channel 0 and were dropped
+    pub fn disconnected_events(&self) -> u64 {
+        self.disconnected_events
+    }
+
+    /// Counts an event of channel 0, which marks disconnected inputs and
+    /// shouldn't be emitted by the TimeTagger. Only the first of these events
+    /// is reported, so that a misbehaving stream can't flood the log
+    fn drop_disconnected_event(&mut self, event: &Event) {
+        if self.disconnected_events == 0 {
+            warn!(
+                "Dropping the events of the disconnected channel 0, starting with {:?}",
+                event
+            );
+        }
+        self.disconnected_events += 1;
+    }
+
+    /// The index of the frame which is currently acquired, starting from 0
+    /// with the first frame of the acquisition
+    pub fn current_frame_index(&self) -> u64 {
+        self.frames_started.saturating_sub(1)
+    }
+
+    /// How far through the current frame the acquisition is, from 0 at its
+    /// start to 1 once all of its lines arrived, based on the number of line
+    /// events since the frame started
+    pub fn frame_progress(&self) -> f32 {
+        (self.line_count as f32 / self.rows_per_frame.max(1) as f32).min(1.0)
+    }
+
+    /// Called when an event from the line channel arrives to the event stream.
+    ///
+    /// It handles the first line of the experiment, by returning a special
+    /// signal, a standard line in the middle of the frame or a line which
+    /// is the first in the next frame's line count.
+    ///
+    /// Up to `max_extra_lines` spurious lines are tolerated as part of the
+    /// current frame, after which a new frame is forced so that a noisy line
+    /// channel can't hang the acquisition.
+    fn handle_line_event(&mut self, time: Picosecond) -> ProcessedEvent {
+        if let Some(monitor) = self.sync_monitor.as_mut() {
+            monitor.line_arrived(time);
+        }
+        let line_number = self.line_number_of(time);
+        // Extra lines are only tolerated while the current frame is still
+        // being scanned, since later ones start the next frame
+        let slack = if time <= self.snake.get_max_frame_time() {
+            self.max_extra_lines
+        } else {
+            0
+        };
+        // The new line that arrived is the first of the next frame
+        if line_number > self.rows_per_frame + slack {
+            if self.line_count > self.rows_per_frame {
+                warn!(
+                    "Forcing a new frame after {} lines while expecting {}",
+                    self.line_count, self.rows_per_frame
+                );
+            }
+            self.line_count = 0;
+            debug!("Here are the lines: {:#?}", self.lines_vec);
+            self.lines_vec.clear();
+            self.update_snakes_for_next_frame(time);
+            self.tick_line_clock(0, time);
+            ProcessedEvent::LineNewFrame
+        } else {
+            self.tick_line_clock(line_number - 1, time);
+            self.line_count = line_number;
+            self.lines_vec.push(time);
+            self.snake.new_line_event(time);
+            if let Some(planar) = self.planar_snake.as_mut() {
+                planar.snake.new_line_event(time);
+            }
+            ProcessedEvent::NoOp
+        }
+    }
+
+    /// Passes the line to the line clock, if there's one, with its index
+    /// within the current frame
+    fn tick_line_clock(&mut self, line: u32, time: Picosecond) {
+        if let Some(line_clock) = self.line_clock.as_mut() {
+            line_clock(line, time);
+        }
+    }
+
+    /// The number of the line within the current frame, starting from 1.
+    ///
+    /// Lines matched to the nearest expected row are numbered by the time
+    /// since the start of the frame, so a missing or spurious line event
+    /// doesn't shift the numbers of the following lines.
+    fn line_number_of(&self, time: Picosecond) -> u32 {
+        match self.line_matching {
+            LineMatching::Counted => self.line_count + 1,
+            LineMatching::NearestExpected => {
+                let since_frame_start = (time - self.snake.get_earliest_frame_time()).max(0);
+                let row = (since_frame_start as f64 / self.line_period as f64).round() as u32;
+                if row != self.line_count {
+                    debug!(
+                        "Line at {} matched to row {} after {} lines",
+                        time, row, self.line_count
+                    );
+                }
+                row + 1
+            }
+        }
+    }
+
+    /// Moves the snakes of all channels to the frame starting at the given
+    /// time
+    fn update_snakes_for_next_frame(&mut self, next_frame_at: Picosecond) {
+        self.frames_started += 1;
+        self.snake.update_snake_for_next_frame(next_frame_at);
+        if let Some(planar) = self.planar_snake.as_mut() {
+            planar.snake.update_snake_for_next_frame(next_frame_at);
+        }
+    }
+
+    /// Called when an event from the frame channel arrives.
+    ///
+    /// Signals arriving within `min_frame_interval` of the previous one are
+    /// glitches of the frame channel and don't start a new frame.
+    fn handle_frame_event(&mut self, time: Picosecond) -> ProcessedEvent {
+        if self.is_frame_glitch(time) {
+            return ProcessedEvent::NoOp;
+        }
+        self.last_frame_signal = Some(time);
+        debug!("A new frame due to a frame signal");
+        self.line_count = 0;
+        self.lines_vec.clear();
+        self.frame_gate_open = true;
+        self.update_snakes_for_next_frame(time);
+        ProcessedEvent::FrameNewFrame
+    }
+
+    /// Whether the frame signal at the given time arrived within
+    /// `min_frame_interval` of the previous frame signal, which makes it a
+    /// glitch of the frame channel
+    fn is_frame_glitch(&self, time: Picosecond) -> bool {
+        match self.last_frame_signal {
+            Some(previous) if (time - previous).abs() < self.min_frame_interval => {
+                debug!(
+                    "Ignoring a frame signal {} ps after the previous one",
+                    time - previous
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Called when the closing edge of a gated frame channel arrives.
+    ///
+    /// Photons arriving from this point and until the next frame starts are
+    /// outside the gate and will be discarded.
+    fn handle_frame_end_event(&mut self, time: Picosecond) -> ProcessedEvent {
+        debug!("Frame gate closed at {}", time);
+        self.frame_gate_open = false;
+        ProcessedEvent::NoOp
+    }
+
+    /// Called when a time tag arrives from one of the PMT channels, which
+    /// first marks the voxels whose photons the TimeTagger missed before it
+    fn handle_pmt_event(&mut self, event: &Event, ch: usize) -> ProcessedEvent {
+        self.mark_missed_interval(event, ch);
+        self.handle_photon_event(event.time, ch)
+    }
+
+    /// Marks the voxels swept since the previous photon of the channel as
+    /// missing data when the TimeTagger reports that it missed events before
+    /// this one, if the configuration asks for it.
+    ///
+    /// The swept voxels are placed like the photons in
+    /// [`AppState::add_displayed_point`], so masked, flyback and transformed
+    /// voxels line up with the rendered photons.
+    fn mark_missed_interval(&mut self, event: &Event, ch: usize) {
+        let previous = std::mem::replace(&mut self.last_photon_times[ch], event.time);
+        if !self.mark_missed_events || event.missed_event == 0 {
+            return;
+        }
+        let swept = match self.planar_snake.as_ref() {
+            Some(planar) if planar.channels[ch] => planar.coords_between(previous, event.time),
+            _ => self.snake.coords_between(previous, event.time),
+        };
+        debug!(
+            "Marking {} voxels as missing after {} missed events",
+            swept.len(),
+            event.missed_event
+        );
+        for point in swept {
+            if is_masked(&point) || point.x.is_nan() || point.y.is_nan() {
+                continue;
+            }
+            let point = match &self.affine {
+                Some(affine) => match apply_affine(affine, &point) {
+                    Some(point) => point,
+                    None => continue,
+                },
+                None => point,
+            };
+            self.frame_buffers.mark_missing(point);
+        }
+    }
+
+    /// Called when a photon arrives from one of the PMT channels
+    fn handle_photon_event(&mut self, time: Picosecond, ch: usize) -> ProcessedEvent {
+        let synced = self
+            .sync_monitor
+            .as_mut()
+            .is_none_or(|monitor| monitor.photon_arrived(time));
+        if !synced && self.blank_on_sync_loss {
+            trace!("Discarding a photon after the sync was lost: {}", time);
+            self.diagnostics.unsynced += 1;
+            ProcessedEvent::NoOp
+        } else if !self.channel_enabled[ch] {
+            trace!("Discarding a photon of the disabled channel {}", ch);
+            self.diagnostics.disabled += 1;
+            ProcessedEvent::NoOp
+        } else if self.frame_gate_open {
+            match self.planar_snake.as_mut() {
+                Some(planar) if planar.channels[ch] => planar.time_to_coord_linear(time, ch),
+                _ => self.snake.time_to_coord_linear(time, ch),
+            }
+        } else {
+            trace!("Discarding a photon outside of the frame gate: {}", time);
+            self.diagnostics.gated += 1;
+            ProcessedEvent::NoOp
+        }
+    }
+
+    /// Checks whether the event closes the current time window, in which case
+    /// it also opens the next one.
+    ///
+    /// The first window opens with the first event that arrives, and every
+    /// following window opens with the first event that falls outside of its
+    /// predecessor.
+    fn time_window_elapsed(&mut self, time: Picosecond) -> bool {
+        let window = match self.integration_window {
+            IntegrationWindow::PerFrame => return false,
+            IntegrationWindow::Time(window) => window,
+        };
+        match self.window_start {
+            Some(start) if time - start < window => false,
+            Some(_) => {
+                self.window_start = Some(time);
+                true
+            }
+            None => {
+                self.window_start = Some(time);
+                false
+            }
+        }
+    }
+
+    /// Decides whether a new scanner frame also ends the integration period,
+    /// which is only the case when integrating per frame
+    fn ends_integration(&self, reason: ProcessedEvent) -> Option<ProcessedEvent> {
+        match self.integration_window {
+            IntegrationWindow::PerFrame => Some(reason),
+            IntegrationWindow::Time(_) => None,
+        }
+    }
+
+    /// Process events in an existing stream of events.
+    ///
+    /// The method will iterate over each event and "act" on it by calling the
+    /// correct methods. If one of the events is a new frame-signaling event it
     /// will halt iteration and return the remaining events, not including that
     /// last new-frame event.
     ///
@@ -299,20 +1711,48 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
     /// frame, the method will first drain the remaining events until it finds
     /// start of the next frame and then will return the events from that point
     /// on.
-    fn drain_existing_data<E>(&mut self, mut events_iter: E) -> Option<Vec<Event>>
+    ///
+    /// When integrating over time windows the event which closed the window
+    /// belongs to the next one, so it's returned as the first remaining event.
+    ///
+    /// When an external trigger is used, a frame which started without a
+    /// trigger is skipped until a triggered one starts.
+    fn drain_existing_data<E>(
+        &mut self,
+        mut events_iter: E,
+    ) -> Result<Option<Vec<Event>>, AcquisitionError>
     where
         E: core::fmt::Debug + Iterator<Item = Event>,
     {
-        let new_frame_in_pre_events =
-            events_iter.find_map(|event: Event| self.act_on_single_event(event));
+        let mut window_closing_event = None;
+        let new_frame_in_pre_events = events_iter.find_map(|event: Event| {
+            let processed = self.act_on_single_event(event);
+            if processed == Some(ProcessedEvent::TimeWindowNewFrame) {
+                window_closing_event = Some(event);
+            }
+            processed
+        });
         match new_frame_in_pre_events {
+            Some(ProcessedEvent::FrameNewFrame) | Some(ProcessedEvent::LineNewFrame)
+                if self.trigger_armed =>
+            {
+                debug!("The next frame wasn't triggered, looking for a triggered one");
+                self.advance_till_first_frame_line(Some(events_iter.collect::<Vec<Event>>()))
+            }
             Some(ProcessedEvent::FrameNewFrame) | Some(ProcessedEvent::LineNewFrame) => {
-                return Some(events_iter.collect::<Vec<Event>>())
+                self.trigger_armed = self.uses_trigger;
+                Ok(Some(events_iter.collect::<Vec<Event>>()))
             }
+            Some(ProcessedEvent::TimeWindowNewFrame) => Ok(Some(
+                window_closing_event
+                    .into_iter()
+                    .chain(events_iter)
+                    .collect::<Vec<Event>>(),
+            )),
             Some(ProcessedEvent::PhotonNewFrame) => {
                 self.advance_till_first_frame_line(Some(events_iter.collect::<Vec<Event>>()))
             }
-            Some(_) | None => None,
+            Some(_) | None => Ok(None),
         }
     }
 
@@ -327,42 +1767,91 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
     /// "remember" the last location on the batch that we visited. The method
     /// "find_map" mutates the iterator so that when we re-visit it we start
     /// at the next event in line, which is very efficient.
+    ///
+    /// Errors in reading the stream are returned to the caller, which decides
+    /// whether to retry or abort the acquisition.
+    ///
+    /// When the frame table is enabled, a row describing the completed frame
+    /// is added to it, marked as partial if the stream ended before the frame
+    /// did. The counts of rendered and dropped photons of the frame are kept
+    /// as its diagnostics, and its start time updates the measured frame rate.
+    /// Frames interrupted by an error are left in progress.
     pub fn populate_single_frame(
         &mut self,
         events_after_newframe: Option<Vec<Event>>,
-    ) -> Option<Vec<Event>> {
+    ) -> Result<Option<Vec<Event>>, AcquisitionError> {
+        let start_time = self.snake.get_earliest_frame_time();
+        let end_time = self.snake.get_max_frame_time();
+        self.diagnostics = RenderDiagnostics::default();
+        self.frame_in_progress = true;
+        let remaining = self.populate_frame_events(events_after_newframe)?;
+        self.frame_in_progress = false;
+        let diagnostics = self.diagnostics;
+        if remaining.is_some() || diagnostics.rendered > 0 {
+            if let Some(table) = self.frame_table.as_mut() {
+                let partial = remaining.is_none();
+                if let Err(e) = table.push(start_time, end_time, diagnostics.rendered, partial) {
+                    warn!("Couldn't write to the frame table: {}", e);
+                }
+            }
+            let frame_rate = self.frame_rate_meter.push(start_time);
+            info!(
+                "Rendered {} photons and dropped {} ({:?}), measured frame rate: {:?} Hz",
+                diagnostics.rendered,
+                diagnostics.dropped(),
+                diagnostics,
+                frame_rate
+            );
+            self.last_frame_diagnostics = diagnostics;
+        }
+        Ok(remaining)
+    }
+
+    /// Iterates over the events of a single frame, returning the events that
+    /// follow it
+    fn populate_frame_events(
+        &mut self,
+        events_after_newframe: Option<Vec<Event>>,
+    ) -> Result<Option<Vec<Event>>, AcquisitionError> {
         if let Some(previous_events) = events_after_newframe {
             debug!("Looking for leftover events");
             // Start with the leftover events from the previous frame
-            if let Some(remaining) = self.drain_existing_data(previous_events.iter().copied()) {
-                return Some(remaining);
+            if let Some(remaining) = self.drain_existing_data(previous_events.iter().copied())? {
+                return Ok(Some(remaining));
             }
         };
         // New experiments will start out here, by loading the data and
         // looking for the first line signal
         debug!("Starting a frame loop");
-        while !self.data_stream.as_ref().unwrap().is_finished() {
+        while !self.get_data_stream()?.is_finished() {
             // The following lines cannot be factored to a function due to
             // borrowing - the data stream contains a reference to 'batch', so
             // 'batch' cannot go out of scope
-            let batch = match self.data_stream.as_mut().unwrap().next() {
+            let batch = match self.get_data_stream()?.next() {
                 Some(batch) => match batch {
                     Ok(b) => match b {
                         StreamState::Some(x) => {
                             self.batch_readout_count += 1;
+                            self.record_raw_batch(&x);
                             x
                         }
+                        StreamState::Waiting if self.stop_at_stream_end => {
+                            info!(
+                                "The replayed stream ended after {} batches",
+                                self.batch_readout_count
+                            );
+                            break;
+                        }
                         StreamState::Waiting => {
                             debug!("Waiting on new stream");
                             continue;
                         }
                     },
                     Err(b) => {
-                        error!(
-                            "In populate, batch couldn't be extracted. Num: {}, error: {:?}",
-                            self.batch_readout_count, b
-                        );
-                        continue;
+                        return Err(AcquisitionError::BatchRead {
+                            batch_number: self.batch_readout_count + 1,
+                            source: b,
+                        });
                     }
                 },
                 None => {
@@ -370,22 +1859,132 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
                     break;
                 }
             };
-            let event_stream = match self.get_event_stream(&batch) {
+            let event_stream = match self.get_event_stream(&batch)? {
                 Some(stream) => stream,
                 None => {
                     debug!("Couldn't get event stream");
                     continue;
                 }
             };
+            if self.is_duplicate_batch(&event_stream) {
+                continue;
+            }
+            if !self.check_relevance_of_batch(&event_stream) {
+                debug!("Skipping a batch which ended before the current frame");
+                continue;
+            }
+            if self.is_skipped_out_of_order_batch(&event_stream) {
+                continue;
+            }
             info!("Starting iteration on this stream");
             // Main iteration on events from this current batch
-            if let Some(remaining_events) = self.drain_existing_data(event_stream.iter()) {
+            if let Some(remaining_events) = self.drain_existing_data(event_stream.iter())? {
                 debug!("New frame found in the batch. [x={:?}]", remaining_events);
-                return Some(remaining_events);
+                return Ok(Some(remaining_events));
             }
             info!("Let's loop again, we're still inside a single frame");
         }
-        None
+        Ok(None)
+    }
+
+    /// Archives the batch as it arrived if raw events are recorded
+    fn record_raw_batch(&mut self, batch: &RecordBatch) {
+        if let Some(recorder) = self.raw_recorder.as_mut() {
+            if let Err(e) = recorder.push(batch) {
+                warn!("Couldn't record the raw events: {}", e);
+            }
+        }
+    }
+
+    /// The data stream, or an error if it wasn't acquired yet
+    fn get_data_stream(&mut self) -> Result<&mut StreamReader<R>, AcquisitionError> {
+        self.data_stream
+            .as_mut()
+            .ok_or(AcquisitionError::StreamNotAcquired)
+    }
+
+    /// Processes the event according to the type of its channel
+    fn dispatch_event(&mut self, event: &Event, data_type: DataType) -> ProcessedEvent {
+        match data_type {
+            DataType::Pmt1 => self.handle_pmt_event(event, 0),
+            DataType::Pmt2 => self.handle_pmt_event(event, 1),
+            DataType::Pmt3 => self.handle_pmt_event(event, 2),
+            DataType::Pmt4 => self.handle_pmt_event(event, 3),
+            DataType::Line => self.handle_line_event(event.time),
+            DataType::TagLens => self.snake.new_taglens_period(event.time),
+            DataType::Laser => {
+                self.laser_phase.laser_arrived(event.time);
+                self.snake.new_laser_event(event.time)
+            }
+            DataType::Frame => self.handle_frame_event(event.time),
+            DataType::FrameEnd => self.handle_frame_end_event(event.time),
+            DataType::Trigger => {
+                self.trigger_armed = false;
+                ProcessedEvent::NoOp
+            }
+            DataType::Invalid => {
+                warn!("Unsupported event: {:?}", event);
+                ProcessedEvent::NoOp
+            }
+        }
+    }
+
+    /// Queues the point of a photon which arrived at the given time for
+    /// rendering, unless it fell on a masked voxel or on the flyback.
+    ///
+    /// The point is moved by the affine transform, if there's one, so that
+    /// both the displayed and the serialized voxels are transformed. The
+    /// indices carried by the snake belong to the original voxel, so the
+    /// transformed one is looked up instead.
+    fn add_displayed_point(&mut self, point: Point3<Coordinate>, channel: usize, time: Picosecond) {
+        if is_masked(&point) {
+            self.diagnostics.masked += 1;
+            return;
+        }
+        if point.x.is_nan() || point.y.is_nan() {
+            self.diagnostics.flyback += 1;
+            return;
+        }
+        let (point, index) = match &self.affine {
+            Some(affine) => match apply_affine(affine, &point) {
+                Some(transformed) => (transformed, None),
+                None => {
+                    self.diagnostics.outside += 1;
+                    return;
+                }
+            },
+            None => (point, self.snake.last_voxel_index()),
+        };
+        match index {
+            Some(index) => self
+                .frame_buffers
+                .add_indexed_to_render_queue(point, channel, index),
+            None => self.frame_buffers.add_to_render_queue(point, channel),
+        }
+        self.diagnostics.rendered += 1;
+        let laser_relative_time = self.laser_phase.relative_time(time);
+        self.frame_buffers
+            .add_lifetime(&point, channel, laser_relative_time);
+    }
+
+    /// Renders a photon which was held back until its coincidence arrived.
+    ///
+    /// The search of the snake is rewound to the photon, which is dropped if
+    /// its frame already ended.
+    fn render_released_photon(&mut self, photon: Event) {
+        if photon.time < self.snake.get_earliest_frame_time() {
+            self.diagnostics.stale += 1;
+            return;
+        }
+        if photon.time > self.snake.get_max_frame_time() {
+            self.diagnostics.out_of_frame += 1;
+            return;
+        }
+        self.snake.rewind_to(photon.time);
+        let data_type = self.inputs[photon.channel];
+        if let ProcessedEvent::Displayed(point, channel) = self.dispatch_event(&photon, data_type) {
+            self.add_displayed_point(point, channel, photon.time);
+        }
     }
 
     /// The function called on each event in the processed batch.
@@ -396,43 +1995,131 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
     /// halts only when Some(val) is returned, and the values themselves
     /// only act as identifying helpers.
     fn act_on_single_event(&mut self, event: Event) -> Option<ProcessedEvent> {
+        if !self.is_event_relevant(&event) {
+            trace!("Discarding a stale event: {:?}", event);
+            if self.is_photon(&event) {
+                self.diagnostics.stale += 1;
+            }
+            return None;
+        }
+        if self.time_window_elapsed(event.time) {
+            info!("New frame due to the end of the time window");
+            return Some(ProcessedEvent::TimeWindowNewFrame);
+        }
+        if self.is_photon(&event) {
+            self.feed_live_photon(&event);
+        }
         match self.event_to_coordinate(event) {
             ProcessedEvent::Displayed(point, channel) => {
-                self.frame_buffers.add_to_render_queue(point, channel);
+                self.add_displayed_point(point, channel, event.time);
                 None
             }
             ProcessedEvent::NoOp => None,
             ProcessedEvent::FrameNewFrame => {
                 info!("New frame due to frame signal");
-                Some(ProcessedEvent::FrameNewFrame)
+                self.ends_integration(ProcessedEvent::FrameNewFrame)
             }
             ProcessedEvent::PhotonNewFrame => {
                 info!(
                     "New frame due to photon {} while we had {} lines",
                     event.time, self.line_count
                 );
-                Some(ProcessedEvent::PhotonNewFrame)
+                self.diagnostics.out_of_frame += 1;
+                self.ends_integration(ProcessedEvent::PhotonNewFrame)
             }
             ProcessedEvent::LineNewFrame => {
                 info!("New frame due to line");
-                Some(ProcessedEvent::LineNewFrame)
+                self.ends_integration(ProcessedEvent::LineNewFrame)
             }
-            ProcessedEvent::Error => {
+            ProcessedEvent::TimeWindowNewFrame | ProcessedEvent::Error => {
                 error!("Received an erroneuous event: {:?}", event);
                 None
             }
         }
     }
 
+    /// Checks whether the batch contains any events belonging to the current
+    /// frame.
+    ///
+    /// Events in a batch are sorted, so it's enough to look at the last one.
+    /// The batch is irrelevant only if even that event arrived more than
+    /// `batch_relevance_tolerance` before the start of the current frame.
+    fn check_relevance_of_batch(&self, event_stream: &EventStream) -> bool {
+        match Event::from_stream_idx(event_stream, event_stream.num_rows().saturating_sub(1)) {
+            Some(last_event) => self.is_event_relevant(&last_event),
+            None => false,
+        }
+    }
+
+    /// Whether the batch is a re-sent copy of the previous one, whose photons
+    /// were already counted.
+    ///
+    /// Each batch is compared to the previous one by the times of its first
+    /// and last events and by its number of events.
+    fn is_duplicate_batch(&mut self, event_stream: &EventStream) -> bool {
+        let range = BatchRange::of(event_stream);
+        let duplicate = range.is_some() && range == self.last_batch_range;
+        self.last_batch_range = range;
+        if duplicate {
+            warn!(
+                "Batch {} duplicates the previous one ({:?})",
+                self.batch_readout_count, range
+            );
+        }
+        duplicate && self.skip_duplicate_batches
+    }
+
+    /// Whether the batch starts before the previous batches ended and should
+    /// be skipped.
+    ///
+    /// Events in a batch are sorted, but the batches themselves may arrive out
+    /// of time order, e.g. if the transport reorders them. Such batches are
+    /// logged, and unless they're skipped the snake is rewound to their first
+    /// event, so that their photons are still placed in their voxels.
+    fn is_skipped_out_of_order_batch(&mut self, event_stream: &EventStream) -> bool {
+        let range = match BatchRange::of(event_stream) {
+            Some(range) => range,
+            None => return false,
+        };
+        if let Some(latest) = self.latest_batch_end.filter(|latest| range.first < *latest) {
+            warn!(
+                "Batch {} starts at {} before the previous batches ended at {}",
+                self.batch_readout_count, range.first, latest
+            );
+            match self.out_of_order_batches {
+                OutOfOrderBatches::Rewind => {
+                    self.snake.rewind_to(range.first);
+                    if let Some(planar) = self.planar_snake.as_mut() {
+                        planar.snake.rewind_to(range.first);
+                    }
+                }
+                OutOfOrderBatches::Skip => return true,
+            }
+        }
+        self.latest_batch_end = Some(
+            self.latest_batch_end
+                .map_or(range.last, |latest| latest.max(range.last)),
+        );
+        false
+    }
+
+    /// Whether the event arrived late enough to be a part of the current
+    /// frame, up to the configured tolerance.
+    fn is_event_relevant(&self, event: &Event) -> bool {
+        event.time >= self.snake.get_earliest_frame_time() - self.batch_relevance_tolerance
+    }
+
     /// Returns the event stream only from the first event after the first line
     /// of the frame.
     ///
     /// When it finds the first line it also updates the internal state of this
-    /// object with this knowledge.
+    /// object with this knowledge. If an external trigger is used, lines and
+    /// frames are only accepted after the trigger arrives, and everything
+    /// before it is discarded.
     fn advance_till_first_frame_line(
         &mut self,
         event_stream: Option<Vec<Event>>,
-    ) -> Option<Vec<Event>> {
+    ) -> Result<Option<Vec<Event>>, AcquisitionError> {
         if let Some(previous_events) = event_stream {
             info!("Looking for the first line/frame in the previous event stream");
             let mut previous_events_mut = previous_events.iter();
@@ -440,8 +2127,22 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
             let frame_started = previous_events_mut.find_map(|event| {
                 if event.type_ == 0 {
                     match self.inputs.get(event.channel) {
-                        DataType::Line => Some((DataType::Line, event.time)),
-                        DataType::Frame => Some((DataType::Frame, event.time)),
+                        DataType::Trigger => {
+                            self.trigger_armed = false;
+                            steps += 1;
+                            None
+                        }
+                        DataType::Pmt1 | DataType::Pmt2 | DataType::Pmt3 | DataType::Pmt4 => {
+                            self.diagnostics.out_of_frame += 1;
+                            steps += 1;
+                            None
+                        }
+                        DataType::Line if !self.trigger_armed => Some((DataType::Line, event.time)),
+                        DataType::Frame
+                            if !self.trigger_armed && !self.is_frame_glitch(event.time) =>
+                        {
+                            Some((DataType::Frame, event.time))
+                        }
                         _ => {
                             steps += 1;
                             None
@@ -455,12 +2156,15 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
             if let Some(started) = frame_started {
                 self.lines_vec.clear();
                 self.line_count = 0;
+                self.trigger_armed = self.uses_trigger;
                 match started.0 {
                     DataType::Line => {
                         self.line_count = 1;
                     }
                     DataType::Frame => {
                         self.line_count = 0;
+                        self.frame_gate_open = true;
+                        self.last_frame_signal = Some(started.1);
                     }
                     _ => {}
                 }
@@ -468,134 +2172,229 @@ impl<T: PointDisplay, R: Read> AppState<T, R> {
                     "Found the first line/frame in the previous event stream ({}) after {} steps",
                     started.1, steps
                 );
-                self.snake.update_snake_for_next_frame(started.1);
-                return Some(previous_events_mut.copied().collect::<Vec<Event>>());
+                self.update_snakes_for_next_frame(started.1);
+                if started.0 == DataType::Line {
+                    self.tick_line_clock(0, started.1);
+                }
+                return Ok(Some(previous_events_mut.copied().collect::<Vec<Event>>()));
             };
         }
         // We'll look for the first line\frame until the stream is finished
-        while !self.data_stream.as_ref().unwrap().is_finished() {
+        while !self.get_data_stream()?.is_finished() {
             // The following lines cannot be factored to a function due to
             // borrowing - the data stream contains a reference to 'batch', so
             // 'batch' cannot go out of scope
-            let batch = match self.data_stream.as_mut().unwrap().next() {
+            let batch = match self.get_data_stream()?.next() {
                 Some(batch) => match batch {
                     Ok(b) => match b {
                         StreamState::Some(x) => {
                             self.batch_readout_count += 1;
+                            self.record_raw_batch(&x);
                             x
                         }
+                        StreamState::Waiting if self.stop_at_stream_end => {
+                            info!(
+                                "The replayed stream ended after {} batches",
+                                self.batch_readout_count
+                            );
+                            break;
+                        }
                         StreamState::Waiting => {
                             debug!("Waiting on new stream");
                             continue;
                         }
                     },
                     Err(b) => {
-                        error!(
-                            "Couldn't extract batch from stream ({}): {:?}",
-                            self.batch_readout_count, b
-                        );
-                        continue;
+                        return Err(AcquisitionError::BatchRead {
+                            batch_number: self.batch_readout_count + 1,
+                            source: b,
+                        });
                     }
                 },
                 None => break,
             };
-            let event_stream = match self.get_event_stream(&batch) {
+            let event_stream = match self.get_event_stream(&batch)? {
                 Some(stream) => stream,
                 None => {
                     info!("No stream found, restarting loop");
                     continue;
                 }
             };
+            if self.is_duplicate_batch(&event_stream) {
+                continue;
+            }
             let mut leftover_event_stream = event_stream.iter();
             info!("Looking for the first line/frame in a newly acquired stream");
             let frame_started = leftover_event_stream.find_map(|event| {
-                if event.type_ == 0 {
+                if event.type_ == 0 && event.channel == 0 {
+                    self.drop_disconnected_event(&event);
+                    None
+                } else if event.type_ == 0 {
                     match self.inputs.get(event.channel) {
-                        &DataType::Line => Some((DataType::Line, event.time)),
-                        &DataType::Frame => Some((DataType::Frame, event.time)),
-                        &DataType::Invalid => {
-                            warn!("Out of bounds access: {:?}", event);
+                        &DataType::Trigger => {
+                            self.trigger_armed = false;
                             None
                         }
-                        _ => None,
-                    }
-                } else {
+                        &DataType::Pmt1 | &DataType::Pmt2 | &DataType::Pmt3 | &DataType::Pmt4 => {
+                            self.diagnostics.out_of_frame += 1;
+                            None
+                        }
+                        &DataType::Line if !self.trigger_armed => {
+                            Some((DataType::Line, event.time))
+                        }
+                        &DataType::Frame
+                            if !self.trigger_armed && !self.is_frame_glitch(event.time) =>
+                        {
+                            Some((DataType::Frame, event.time))
+                        }
+                        &DataType::Invalid => {
+                            warn!("Out of bounds access: {:?}", event);
+                            None
+                        }
+                        _ => None,
+                    }
+                } else {
                     warn!("Overflow: {:?}", event);
                     None
                 }
             });
             if let Some(started) = frame_started {
                 self.lines_vec.clear();
+                self.trigger_armed = self.uses_trigger;
                 match started.0 {
-                    DataType::Frame => self.line_count = 0,
+                    DataType::Frame => {
+                        self.line_count = 0;
+                        self.frame_gate_open = true;
+                        self.last_frame_signal = Some(started.1);
+                    }
                     DataType::Line => self.line_count = 1,
                     _ => {}
                 }
                 info!("Found the first line/frame: {}", started.1);
-                self.snake.update_snake_for_next_frame(started.1);
-                return Some(leftover_event_stream.collect::<Vec<Event>>());
+                self.update_snakes_for_next_frame(started.1);
+                if started.0 == DataType::Line {
+                    self.tick_line_clock(0, started.1);
+                }
+                return Ok(Some(leftover_event_stream.collect::<Vec<Event>>()));
             }
         }
-        None
+        Ok(None)
     }
 }
 
-impl<T: PointDisplay> AppState<T, TcpStream> {
+impl<T: PointDisplay, R: Read> AppState<T, R> {
     /// Main loop of the app. Following a bit of a setup, during each frame
     /// loop we advance the photon stream iterator until the first line event,
     /// and then we iterate over all of the photons of that frame, until we
     /// detect the last of the photons or a new frame signal.
     ///
     /// The loop will continue indefinitely - always looking for new events on
-    /// the stream. The two stop conditions are when a user clicks the "X"
-    /// button on the renderer, or when the TimeTagger reports that it has
-    /// finished sending data, probably due to it replaying an older file.
-    pub fn start_inf_acq_loop(&mut self, config: AppConfig) -> Result<()> {
-        self.acquire_stream_filehandle()?;
-        let mut events_after_newframe = self.advance_till_first_frame_line(None);
+    /// the already acquired stream. It stops when `should_stop`, which is
+    /// checked before each frame, returns true, or when the stream ends.
+    pub fn run_acq_loop<F: FnMut(&Channels<T>) -> bool>(
+        &mut self,
+        config: AppConfig,
+        mut should_stop: F,
+    ) -> Result<()> {
+        let mut events_after_newframe = self.advance_till_first_frame_line(None)?;
         let mut frame_number = 1usize;
         let rolling_avg = config.rolling_avg as usize;
         let (sender, receiver) = unbounded();
         let voxel_delta = self.snake.get_voxel_delta_im();
         let z_im_vec = self.snake.get_z_imagespace_planes();
         let handle =
-            std::thread::spawn(move || serialize_data(receiver, voxel_delta, z_im_vec, config.filename));
-        while !self.channels.should_close() {
+            std::thread::spawn(move || serialize_data(receiver, voxel_delta, z_im_vec, config));
+        self.serializer = Some(sender);
+        let mut failed_attempts = 0u32;
+        let mut acq_result = Ok(());
+        while !should_stop(&self.channels) {
             info!("Starting the population of single frame");
-            events_after_newframe = self.populate_single_frame(events_after_newframe);
-            if frame_number % rolling_avg == 0 {
-                match sender.send(self.frame_buffers.clone()) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        error!(
-                            "Couldn't send frame number {} due to an error: {:#?}",
-                            frame_number, e.0
-                        );
+            match self.populate_single_frame(events_after_newframe) {
+                Ok(events) => {
+                    failed_attempts = 0;
+                    events_after_newframe = events;
+                }
+                Err(e) => {
+                    events_after_newframe = None;
+                    if let Err(e) = retry_or_abort(e, &mut failed_attempts) {
+                        acq_result = Err(e);
+                        break;
                     }
-                };
-                self.render();
+                    continue;
+                }
+            }
+            self.write_frame_previews(frame_number);
+            if self.is_warm_up_frame(frame_number) {
+                debug!("Discarding the photons of warm-up frame {}", frame_number);
+                self.frame_buffers.clear_non_rendered_channels();
+            }
+            if frame_number % rolling_avg == 0 {
+                self.frame_centroid = self.frame_buffers.centroid();
+                info!(
+                    "Centroid of frame {}: {:?}",
+                    frame_number, self.frame_centroid
+                );
+                self.serialize_frame(frame_number);
+                self.apply_control_commands();
+                if self.display_paused {
+                    debug!("The display is paused, skipping frame {}", frame_number);
+                    self.frame_buffers.merged_channel().clear();
+                    self.frame_buffers.clear_merged_counts();
+                    self.frame_buffers.clear_non_rendered_channels();
+                } else if self.frame_pacer.should_show(Instant::now()) {
+                    self.render();
+                } else {
+                    debug!(
+                        "Holding the displayed frame, accumulating frame {}",
+                        frame_number
+                    );
+                    self.frame_buffers.clear_non_rendered_channels();
+                }
             };
             frame_number += 1;
             if let None = events_after_newframe {
                 break;
             }
         }
+        self.flush_partial_frame(frame_number);
         info!("Writing to disk");
-        drop(sender);
-        handle.join().unwrap();
+        self.shutdown();
+        handle
+            .join()
+            .map_err(|_| AcquisitionError::SerializationPanicked)?;
+        acq_result?;
         Ok(())
     }
+}
+
+/// Reads the metadata at the head of an IPC stream and verifies that its
+/// schema is the one of the TimeTagger's stream before any batch is read.
+fn open_event_stream<R: Read>(mut reader: R) -> Result<StreamReader<R>> {
+    let meta = read_stream_metadata(&mut reader).context("Can't read stream metadata")?;
+    let stream = StreamReader::new(reader, meta);
+    validate_schema(stream.schema()).map_err(AcquisitionError::InvalidSchema)?;
+    Ok(stream)
+}
+
+impl<T: PointDisplay> AppState<T, TcpStream> {
+    /// Connects to the TimeTagger stream and runs the main loop of the app.
+    ///
+    /// The two stop conditions are when a user clicks the "X" button on the
+    /// renderer, or when the TimeTagger reports that it has finished sending
+    /// data, probably due to it replaying an older file.
+    pub fn start_inf_acq_loop(&mut self, config: AppConfig) -> Result<()> {
+        self.acquire_stream_filehandle()?;
+        self.run_acq_loop(config, |channels| channels.should_close())
+    }
 
     /// Instantiate an IPC StreamReader using an existing file handle.
     fn acquire_stream_filehandle(&mut self) -> Result<()> {
         if self.data_stream.is_none() {
             std::thread::sleep(std::time::Duration::from_secs(9));
             debug!("Finished waiting");
-            let mut reader = TcpStream::connect(&self.data_stream_fh)
+            let reader = TcpStream::connect(&self.data_stream_fh)
                 .context("Can't open stream file, exiting.")?;
-            let meta = read_stream_metadata(&mut reader).context("Can't read stream metadata")?;
-            let stream = StreamReader::new(reader, meta);
-            self.data_stream = Some(stream);
+            self.data_stream = Some(open_event_stream(reader)?);
             debug!("File handle for stream acquired!");
         } else {
             debug!("File handle already acquired.");
@@ -610,11 +2409,9 @@ impl<T: PointDisplay> AppState<T, File> {
     /// Used for testing purposes.
     pub fn acquire_filehandle(&mut self) -> Result<()> {
         if self.data_stream.is_none() {
-            let mut reader =
+            let reader =
                 File::open(&self.data_stream_fh).context("Can't open stream file, exiting.")?;
-            let meta = read_stream_metadata(&mut reader).context("Can't read stream metadata")?;
-            let stream = StreamReader::new(reader, meta);
-            self.data_stream = Some(stream);
+            self.data_stream = Some(open_event_stream(reader)?);
             debug!("File handle for stream acquired!");
         } else {
             debug!("File handle already acquired.");
@@ -628,24 +2425,226 @@ impl<T: PointDisplay> AppState<T, File> {
     /// detect the last of the photons or a new frame signal.
     pub fn start_acq_loop_for(&mut self, steps: usize, rolling_avg: u16) -> Result<()> {
         self.acquire_filehandle()?;
-        let mut events_after_newframe = self.advance_till_first_frame_line(None);
+        let mut events_after_newframe = self.advance_till_first_frame_line(None)?;
         let rolling_avg = rolling_avg as usize;
         let mut frame_number = 1usize;
+        let mut failed_attempts = 0u32;
         for _ in 0..steps {
             debug!("Starting population");
-            events_after_newframe = self.populate_single_frame(events_after_newframe);
+            events_after_newframe = match self.populate_single_frame(events_after_newframe) {
+                Ok(events) => {
+                    failed_attempts = 0;
+                    events
+                }
+                Err(e) => {
+                    retry_or_abort(e, &mut failed_attempts)?;
+                    None
+                }
+            };
             if frame_number % rolling_avg == 0 {
                 debug!("Calling render");
                 self.channels.channel_merge.render();
             };
             frame_number += 1;
-            events_after_newframe = self.advance_till_first_frame_line(events_after_newframe);
+            events_after_newframe = match self.advance_till_first_frame_line(events_after_newframe)
+            {
+                Ok(events) => events,
+                Err(e) => {
+                    retry_or_abort(e, &mut failed_attempts)?;
+                    None
+                }
+            };
         }
         info!("Acq loop done");
         Ok(())
     }
 }
 
+/// The photons accumulated during a single frame, per spectral channel.
+///
+/// Each channel maps the coordinates of a voxel to its aggregated photon
+/// count, as it would be serialized.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub channels: Vec<HashMap<ImageCoor, u8>>,
+}
+
+/// Iterates over the frames of a recorded stream without rendering them.
+///
+/// Each call to `next()` processes the events of a single frame and returns
+/// its accumulated buffers, or the error that stopped the processing.
+pub struct FrameStream<R: Read> {
+    app: AppState<HeadlessChannel, R>,
+    events_after_newframe: Option<Vec<Event>>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> FrameStream<R> {
+    pub fn new(data_stream: StreamReader<R>, config: AppConfig) -> Self {
+        let channels = Channels::new(vec![HeadlessChannel; SUPPORTED_SPECTRAL_CHANNELS + 1]);
+        let mut app = AppState::new(channels, String::new(), config);
+        app.data_stream = Some(data_stream);
+        FrameStream {
+            app,
+            events_after_newframe: None,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Moves the photons of the current frame out of the buffers
+    fn take_frame(&mut self) -> Frame {
+        let frame = Frame {
+            channels: self
+                .app
+                .frame_buffers
+                .iter()
+                .map(|channel| {
+                    channel
+                        .iter()
+                        .map(|(point, voxel)| (*point, voxel.count))
+                        .collect()
+                })
+                .collect(),
+        };
+        self.app.render();
+        frame
+    }
+}
+
+impl FrameStream<File> {
+    /// Opens a recorded stream file
+    pub fn from_file<P: AsRef<Path>>(filename: P, config: AppConfig) -> Result<Self> {
+        let reader = File::open(filename).context("Can't open stream file")?;
+        Ok(FrameStream::new(open_event_stream(reader)?, config))
+    }
+}
+
+impl FrameStream<ProgressReader<File>> {
+    /// Opens a recorded stream file, sending the progress of its replay to
+    /// the given channel as the file is consumed
+    pub fn from_file_with_progress<P: AsRef<Path>>(
+        filename: P,
+        config: AppConfig,
+        progress: Sender<ReplayProgress>,
+    ) -> Result<Self> {
+        let file = File::open(filename).context("Can't open stream file")?;
+        let total_bytes = file
+            .metadata()
+            .context("Can't read stream file size")?
+            .len();
+        let reader = ProgressReader::new(file, total_bytes, progress);
+        Ok(FrameStream::new(open_event_stream(reader)?, config))
+    }
+}
+
+/// The part of a replayed stream file which was already consumed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayProgress {
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+}
+
+impl ReplayProgress {
+    /// The consumed fraction of the file, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            self.bytes_read as f64 / self.total_bytes as f64
+        }
+    }
+}
+
+/// Reads a stream of a known size while reporting how much of it was read.
+///
+/// Progress is reported whenever another percent of the stream was read, and
+/// once it was read in full, so that a progress bar can follow the replay
+/// without being flooded by the many small reads of the decoder.
+pub struct ProgressReader<R: Read> {
+    inner: R,
+    bytes_read: u64,
+    total_bytes: u64,
+    last_reported: u64,
+    progress: Sender<ReplayProgress>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, total_bytes: u64, progress: Sender<ReplayProgress>) -> Self {
+        ProgressReader {
+            inner,
+            bytes_read: 0,
+            total_bytes,
+            last_reported: 0,
+            progress,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes_read += read as u64;
+        let report_every = (self.total_bytes / 100).max(1);
+        let unreported = self.bytes_read - self.last_reported;
+        if read > 0 && (unreported >= report_every || self.bytes_read >= self.total_bytes) {
+            self.last_reported = self.bytes_read;
+            // The replay goes on even if no one follows its progress
+            let _ = self.progress.send(ReplayProgress {
+                bytes_read: self.bytes_read,
+                total_bytes: self.total_bytes,
+            });
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Read> Iterator for FrameStream<R> {
+    type Item = Result<Frame, AcquisitionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            match self.app.advance_till_first_frame_line(None) {
+                Ok(Some(events)) => self.events_after_newframe = Some(events),
+                Ok(None) => {
+                    self.finished = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let events = self.events_after_newframe.take();
+        match self.app.populate_single_frame(events) {
+            Ok(Some(remaining)) => {
+                self.events_after_newframe = Some(remaining);
+                Some(Ok(self.take_frame()))
+            }
+            // The stream ended in the middle of this frame
+            Ok(None) => {
+                self.finished = true;
+                let frame = self.take_frame();
+                if frame.channels.iter().all(|channel| channel.is_empty()) {
+                    None
+                } else {
+                    Some(Ok(frame))
+                }
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
 impl<T: PointDisplay, R: Read> EventStreamHandler for AppState<T, R> {
     /// Convert a raw event tag to a coordinate which will be displayed on the
     /// screen.
@@ -663,35 +2662,52 @@ impl<T: PointDisplay, R: Read> EventStreamHandler for AppState<T, R> {
             return ProcessedEvent::NoOp;
         }
         trace!("Received the following event: {:?}", event);
-        match self.inputs[event.channel] {
-            DataType::Pmt1 => self.snake.time_to_coord_linear(event.time, 0),
-            DataType::Pmt2 => self.snake.time_to_coord_linear(event.time, 1),
-            DataType::Pmt3 => self.snake.time_to_coord_linear(event.time, 2),
-            DataType::Pmt4 => self.snake.time_to_coord_linear(event.time, 3),
-            DataType::Line => self.handle_line_event(event.time),
-            DataType::TagLens => self.snake.new_taglens_period(event.time),
-            DataType::Laser => self.snake.new_laser_event(event.time),
-            DataType::Frame => self.handle_frame_event(event.time),
-            DataType::Invalid => {
-                warn!("Unsupported event: {:?}", event);
-                ProcessedEvent::NoOp
+        if event.channel == 0 {
+            self.drop_disconnected_event(&event);
+            return ProcessedEvent::NoOp;
+        }
+        let data_type = self.inputs[event.channel];
+        let released = match self.coincidence.as_mut() {
+            Some(filter) => {
+                self.diagnostics.uncorrelated += filter.expire(event.time);
+                if !filter.admits(data_type, &event) {
+                    trace!("Holding back a photon until its coincidence: {:?}", event);
+                    return ProcessedEvent::NoOp;
+                }
+                filter.release(data_type, event.time)
             }
+            None => Vec::new(),
+        };
+        for photon in released {
+            self.render_released_photon(photon);
         }
+        self.dispatch_event(&event, data_type)
     }
 
-    /// Generates an EventStream instance from the loaded record batch
+    /// Generates an EventStream instance from the loaded record batch.
+    ///
+    /// Empty batches are skipped, while batches which don't match the
+    /// expected schema return an error.
     #[inline]
-    fn get_event_stream<'b>(&mut self, batch: &'b RecordBatch) -> Option<EventStream<'b>> {
+    fn get_event_stream<'b>(
+        &mut self,
+        batch: &'b RecordBatch,
+    ) -> Result<Option<EventStream<'b>>, AcquisitionError> {
         debug!(
             "When generating the EventStream we received {} rows",
             batch.num_rows()
         );
-        let event_stream = EventStream::from_streamed_batch(batch);
+        let event_stream = EventStream::from_streamed_batch(batch).map_err(|source| {
+            AcquisitionError::InvalidBatch {
+                batch_number: self.batch_readout_count,
+                source,
+            }
+        })?;
         if event_stream.num_rows() == 0 {
             info!("A batch with 0 rows was received");
-            None
+            Ok(None)
         } else {
-            Some(event_stream)
+            Ok(Some(event_stream))
         }
     }
 }
@@ -699,10 +2715,102 @@ impl<T: PointDisplay, R: Read> EventStreamHandler for AppState<T, R> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::configuration::{AppConfigBuilder, Bidirectionality, InputChannel, Period};
+    use crate::configuration::{
+        AppConfigBuilder, Bidirectionality, FillFraction, InputChannel, Period,
+    };
+    use crate::serialize_and_render::mip_preview_path;
     use crate::snakes::*;
+    use arrow2::array::{Array, Int32Array, Int64Array, UInt16Array, UInt32Array, UInt8Array};
+    use arrow2::datatypes::{DataType as ArrowDataType, Field, Schema};
+    use arrow2::io::ipc::write::StreamWriter;
+    use assert_approx_eq::assert_approx_eq;
     use std::env::temp_dir;
-    use std::path::Path;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    fn setup_app_state(config: AppConfig) -> AppState<HeadlessChannel, File> {
+        let channels = Channels::new(vec![HeadlessChannel; SUPPORTED_SPECTRAL_CHANNELS + 1]);
+        AppState::new(channels, String::new(), config)
+    }
+
+    /// Writes a stream whose schema is valid but whose first batch is garbage
+    fn write_malformed_stream(filename: &Path) {
+        let schema = Schema::new(vec![
+            Field::new("type_", ArrowDataType::UInt8, false),
+            Field::new("missed_events", ArrowDataType::UInt16, false),
+            Field::new("channel", ArrowDataType::Int32, false),
+            Field::new("time", ArrowDataType::Int64, false),
+        ]);
+        let mut buffer = Vec::new();
+        // The writer appends an end-of-stream marker when dropped, which we
+        // then replace with a garbage message
+        StreamWriter::try_new(&mut buffer, &schema).unwrap();
+        buffer.truncate(buffer.len() - 8);
+        buffer.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        buffer.extend_from_slice(&8i32.to_le_bytes());
+        buffer.extend_from_slice(&[0xab; 8]);
+        File::create(filename).unwrap().write_all(&buffer).unwrap();
+    }
+
+    /// Writes a valid batch of the given events followed by enough garbage
+    /// messages to abort the acquisition, like a stream which broke down in
+    /// the middle of a frame
+    fn write_stream_broken_after(filename: &Path, events: &[Event]) {
+        write_event_stream(filename, events);
+        let mut bytes = std::fs::read(filename).unwrap();
+        bytes.truncate(bytes.len() - 8);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            bytes.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+            bytes.extend_from_slice(&8i32.to_le_bytes());
+            bytes.extend_from_slice(&[0xab; 8]);
+        }
+        std::fs::write(filename, bytes).unwrap();
+    }
+
+    /// Writes a valid stream with a single batch of the given events
+    fn write_event_stream(filename: &Path, events: &[Event]) {
+        write_event_batches(filename, &[events]);
+    }
+
+    /// Writes a valid stream with a batch for each of the given event lists
+    fn write_event_batches(filename: &Path, batches: &[&[Event]]) {
+        let schema = Schema::new(vec![
+            Field::new("type_", ArrowDataType::UInt8, false),
+            Field::new("missed_events", ArrowDataType::UInt16, false),
+            Field::new("channel", ArrowDataType::Int32, false),
+            Field::new("time", ArrowDataType::Int64, false),
+        ]);
+        let mut writer = StreamWriter::try_new(File::create(filename).unwrap(), &schema).unwrap();
+        for events in batches {
+            let columns: Vec<Arc<dyn Array>> = vec![
+                Arc::new(UInt8Array::from_slice(
+                    events.iter().map(|e| e.type_).collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt16Array::from_slice(
+                    events.iter().map(|e| e.missed_event).collect::<Vec<_>>(),
+                )),
+                Arc::new(Int32Array::from_slice(
+                    events.iter().map(|e| e.channel).collect::<Vec<_>>(),
+                )),
+                Arc::new(Int64Array::from_slice(
+                    events.iter().map(|e| e.time).collect::<Vec<_>>(),
+                )),
+            ];
+            let batch = RecordBatch::try_new(Arc::new(schema.clone()), columns).unwrap();
+            writer.write(&batch).unwrap();
+        }
+    }
+
+    /// Arrays of photon events arriving in the first PMT channel
+    fn photon_arrays(times: Vec<i64>) -> (UInt8Array, UInt16Array, Int32Array, Int64Array) {
+        let len = times.len();
+        (
+            UInt8Array::from_slice(vec![0; len]),
+            UInt16Array::from_slice(vec![0; len]),
+            Int32Array::from_slice(vec![-1; len]),
+            Int64Array::from_slice(times),
+        )
+    }
 
     fn setup_default_config() -> AppConfigBuilder {
         AppConfigBuilder::default()
@@ -713,7 +2821,7 @@ mod tests {
             .with_scan_period(Period::from_freq(7926.17))
             .with_tag_period(Period::from_freq(189800))
             .with_bidir(Bidirectionality::Bidir)
-            .with_fill_fraction(71.3)
+            .with_fill_fraction(FillFraction::from_percent(71.3).unwrap())
             .with_frame_dead_time(8 * *Period::from_freq(7926.17))
             .with_pmt1_ch(InputChannel::new(-1, 0.0))
             .with_pmt2_ch(InputChannel::new(0, 0.0))
@@ -727,6 +2835,15 @@ mod tests {
             .clone()
     }
 
+    /// The default configuration reduced to a single plane of 10 by 10 voxels
+    fn setup_small_volume() -> AppConfigBuilder {
+        setup_default_config()
+            .with_planes(1)
+            .with_rows(10)
+            .with_columns(10)
+            .clone()
+    }
+
     fn create_record_batch() -> RecordBatch {
         todo!()
     }
@@ -750,4 +2867,1574 @@ mod tests {
         // assert_eq!(truth_recordbatch, streamed_data);
         
     }
+
+    #[test]
+    fn batch_of_stale_events_is_irrelevant() {
+        let mut app = setup_app_state(setup_default_config().with_planes(1).build());
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let (type_, missed, channel, time) = photon_arrays(vec![100, 200, 300]);
+        let stream = EventStream::new(&type_, &missed, &channel, &time);
+        assert!(!app.check_relevance_of_batch(&stream));
+    }
+
+    #[test]
+    fn batch_relevance_respects_tolerance() {
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_batch_relevance_tolerance(1_000)
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let (type_, missed, channel, time) = photon_arrays(vec![100, 999_999_500]);
+        let stream = EventStream::new(&type_, &missed, &channel, &time);
+        assert!(app.check_relevance_of_batch(&stream));
+    }
+
+    #[test]
+    fn stale_events_in_relevant_batch_are_discarded() {
+        let config = setup_small_volume().build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let (type_, missed, channel, time) =
+            photon_arrays(vec![100, 200, 1_000_001_000, 1_010_000_000]);
+        let stream = EventStream::new(&type_, &missed, &channel, &time);
+        assert!(app.check_relevance_of_batch(&stream));
+        assert!(app.drain_existing_data(stream.iter()).unwrap().is_none());
+        assert_eq!(app.frame_buffers.len(), 2);
+    }
+
+    #[test]
+    fn replay_stops_after_the_last_batch_of_its_stream() {
+        let mut stream = temp_dir();
+        stream.push("rpysight_finite_replay.arrow_stream");
+        let mut data = temp_dir();
+        data.push("rpysight_finite_replay_data.arrow_stream");
+        let config = setup_small_volume()
+            .with_replay_existing(true)
+            .with_filename(data.to_string_lossy().into_owned())
+            .build();
+        let line_period = *config.line_period();
+        let frame_period = 10 * line_period + config.frame_dead_time;
+        let frames: Vec<Vec<Event>> = (0..3)
+            .map(|frame| {
+                (0..10)
+                    .flat_map(|line| {
+                        let start = frame * frame_period + line * line_period;
+                        [
+                            Event::new(0, 0, 2, start),
+                            Event::new(0, 0, -1, start + line_period / 4),
+                        ]
+                    })
+                    .collect()
+            })
+            .collect();
+        let batches: Vec<&[Event]> = frames.iter().map(|frame| frame.as_slice()).collect();
+        write_event_batches(&stream, &batches);
+        // Replayed streams may end without an end-of-stream marker
+        let mut bytes = std::fs::read(&stream).unwrap();
+        bytes.truncate(bytes.len() - 8);
+        std::fs::write(&stream, bytes).unwrap();
+        let (done, finished) = bounded(1);
+        let data_stream_fh = stream.to_string_lossy().into_owned();
+        std::thread::spawn(move || {
+            let mut app = setup_app_state(config.clone());
+            app.data_stream_fh = data_stream_fh;
+            app.acquire_filehandle().unwrap();
+            let result = app.run_acq_loop(config, |_| false);
+            let _ = done.send((result.is_ok(), app.batch_readout_count));
+        });
+        let finished = finished.recv_timeout(Duration::from_secs(30));
+        assert_eq!(finished, Ok((true, 3)));
+        std::fs::remove_file(stream).unwrap();
+        let _ = std::fs::remove_file(data);
+    }
+
+    #[test]
+    fn malformed_batch_returns_error() {
+        let mut filename = temp_dir();
+        filename.push("rpysight_malformed_batch.arrow_stream");
+        write_malformed_stream(&filename);
+        let mut app = setup_app_state(setup_default_config().build());
+        app.data_stream_fh = filename.to_str().unwrap().to_string();
+        app.acquire_filehandle().unwrap();
+        let result = app.populate_single_frame(None);
+        assert!(matches!(
+            result,
+            Err(AcquisitionError::BatchRead {
+                batch_number: 1,
+                ..
+            })
+        ));
+    }
+
+    /// Opens a stream with the given schema and no batches, returning the
+    /// error of the schema validation
+    fn open_stream_with_schema(filename: &str, fields: Vec<Field>) -> AcquisitionError {
+        let mut path = temp_dir();
+        path.push(filename);
+        StreamWriter::try_new(File::create(&path).unwrap(), &Schema::new(fields)).unwrap();
+        let mut app = setup_app_state(setup_default_config().build());
+        app.data_stream_fh = path.to_str().unwrap().to_string();
+        app.acquire_filehandle()
+            .unwrap_err()
+            .downcast::<AcquisitionError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn stream_with_mismatched_schema_is_rejected_on_open() {
+        let reordered = open_stream_with_schema(
+            "rpysight_reordered_schema.arrow_stream",
+            vec![
+                Field::new("missed_events", ArrowDataType::UInt16, false),
+                Field::new("type_", ArrowDataType::UInt8, false),
+                Field::new("channel", ArrowDataType::Int32, false),
+                Field::new("time", ArrowDataType::Int64, false),
+            ],
+        );
+        assert!(matches!(
+            reordered,
+            AcquisitionError::InvalidSchema(EventStreamError::WrongColumnName { index: 0, .. })
+        ));
+        let renamed = open_stream_with_schema(
+            "rpysight_renamed_schema.arrow_stream",
+            vec![
+                Field::new("type_", ArrowDataType::UInt8, false),
+                Field::new("missed_events", ArrowDataType::UInt16, false),
+                Field::new("time", ArrowDataType::Int32, false),
+                Field::new("channel", ArrowDataType::Int64, false),
+            ],
+        );
+        assert_eq!(
+            renamed.to_string(),
+            "The schema of the stream doesn't match the expected one \
+             (`Expected column 2 to be named 'channel', found 'time'`)"
+        );
+    }
+
+    #[test]
+    fn populate_without_stream_returns_error() {
+        let mut app = setup_app_state(setup_default_config().build());
+        let result = app.populate_single_frame(None);
+        assert!(matches!(result, Err(AcquisitionError::StreamNotAcquired)));
+    }
+
+    #[test]
+    fn acq_loop_returns_error_on_malformed_stream() {
+        let mut filename = temp_dir();
+        filename.push("rpysight_malformed_loop.arrow_stream");
+        write_malformed_stream(&filename);
+        let mut app = setup_app_state(setup_default_config().build());
+        app.data_stream_fh = filename.to_str().unwrap().to_string();
+        assert!(app.start_acq_loop_for(1, 1).is_err());
+    }
+
+    #[test]
+    fn retry_or_abort_stops_after_max_failures() {
+        let mut failed_attempts = 0u32;
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            let error = AcquisitionError::BatchRead {
+                batch_number: 1,
+                source: ArrowError::Ipc("test".to_string()),
+            };
+            assert!(retry_or_abort(error, &mut failed_attempts).is_ok());
+        }
+        let error = AcquisitionError::BatchRead {
+            batch_number: 1,
+            source: ArrowError::Ipc("test".to_string()),
+        };
+        assert!(retry_or_abort(error, &mut failed_attempts).is_err());
+    }
+
+    #[test]
+    fn unrecoverable_error_aborts_immediately() {
+        let mut failed_attempts = 0u32;
+        assert!(retry_or_abort(AcquisitionError::StreamNotAcquired, &mut failed_attempts).is_err());
+    }
+
+    #[test]
+    fn disconnected_channel_events_are_counted_and_dropped() {
+        let config = setup_default_config().with_planes(1).build();
+        let mut app = setup_app_state(config);
+        for time in 0..100 {
+            let event = Event::new(0, 0, 0, 1_000_000_000 + time);
+            assert_eq!(app.event_to_coordinate(event), ProcessedEvent::NoOp);
+        }
+        assert_eq!(app.disconnected_events(), 100);
+        assert_eq!(app.diagnostics, RenderDiagnostics::default());
+        assert_eq!(app.frame_buffers.len(), 0);
+    }
+
+    /// Feeds a gate opening at 1 ms, a photon inside it, the gate closing and
+    /// a photon after it, returning the number of rendered voxels
+    fn render_photons_around_frame_gate(gated_frame: bool) -> usize {
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_gated_frame(gated_frame)
+            .build();
+        let mut app = setup_app_state(config);
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -4, 1_020_000_000),
+            Event::new(0, 0, -1, 1_030_000_000),
+        ];
+        for event in events {
+            app.act_on_single_event(event);
+        }
+        app.frame_buffers.len()
+    }
+
+    #[test]
+    fn gated_frame_drops_photons_outside_gate() {
+        assert_eq!(render_photons_around_frame_gate(true), 1);
+    }
+
+    #[test]
+    fn ungated_frame_keeps_photons_after_falling_edge() {
+        assert_eq!(render_photons_around_frame_gate(false), 2);
+    }
+
+    #[test]
+    fn extra_line_within_slack_forces_frame() {
+        let config = setup_small_volume().with_max_extra_lines(1).build();
+        let mut app = setup_app_state(config);
+        // The lines are spurious ones, all arriving while the first frame is
+        // still being scanned
+        for line in 0..11 {
+            assert_eq!(app.handle_line_event(line * 1_000), ProcessedEvent::NoOp);
+        }
+        assert_eq!(app.handle_line_event(11_000), ProcessedEvent::LineNewFrame);
+        assert_eq!(app.line_count, 0);
+    }
+
+    #[test]
+    fn slack_doesnt_delay_the_next_frame_of_a_clean_stream() {
+        let config = setup_small_volume().with_max_extra_lines(2).build();
+        let line_period = *config.line_period();
+        let frame_period = 10 * line_period + config.frame_dead_time;
+        let mut app = setup_app_state(config);
+        for line in 0..10 {
+            assert_eq!(
+                app.handle_line_event(line * line_period),
+                ProcessedEvent::NoOp
+            );
+        }
+        // The first line of the next frame arrives after the end of the
+        // current one, so it isn't taken for an extra line
+        assert_eq!(
+            app.handle_line_event(frame_period),
+            ProcessedEvent::LineNewFrame
+        );
+        assert_eq!(app.snake.get_earliest_frame_time(), frame_period);
+    }
+
+    #[test]
+    fn line_clock_ticks_once_per_line_with_its_index_in_the_frame() {
+        let config = setup_small_volume().build();
+        let ticks = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&ticks);
+        let mut app = setup_app_state(config)
+            .with_line_clock(move |line, time| recorded.borrow_mut().push((line, time)));
+        for line in 0..11 {
+            app.handle_line_event(line * 1_000);
+        }
+        let mut expected: Vec<_> = (0..10)
+            .chain([0])
+            .zip((0..11).map(|line| line * 1_000))
+            .collect();
+        assert_eq!(*ticks.borrow(), expected);
+        // The line following a frame signal is the first of its frame
+        app.handle_frame_event(20_000);
+        app.handle_line_event(21_000);
+        expected.push((0, 21_000));
+        assert_eq!(*ticks.borrow(), expected);
+    }
+
+    #[test]
+    fn line_clock_follows_the_matched_rows_past_a_missing_line() {
+        let config = setup_small_volume()
+            .with_line_matching(LineMatching::NearestExpected)
+            .build();
+        let line_period = *config.line_period();
+        let ticks = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&ticks);
+        let mut app = setup_app_state(config)
+            .with_line_clock(move |line, _| recorded.borrow_mut().push(line));
+        app.handle_frame_event(1_000_000_000);
+        for line in [0, 1, 3, 4] {
+            app.handle_line_event(1_000_000_000 + line * line_period);
+        }
+        assert_eq!(*ticks.borrow(), vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn no_slack_starts_frame_after_expected_lines() {
+        let config = setup_small_volume().build();
+        let mut app = setup_app_state(config);
+        for line in 0..10 {
+            assert_eq!(app.handle_line_event(line * 1_000), ProcessedEvent::NoOp);
+        }
+        assert_eq!(app.handle_line_event(10_000), ProcessedEvent::LineNewFrame);
+    }
+
+    #[test]
+    fn frame_signals_closer_than_the_minimal_interval_start_a_single_frame() {
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_min_frame_interval(1_000_000)
+            .build();
+        let mut app = setup_app_state(config);
+        assert_eq!(app.handle_frame_event(0), ProcessedEvent::FrameNewFrame);
+        let frame_index = app.current_frame_index();
+        assert_eq!(app.handle_frame_event(5_000), ProcessedEvent::NoOp);
+        assert_eq!(app.current_frame_index(), frame_index);
+        assert_eq!(
+            app.handle_frame_event(2_000_000),
+            ProcessedEvent::FrameNewFrame
+        );
+        assert_eq!(app.current_frame_index(), frame_index + 1);
+    }
+
+    #[test]
+    fn frame_glitches_after_the_first_frame_signal_are_ignored() {
+        let mut filename = temp_dir();
+        filename.push("test_frame_glitches_after_the_first_frame.arrow_stream");
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, 4, 1_000_005_000),
+            Event::new(0, 0, -1, 1_001_000_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, 4, 2_000_005_000),
+            Event::new(0, 0, -1, 2_001_000_000),
+        ];
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_min_frame_interval(1_000_000)
+            .build();
+        let frames: Vec<Frame> = FrameStream::from_file(&filename, config)
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        std::fs::remove_file(&filename).unwrap();
+        let photons: Vec<usize> = frames.iter().map(|frame| frame.channels[0].len()).collect();
+        assert_eq!(photons, vec![2, 1]);
+    }
+
+    #[test]
+    fn frame_progress_advances_with_lines_and_resets_on_new_frame() {
+        let config = setup_small_volume().build();
+        let mut app = setup_app_state(config);
+        assert_eq!(app.handle_frame_event(0), ProcessedEvent::FrameNewFrame);
+        assert_eq!(app.current_frame_index(), 0);
+        assert_eq!(app.frame_progress(), 0.0);
+        let mut previous = 0.0;
+        for line in 0..10 {
+            app.handle_line_event(line * 1_000);
+            assert!(app.frame_progress() > previous);
+            previous = app.frame_progress();
+        }
+        assert_eq!(app.frame_progress(), 1.0);
+        assert_eq!(app.handle_line_event(10_000), ProcessedEvent::LineNewFrame);
+        assert_eq!(app.current_frame_index(), 1);
+        assert_eq!(app.frame_progress(), 0.0);
+    }
+
+    /// The row of a photon arriving in the third row of the frame following
+    /// a frame with a missing line event
+    fn row_after_missing_line(line_matching: LineMatching) -> u32 {
+        let config = setup_small_volume()
+            .with_line_matching(line_matching)
+            .build();
+        let line_period = *config.line_period();
+        let next_frame = 1_000_000_000 + 10 * line_period + config.frame_dead_time;
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        app.line_count = 1;
+        for line in (1..10).filter(|line| *line != 3) {
+            assert_eq!(
+                app.handle_line_event(1_000_000_000 + line * line_period),
+                ProcessedEvent::NoOp
+            );
+        }
+        for line in 0..3 {
+            app.handle_line_event(next_frame + line * line_period);
+        }
+        let photon = next_frame + 2 * line_period + line_period / 2;
+        let grid = app.snake.get_voxel_delta_im();
+        match app.snake.time_to_coord_linear(photon, 0) {
+            ProcessedEvent::Displayed(point, _) => grid.voxel_index(&point).unwrap().0,
+            other => panic!("The photon wasn't displayed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nearest_line_matching_recovers_from_missing_line() {
+        assert_eq!(row_after_missing_line(LineMatching::NearestExpected), 2);
+        assert_ne!(row_after_missing_line(LineMatching::Counted), 2);
+    }
+
+    #[test]
+    fn planar_channels_ignore_the_tag_lens() {
+        let config = setup_default_config()
+            .with_rows(10)
+            .with_columns(10)
+            .with_planes(10)
+            .with_planar_channels(vec![0])
+            .build();
+        let photon = 1_000_000_000 + *config.line_period() / 2 + *config.tag_period / 4;
+        let mut app = setup_app_state(config);
+        app.update_snakes_for_next_frame(1_000_000_000);
+        app.snake.new_taglens_period(1_000_000_000);
+        let planar = match app.handle_photon_event(photon, 0) {
+            ProcessedEvent::Displayed(point, _) => point,
+            other => panic!("The planar photon wasn't displayed: {:?}", other),
+        };
+        let volumetric = match app.handle_photon_event(photon, 1) {
+            ProcessedEvent::Displayed(point, _) => point,
+            other => panic!("The volumetric photon wasn't displayed: {:?}", other),
+        };
+        assert!(planar.z.abs() < 0.1);
+        assert_eq!((planar.x, planar.y), (volumetric.x, volumetric.y));
+        assert_ne!(volumetric.z, planar.z);
+        assert!((-0.5..=0.5).contains(&*volumetric.z));
+    }
+
+    #[test]
+    fn photons_without_line_events_report_sync_loss() {
+        let config = setup_small_volume()
+            .with_sync_loss_lines(5)
+            .with_blank_on_sync_loss(true)
+            .build();
+        let timeout = config.frame_dead_time + 5 * *config.line_period();
+        let mut app = setup_app_state(config);
+        app.handle_line_event(1_000);
+        app.handle_photon_event(1_000 + timeout, 0);
+        assert_eq!(app.sync_status(), SyncStatus::Synced);
+        assert_eq!(app.diagnostics.unsynced, 0);
+        for photon in 1..=3 {
+            app.handle_photon_event(1_000 + timeout + photon, 0);
+        }
+        assert_eq!(app.sync_status(), SyncStatus::SyncLost { last_line: 1_000 });
+        assert_eq!(app.diagnostics.unsynced, 3);
+        app.handle_line_event(2_000 + timeout);
+        assert_eq!(app.sync_status(), SyncStatus::Synced);
+    }
+
+    #[test]
+    fn laser_markers_reset_the_phase_of_strobed_photons() {
+        let config = setup_default_config()
+            .with_laser_ch(InputChannel::new(5, 0.0))
+            .with_laser_period(Period { period: 1_000 })
+            .with_strobe_laser_marker(true)
+            .build();
+        let mut app = setup_app_state(config);
+        assert_eq!(app.laser_phase.pulse_index(5_250), None);
+        app.event_to_coordinate(Event::new(0, 0, 5, 5_000));
+        assert_eq!(app.laser_phase.relative_time(5_250), 250);
+        assert_eq!(app.laser_phase.relative_time(8_250), 250);
+        assert_eq!(app.laser_phase.pulse_index(8_250), Some(3));
+        app.event_to_coordinate(Event::new(0, 0, 5, 8_100));
+        assert_eq!(app.laser_phase.relative_time(8_250), 150);
+        assert_eq!(app.laser_phase.pulse_index(8_250), Some(0));
+        let standard = LaserPhase {
+            reference: Some(5_000),
+            strobe: false,
+            ..app.laser_phase
+        };
+        assert_eq!(standard.relative_time(8_250), 3_250);
+        assert_eq!(standard.pulse_index(8_250), None);
+    }
+
+    #[test]
+    fn overflowing_line_count_forces_frame() {
+        let config = setup_small_volume().build();
+        let mut app = setup_app_state(config);
+        app.line_count = 12;
+        assert_eq!(app.handle_line_event(1_000), ProcessedEvent::LineNewFrame);
+    }
+
+    #[test]
+    fn frame_pacer_holds_fast_frames() {
+        let mut pacer = FramePacer::new(Duration::from_millis(100));
+        let start = Instant::now();
+        assert!(pacer.should_show(start));
+        assert!(!pacer.should_show(start + Duration::from_millis(30)));
+        assert!(!pacer.should_show(start + Duration::from_millis(99)));
+        assert!(pacer.should_show(start + Duration::from_millis(100)));
+        assert!(!pacer.should_show(start + Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn frame_pacer_without_minimum_shows_all_frames() {
+        let mut pacer = FramePacer::new(Duration::from_millis(0));
+        let start = Instant::now();
+        assert!(pacer.should_show(start));
+        assert!(pacer.should_show(start));
+        assert!(pacer.should_show(start + Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn measured_frame_rate_is_smoothed() {
+        let mut meter = FrameRateMeter::new(0.1);
+        // Frame intervals of 32 ms on average with a jitter of 4 ms
+        let jitter = [4, -4, 2, -2, 4, -4, 0, 3, -3, 0];
+        let mut frame_start: Picosecond = 0;
+        let mut raw = Vec::new();
+        let mut smoothed = Vec::new();
+        assert_eq!(meter.push(frame_start), None);
+        for step in 0..200usize {
+            let interval = (32 + jitter[step % jitter.len()]) * 1_000_000_000;
+            frame_start += interval;
+            raw.push(1e12 / interval as f32);
+            smoothed.push(meter.push(frame_start).unwrap());
+        }
+        let mean = raw.iter().sum::<f32>() / raw.len() as f32;
+        let settled = &smoothed[100..];
+        assert!(settled.iter().all(|rate| (rate - mean).abs() < 0.5));
+        let spread = |rates: &[f32]| {
+            rates.iter().copied().fold(f32::MIN, f32::max)
+                - rates.iter().copied().fold(f32::MAX, f32::min)
+        };
+        assert!(spread(settled) < spread(&raw[100..]) / 4.0);
+        assert_eq!(meter.rate(), smoothed.last().copied());
+    }
+
+    #[test]
+    fn frame_stream_iterates_over_recorded_frames() {
+        let mut filename = temp_dir();
+        filename.push("test_frame_stream.arrow_stream");
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+            Event::new(0, 0, -1, 2_010_000_000),
+        ];
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .build();
+        let frames: Vec<Frame> = FrameStream::from_file(&filename, config)
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].channels[0].len(), 3);
+        assert_eq!(frames[1].channels[0].len(), 2);
+        assert!(frames[0].channels[1..].iter().all(|ch| ch.is_empty()));
+    }
+
+    #[test]
+    fn replay_progress_reaches_the_end_of_the_file() {
+        let mut filename = temp_dir();
+        filename.push("test_replay_progress.arrow_stream");
+        let events: Vec<Event> = (0..3)
+            .flat_map(|frame| {
+                let start = 1_000_000_000 * (frame + 1);
+                vec![
+                    Event::new(0, 0, 4, start),
+                    Event::new(0, 0, -1, start + 1_000),
+                    Event::new(0, 0, -1, start + 10_000_000),
+                ]
+            })
+            .collect();
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .build();
+        let (sender, receiver) = unbounded();
+        let frames = FrameStream::from_file_with_progress(&filename, config, sender)
+            .unwrap()
+            .count();
+        assert_eq!(frames, 3);
+        let fractions: Vec<f64> = receiver.try_iter().map(|p| p.fraction()).collect();
+        assert!(!fractions.is_empty());
+        assert!(fractions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((fractions[fractions.len() - 1] - 1.0).abs() < 1e-9);
+    }
+
+    /// The frames of a stream made of the given batches
+    fn frames_of_batches(
+        name: &str,
+        batches: &[&[Event]],
+        config: &mut AppConfigBuilder,
+    ) -> Vec<Frame> {
+        let mut filename = temp_dir();
+        filename.push(name);
+        write_event_batches(&filename, batches);
+        let config = config
+            .with_planes(1)
+            .with_rows(10)
+            .with_columns(10)
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .build();
+        let frames = FrameStream::from_file(&filename, config)
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        std::fs::remove_file(&filename).unwrap();
+        frames
+    }
+
+    /// The photon counts of the frames in a stream made of the given batches
+    fn frame_counts_of_batches(name: &str, batches: &[&[Event]], skip: bool) -> Vec<Vec<u32>> {
+        frames_of_batches(
+            name,
+            batches,
+            setup_default_config().with_skip_duplicate_batches(skip),
+        )
+        .iter()
+        .map(|frame| {
+            frame
+                .channels
+                .iter()
+                .map(|ch| ch.values().map(|count| *count as u32).sum())
+                .collect()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn duplicated_batch_is_skipped() {
+        let first = [
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+        ];
+        let second = [
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, -1, 1_020_000_000),
+        ];
+        let third = [
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+        ];
+        let expected = frame_counts_of_batches(
+            "test_unique_batches.arrow_stream",
+            &[&first, &second, &third],
+            true,
+        );
+        let deduplicated = frame_counts_of_batches(
+            "test_skipped_duplicate_batch.arrow_stream",
+            &[&first, &second, &second, &third],
+            true,
+        );
+        let doubled = frame_counts_of_batches(
+            "test_counted_duplicate_batch.arrow_stream",
+            &[&first, &second, &second, &third],
+            false,
+        );
+        assert_eq!(expected.len(), 2);
+        assert_eq!(deduplicated, expected);
+        assert!(doubled[0][0] > expected[0][0]);
+    }
+
+    #[test]
+    fn photons_of_batches_out_of_time_order_are_placed_in_their_voxels() {
+        let start = [
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_030_000_000),
+        ];
+        let earlier = [
+            Event::new(0, 0, -1, 1_100_000_000),
+            Event::new(0, 0, -1, 1_200_000_000),
+        ];
+        let later = [
+            Event::new(0, 0, -1, 1_300_000_000),
+            Event::new(0, 0, -1, 1_400_000_000),
+        ];
+        let end = [
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+        ];
+        let ordered = frames_of_batches(
+            "test_ordered_batches.arrow_stream",
+            &[&start, &earlier, &later, &end],
+            &mut setup_default_config(),
+        );
+        let rewound = frames_of_batches(
+            "test_rewound_batches.arrow_stream",
+            &[&start, &later, &earlier, &end],
+            &mut setup_default_config(),
+        );
+        let skipped = frames_of_batches(
+            "test_skipped_batches.arrow_stream",
+            &[&start, &later, &earlier, &end],
+            setup_default_config().with_out_of_order_batches(OutOfOrderBatches::Skip),
+        );
+        assert_eq!(ordered.len(), 2);
+        assert_eq!(rewound, ordered);
+        assert!(skipped[0].channels[0].len() < ordered[0].channels[0].len());
+    }
+
+    #[test]
+    fn rows_without_line_events_follow_the_timing_within_the_frame() {
+        let mut builder = setup_small_volume();
+        builder.with_line_ch(InputChannel::new(0, 0.0));
+        let line_period = *builder.build().line_period();
+        let frame_start = 1_000_000_000;
+        let row_middle = |row: i64| frame_start + row * line_period + line_period / 2;
+        let first = [
+            Event::new(0, 0, 4, frame_start),
+            Event::new(0, 0, -1, row_middle(0)),
+            Event::new(0, 0, -1, row_middle(3)),
+        ];
+        let second = [
+            Event::new(0, 0, -1, row_middle(7)),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+        ];
+        let frames = frames_of_batches(
+            "test_line_less_rows.arrow_stream",
+            &[&first, &second],
+            &mut builder,
+        );
+        let (row_mapping, _) =
+            VoxelDelta::<Coordinate>::from_config(&builder.build()).map_coord_to_index();
+        let mut rows: Vec<u32> = frames[0].channels[0]
+            .keys()
+            .map(|point| row_mapping[&point.x])
+            .collect();
+        rows.sort_unstable();
+        assert_eq!(rows, vec![0, 3, 7]);
+    }
+
+    #[test]
+    fn time_window_splits_a_single_frame() {
+        let mut filename = temp_dir();
+        filename.push("test_time_window_split.arrow_stream");
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, -1, 1_030_000_000),
+        ];
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_integration_window(IntegrationWindow::Time(15_000_000))
+            .build();
+        let frames: Vec<Frame> = FrameStream::from_file(&filename, config)
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].channels[0].len(), 2);
+        assert_eq!(frames[1].channels[0].len(), 2);
+    }
+
+    #[test]
+    fn time_window_spans_frame_signals() {
+        let mut filename = temp_dir();
+        filename.push("test_time_window_span.arrow_stream");
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_020_000_000),
+        ];
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_integration_window(IntegrationWindow::Time(5_000_000_000))
+            .build();
+        let frames: Vec<Frame> = FrameStream::from_file(&filename, config)
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].channels[0].len(), 3);
+    }
+
+    #[test]
+    fn events_before_the_trigger_are_discarded() {
+        let mut filename = temp_dir();
+        filename.push("test_external_trigger.arrow_stream");
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, 2, 1_000_000_500),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, 5, 1_500_000_000),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+            Event::new(0, 0, -1, 2_010_000_000),
+        ];
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_trigger_ch(InputChannel::new(5, 0.0))
+            .build();
+        let frames: Vec<Frame> = FrameStream::from_file(&filename, config)
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].channels[0].len(), 2);
+    }
+
+    #[test]
+    fn frame_table_has_a_row_per_frame() {
+        let mut filename = temp_dir();
+        filename.push("test_frame_table.arrow_stream");
+        let events = vec![
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+            Event::new(0, 0, -1, 2_010_000_000),
+        ];
+        write_event_stream(&filename, &events);
+        let config = setup_small_volume()
+            .with_filename(filename.to_string_lossy().into_owned())
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .with_frame_table(true)
+            .build();
+        let frames = FrameStream::from_file(&filename, config).unwrap().count();
+        assert_eq!(frames, 2);
+        let table_path = frame_table_path(&filename.to_string_lossy());
+        let table = std::fs::read_to_string(table_path).unwrap();
+        let rows: Vec<Vec<i64>> = table
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], 0);
+        assert_eq!(rows[1][0], 1);
+        assert_eq!(rows[0][1], 1_000_000_000);
+        assert_eq!(rows[1][1], 2_000_000_000);
+        assert!(rows[0][2] > rows[0][1]);
+        assert!(rows[1][1] > rows[0][2]);
+        assert_eq!(rows[0][3], 3);
+        assert_eq!(rows[1][3], 2);
+        assert_eq!(rows[0][4], 0);
+        assert_eq!(rows[1][4], 1);
+    }
+
+    #[test]
+    fn dumped_snakes_can_be_loaded_back_into_the_acquisition() {
+        let mut path = temp_dir();
+        path.push("dumped_snakes_can_be_loaded_back.toml");
+        let path_str = path.to_string_lossy().into_owned();
+        let dumping = setup_small_volume()
+            .with_snake_dump_path(Some(path_str.clone()))
+            .build();
+        let mut built = setup_app_state(dumping);
+        let loading = setup_small_volume()
+            .with_snake_path(Some(path_str))
+            .with_snake_length_tolerance(Some(0))
+            .build();
+        let mut loaded = setup_app_state(loading.clone());
+        let dumped = TwoDimensionalSnake::from_serialized(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dumped.verify_length(&loading), None);
+        let frame = built.snake.get_max_frame_time();
+        assert_eq!(loaded.snake.get_max_frame_time(), frame);
+        for time in (0..frame).step_by(997) {
+            assert_eq!(
+                loaded.snake.time_to_coord_linear(time, 0),
+                built.snake.time_to_coord_linear(time, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn recorded_raw_events_replay_to_the_same_frames() {
+        let mut filename = temp_dir();
+        filename.push("test_raw_recording.arrow_stream");
+        let first = [
+            Event::new(0, 0, 4, 1_000_000_000),
+            Event::new(0, 0, -1, 1_000_001_000),
+            Event::new(0, 0, -1, 1_010_000_000),
+        ];
+        let second = [
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, 4, 2_000_000_000),
+            Event::new(0, 0, -1, 2_000_001_000),
+        ];
+        write_event_batches(&filename, &[&first, &second]);
+        let mut builder = setup_small_volume();
+        builder
+            .with_filename(filename.to_string_lossy().into_owned())
+            .with_frame_ch(InputChannel::new(4, 0.0));
+        let live_config = builder.clone().with_record_raw_events(true).build();
+        let replay_config = builder.build();
+        let live: Vec<_> = FrameStream::from_file(&filename, live_config)
+            .unwrap()
+            .map(|frame| frame.unwrap().channels)
+            .collect();
+        let raw_path = raw_events_path(&filename.to_string_lossy());
+        let replayed: Vec<_> = FrameStream::from_file(&raw_path, replay_config)
+            .unwrap()
+            .map(|frame| frame.unwrap().channels)
+            .collect();
+        std::fs::remove_file(&filename).unwrap();
+        std::fs::remove_file(&raw_path).unwrap();
+        assert_eq!(live.len(), 2);
+        assert_eq!(replayed, live);
+    }
+
+    #[test]
+    fn control_commands_pause_and_resume_the_display() {
+        let (control, commands) = unbounded();
+        let mut app = setup_app_state(setup_default_config().build()).with_control(commands);
+        control.send(AcquisitionControl::PauseDisplay).unwrap();
+        app.apply_control_commands();
+        assert!(app.is_display_paused());
+        control.send(AcquisitionControl::ResumeDisplay).unwrap();
+        control.send(AcquisitionControl::PauseDisplay).unwrap();
+        control.send(AcquisitionControl::ResumeDisplay).unwrap();
+        app.apply_control_commands();
+        assert!(!app.is_display_paused());
+    }
+
+    #[test]
+    fn warm_up_frames_are_not_serialized() {
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_skip_initial_frames(2)
+            .build();
+        let (sender, receiver) = unbounded();
+        let mut app = setup_app_state(config);
+        app.serializer = Some(sender);
+        let point = ImageCoor::new(OrderedFloat(0.0), OrderedFloat(0.0), OrderedFloat(0.0));
+        for frame_number in 1..=3 {
+            app.frame_buffers.add_to_render_queue(point, 0);
+            app.serialize_frame(frame_number);
+            app.frame_buffers.clear_non_rendered_channels();
+        }
+        let serialized: Vec<FrameBuffers> = receiver.try_iter().collect();
+        assert_eq!(serialized.len(), 1);
+        assert!(serialized[0].centroid().is_some());
+    }
+
+    #[test]
+    fn mip_previews_are_written_at_the_requested_interval() {
+        let mut filename = temp_dir();
+        filename.push("test_mip_preview.arrow_stream");
+        let filename = filename.to_string_lossy().into_owned();
+        let previews: Vec<_> = (1..=4)
+            .map(|frame| mip_preview_path(&filename, frame))
+            .collect();
+        for path in &previews {
+            let _ = std::fs::remove_file(path);
+        }
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_filename(filename)
+            .with_mip_preview_interval_frames(2)
+            .build();
+        let mut app = setup_app_state(config);
+        let point = ImageCoor::new(OrderedFloat(0.0), OrderedFloat(0.0), OrderedFloat(0.0));
+        for frame_number in 1..=4 {
+            app.frame_buffers.add_to_render_queue(point, 0);
+            app.write_frame_previews(frame_number);
+        }
+        let written: Vec<bool> = previews.iter().map(|path| path.exists()).collect();
+        assert_eq!(written, vec![false, true, false, true]);
+        std::fs::remove_file(&previews[1]).unwrap();
+        std::fs::remove_file(&previews[3]).unwrap();
+    }
+
+    #[test]
+    fn each_completed_frame_appends_a_thumbnail_of_the_configured_size() {
+        let mut filename = temp_dir();
+        filename.push("test_thumbnails.arrow_stream");
+        let filename = filename.to_string_lossy().into_owned();
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_filename(filename.clone())
+            .with_thumbnail_size(32)
+            .build();
+        let mut app = setup_app_state(config);
+        let point = ImageCoor::new(OrderedFloat(0.0), OrderedFloat(0.0), OrderedFloat(0.0));
+        for frame_number in 1..=3 {
+            app.frame_buffers.add_to_render_queue(point, 0);
+            app.write_frame_previews(frame_number);
+        }
+        drop(app);
+        let path = thumbnails_path(&filename);
+        let thumbnails = std::fs::read(&path).unwrap();
+        assert_eq!(thumbnails.len(), 3 * 32 * 32);
+        for thumbnail in thumbnails.chunks(32 * 32) {
+            assert_eq!(thumbnail.iter().filter(|pixel| **pixel == 255).count(), 1);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn thumbnails_of_a_rolling_average_show_only_their_own_frame() {
+        let mut filename = temp_dir();
+        filename.push("test_rolling_thumbnails.arrow_stream");
+        let filename = filename.to_string_lossy().into_owned();
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_filename(filename.clone())
+            .with_thumbnail_size(32)
+            .with_rolling_avg(2)
+            .build();
+        let mut app = setup_app_state(config);
+        let first = ImageCoor::new(OrderedFloat(-0.5), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let second = ImageCoor::new(OrderedFloat(0.5), OrderedFloat(0.5), OrderedFloat(0.0));
+        for (frame_number, point) in [(1, first), (2, second)] {
+            app.frame_buffers.add_to_render_queue(point, 0);
+            app.write_frame_previews(frame_number);
+        }
+        drop(app);
+        let path = thumbnails_path(&filename);
+        let thumbnails = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lit: Vec<Vec<usize>> = thumbnails
+            .chunks(32 * 32)
+            .map(|thumbnail| {
+                (0..thumbnail.len())
+                    .filter(|pixel| thumbnail[*pixel] > 0)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(lit, vec![vec![0], vec![32 * 32 - 1]]);
+    }
+
+    #[test]
+    fn missed_events_mark_the_voxels_swept_since_the_previous_photon() {
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_mark_missed_events(true)
+            .build();
+        let mut app = setup_app_state(config);
+        let (previous, missed) = (30_000_000, 40_000_000);
+        let first = app.event_to_coordinate(Event::new(0, 0, -1, previous));
+        assert_eq!(app.frame_buffers.merged_channel().len(), 0);
+        let second = app.event_to_coordinate(Event::new(0, 3, -1, missed));
+        let swept = app.snake.coords_between(previous, missed);
+        assert!(swept.len() > 1);
+        for point in &swept {
+            assert!(app.frame_buffers.is_missing(point));
+        }
+        assert_eq!(app.frame_buffers.merged_channel().len(), swept.len());
+        match (first, second) {
+            (ProcessedEvent::Displayed(first, _), ProcessedEvent::Displayed(second, _)) => {
+                assert!(app.frame_buffers.is_missing(&first));
+                assert!(!app.frame_buffers.is_missing(&second));
+            }
+            other => panic!("The photons weren't displayed: {:?}", other),
+        }
+        app.event_to_coordinate(Event::new(0, 0, -1, 50_000_000));
+        assert_eq!(app.frame_buffers.merged_channel().len(), swept.len());
+    }
+
+    #[test]
+    fn missing_voxels_are_moved_by_the_affine_transform() {
+        let config = setup_small_volume()
+            .with_mark_missed_events(true)
+            .with_affine(Some([
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.5, 0.0, 0.0],
+            ]))
+            .build();
+        let (previous, missed) = (30_000_000, config.calc_frame_duration() * 4 / 5);
+        let mut app = setup_app_state(config);
+        app.event_to_coordinate(Event::new(0, 0, -1, previous));
+        app.event_to_coordinate(Event::new(0, 3, -1, missed));
+        let swept = app.snake.coords_between(previous, missed);
+        assert!(swept.iter().any(|point| *point.x > 0.0));
+        let merged = app.frame_buffers.merged_channel();
+        assert!(merged.len() > 2);
+        assert!(merged
+            .keys()
+            .all(|point| *point.x >= 0.0 && *point.x <= 0.5));
+    }
+
+    #[test]
+    fn stopping_mid_frame_flushes_its_photons_when_asked_to() {
+        for flush in [false, true] {
+            let mut stream = temp_dir();
+            stream.push(format!("rpysight_stopped_mid_frame_{}.arrow_stream", flush));
+            let mut data = temp_dir();
+            data.push(format!("rpysight_mid_frame_output_{}.arrow_stream", flush));
+            let events = [
+                Event::new(0, 0, 4, 1_000_000_000),
+                Event::new(0, 0, -1, 1_000_001_000),
+                Event::new(0, 0, -1, 1_000_002_000),
+                Event::new(0, 0, -1, 1_000_003_000),
+            ];
+            write_stream_broken_after(&stream, &events);
+            let config = setup_small_volume()
+                .with_filename(data.to_string_lossy().into_owned())
+                .with_frame_ch(InputChannel::new(4, 0.0))
+                .with_frame_table(true)
+                .with_flush_partial_frame(flush)
+                .build();
+            let mut app = setup_app_state(config.clone());
+            app.data_stream_fh = stream.to_string_lossy().into_owned();
+            app.acquire_filehandle().unwrap();
+            let result = app.run_acq_loop(config, |_| false);
+            drop(app);
+            let table_path = frame_table_path(&data.to_string_lossy());
+            let table = std::fs::read_to_string(&table_path).unwrap();
+            let mut output = File::open(&data).unwrap();
+            let meta = read_stream_metadata(&mut output).unwrap();
+            let serialized: Vec<_> = StreamReader::new(output, meta)
+                .map(|state| match state.unwrap() {
+                    StreamState::Some(batch) => batch.num_rows(),
+                    StreamState::Waiting => 0,
+                })
+                .collect();
+            std::fs::remove_file(&stream).unwrap();
+            std::fs::remove_file(&data).unwrap();
+            std::fs::remove_file(&table_path).unwrap();
+            assert!(result.is_err());
+            let rows: Vec<&str> = table.lines().skip(1).collect();
+            if flush {
+                assert_eq!(rows.len(), 1);
+                assert!(rows[0].starts_with("0,1000000000,"));
+                assert!(rows[0].ends_with(",1"));
+                assert_eq!(serialized.len(), 1);
+                assert!(serialized[0] > 0);
+            } else {
+                assert!(rows.is_empty());
+                assert!(serialized.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn shutdown_signals_the_serializer_to_finish() {
+        let (sender, receiver) = unbounded();
+        let mut app = setup_app_state(setup_default_config().build());
+        app.serializer = Some(sender);
+        assert!(!app.is_shut_down());
+        app.shutdown();
+        assert!(app.is_shut_down());
+        assert!(app.serializer.is_none());
+        assert!(app.data_stream.is_none());
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn diagnostics_break_down_dropped_photons() {
+        let config = setup_small_volume()
+            .with_frame_ch(InputChannel::new(4, 0.0))
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let frame_end = app.snake.get_max_frame_time();
+        let events = vec![
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, -1, 1_030_000_000),
+            // During the turnaround of the mirror at the end of the first line
+            Event::new(0, 0, -1, 1_050_000_000),
+            // After the end of the frame, and before the next one starts
+            Event::new(0, 0, -1, frame_end + 1),
+            Event::new(0, 0, -1, frame_end + 2),
+            Event::new(0, 0, 4, frame_end + 1_000_000),
+        ];
+        assert!(app.populate_single_frame(Some(events)).unwrap().is_some());
+        let diagnostics = app.frame_diagnostics();
+        assert_eq!(
+            diagnostics,
+            RenderDiagnostics {
+                rendered: 2,
+                flyback: 1,
+                out_of_frame: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(diagnostics.dropped(), 3);
+        assert_eq!(diagnostics.rendered_fraction(), Some(0.4));
+    }
+
+    #[test]
+    fn only_coincident_photons_are_rendered() {
+        let config = setup_small_volume()
+            .with_laser_ch(InputChannel::new(5, 0.0))
+            .with_coincidence(Some(Coincidence {
+                channel: DataType::Pmt1,
+                reference: DataType::Laser,
+                window: 1_000,
+            }))
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let events = [
+            Event::new(0, 0, 5, 1_010_000_000),
+            Event::new(0, 0, -1, 1_010_000_500),
+            Event::new(0, 0, -1, 1_020_000_000),
+            Event::new(0, 0, 5, 1_030_000_000),
+            Event::new(0, 0, -1, 1_030_005_000),
+            Event::new(0, 0, 5, 1_040_000_000),
+            Event::new(0, 0, -1, 1_040_001_000),
+        ];
+        for event in events {
+            assert!(app.act_on_single_event(event).is_none());
+        }
+        assert_eq!(app.diagnostics.rendered, 2);
+        assert_eq!(app.diagnostics.uncorrelated, 2);
+    }
+
+    #[test]
+    fn photons_preceding_their_reference_are_released_by_it() {
+        let config = setup_small_volume()
+            .with_laser_ch(InputChannel::new(5, 0.0))
+            .with_coincidence(Some(Coincidence {
+                channel: DataType::Pmt1,
+                reference: DataType::Laser,
+                window: 1_000,
+            }))
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        assert!(app
+            .act_on_single_event(Event::new(0, 0, -1, 1_010_000_000))
+            .is_none());
+        assert_eq!(app.diagnostics.rendered, 0);
+        assert!(app
+            .act_on_single_event(Event::new(0, 0, 5, 1_010_000_500))
+            .is_none());
+        assert_eq!(app.diagnostics.rendered, 1);
+        assert_eq!(app.diagnostics.uncorrelated, 0);
+        app.snake.rewind_to(1_010_000_000);
+        let expected = match app.snake.time_to_coord_linear(1_010_000_000, 0) {
+            ProcessedEvent::Displayed(point, _) => point,
+            other => panic!("The photon wasn't displayed: {:?}", other),
+        };
+        assert_eq!(
+            app.frame_buffers
+                .merged_channel()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&expected]
+        );
+    }
+
+    #[test]
+    fn late_photons_coincide_with_the_reference_before_the_latest() {
+        let config = setup_small_volume()
+            .with_laser_ch(InputChannel::new(5, 0.0))
+            .with_coincidence(Some(Coincidence {
+                channel: DataType::Pmt1,
+                reference: DataType::Laser,
+                window: 1_000,
+            }))
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let events = [
+            Event::new(0, 0, 5, 1_010_000_000),
+            Event::new(0, 0, 5, 1_010_001_500),
+            Event::new(0, 0, -1, 1_010_000_100),
+            Event::new(0, 0, 5, 1_010_010_000),
+            Event::new(0, 0, -1, 1_010_005_000),
+            Event::new(0, 0, 5, 1_010_020_000),
+        ];
+        for event in events {
+            assert!(app.act_on_single_event(event).is_none());
+        }
+        assert_eq!(app.diagnostics.rendered, 1);
+        assert_eq!(app.diagnostics.uncorrelated, 1);
+    }
+
+    #[test]
+    fn disabled_channel_stops_rendering_while_others_continue() {
+        let config = setup_small_volume()
+            .with_pmt2_ch(InputChannel::new(-5, 0.0))
+            .build();
+        let (control, commands) = unbounded();
+        let mut app = setup_app_state(config).with_control(commands);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let column_time = 4_500_000;
+        for column in 0..4 {
+            if column == 2 {
+                control
+                    .send(AcquisitionControl::SetChannelEnabled {
+                        channel: 0,
+                        enabled: false,
+                    })
+                    .unwrap();
+                app.apply_control_commands();
+            }
+            let time = 1_000_001_000 + column * column_time;
+            app.act_on_single_event(Event::new(0, 0, -1, time));
+            app.act_on_single_event(Event::new(0, 0, -5, time));
+        }
+        let voxels: Vec<usize> = app
+            .frame_buffers
+            .iter()
+            .map(|channel| channel.len())
+            .collect();
+        assert_eq!(voxels, vec![2, 4, 0, 0]);
+        assert_eq!(app.diagnostics.disabled, 2);
+        assert_eq!(app.diagnostics.rendered, 6);
+    }
+
+    #[test]
+    fn live_feed_is_decimated() {
+        let config = setup_small_volume().with_live_feed_decimation(3).build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        let feed = app.live_feed(100);
+        for time in (0..10).map(|idx| 1_010_000_000 + idx * 1_000) {
+            app.act_on_single_event(Event::new(0, 0, -1, time));
+        }
+        // Line events aren't photons
+        app.act_on_single_event(Event::new(0, 0, 2, 1_020_000_000));
+        let photons: Vec<LivePhoton> = feed.try_iter().collect();
+        assert_eq!(
+            photons,
+            vec![
+                (1_010_000_000, -1),
+                (1_010_003_000, -1),
+                (1_010_006_000, -1),
+                (1_010_009_000, -1)
+            ]
+        );
+    }
+
+    #[test]
+    fn tied_events_are_counted_in_their_own_channels() {
+        let config = setup_small_volume()
+            .with_pmt2_ch(InputChannel::new(-5, 0.0))
+            .with_pmt3_ch(InputChannel::new(-6, 0.0))
+            .with_pmt4_ch(InputChannel::new(-7, 0.0))
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        for channel in [-1, -5, -6, -7] {
+            assert!(app
+                .act_on_single_event(Event::new(0, 0, channel, 1_010_000_000))
+                .is_none());
+        }
+        let (point, _) = app.frame_buffers.merged_channel().iter().next().unwrap();
+        let point = *point;
+        assert_eq!(app.frame_buffers.len(), 1);
+        for channel in app.frame_buffers.iter() {
+            assert_eq!(channel.len(), 1);
+            assert!(channel.contains_key(&point));
+        }
+    }
+
+    /// The channel, x, y, z and value of a serialized voxel
+    type SerializedRow = (u8, u32, u32, u32, u8);
+
+    /// Processes photons arriving at the given times in a single frame and
+    /// returns their buffers together with the rows they were serialized to,
+    /// sorted since the order of the voxels in the buffers is arbitrary
+    fn serialize_photons(
+        name: &str,
+        carry_voxel_indices: bool,
+        affine: Option<[[f32; 3]; 4]>,
+        times: &[Picosecond],
+    ) -> (FrameBuffers, Vec<SerializedRow>) {
+        let mut filename = temp_dir();
+        filename.push(name);
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_filename(filename.to_str().unwrap().to_string())
+            .with_carry_voxel_indices(carry_voxel_indices)
+            .with_affine(affine)
+            .build();
+        let mut app = setup_app_state(config.clone());
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        for time in times {
+            app.act_on_single_event(Event::new(0, 0, -1, *time));
+        }
+        let (sender, receiver) = unbounded();
+        sender.send(app.frame_buffers.clone()).unwrap();
+        drop(sender);
+        serialize_data(receiver, app.snake.get_voxel_delta_im(), None, config);
+        let path = filename.with_extension("arrow_stream");
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let mut reader = written.as_slice();
+        let meta = read_stream_metadata(&mut reader).unwrap();
+        let batch = match StreamReader::new(reader, meta).next().unwrap().unwrap() {
+            StreamState::Some(batch) => batch,
+            StreamState::Waiting => panic!("The stream is missing its batch"),
+        };
+        let column = |idx: usize| batch.column(idx).as_any();
+        let channels = column(0).downcast_ref::<UInt8Array>().unwrap();
+        let xs = column(1).downcast_ref::<UInt32Array>().unwrap();
+        let ys = column(2).downcast_ref::<UInt32Array>().unwrap();
+        let zs = column(3).downcast_ref::<UInt32Array>().unwrap();
+        let values = column(4).downcast_ref::<UInt8Array>().unwrap();
+        let mut rows: Vec<_> = (0..batch.num_rows())
+            .map(|row| {
+                (
+                    channels.value(row),
+                    xs.value(row),
+                    ys.value(row),
+                    zs.value(row),
+                    values.value(row),
+                )
+            })
+            .collect();
+        rows.sort_unstable();
+        (app.frame_buffers, rows)
+    }
+
+    #[test]
+    fn photons_carrying_voxel_indices_serialize_like_looked_up_ones() {
+        let times: Vec<Picosecond> = (0..500)
+            .map(|photon| 1_000_000_000 + photon * 13_000_003)
+            .collect();
+        let (looked_up, looked_up_rows) =
+            serialize_photons("rpysight_looked_up_indices", false, None, &times);
+        let (indexed, indexed_rows) =
+            serialize_photons("rpysight_carried_indices", true, None, &times);
+        let indexed = indexed.iter().next().unwrap();
+        let looked_up = looked_up.iter().next().unwrap();
+        assert!(indexed.len() > 100);
+        assert!(indexed
+            .iter()
+            .all(|(point, voxel)| voxel.index.is_some() && looked_up[point].index.is_none()));
+        assert_eq!(indexed_rows.len(), indexed.len());
+        assert_eq!(indexed_rows, looked_up_rows);
+    }
+
+    #[test]
+    fn affine_transform_moves_the_serialized_voxels() {
+        let times: Vec<Picosecond> = (0..500)
+            .map(|photon| 1_000_000_000 + photon * 13_000_003)
+            .collect();
+        let transpose = [
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0],
+        ];
+        let (_, rows) = serialize_photons("rpysight_untransformed", true, None, &times);
+        let (_, transposed_rows) =
+            serialize_photons("rpysight_transposed", true, Some(transpose), &times);
+        assert!(rows.len() > 100);
+        let mut expected: Vec<SerializedRow> = rows
+            .into_iter()
+            .map(|(channel, x, y, z, value)| (channel, y, x, z, value))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(transposed_rows, expected);
+    }
+
+    #[test]
+    fn photons_moved_outside_of_the_volume_are_dropped() {
+        let config = setup_small_volume()
+            .with_affine(Some([
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.5, 0.0, 0.0],
+            ]))
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        for photon in 0..200 {
+            app.act_on_single_event(Event::new(0, 0, -1, 1_000_000_000 + photon * 3_000_007));
+        }
+        assert!(app.diagnostics.rendered > 0);
+        assert!(app.diagnostics.outside > 0);
+        assert!(app
+            .frame_buffers
+            .merged_channel()
+            .keys()
+            .all(|point| *point.x >= 0.0 && *point.x <= 0.5));
+    }
+
+    #[test]
+    fn lifetimes_are_relative_to_last_laser_pulse() {
+        let config = setup_default_config()
+            .with_planes(1)
+            .with_laser_ch(InputChannel::new(5, 0.0))
+            .with_lifetime_bins(4)
+            .build();
+        let mut app = setup_app_state(config);
+        app.snake.update_snake_for_next_frame(1_000_000_000);
+        app.act_on_single_event(Event::new(0, 0, 5, 1_000_000_000));
+        app.act_on_single_event(Event::new(0, 0, -1, 1_000_001_000));
+        app.act_on_single_event(Event::new(0, 0, 5, 1_000_012_500));
+        app.act_on_single_event(Event::new(0, 0, -1, 1_000_022_000));
+        let (point, _) = app.frame_buffers.merged_channel().iter().next().unwrap();
+        let point = *point;
+        let hist = app.frame_buffers.get_lifetime_histogram(0, &point).unwrap();
+        assert_eq!(hist, &vec![1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn exactly_one_window_is_designated_for_the_controls() {
+        for (index, control_window) in ChannelNames::ALL.into_iter().enumerate() {
+            let roles = window_roles(control_window);
+            let controls: Vec<usize> = roles
+                .iter()
+                .enumerate()
+                .filter(|(_, role)| **role == WindowRole::Control)
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(controls, vec![index]);
+        }
+    }
+
+    #[test]
+    fn affine_transform_rotates_coordinates_and_keeps_nans() {
+        let quarter_turn = [
+            [0.0, -1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.1, 0.0, 0.0],
+        ];
+        let coord = ImageCoor::new(OrderedFloat(0.25), OrderedFloat(0.0), OrderedFloat(0.5));
+        let transformed = apply_affine(&quarter_turn, &coord).unwrap();
+        assert_approx_eq!(*transformed.x, 0.1);
+        assert_approx_eq!(*transformed.y, 0.25);
+        assert_approx_eq!(*transformed.z, 0.5);
+        let nan = OrderedFloat(f32::NAN);
+        let rotation_cell = apply_affine(&quarter_turn, &ImageCoor::new(nan, nan, nan));
+        assert!(rotation_cell.unwrap().x.is_nan());
+        let masked = OrderedFloat(-f32::NAN);
+        let masked_cell = apply_affine(&quarter_turn, &ImageCoor::new(masked, masked, masked));
+        assert!(is_masked(&masked_cell.unwrap()));
+        let edge = ImageCoor::new(OrderedFloat(0.0), OrderedFloat(-0.45), OrderedFloat(0.0));
+        assert_eq!(apply_affine(&quarter_turn, &edge), None);
+    }
+
+    /// A headless display whose window was closed by the user
+    #[derive(Clone, Copy, Debug)]
+    struct ClosedChannel(bool);
+
+    impl PointDisplay for ClosedChannel {
+        fn display_point(&mut self, _p: &ImageCoor, _c: &Point3<f32>, _time: Picosecond) {}
+
+        fn render(&mut self) {}
+
+        fn hide(&mut self) {}
+
+        fn should_close(&self) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn only_the_control_window_closes_the_acquisition() {
+        let windows = vec![
+            ClosedChannel(true),
+            ClosedChannel(false),
+            ClosedChannel(false),
+            ClosedChannel(false),
+            ClosedChannel(false),
+        ];
+        let channels = Channels::new(windows);
+        assert!(!channels.should_close());
+        assert!(channels
+            .with_control_window(ChannelNames::Channel1)
+            .should_close());
+    }
 }
@@ -1,15 +1,18 @@
 //! Serialization and rendering actions
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::path::Path;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::Arc;
 
 use anyhow::Result;
-use arrow2::array::{Array, UInt32Array, UInt8Array};
+use arrow2::array::{Array, Float32Array, UInt16Array, UInt32Array, UInt8Array};
 use arrow2::datatypes::{
-    DataType::{UInt32, UInt8},
+    DataType::{Float32, UInt16, UInt32, UInt8},
     Field, Schema,
 };
 use arrow2::io::ipc::write::StreamWriter;
@@ -17,23 +20,260 @@ use arrow2::record_batch::RecordBatch;
 use crossbeam::channel::Receiver;
 use nalgebra::{Point3, DVector};
 use ordered_float::OrderedFloat;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
+use crate::configuration::{
+    AppConfig, AutoExposure, ColorMode, DepthColorMode, IntensityScale, OutputCompression,
+    PileupCorrection, SerializedColumn,
+};
 use crate::point_cloud_renderer::ImageCoor;
-use crate::snakes::{Coordinate, VoxelDelta};
-use crate::{DISPLAY_COLORS, SUPPORTED_SPECTRAL_CHANNELS};
+use crate::snakes::{Coordinate, Picosecond, VoxelDelta, VoxelIndex, WaveformEvent};
+use crate::{DIRECT_RGB_COLORS, DISPLAY_COLORS, GRAYSCALE_START, SUPPORTED_SPECTRAL_CHANNELS};
+
+/// Size in bytes of the channel, x, y, z and value columns, which are
+/// serialized for every voxel
+const FIXED_ROW_SIZE: usize = 1 + 3 * 4 + 1;
+
+/// The number of bytes each serialized voxel occupies in the stream, not
+/// including the overhead of the batches and their compression
+pub(crate) fn serialized_row_size(config: &AppConfig) -> usize {
+    let corrected_value_size = match config.pileup_correction {
+        Some(_) => 4,
+        None => 0,
+    };
+    FIXED_ROW_SIZE + corrected_value_size + 2 * config.lifetime_bins as usize
+}
+
+/// Writes a CSV table with a row for each completed frame, describing its
+/// start and end times and the number of photons it contained.
+///
+/// The rows are flushed as soon as they're written so that the table can be
+/// correlated with external events during the acquisition.
+pub struct FrameTable {
+    writer: BufWriter<File>,
+    frames_written: u64,
+}
+
+impl FrameTable {
+    /// Creates the table file and writes its header
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            "frame_index,start_time_ps,end_time_ps,photon_count,partial"
+        )?;
+        writer.flush()?;
+        Ok(FrameTable {
+            writer,
+            frames_written: 0,
+        })
+    }
+
+    /// Adds a row for the frame that was just completed, or for a partial one
+    /// whose end wasn't reached
+    pub fn push(
+        &mut self,
+        start_time: Picosecond,
+        end_time: Picosecond,
+        photon_count: u64,
+        partial: bool,
+    ) -> Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{},{}",
+            self.frames_written, start_time, end_time, photon_count, partial as u8
+        )?;
+        self.writer.flush()?;
+        self.frames_written += 1;
+        Ok(())
+    }
+}
+
+/// Archives the raw batches of events as they arrive, before any processing,
+/// in a second Arrow stream file.
+///
+/// The recorded stream has the schema of the incoming batches, so it can be
+/// replayed later, e.g. with different rendering parameters.
+pub struct RawEventRecorder {
+    path: PathBuf,
+    stream: Option<(StreamWriter<SharedWriter<File>>, SharedWriter<File>)>,
+}
+
+/// A buffered writer which remains reachable after a clone of it was moved
+/// into a stream, so that the last bytes the stream writes once dropped can
+/// still be flushed with their errors reported
+struct SharedWriter<W: Write>(Rc<RefCell<BufWriter<W>>>);
+
+impl<W: Write> Clone for SharedWriter<W> {
+    fn clone(&self) -> Self {
+        SharedWriter(Rc::clone(&self.0))
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl RawEventRecorder {
+    /// Prepares a recorder for the given path. The file is only created once
+    /// the first batch arrives, since its schema is taken from that batch
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        RawEventRecorder {
+            path: path.as_ref().to_path_buf(),
+            stream: None,
+        }
+    }
+
+    /// Appends the batch to the recorded stream
+    pub fn push(&mut self, batch: &RecordBatch) -> Result<()> {
+        let (stream, _) = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => {
+                let output = SharedWriter(Rc::new(RefCell::new(BufWriter::new(File::create(
+                    &self.path,
+                )?))));
+                let stream = StreamWriter::try_new(output.clone(), batch.schema())?;
+                info!("Recording the raw events to {:?}", self.path);
+                self.stream.insert((stream, output))
+            }
+        };
+        stream.write(batch)?;
+        Ok(())
+    }
+
+    /// Ends the recorded stream and flushes it to the file. Nothing is
+    /// written if no batch was recorded
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some((mut stream, mut output)) = self.stream.take() {
+            stream.finish()?;
+            drop(stream);
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the expected clock edges of a scan as a CSV table, which can be
+/// overlaid on an oscilloscope capture to validate the timing of the snake
+pub fn write_timing_waveform<P: AsRef<Path>>(
+    path: P,
+    waveform: &[(Picosecond, WaveformEvent)],
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "time_ps,event")?;
+    for (time, event) in waveform {
+        writeln!(writer, "{},{}", time, event.name())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The path of a file accompanying the given data filename, named after it
+/// with the given suffix and extension
+fn sibling_path(filename: &str, suffix: &str, extension: &str) -> PathBuf {
+    let data = Path::new(filename);
+    let stem = data
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    data.with_file_name(format!("{}_{}.{}", stem, suffix, extension))
+}
+
+/// The path of the frame table accompanying the given data filename
+pub(crate) fn frame_table_path(filename: &str) -> PathBuf {
+    sibling_path(filename, "frames", "csv")
+}
+
+/// The path of the recorded raw events accompanying the given data filename
+pub(crate) fn raw_events_path(filename: &str) -> PathBuf {
+    sibling_path(filename, "raw", "arrow_stream")
+}
+
+/// The path of the preview of the given frame accompanying the given data
+/// filename
+pub(crate) fn mip_preview_path(filename: &str, frame_number: usize) -> PathBuf {
+    sibling_path(filename, &format!("mip_{}", frame_number), "png")
+}
+
+/// The path of the thumbnail stream accompanying the given data filename
+pub(crate) fn thumbnails_path(filename: &str) -> PathBuf {
+    sibling_path(filename, "thumbnails", "bin")
+}
+
+/// Bins an image of the given dimensions into a square of `size` by `size`
+/// pixels, each holding the sum of the pixels that fell into it
+pub(crate) fn downsample(image: &[u32], rows: u32, columns: u32, size: u32) -> Vec<u32> {
+    let mut binned = vec![0u32; (size * size) as usize];
+    for row in 0..rows {
+        let binned_row = row * size / rows;
+        for column in 0..columns {
+            let binned_column = column * size / columns;
+            binned[(binned_row * size + binned_column) as usize] +=
+                image[(row * columns + column) as usize];
+        }
+    }
+    binned
+}
+
+/// Scales the values of an image to bytes, so that its brightest pixel is
+/// white and pixels without photons are black
+fn to_grayscale(image: &[u32]) -> Vec<u8> {
+    let brightest = image.iter().copied().max().unwrap_or(0).max(1);
+    image
+        .iter()
+        .map(|count| (*count as f32 / brightest as f32 * 255.0).round() as u8)
+        .collect()
+}
 
 /// Write the data to disk in a tabular format.
 ///
 /// This function will take the per-frame data, convert it to a clearer
 /// serialization format and finally write it to disk.
-pub(crate) fn serialize_data<P: AsRef<Path>>(
+///
+/// When the configured `lifetime_bins` isn't zero, each row also contains the
+/// lifetime histogram of its voxel, flattened into one column per bin. Only
+/// the photons of the channels listed in `serialize_channels` are written.
+/// When a `pileup_correction` is given, each row also contains the photon
+/// count of its voxel corrected for pile-up. The data is streamed to stdout
+/// instead of the file when `stream_to_stdout` is set. With
+/// `split_bidir_direction`, the photons of reverse rows are written to their
+/// own channels. With more than one `serialization_threads`, the mapping of
+/// several pending frames to batches runs concurrently, while the batches are
+/// still written in the order of their frames.
+pub(crate) fn serialize_data(
     recv: Receiver<FrameBuffers>,
     voxel_delta: VoxelDelta<Coordinate>,
     im_planes: Option<DVector<Coordinate>>,
-    filename: P,
+    config: AppConfig,
 ) {
-    let mut coord_to_index = match CoordToIndex::try_new(&voxel_delta, im_planes, filename) {
-        Ok(cti) => cti,
+    let split_directions = config.splits_bidir_direction();
+    let pool = build_serialization_pool(config.serialization_threads);
+    let writer = if config.stream_to_stdout {
+        info!("Streaming the table to stdout");
+        compressed_writer(io::stdout(), config.output_compression)
+    } else {
+        table_file_writer(&config.filename, config.output_compression)
+    };
+    let coord_to_index = writer.and_then(|writer| {
+        CoordToIndex::try_with_writer(
+            &voxel_delta,
+            im_planes,
+            writer,
+            config.lifetime_bins as usize,
+            config.serialize_channels,
+            config.pileup_correction,
+            &config.column_order,
+        )
+    });
+    let mut coord_to_index = match coord_to_index {
+        Ok(cti) => cti.with_split_directions(split_directions),
         Err(e) => {
             error!(
                 "Cannot create a file: {:?}. Not writing columnar data to disk",
@@ -43,23 +283,58 @@ pub(crate) fn serialize_data<P: AsRef<Path>>(
         }
     };
     loop {
-        match recv.recv() {
-            Ok(new_data) => {
-                let (channels, xs, ys, zs, values) = coord_to_index.map_data_to_indices(new_data);
-                let rb = coord_to_index.convert_vecs_to_recordbatch(channels, xs, ys, zs, values);
-                match coord_to_index.serialize_to_stream(rb) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        error!("Failed to serialize: {:?}", e);
-                    }
-                };
-            }
+        let new_data = match recv.recv() {
+            Ok(new_data) => new_data,
             Err(_) => break,
         };
+        let batches: Vec<RecordBatch> = match &pool {
+            Some(pool) => {
+                let mut frames = vec![new_data];
+                frames.extend(recv.try_iter().take(pool.current_num_threads() - 1));
+                let mapping = &coord_to_index.mapping;
+                pool.install(|| {
+                    frames
+                        .into_par_iter()
+                        .map(|frame| mapping.frame_to_recordbatch(frame))
+                        .collect()
+                })
+            }
+            None => vec![coord_to_index.mapping.frame_to_recordbatch(new_data)],
+        };
+        for rb in batches {
+            match coord_to_index.serialize_to_stream(rb) {
+                Ok(()) => {}
+                Err(e) => {
+                    error!("Failed to serialize: {:?}", e);
+                }
+            };
+        }
     }
     coord_to_index.stream.finish().unwrap();
 }
 
+/// A pool of the given number of threads which map frames to batches, or
+/// None when a single thread was requested or the pool couldn't be created,
+/// in which case the frames are mapped on the serialization thread itself
+fn build_serialization_pool(threads: usize) -> Option<ThreadPool> {
+    if threads <= 1 {
+        return None;
+    }
+    match ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => {
+            info!("Mapping the serialized frames using {} threads", threads);
+            Some(pool)
+        }
+        Err(e) => {
+            warn!(
+                "Couldn't create the serialization threads, serializing on a single one: {:?}",
+                e
+            );
+            None
+        }
+    }
+}
+
 /// Convert the GPU-focused coordinates to array indexing.
 ///
 /// We wish to have access to the GPU array that is rendered in each step, but
@@ -67,19 +342,49 @@ pub(crate) fn serialize_data<P: AsRef<Path>>(
 /// between the GPU-based coordinates (probably in the range [-0.5, 0.5]) to
 /// array indices ([0..len]).
 struct CoordToIndex {
+    mapping: FrameMapping,
+    stream: StreamWriter<Box<dyn Write>>,
+}
+
+/// The part of [`CoordToIndex`] which converts frames to batches.
+///
+/// It's kept apart from the output stream so that frames may be mapped from
+/// several threads at once.
+struct FrameMapping {
     row_mapping: BTreeMap<OrderedFloat<f32>, u32>,
     column_mapping: BTreeMap<OrderedFloat<f32>, u32>,
     plane_mapping: BTreeMap<OrderedFloat<f32>, u32>,
-    stream: StreamWriter<File>,
     schema: Arc<Schema>,
+    lifetime_bins: usize,
+    serialize_channels: Vec<usize>,
+    pileup_correction: Option<PileupCorrection>,
+    split_directions: bool,
+    column_order: Vec<SerializedColumn>,
+}
+
+/// Opens the file the table is written to, whose extension is set by the
+/// requested compression
+fn table_file_writer<P: AsRef<Path>>(
+    filename: P,
+    compression: OutputCompression,
+) -> Result<Box<dyn Write>> {
+    let f = File::create(filename.as_ref().with_extension(compression.extension()))?;
+    info!("Writing the table to disk at: {:?}", f);
+    compressed_writer(f, compression)
 }
 
 impl CoordToIndex {
-    /// Try to create a new mapping from the voxel delta information
-    pub fn try_new<P: AsRef<Path>>(
+    /// Try to create a new mapping from the voxel delta information, writing
+    /// the table to any writer, e.g. a file or stdout. The columns of the
+    /// table are written in the given order
+    pub fn try_with_writer(
         voxel_delta: &VoxelDelta<Coordinate>,
         im_vec: Option<DVector<Coordinate>>,
-        filename: P,
+        writer: Box<dyn Write>,
+        lifetime_bins: usize,
+        serialize_channels: Vec<usize>,
+        pileup_correction: Option<PileupCorrection>,
+        column_order: &[SerializedColumn],
     ) -> Result<Self> {
         let (row, col) = voxel_delta.map_coord_to_index();
         let plane = match im_vec {
@@ -102,68 +407,149 @@ impl CoordToIndex {
             "Got the following mapping for serialization: Row: {:#?}\nCol: {:#?}\nPlane: {:#?}",
             row, col, plane
         );
-        let schema = Schema::new(vec![
-            Field::new("channel", UInt8, false),
-            Field::new("x", UInt32, false),
-            Field::new("y", UInt32, false),
-            Field::new("z", UInt32, false),
-            Field::new("value", UInt8, false),
-        ]);
-        let f = File::create(filename.as_ref().with_extension("arrow_stream"))?;
-        info!("Writing the table to disk at: {:?}", f);
-        let stream = StreamWriter::try_new(f, &schema)?;
+        let mut fields: Vec<Field> = column_order
+            .iter()
+            .map(|column| match column {
+                SerializedColumn::Channel | SerializedColumn::Value => {
+                    Field::new(column.name(), UInt8, false)
+                }
+                _ => Field::new(column.name(), UInt32, false),
+            })
+            .collect();
+        if pileup_correction.is_some() {
+            fields.push(Field::new("corrected_value", Float32, false));
+        }
+        for bin in 0..lifetime_bins {
+            fields.push(Field::new(&format!("lifetime_bin_{}", bin), UInt16, false));
+        }
+        let schema = Schema::new(fields);
+        let stream = StreamWriter::try_new(writer, &schema)?;
         Ok(Self {
-            row_mapping: row,
-            column_mapping: col,
-            plane_mapping: plane,
+            mapping: FrameMapping {
+                row_mapping: row,
+                column_mapping: col,
+                plane_mapping: plane,
+                schema: Arc::new(schema),
+                lifetime_bins,
+                serialize_channels,
+                pileup_correction,
+                split_directions: false,
+                column_order: column_order.to_vec(),
+            },
             stream,
-            schema: Arc::new(schema),
         })
     }
 
+    /// Write the photons of odd rows, which are the reverse rows of
+    /// bidirectional scans, to their own channels, following the
+    /// [`SUPPORTED_SPECTRAL_CHANNELS`] regular ones
+    pub fn with_split_directions(mut self, split_directions: bool) -> Self {
+        self.mapping.split_directions = split_directions;
+        self
+    }
+
+    /// Write the data to disk
+    pub fn serialize_to_stream(&mut self, rb: RecordBatch) -> Result<()> {
+        self.stream.write(&rb)?;
+        Ok(())
+    }
+}
+
+impl FrameMapping {
+    /// Convert the frame to the [`RecordBatch`] which is written to the
+    /// stream
+    pub fn frame_to_recordbatch(&self, data: FrameBuffers) -> RecordBatch {
+        let (channels, xs, ys, zs, values, lifetimes) = self.map_data_to_indices(data);
+        self.convert_vecs_to_recordbatch(channels, xs, ys, zs, values, lifetimes)
+    }
+
+    /// The serialized channel of a photon of the given channel in the given
+    /// row
+    fn output_channel(&self, channel: usize, row: u32) -> u8 {
+        if self.split_directions && row % 2 == 1 {
+            (channel + SUPPORTED_SPECTRAL_CHANNELS) as u8
+        } else {
+            channel as u8
+        }
+    }
+
     /// Convert the GPU-based coordinates and brightness levels to a table of
     /// array-focused coordinates.
     ///
     /// Note that we don't serialize the merged channel, only the individual
-    /// ones.
+    /// ones which were requested. The lifetime histograms are returned as one
+    /// vector per bin.
     pub fn map_data_to_indices(
         &self,
         data: FrameBuffers,
-    ) -> (Vec<u8>, Vec<u32>, Vec<u32>, Vec<u32>, Vec<u8>) {
+    ) -> (
+        Vec<u8>,
+        Vec<u32>,
+        Vec<u32>,
+        Vec<u32>,
+        Vec<u8>,
+        Vec<Vec<u16>>,
+    ) {
         let length = data.len();
         let mut channels = Vec::<u8>::with_capacity(length);
         let mut xs = Vec::<u32>::with_capacity(length);
         let mut ys = Vec::<u32>::with_capacity(length);
         let mut zs = Vec::<u32>::with_capacity(length);
         let mut values = Vec::<u8>::with_capacity(length);
+        let mut lifetimes = vec![Vec::<u16>::with_capacity(length); self.lifetime_bins];
         for (ch, single_channel_data) in data.iter().enumerate() {
-            for (point, value) in single_channel_data.iter() {
+            if !self.serialize_channels.contains(&ch) {
+                continue;
+            }
+            for (point, voxel) in single_channel_data.iter() {
                 trace!("Point to push: {:?}", point);
-                let r = match self.row_mapping.get(&point.x) {
-                    Some(r) => *r,
-                    None => { warn!("Row non-existent: {}", &point.x); continue },
-                };
-                let c = match self.column_mapping.get(&point.y) {
-                    Some(c) => *c,
-                    None => { warn!("Column non-existent: {}", &point.y); continue },
-                };
-                let p = match self.plane_mapping.get(&point.z) {
-                    Some(p) => *p,
-                    None => { warn!("Plane non-existent: {}", &point.z); continue },
+                let index = match voxel.index {
+                    Some(index) => index,
+                    None => match self.lookup_index(point) {
+                        Some(index) => index,
+                        None => continue,
+                    },
                 };
+                let (r, c, p) = (index.row, index.column, index.plane);
                 // All points are not NaNs, we can add them to the buffers
-                channels.push(ch as u8);
+                channels.push(self.output_channel(ch, r));
                 xs.push(r);
                 ys.push(c);
                 zs.push(p);
-                values.push(*value);
+                values.push(voxel.count);
+                match data.get_lifetime_histogram(ch, point) {
+                    Some(hist) => lifetimes
+                        .iter_mut()
+                        .zip(hist)
+                        .for_each(|(column, count)| column.push(*count)),
+                    None => lifetimes.iter_mut().for_each(|column| column.push(0)),
+                }
             }
         }
-        (channels, xs, ys, zs, values)
+        (channels, xs, ys, zs, values, lifetimes)
+    }
+
+    /// The array indices of the voxel of a point whose photons arrived
+    /// without them, looked up in the coordinate mappings
+    fn lookup_index(&self, point: &ImageCoor) -> Option<VoxelIndex> {
+        let row = match self.row_mapping.get(&point.x) {
+            Some(r) => *r,
+            None => { warn!("Row non-existent: {}", &point.x); return None },
+        };
+        let column = match self.column_mapping.get(&point.y) {
+            Some(c) => *c,
+            None => { warn!("Column non-existent: {}", &point.y); return None },
+        };
+        let plane = match self.plane_mapping.get(&point.z) {
+            Some(p) => *p,
+            None => { warn!("Plane non-existent: {}", &point.z); return None },
+        };
+        Some(VoxelIndex { row, column, plane })
     }
 
     /// Convert the "raw" table of data into a [`RecordBatch`] that can be
-    /// streamed and serialized.
+    /// streamed and serialized, correcting the photon counts for pile-up if
+    /// requested.
     pub fn convert_vecs_to_recordbatch(
         &self,
         channels: Vec<u8>,
@@ -171,6 +557,7 @@ impl CoordToIndex {
         ys: Vec<u32>,
         zs: Vec<u32>,
         values: Vec<u8>,
+        lifetimes: Vec<Vec<u16>>,
     ) -> RecordBatch {
         let channels = Arc::new(UInt8Array::from_trusted_len_values_iter(
             channels.into_iter(),
@@ -178,25 +565,156 @@ impl CoordToIndex {
         let xs = Arc::new(UInt32Array::from_trusted_len_values_iter(xs.into_iter()));
         let ys = Arc::new(UInt32Array::from_trusted_len_values_iter(ys.into_iter()));
         let zs = Arc::new(UInt32Array::from_trusted_len_values_iter(zs.into_iter()));
+        let corrected = self.pileup_correction.map(|correction| {
+            Arc::new(Float32Array::from_trusted_len_values_iter(
+                values.iter().map(|value| correction.correct(*value as f32)),
+            ))
+        });
         let values = Arc::new(UInt8Array::from_trusted_len_values_iter(values.into_iter()));
-        let iter_over_vecs: Vec<Arc<dyn Array>> = vec![channels, xs, ys, zs, values];
+        let mut iter_over_vecs: Vec<Arc<dyn Array>> = self
+            .column_order
+            .iter()
+            .map(|column| -> Arc<dyn Array> {
+                match column {
+                    SerializedColumn::Channel => channels.clone(),
+                    SerializedColumn::X => xs.clone(),
+                    SerializedColumn::Y => ys.clone(),
+                    SerializedColumn::Z => zs.clone(),
+                    SerializedColumn::Value => values.clone(),
+                }
+            })
+            .collect();
+        if let Some(corrected) = corrected {
+            iter_over_vecs.push(corrected);
+        }
+        for column in lifetimes {
+            iter_over_vecs.push(Arc::new(UInt16Array::from_trusted_len_values_iter(
+                column.into_iter(),
+            )));
+        }
         RecordBatch::try_new(self.schema.clone(), iter_over_vecs).unwrap()
     }
+}
 
-    /// Write the data to disk
-    pub fn serialize_to_stream(&mut self, rb: RecordBatch) -> Result<()> {
-        self.stream.write(&rb)?;
-        Ok(())
+/// Wraps the output with an encoder of the requested compression, which is
+/// finalized once the writer is dropped
+fn compressed_writer<W: Write + 'static>(
+    output: W,
+    compression: OutputCompression,
+) -> Result<Box<dyn Write>> {
+    Ok(match compression {
+        OutputCompression::Uncompressed => Box::new(output),
+        OutputCompression::Zstd { level } => {
+            Box::new(zstd::stream::write::Encoder::new(output, level)?.auto_finish())
+        }
+        OutputCompression::Lz4 { level } => Box::new(Lz4Writer {
+            encoder: Some(lz4::EncoderBuilder::new().level(level).build(output)?),
+        }),
+    })
+}
+
+/// An LZ4 encoder which writes the end of its frame when dropped, like
+/// the auto-finishing encoder of zstd
+struct Lz4Writer<W: Write> {
+    encoder: Option<lz4::Encoder<W>>,
+}
+
+impl<W: Write> Write for Lz4Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.encoder
+            .as_mut()
+            .map_or(Ok(0), |encoder| encoder.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.encoder
+            .as_mut()
+            .map_or(Ok(()), |encoder| encoder.flush())
     }
 }
 
+impl<W: Write> Drop for Lz4Writer<W> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            if let (_, Err(e)) = encoder.finish() {
+                error!("Failed to finish the compressed stream: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Number of hashmap entries allocated per pixel of the volume, leaving some
+/// room for the entries of discarded events
+const FRAME_BUFFERS_ENTRIES_PER_PIXEL: usize = 2;
+/// Number of photons each detection adds to the aggregated count of its voxel,
+/// independently of the color increment of the rendered voxel
+const AGGREGATION_INCREMENT: u8 = 1;
+/// The color of voxels whose photons were lost by the TimeTagger, which
+/// differs from the colors of all channels
+pub(crate) const MISSING_DATA_COLOR: [f32; 3] = [0.6, 0.3, 0.0];
+
 type HashMapForRendering = HashMap<Point3<OrderedFloat<f32>>, Point3<f32>>;
-type HashMapForAggregation = HashMap<Point3<OrderedFloat<f32>>, u8>;
+type HashMapForAggregation = HashMap<Point3<OrderedFloat<f32>>, AggregatedVoxel>;
+type HashMapForLifetimes = HashMap<Point3<OrderedFloat<f32>>, Vec<u16>>;
+type HashMapForCounts = HashMap<Point3<OrderedFloat<f32>>, [u32; SUPPORTED_SPECTRAL_CHANNELS]>;
+
+/// The photons aggregated in a voxel of a single channel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AggregatedVoxel {
+    /// The number of photons, saturating at the largest serializable count
+    pub count: u8,
+    /// The array indices of the voxel, if its photons arrived with them
+    pub index: Option<VoxelIndex>,
+}
+
+/// The maximum intensity projection of the voxel counts along the planes of
+/// the volume, stored row by row
+fn project_counts(
+    counts: &HashMap<ImageCoor, u32>,
+    grid: &VoxelDelta<Coordinate>,
+    rows: u32,
+    columns: u32,
+) -> Vec<u32> {
+    let mut projection = vec![0u32; (rows * columns) as usize];
+    for (point, count) in counts {
+        if let Some((row, column)) = grid.voxel_index(point) {
+            let pixel = &mut projection[(row * columns + column) as usize];
+            *pixel = (*pixel).max(*count);
+        }
+    }
+    projection
+}
+
+/// The color component gamma-corrected with the given gamma, relative to the
+/// maximal level a component may reach
+fn gamma_corrected(component: f32, max_level: f32, gamma: f32) -> f32 {
+    max_level * (component / max_level).powf(1.0 / gamma)
+}
+
+/// The photon count of a voxel after the logarithmic normalization, scaled so
+/// that a single photon maps to 1
+fn log_normalized_count(count: u32) -> f32 {
+    (1.0 + count as f32).ln() / 2.0f32.ln()
+}
 
 /// A buffer for the data-to-be-rendered on a per-channel basis.
 ///
 /// It contains two types of hashmaps - the one used for keeping rendering data
-/// and the one used to keep data for aggregation and serialization.
+/// and the one used to keep data for aggregation and serialization. When
+/// enabled, it also keeps a histogram of the laser-relative arrival times of
+/// the photons in each voxel, and the array indices of the voxels whose
+/// photons arrived with them.
+///
+/// The aggregation hashmaps are the quantitative output of the acquisition.
+/// Each holds the exact photon counts of its own channel, regardless of the
+/// other channels' photons and of anything that only shapes the display -
+/// the blending of the merged channel, display decimation, the render cap,
+/// hidden low-count voxels, exposure and gamma - so channels may be compared
+/// directly, e.g. for ratio imaging.
+///
+/// The merged channel is drained by each render, so it only ever holds the
+/// voxels which received photons, or were marked as missing, since the
+/// previous one. Displays need no other record of the changed voxels.
 #[derive(Clone, Debug)]
 pub struct FrameBuffers {
     merge: HashMapForRendering,
@@ -205,29 +723,283 @@ pub struct FrameBuffers {
     channel3: HashMapForAggregation,
     channel4: HashMapForAggregation,
     increment_color_by: f32,
+    color_mode: ColorMode,
+    depth_color_mode: DepthColorMode,
+    lifetimes: Vec<HashMapForLifetimes>,
+    lifetime_bins: usize,
+    laser_period: Picosecond,
+    intensity_scale: IntensityScale,
+    merge_counts: HashMapForCounts,
+    display_decimation: u32,
+    photons_until_displayed: u32,
+    max_render_points: usize,
+    render_cap_reached: bool,
+    display_min_count: u16,
+    grid: Option<VoxelDelta<Coordinate>>,
+    background_level: f32,
+    color_start_level: f32,
+    max_color_level: f32,
+    display_gamma: f32,
+    auto_exposure: Option<AutoExposure>,
+    missing: HashSet<ImageCoor>,
+    previous_frame_counts: HashMap<ImageCoor, u32>,
 }
 
 impl<'a> FrameBuffers {
-    pub fn new(increment_color_by: f32) -> Self {
+    pub fn new(increment_color_by: f32, color_mode: ColorMode, capacity: usize) -> Self {
         Self {
-            merge: HashMap::with_capacity(600_000),
-            channel1: HashMap::with_capacity(600_000),
-            channel2: HashMap::with_capacity(600_000),
-            channel3: HashMap::with_capacity(600_000),
-            channel4: HashMap::with_capacity(600_000),
+            merge: HashMap::with_capacity(capacity),
+            channel1: HashMap::with_capacity(capacity),
+            channel2: HashMap::with_capacity(capacity),
+            channel3: HashMap::with_capacity(capacity),
+            channel4: HashMap::with_capacity(capacity),
             increment_color_by,
+            color_mode,
+            depth_color_mode: DepthColorMode::Off,
+            lifetimes: Vec::new(),
+            lifetime_bins: 0,
+            laser_period: 1,
+            intensity_scale: IntensityScale::Linear,
+            merge_counts: HashMap::new(),
+            display_decimation: 1,
+            photons_until_displayed: 0,
+            max_render_points: 0,
+            render_cap_reached: false,
+            display_min_count: 0,
+            grid: None,
+            background_level: 0.0,
+            color_start_level: GRAYSCALE_START,
+            max_color_level: 1.0,
+            display_gamma: 1.0,
+            auto_exposure: None,
+            missing: HashSet::new(),
+            previous_frame_counts: HashMap::new(),
         }
     }
 
+    /// Clamp each color component of the merged channel to the given level,
+    /// so that voxels with many photons saturate instead of overflowing
+    pub fn with_max_color_level(mut self, max_color_level: f32) -> Self {
+        self.max_color_level = max_color_level;
+        self
+    }
+
+    /// Gamma-correct the colors of the merged channel with the given gamma
+    /// before they're displayed
+    pub fn with_display_gamma(mut self, display_gamma: f32) -> Self {
+        self.display_gamma = display_gamma;
+        self
+    }
+
+    /// Show voxels without photons in the given gray level, and voxels after
+    /// their first photon with components at the given start level instead
+    /// of [`GRAYSCALE_START`]
+    pub fn with_color_levels(mut self, background_level: f32, color_start_level: f32) -> Self {
+        self.background_level = background_level;
+        self.color_start_level = color_start_level;
+        self
+    }
+
+    /// Snap the coordinates of incoming photons to the voxel grid described by
+    /// `voxel_delta` before they're added to the buffers, so that each voxel
+    /// is aggregated under a single key
+    pub fn with_coordinate_grid(mut self, voxel_delta: VoxelDelta<Coordinate>) -> Self {
+        self.grid = Some(voxel_delta);
+        self
+    }
+
+    /// Display only every `decimation`-th photon. The photons are counted from
+    /// the last rendered frame, so the displayed subset is stable across
+    /// frames.
+    pub fn with_display_decimation(mut self, decimation: u32) -> Self {
+        self.display_decimation = decimation.max(1);
+        self
+    }
+
+    /// Display at most `max_render_points` voxels in each rendered frame.
+    /// Photons of further voxels are still aggregated for serialization. 0
+    /// displays all voxels
+    pub fn with_max_render_points(mut self, max_render_points: usize) -> Self {
+        self.max_render_points = max_render_points;
+        self
+    }
+
+    /// Display only voxels with at least `display_min_count` photons in the
+    /// rendered frame, summed over all channels. All voxels are still
+    /// serialized. 0 displays all voxels
+    pub fn with_display_min_count(mut self, display_min_count: u16) -> Self {
+        self.display_min_count = display_min_count;
+        self
+    }
+
+    /// Adjust the color increment after each rendered frame, so that the mean
+    /// brightness of the merged channel approaches the target of the given
+    /// auto-exposure
+    pub fn with_auto_exposure(mut self, auto_exposure: Option<AutoExposure>) -> Self {
+        self.auto_exposure = auto_exposure;
+        self
+    }
+
+    /// Color the voxels of the merged channel by their depth, using the
+    /// colormap of the given mode, instead of by their channel
+    pub fn with_depth_color_mode(mut self, depth_color_mode: DepthColorMode) -> Self {
+        self.depth_color_mode = depth_color_mode;
+        self
+    }
+
+    /// Map the photon counts of the merged channel to colors using the given
+    /// scale
+    pub fn with_intensity_scale(mut self, intensity_scale: IntensityScale) -> Self {
+        self.intensity_scale = intensity_scale;
+        self
+    }
+
+    /// Keep a histogram of photon arrival times, relative to the laser pulse,
+    /// with `bins` bins spanning a single laser period.
+    pub fn with_lifetime_histograms(mut self, bins: usize, laser_period: Picosecond) -> Self {
+        self.lifetimes = vec![HashMap::new(); SUPPORTED_SPECTRAL_CHANNELS];
+        self.lifetime_bins = bins;
+        self.laser_period = laser_period.max(1);
+        self
+    }
+
+    /// Creates the buffers with a capacity fitting the size of the imaged
+    /// volume
+    pub fn from_config(config: &AppConfig) -> Self {
+        let fb = FrameBuffers::new(
+            config.increment_color_by,
+            config.color_mode,
+            config.get_num_pixels() * FRAME_BUFFERS_ENTRIES_PER_PIXEL,
+        )
+        .with_intensity_scale(config.intensity_scale)
+        .with_depth_color_mode(match config.planes {
+            0 | 1 => DepthColorMode::Off,
+            _ => config.depth_color_mode,
+        })
+        .with_display_decimation(config.display_decimation)
+        .with_max_render_points(config.max_render_points)
+        .with_display_min_count(config.display_min_count)
+        .with_color_levels(config.background_level, config.color_start_level)
+        .with_max_color_level(config.max_color_level)
+        .with_display_gamma(config.display_gamma)
+        .with_auto_exposure(config.auto_exposure)
+        .with_coordinate_grid(VoxelDelta::<Coordinate>::from_config(config));
+        match config.lifetime_bins {
+            0 => fb,
+            bins => fb.with_lifetime_histograms(bins as usize, *config.laser_period),
+        }
+    }
+
+    /// Number of entries each of the buffers can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.merge.capacity()
+    }
+
     pub fn merged_channel(&mut self) -> &mut HashMapForRendering {
         &mut self.merge
     }
 
+    /// The color the voxel at the given point is rendered in, which is the
+    /// background for voxels without photons
+    pub fn color_at(&self, point: &ImageCoor) -> Point3<f32> {
+        match self.merge.get(point) {
+            Some(color) => *color,
+            None => Point3::new(
+                self.background_level,
+                self.background_level,
+                self.background_level,
+            ),
+        }
+    }
+
+    /// Removes the voxels with fewer photons than the minimal displayed count
+    /// from the merged channel, so that they stay dark in the rendered frame
+    pub fn hide_low_count_voxels(&mut self) {
+        if self.display_min_count <= 1 {
+            return;
+        }
+        let min_count = self.display_min_count as u32;
+        let mut merge = std::mem::take(&mut self.merge);
+        merge.retain(|point, _| {
+            self.missing.contains(point) || self.photon_count(point) >= min_count
+        });
+        self.merge = merge;
+    }
+
+    /// Gamma-corrects the colors of the merged channel. It should be called
+    /// once per frame, right before the merged channel is rendered, since the
+    /// colors keep growing multiplicatively with each photon until then
+    pub fn apply_display_gamma(&mut self) {
+        if self.display_gamma == 1.0 {
+            return;
+        }
+        let (max, gamma) = (self.max_color_level, self.display_gamma);
+        self.merge.values_mut().for_each(|color| {
+            color
+                .coords
+                .iter_mut()
+                .for_each(|comp| *comp = gamma_corrected(*comp, max, gamma))
+        });
+    }
+
+    /// The factor by which the color of a voxel is currently multiplied with
+    /// each new photon
+    pub fn color_increment(&self) -> f32 {
+        self.increment_color_by
+    }
+
+    /// The mean brightness of the voxels of the merged channel, taking the
+    /// brightest color component of each, or `None` if it's empty
+    pub fn mean_brightness(&self) -> Option<f32> {
+        if self.merge.is_empty() {
+            return None;
+        }
+        let total: f32 = self.merge.values().map(|color| color.coords.max()).sum();
+        Some(total / self.merge.len() as f32)
+    }
+
+    /// Nudges the color increment towards the one which brings the mean
+    /// brightness of the merged channel to the target of the auto-exposure,
+    /// if there's one. It should be called once per frame, before the merged
+    /// channel is rendered
+    pub fn adjust_exposure(&mut self) {
+        let auto = match self.auto_exposure {
+            Some(auto) => auto,
+            None => return,
+        };
+        if let Some(mean) = self.mean_brightness() {
+            self.increment_color_by =
+                auto.adjust(self.increment_color_by, mean, self.color_start_level);
+            debug!(
+                "Auto-exposure set the color increment to {}",
+                self.increment_color_by
+            );
+        }
+    }
+
+    /// The number of photons aggregated in the voxel, summed over all channels
+    fn photon_count(&self, point: &ImageCoor) -> u32 {
+        self.iter()
+            .filter_map(|channel| channel.get(point))
+            .map(|voxel| voxel.count as u32)
+            .sum()
+    }
+
+    /// Forget the photon counts of the merged channel, once it was rendered
+    pub fn clear_merged_counts(&mut self) {
+        self.merge_counts.clear();
+        self.missing.clear();
+        self.photons_until_displayed = 0;
+        self.render_cap_reached = false;
+    }
+
     pub fn clear_non_rendered_channels(&mut self) {
         self.channel1.clear();
         self.channel2.clear();
         self.channel3.clear();
         self.channel4.clear();
+        self.lifetimes.iter_mut().for_each(|hist| hist.clear());
+        self.previous_frame_counts.clear();
     }
 
     /// Adds the point with its color to a pixel list that will be drawn in the
@@ -246,24 +1018,193 @@ impl<'a> FrameBuffers {
     /// Due to limitations of kiss3d all frame_buffers others than the 4th one
     /// (merge) aren't rendered, but their photons are still added to these
     /// buffers because they'll be used in the serialization process later on.
+    ///
+    /// With display decimation only every Nth photon reaches the merged
+    /// channel, while all of them are aggregated for serialization. The same
+    /// goes for photons of new voxels once the merged channel holds the
+    /// maximal number of rendered points.
     pub fn add_to_render_queue(&mut self, point: ImageCoor, channel: usize) {
-        self.add_to_merge(&point, channel);
-        self.add_to_agg(&point, channel);
+        self.queue_photon(point, channel, None);
+    }
+
+    /// Adds the photon like [`FrameBuffers::add_to_render_queue`], keeping
+    /// the array indices of its voxel for the serialization
+    pub fn add_indexed_to_render_queue(
+        &mut self,
+        point: ImageCoor,
+        channel: usize,
+        index: VoxelIndex,
+    ) {
+        self.queue_photon(point, channel, Some(index));
+    }
+
+    fn queue_photon(&mut self, point: ImageCoor, channel: usize, index: Option<VoxelIndex>) {
+        let point = self.snap_to_grid(point);
+        if self.photons_until_displayed == 0 {
+            if self.fits_render_cap(&point) {
+                self.add_to_merge(&point, channel);
+            }
+            self.photons_until_displayed = self.display_decimation;
+        }
+        self.photons_until_displayed -= 1;
+        self.add_to_agg(&point, channel, index);
+    }
+
+    /// Renders the voxel at the given point in [`MISSING_DATA_COLOR`], since
+    /// its photons were lost, so that it doesn't look like a voxel without
+    /// photons. Voxels which already show photons keep their color, and no
+    /// voxels are marked beyond the maximal number of rendered points
+    pub fn mark_missing(&mut self, point: ImageCoor) {
+        let point = self.snap_to_grid(point);
+        if !self.merge.contains_key(&point) && self.fits_render_cap(&point) {
+            self.merge.insert(point, Point3::from(MISSING_DATA_COLOR));
+            self.missing.insert(point);
+        }
+    }
+
+    /// Whether the voxel at the given point is rendered as missing data
+    pub fn is_missing(&self, point: &ImageCoor) -> bool {
+        self.missing.contains(&self.snap_to_grid(*point))
+    }
+
+    /// Whether the point may be rendered without exceeding the maximal number
+    /// of rendered points. Points which are already rendered always fit
+    fn fits_render_cap(&mut self, point: &ImageCoor) -> bool {
+        if self.max_render_points == 0
+            || self.merge.len() < self.max_render_points
+            || self.merge.contains_key(point)
+        {
+            return true;
+        }
+        if !self.render_cap_reached {
+            warn!(
+                "Rendering is capped at {} points, further points of this frame aren't displayed",
+                self.max_render_points
+            );
+            self.render_cap_reached = true;
+        }
+        false
     }
 
+    /// The point snapped to the voxel grid, if there's one
+    fn snap_to_grid(&self, point: ImageCoor) -> ImageCoor {
+        match &self.grid {
+            Some(grid) => grid.snap_to_grid(point),
+            None => point,
+        }
+    }
+
+    /// Adds the photon to the merged channel.
+    ///
+    /// In [`ColorMode::DirectRGB`] each channel only affects its own color
+    /// components, so a pixel hit by several channels shows their sum. Voxels
+    /// colored by depth start at the colormap's color of their plane, whatever
+    /// their channel. The components never exceed the maximal color level.
     fn add_to_merge(&mut self, point: &ImageCoor, channel: usize) {
-        let inc = self.increment_color_by;
-        self.merge
-            .entry(*point)
-            .and_modify(|c| *c *= inc)
-            .or_insert(DISPLAY_COLORS[channel]);
+        if self.missing.remove(point) {
+            self.merge.remove(point);
+        }
+        let inc = self.next_intensity_step(point, channel);
+        let start_scale = self.color_start_level / GRAYSCALE_START;
+        let max = self.max_color_level;
+        match (self.color_mode, self.depth_color(point)) {
+            (ColorMode::PerChannelPalette, base) | (_, base @ Some(_)) => {
+                let base = base.unwrap_or(DISPLAY_COLORS[channel] * start_scale);
+                self.merge
+                    .entry(*point)
+                    .and_modify(|c| {
+                        c.coords
+                            .iter_mut()
+                            .for_each(|comp| *comp = (*comp * inc).min(max))
+                    })
+                    .or_insert(base);
+            }
+            (ColorMode::DirectRGB, None) => {
+                let primary = DIRECT_RGB_COLORS[channel] * start_scale;
+                self.merge
+                    .entry(*point)
+                    .and_modify(|c| {
+                        for (comp, base) in c.coords.iter_mut().zip(primary.coords.iter()) {
+                            if *base > 0.0 {
+                                *comp = if *comp > 0.0 {
+                                    (*comp * inc).min(max)
+                                } else {
+                                    *base
+                                };
+                            }
+                        }
+                    })
+                    .or_insert(primary);
+            }
+        }
+    }
+
+    /// The color of the voxel after its first photon when voxels are colored
+    /// by depth, from the bottom plane at -0.5 to the top one at 0.5
+    fn depth_color(&self, point: &ImageCoor) -> Option<Point3<f32>> {
+        self.depth_color_mode
+            .color_at(*point.z + 0.5)
+            .map(|[r, g, b]| Point3::new(r, g, b) * self.color_start_level)
     }
 
-    fn add_to_agg(&mut self, point: &ImageCoor, channel: usize) {
-        self.get_agg_channel_mut(channel)
+    /// The factor by which the color of a voxel is multiplied when a new
+    /// photon arrives at it.
+    ///
+    /// In [`IntensityScale::Log`] the voxel's color after `n` photons is its
+    /// base color multiplied by the color increment to the power of
+    /// `ln(1 + n) / ln(2) - 1`, so each step depends on the photon count.
+    fn next_intensity_step(&mut self, point: &ImageCoor, channel: usize) -> f32 {
+        match self.intensity_scale {
+            IntensityScale::Linear => self.increment_color_by,
+            IntensityScale::Log => {
+                let counts = self
+                    .merge_counts
+                    .entry(*point)
+                    .or_insert([0; SUPPORTED_SPECTRAL_CHANNELS]);
+                counts[channel] += 1;
+                let count = match self.color_mode {
+                    ColorMode::PerChannelPalette => counts.iter().sum(),
+                    ColorMode::DirectRGB => counts[channel],
+                };
+                self.increment_color_by
+                    .powf(log_normalized_count(count) - log_normalized_count(count - 1))
+            }
+        }
+    }
+
+    /// Adds the photon to the aggregated count of its voxel, which saturates
+    /// at the largest serializable count. The indices of the voxel, if given,
+    /// are kept when its first photon arrives
+    fn add_to_agg(&mut self, point: &ImageCoor, channel: usize, index: Option<VoxelIndex>) {
+        let voxel = self
+            .get_agg_channel_mut(channel)
             .entry(*point)
-            .and_modify(|c| *c += 1)
-            .or_insert(0);
+            .or_insert(AggregatedVoxel { count: 0, index });
+        voxel.count = voxel.count.saturating_add(AGGREGATION_INCREMENT);
+    }
+
+    /// Adds the photon to the lifetime histogram of its voxel.
+    ///
+    /// The time should be given relative to the last laser pulse, and it's
+    /// wrapped into a single laser period. Does nothing if the histograms are
+    /// disabled.
+    pub fn add_lifetime(&mut self, point: &ImageCoor, channel: usize, time: Picosecond) {
+        if self.lifetime_bins == 0 {
+            return;
+        }
+        let point = self.snap_to_grid(*point);
+        let bins = self.lifetime_bins;
+        let relative_time = time.rem_euclid(self.laser_period);
+        let bin = ((relative_time * bins as Picosecond) / self.laser_period) as usize;
+        let hist = self.lifetimes[channel]
+            .entry(point)
+            .or_insert_with(|| vec![0; bins]);
+        hist[bin.min(bins - 1)] = hist[bin.min(bins - 1)].saturating_add(1);
+    }
+
+    /// The lifetime histogram of the given voxel, if any photons arrived at it
+    pub fn get_lifetime_histogram(&self, channel: usize, point: &ImageCoor) -> Option<&Vec<u16>> {
+        self.lifetimes.get(channel).and_then(|hist| hist.get(point))
     }
 
     fn get_agg_channel_mut(&mut self, channel: usize) -> &mut HashMapForAggregation {
@@ -290,6 +1231,75 @@ impl<'a> FrameBuffers {
         self.merge.len()
     }
 
+    /// The centroid of the aggregated photons of all channels, with each voxel
+    /// weighted by its photon count, or `None` if there are no photons
+    pub fn centroid(&self) -> Option<ImageCoor> {
+        let mut total = 0.0f64;
+        let mut sum = [0.0f64; 3];
+        for (point, voxel) in self.iter().flat_map(|channel| channel.iter()) {
+            let count = voxel.count as f64;
+            total += count;
+            sum.iter_mut()
+                .zip(point.coords.iter())
+                .for_each(|(s, coord)| *s += count * coord.into_inner() as f64);
+        }
+        if total == 0.0 {
+            return None;
+        }
+        Some(ImageCoor::new(
+            OrderedFloat((sum[0] / total) as f32),
+            OrderedFloat((sum[1] / total) as f32),
+            OrderedFloat((sum[2] / total) as f32),
+        ))
+    }
+
+    /// The maximum intensity projection of the aggregated photons along the
+    /// planes of the volume.
+    ///
+    /// The photon counts of all channels are summed for each voxel, and each
+    /// pixel of the projection, stored row by row, holds the largest sum of
+    /// the voxels above it.
+    pub fn project_volume(
+        &self,
+        grid: &VoxelDelta<Coordinate>,
+        rows: u32,
+        columns: u32,
+    ) -> Vec<u32> {
+        project_counts(&self.voxel_counts(), grid, rows, columns)
+    }
+
+    /// The photon count of each voxel, summed over all channels
+    fn voxel_counts(&self) -> HashMap<ImageCoor, u32> {
+        let mut voxels = HashMap::<ImageCoor, u32>::new();
+        for (point, voxel) in self.iter().flat_map(|channel| channel.iter()) {
+            *voxels.entry(*point).or_insert(0) += voxel.count as u32;
+        }
+        voxels
+    }
+
+    /// The photon count of each voxel, summed over all channels, which
+    /// arrived since the previous call.
+    ///
+    /// The aggregated channels hold the photons of all frames of the rolling
+    /// average, so calling it once per frame separates the photons of each
+    /// frame from those of the frames before it.
+    pub fn take_frame_counts(&mut self) -> HashMap<ImageCoor, u32> {
+        let counts = self.voxel_counts();
+        let mut frame_counts = counts.clone();
+        for (point, count) in frame_counts.iter_mut() {
+            *count -= self.previous_frame_counts.get(point).copied().unwrap_or(0);
+        }
+        frame_counts.retain(|_, count| *count > 0);
+        self.previous_frame_counts = counts;
+        frame_counts
+    }
+
+    /// Whether any of the aggregation channels holds photons, i.e. whether
+    /// serializing the buffers would write anything
+    pub fn has_aggregated_photons(&self) -> bool {
+        self.iter().any(|channel| !channel.is_empty())
+    }
+
     pub(crate) fn iter(&'a self) -> FrameBuffersIter<'a> {
         self.into_iter()
     }
@@ -326,3 +1336,1112 @@ impl<'a> Iterator for FrameBuffersIter<'a> {
         }
     }
 }
+
+/// The merged channel of a planar frame, assembled into an RGB image for
+/// [`crate::configuration::DisplayMode::Image2D`].
+///
+/// The pixels are stored row by row, starting from the top-left corner, with
+/// three bytes per pixel. Pixels without photons have the background color.
+///
+/// Each frame only draws the voxels of the merged channel, i.e. those which
+/// changed since the previous frame. When sparse clearing is on, the image
+/// remembers the pixels it drew and only resets them, instead of the whole
+/// image, once the frame was displayed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameImage {
+    grid: VoxelDelta<Coordinate>,
+    rows: u32,
+    columns: u32,
+    background: u8,
+    pixels: Vec<u8>,
+    drawn: Option<Vec<usize>>,
+}
+
+impl FrameImage {
+    /// Creates a blank image the size of a single plane of the imaged volume
+    pub fn from_config(config: &AppConfig) -> Self {
+        let background = FrameImage::to_color_byte(config.background_level);
+        Self {
+            grid: VoxelDelta::<Coordinate>::from_config(config),
+            rows: config.rows,
+            columns: config.columns,
+            background,
+            pixels: vec![background; (config.rows * config.columns * 3) as usize],
+            drawn: config.sparse_image_clear.then(Vec::new),
+        }
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    /// The RGB bytes of the image, row by row
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Colors the pixel of the voxel at the given point. Masked points are
+    /// ignored
+    pub fn set_voxel(&mut self, point: &ImageCoor, color: &Point3<f32>) {
+        if let Some((row, column)) = self.grid.voxel_index(point) {
+            let start = ((row * self.columns + column) * 3) as usize;
+            self.pixels[start..start + 3]
+                .iter_mut()
+                .zip(color.coords.iter())
+                .for_each(|(pixel, comp)| *pixel = FrameImage::to_color_byte(*comp));
+            if let Some(drawn) = &mut self.drawn {
+                drawn.push(start);
+            }
+        }
+    }
+
+    /// Assembles the image from the voxels of the merged channel
+    pub fn fill_from(&mut self, merged: &HashMapForRendering) {
+        merged
+            .iter()
+            .for_each(|(point, color)| self.set_voxel(point, color));
+    }
+
+    /// Resets all pixels to the background color, visiting only the pixels
+    /// drawn since the last clear when sparse clearing is on
+    pub fn clear(&mut self) {
+        let background = self.background;
+        match &mut self.drawn {
+            Some(drawn) => drawn.drain(..).for_each(|start| {
+                self.pixels[start..start + 3]
+                    .iter_mut()
+                    .for_each(|pixel| *pixel = background)
+            }),
+            None => self.pixels.iter_mut().for_each(|pixel| *pixel = background),
+        }
+    }
+
+    /// Converts a color component between 0 and 1 to a byte
+    fn to_color_byte(component: f32) -> u8 {
+        (component.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+/// Writes a maximum intensity projection of the imaged volume as a grayscale
+/// PNG every few frames, so that long acquisitions can be monitored without
+/// watching the live window.
+///
+/// The brightest pixel of each preview is white, and pixels without photons
+/// are black.
+pub struct MipPreview {
+    grid: VoxelDelta<Coordinate>,
+    rows: u32,
+    columns: u32,
+    interval_frames: u32,
+    frames_since_preview: u32,
+    filename: String,
+}
+
+impl MipPreview {
+    /// The previews requested by the configuration, or `None` if they're
+    /// disabled
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        if config.mip_preview_interval_frames == 0 {
+            return None;
+        }
+        Some(Self {
+            grid: VoxelDelta::<Coordinate>::from_config(config),
+            rows: config.rows,
+            columns: config.columns,
+            interval_frames: config.mip_preview_interval_frames,
+            frames_since_preview: 0,
+            filename: config.filename.clone(),
+        })
+    }
+
+    /// Counts a completed frame, returning whether a preview should be
+    /// written after it
+    pub fn frame_completed(&mut self) -> bool {
+        self.frames_since_preview += 1;
+        if self.frames_since_preview < self.interval_frames {
+            return false;
+        }
+        self.frames_since_preview = 0;
+        true
+    }
+
+    /// Writes the projection of the voxel counts of a frame, see
+    /// [`FrameBuffers::take_frame_counts`], returning the path of the preview
+    pub fn write(&self, counts: &HashMap<ImageCoor, u32>, frame_number: usize) -> Result<PathBuf> {
+        let projection = project_counts(counts, &self.grid, self.rows, self.columns);
+        let pixels = to_grayscale(&projection);
+        let path = mip_preview_path(&self.filename, frame_number);
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(&path)?),
+            self.columns,
+            self.rows,
+        );
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&pixels)?;
+        Ok(path)
+    }
+}
+
+/// Appends a low resolution grayscale thumbnail of each completed frame to a
+/// file next to the data, for a timeline which can be scrubbed through when
+/// reviewing long acquisitions.
+///
+/// The file has no header. Each thumbnail is `size` by `size` bytes in row
+/// major order, so the thumbnail of frame `n` starts at `n * size * size`.
+pub struct ThumbnailStream {
+    writer: BufWriter<File>,
+    grid: VoxelDelta<Coordinate>,
+    rows: u32,
+    columns: u32,
+    size: u32,
+}
+
+impl ThumbnailStream {
+    /// Creates the stream file for thumbnails of the configured size
+    pub fn create<P: AsRef<Path>>(path: P, config: &AppConfig) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            grid: VoxelDelta::<Coordinate>::from_config(config),
+            rows: config.rows,
+            columns: config.columns,
+            size: config.thumbnail_size,
+        })
+    }
+
+    /// Appends the thumbnail of the voxel counts of a frame, see
+    /// [`FrameBuffers::take_frame_counts`]
+    pub fn push(&mut self, counts: &HashMap<ImageCoor, u32>) -> Result<()> {
+        let projection = project_counts(counts, &self.grid, self.rows, self.columns);
+        let binned = downsample(&projection, self.rows, self.columns, self.size);
+        self.writer.write_all(&to_grayscale(&binned))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::AppConfigBuilder;
+    use arrow2::io::ipc::read::{read_stream_metadata, StreamReader, StreamState};
+    use assert_approx_eq::assert_approx_eq;
+
+    fn point() -> ImageCoor {
+        point_at(0.0)
+    }
+
+    fn point_at(x: f32) -> ImageCoor {
+        ImageCoor::new(OrderedFloat(x), OrderedFloat(0.0), OrderedFloat(0.0))
+    }
+
+    /// A single plane of 10 by 10 voxels
+    fn setup_small_volume() -> AppConfigBuilder {
+        AppConfigBuilder::default()
+            .with_rows(10)
+            .with_columns(10)
+            .with_planes(1)
+            .clone()
+    }
+
+    /// Maps the given channels of the grid into indices which are serialized
+    /// into an output that discards them
+    fn discarding_coord_to_index(
+        voxel_delta: &VoxelDelta<Coordinate>,
+        channels: Vec<usize>,
+    ) -> CoordToIndex {
+        CoordToIndex::try_with_writer(
+            voxel_delta,
+            None,
+            Box::new(io::sink()),
+            0,
+            channels,
+            None,
+            &SerializedColumn::DEFAULT_ORDER,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn direct_rgb_red_and_green_make_yellow() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::DirectRGB, 1);
+        fb.add_to_render_queue(point(), 0);
+        fb.add_to_render_queue(point(), 1);
+        let color = fb.merged_channel()[&point()];
+        assert_eq!(color, Point3::new(0.05, 0.05, 0.0));
+    }
+
+    #[test]
+    fn direct_rgb_increments_only_own_component() {
+        let mut fb = FrameBuffers::new(2.0, ColorMode::DirectRGB, 1);
+        fb.add_to_render_queue(point(), 0);
+        fb.add_to_render_queue(point(), 1);
+        fb.add_to_render_queue(point(), 0);
+        let color = fb.merged_channel()[&point()];
+        assert_eq!(color, Point3::new(0.1, 0.05, 0.0));
+    }
+
+    #[test]
+    fn palette_mode_uses_channel_color() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        fb.add_to_render_queue(point(), 1);
+        let color = fb.merged_channel()[&point()];
+        assert_eq!(color, DISPLAY_COLORS[1]);
+    }
+
+    #[test]
+    fn capacity_of_small_volume_is_small() {
+        let config = AppConfigBuilder::default()
+            .with_rows(8)
+            .with_columns(8)
+            .with_planes(1)
+            .build();
+        let fb = FrameBuffers::from_config(&config);
+        assert!(fb.capacity() >= 64);
+        assert!(fb.capacity() < 1_000);
+    }
+
+    #[test]
+    fn capacity_of_large_volume_is_large() {
+        let config = AppConfigBuilder::default()
+            .with_rows(512)
+            .with_columns(512)
+            .with_planes(10)
+            .build();
+        let fb = FrameBuffers::from_config(&config);
+        assert!(fb.capacity() >= 512 * 512 * 10);
+    }
+
+    #[test]
+    fn lifetime_histogram_populates_distinct_bins() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1)
+            .with_lifetime_histograms(4, 12_500);
+        fb.add_to_render_queue(point(), 0);
+        fb.add_lifetime(&point(), 0, 1_000);
+        fb.add_to_render_queue(point(), 0);
+        fb.add_lifetime(&point(), 0, 12_500 + 7_000);
+        let hist = fb.get_lifetime_histogram(0, &point()).unwrap();
+        assert_eq!(hist, &vec![1, 0, 1, 0]);
+        assert!(fb.get_lifetime_histogram(1, &point()).is_none());
+    }
+
+    #[test]
+    fn lifetimes_disabled_by_default() {
+        let mut fb = FrameBuffers::from_config(&AppConfigBuilder::default().build());
+        fb.add_lifetime(&point(), 0, 1_000);
+        assert!(fb.get_lifetime_histogram(0, &point()).is_none());
+    }
+
+    #[test]
+    fn log_scale_compresses_bright_voxels() {
+        let mut linear = FrameBuffers::new(1.01, ColorMode::PerChannelPalette, 1);
+        let mut log = FrameBuffers::new(1.01, ColorMode::PerChannelPalette, 1)
+            .with_intensity_scale(IntensityScale::Log);
+        for _ in 0..100 {
+            linear.add_to_render_queue(point(), 0);
+            log.add_to_render_queue(point(), 0);
+        }
+        let base = DISPLAY_COLORS[0].x;
+        let linear_color = linear.merged_channel()[&point()].x;
+        let log_color = log.merged_channel()[&point()].x;
+        assert_approx_eq!(linear_color, base * 1.01f32.powi(99), 1e-4);
+        let log_exponent = 101.0f32.ln() / 2.0f32.ln() - 1.0;
+        assert_approx_eq!(log_color, base * 1.01f32.powf(log_exponent), 1e-4);
+        assert!(log_color < linear_color);
+    }
+
+    #[test]
+    fn timing_waveform_is_written_as_csv() {
+        let mut path = std::env::temp_dir();
+        path.push("timing_waveform_is_written_as_csv.csv");
+        let waveform = [
+            (0, WaveformEvent::FrameStart),
+            (0, WaveformEvent::LineStart),
+            (500, WaveformEvent::LineStart),
+        ];
+        write_timing_waveform(&path, &waveform).unwrap();
+        let table = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            table,
+            "time_ps,event\n0,frame_start\n0,line_start\n500,line_start\n"
+        );
+    }
+
+    #[test]
+    fn bright_voxels_saturate_instead_of_overflowing() {
+        let mut unclamped = FrameBuffers::new(2.0, ColorMode::PerChannelPalette, 1)
+            .with_max_color_level(f32::INFINITY);
+        let mut clamped = FrameBuffers::new(2.0, ColorMode::PerChannelPalette, 1);
+        for _ in 0..200 {
+            unclamped.add_to_render_queue(point(), 0);
+            clamped.add_to_render_queue(point(), 0);
+        }
+        assert!(unclamped.color_at(&point()).x.is_infinite());
+        assert_eq!(clamped.color_at(&point()), Point3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn unhit_voxels_show_the_background() {
+        let mut fb =
+            FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1).with_color_levels(0.0, 0.2);
+        fb.add_to_render_queue(point(), 0);
+        assert_eq!(fb.color_at(&point_at(0.5)), Point3::origin());
+        assert_eq!(fb.color_at(&point()), Point3::new(0.2, 0.2, 0.2));
+    }
+
+    #[test]
+    fn display_decimation_keeps_all_photons_for_serialization() {
+        let mut fb =
+            FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1).with_display_decimation(4);
+        for x in 0..16 {
+            fb.add_to_render_queue(point_at(x as f32), 0);
+        }
+        assert_eq!(fb.len(), 4);
+        assert_eq!(fb.iter().next().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn nearly_equal_coordinates_share_a_voxel() {
+        let config = setup_small_volume().build();
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1)
+            .with_coordinate_grid(VoxelDelta::<Coordinate>::from_config(&config));
+        let close = ImageCoor::new(
+            OrderedFloat(-0.5f32 + 3.0 / 9.0),
+            OrderedFloat(-0.5),
+            OrderedFloat(0.0),
+        );
+        let nudged = ImageCoor::new(
+            close.x + OrderedFloat(1e-4),
+            close.y - OrderedFloat(1e-4),
+            close.z,
+        );
+        fb.add_to_render_queue(close, 0);
+        fb.add_to_render_queue(nudged, 0);
+        assert_eq!(fb.len(), 1);
+        let channel = fb.iter().next().unwrap();
+        assert_eq!(channel.len(), 1);
+        let (snapped, _) = channel.iter().next().unwrap();
+        let (rows, columns) = VoxelDelta::<Coordinate>::from_config(&config).map_coord_to_index();
+        assert_eq!(rows[&snapped.x], 3);
+        assert_eq!(columns[&snapped.y], 0);
+    }
+
+    #[test]
+    fn display_decimation_is_stable_across_frames() {
+        let mut fb =
+            FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1).with_display_decimation(4);
+        let points: Vec<ImageCoor> = (0..8).map(|x| point_at(x as f32)).collect();
+        let mut displayed = Vec::new();
+        for _ in 0..2 {
+            points.iter().for_each(|p| fb.add_to_render_queue(*p, 0));
+            let mut frame: Vec<ImageCoor> = fb.merged_channel().drain().map(|(k, _)| k).collect();
+            frame.sort_by_key(|p| p.x);
+            fb.clear_merged_counts();
+            displayed.push(frame);
+        }
+        assert_eq!(displayed[0], vec![points[0], points[4]]);
+        assert_eq!(displayed[0], displayed[1]);
+    }
+
+    #[test]
+    fn only_requested_channels_are_serialized() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let coord_to_index = discarding_coord_to_index(&voxel_delta, vec![0]);
+        let corner =
+            |x: f32| ImageCoor::new(OrderedFloat(x), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        fb.add_to_render_queue(corner(-0.5), 0);
+        fb.add_to_render_queue(corner(0.5), 1);
+        assert_eq!(fb.merged_channel().len(), 2);
+        let (channels, xs, _, _, _, _) = coord_to_index.mapping.map_data_to_indices(fb);
+        assert_eq!(channels, vec![0]);
+        assert_eq!(xs, vec![0]);
+    }
+
+    #[test]
+    fn reverse_row_photons_are_serialized_in_their_own_channels() {
+        let config = setup_small_volume()
+            .with_split_bidir_direction(true)
+            .build();
+        assert!(config.splits_bidir_direction());
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let coord_to_index = discarding_coord_to_index(&voxel_delta, vec![0, 1])
+            .with_split_directions(config.splits_bidir_direction());
+        let row = |r: u32| {
+            ImageCoor::new(
+                OrderedFloat(-0.5 + r as f32 / 9.0),
+                OrderedFloat(-0.5),
+                OrderedFloat(0.0),
+            )
+        };
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 4)
+            .with_coordinate_grid(voxel_delta);
+        fb.add_to_render_queue(row(0), 0);
+        fb.add_to_render_queue(row(1), 0);
+        fb.add_to_render_queue(row(2), 1);
+        fb.add_to_render_queue(row(3), 1);
+        let (channels, xs, _, _, _, _) = coord_to_index.mapping.map_data_to_indices(fb);
+        let mut rows: Vec<_> = xs.into_iter().zip(channels).collect();
+        rows.sort_unstable();
+        assert_eq!(rows, vec![(0, 0), (1, 4), (2, 1), (3, 5)]);
+    }
+
+    /// Compares the time it takes to map a full 512x512 frame to the
+    /// serialized table when its voxels carry their indices and when they're
+    /// looked up. Run it with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_carried_voxel_indices() {
+        let config = AppConfigBuilder::default()
+            .with_rows(512)
+            .with_columns(512)
+            .with_planes(1)
+            .build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let coord_to_index = discarding_coord_to_index(&voxel_delta, vec![0]);
+        let capacity = FrameBuffers::from_config(&config).capacity();
+        let mut looked_up = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, capacity)
+            .with_coordinate_grid(voxel_delta);
+        let mut indexed = looked_up.clone();
+        for row in 0..512 {
+            for column in 0..512 {
+                let point = ImageCoor::new(
+                    OrderedFloat(-0.5 + row as f32 / 511.0),
+                    OrderedFloat(-0.5 + column as f32 / 511.0),
+                    OrderedFloat(0.0),
+                );
+                let index = VoxelIndex {
+                    row,
+                    column,
+                    plane: 0,
+                };
+                looked_up.add_to_render_queue(point, 0);
+                indexed.add_indexed_to_render_queue(point, 0, index);
+            }
+        }
+        let repeats = 20;
+        for (name, fb) in [("Looked up", &looked_up), ("Carried", &indexed)] {
+            let frames: Vec<FrameBuffers> = (0..repeats).map(|_| fb.clone()).collect();
+            let start = std::time::Instant::now();
+            for frame in frames {
+                coord_to_index.mapping.map_data_to_indices(frame);
+            }
+            println!(
+                "{} indices: {:?} per frame",
+                name,
+                start.elapsed() / repeats
+            );
+        }
+    }
+
+    #[test]
+    fn serialized_values_count_every_photon() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let coord_to_index = discarding_coord_to_index(&voxel_delta, vec![0]);
+        let corner =
+            |x: f32| ImageCoor::new(OrderedFloat(x), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 2);
+        fb.add_to_render_queue(corner(-0.5), 0);
+        for _ in 0..7 {
+            fb.add_to_render_queue(corner(0.5), 0);
+        }
+        let (_, xs, _, _, values, _) = coord_to_index.mapping.map_data_to_indices(fb);
+        let mut counts: Vec<_> = xs.into_iter().zip(values).collect();
+        counts.sort_unstable();
+        assert_eq!(counts, vec![(0, 1), (9, 7)]);
+    }
+
+    #[test]
+    fn overlapping_channels_are_serialized_with_their_own_counts() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let coord_to_index = discarding_coord_to_index(&voxel_delta, vec![0, 1]);
+        let corner =
+            |x: f32| ImageCoor::new(OrderedFloat(x), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let mut fb = FrameBuffers::new(1.25, ColorMode::DirectRGB, 2)
+            .with_display_decimation(3)
+            .with_max_render_points(1)
+            .with_display_min_count(4)
+            .with_display_gamma(2.0);
+        for (point, channel, photons) in [(-0.5, 0, 5), (-0.5, 1, 3), (0.5, 0, 2), (0.5, 1, 6)] {
+            for _ in 0..photons {
+                fb.add_to_render_queue(corner(point), channel);
+            }
+        }
+        fb.hide_low_count_voxels();
+        fb.adjust_exposure();
+        fb.apply_display_gamma();
+        let (channels, xs, _, _, values, _) = coord_to_index.mapping.map_data_to_indices(fb);
+        let mut counts: Vec<_> = channels
+            .into_iter()
+            .zip(xs)
+            .zip(values)
+            .map(|((channel, x), value)| (channel, x, value))
+            .collect();
+        counts.sort_unstable();
+        assert_eq!(counts, vec![(0, 0, 5), (0, 9, 2), (1, 0, 3), (1, 9, 6)]);
+    }
+
+    #[test]
+    fn serialized_columns_follow_the_requested_order() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let writer: Box<dyn Write> = Box::new(io::sink());
+        let order = [
+            SerializedColumn::Value,
+            SerializedColumn::Z,
+            SerializedColumn::X,
+            SerializedColumn::Y,
+            SerializedColumn::Channel,
+        ];
+        let coord_to_index =
+            CoordToIndex::try_with_writer(&voxel_delta, None, writer, 0, vec![0, 1], None, &order)
+                .unwrap();
+        let voxel = ImageCoor::new(OrderedFloat(0.5), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        for _ in 0..3 {
+            fb.add_to_render_queue(voxel, 1);
+        }
+        let rb = coord_to_index.mapping.frame_to_recordbatch(fb);
+        let names: Vec<&str> = rb
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(names, vec!["value", "z", "x", "y", "channel"]);
+        let u8_column = |i: usize| {
+            rb.column(i)
+                .as_any()
+                .downcast_ref::<UInt8Array>()
+                .unwrap()
+                .value(0)
+        };
+        let u32_column = |i: usize| {
+            rb.column(i)
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .unwrap()
+                .value(0)
+        };
+        assert_eq!(u8_column(0), 3);
+        assert_eq!(u32_column(1), 0);
+        assert_eq!(u32_column(2), 9);
+        assert_eq!(u32_column(3), 0);
+        assert_eq!(u8_column(4), 1);
+    }
+
+    #[test]
+    fn saturated_voxels_are_corrected_for_pileup() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let mut filename = std::env::temp_dir();
+        filename.push("saturated_voxels_are_corrected_for_pileup");
+        let correction = PileupCorrection::Poisson { max_count: 10.0 };
+        let writer = table_file_writer(&filename, OutputCompression::Uncompressed).unwrap();
+        let coord_to_index = CoordToIndex::try_with_writer(
+            &voxel_delta,
+            None,
+            writer,
+            0,
+            vec![0],
+            Some(correction),
+            &SerializedColumn::DEFAULT_ORDER,
+        )
+        .unwrap();
+        let voxel = ImageCoor::new(OrderedFloat(-0.5), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        for _ in 0..10 {
+            fb.add_to_render_queue(voxel, 0);
+        }
+        let (channels, xs, ys, zs, values, lifetimes) =
+            coord_to_index.mapping.map_data_to_indices(fb);
+        let raw = values[0] as f32;
+        assert_eq!(raw, 10.0);
+        let rb = coord_to_index
+            .mapping
+            .convert_vecs_to_recordbatch(channels, xs, ys, zs, values, lifetimes);
+        std::fs::remove_file(filename.with_extension("arrow_stream")).unwrap();
+        let corrected = rb
+            .column(5)
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .unwrap()
+            .value(0);
+        assert!(corrected.is_finite());
+        assert!(corrected > raw);
+        assert!((corrected - correction.correct(9.0)).abs() < 1e-4);
+    }
+
+    /// Serializes the frame using the given compression and returns the path
+    /// of the written stream
+    fn serialize_test_frame(
+        name: &str,
+        voxel_delta: &VoxelDelta<Coordinate>,
+        fb: &FrameBuffers,
+        compression: OutputCompression,
+    ) -> PathBuf {
+        let mut filename = std::env::temp_dir();
+        filename.push(name);
+        let writer = table_file_writer(&filename, compression).unwrap();
+        let mut coord_to_index = CoordToIndex::try_with_writer(
+            voxel_delta,
+            None,
+            writer,
+            0,
+            vec![0],
+            None,
+            &SerializedColumn::DEFAULT_ORDER,
+        )
+        .unwrap();
+        let rb = coord_to_index.mapping.frame_to_recordbatch(fb.clone());
+        coord_to_index.serialize_to_stream(rb).unwrap();
+        coord_to_index.stream.finish().unwrap();
+        drop(coord_to_index);
+        filename.with_extension(compression.extension())
+    }
+
+    /// Reads the single batch of a serialized stream
+    fn read_single_batch<R: std::io::Read>(mut reader: R) -> RecordBatch {
+        let meta = read_stream_metadata(&mut reader).unwrap();
+        match StreamReader::new(reader, meta).next().unwrap().unwrap() {
+            StreamState::Some(batch) => batch,
+            StreamState::Waiting => panic!("The stream is missing its batch"),
+        }
+    }
+
+    #[test]
+    fn compressed_stream_decodes_to_the_same_smaller_data() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let (rows, _) = voxel_delta.map_coord_to_index();
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        for row in rows.keys() {
+            for column in rows.keys() {
+                fb.add_to_render_queue(ImageCoor::new(*row, *column, OrderedFloat(0.0)), 0);
+            }
+        }
+        let uncompressed = serialize_test_frame(
+            "compressed_stream_uncompressed",
+            &voxel_delta,
+            &fb,
+            OutputCompression::Uncompressed,
+        );
+        let compressed = serialize_test_frame(
+            "compressed_stream_zstd",
+            &voxel_delta,
+            &fb,
+            OutputCompression::Zstd { level: 3 },
+        );
+        let uncompressed_size = std::fs::metadata(&uncompressed).unwrap().len();
+        let compressed_size = std::fs::metadata(&compressed).unwrap().len();
+        let expected = read_single_batch(File::open(&uncompressed).unwrap());
+        let decoded = read_single_batch(
+            zstd::stream::read::Decoder::new(File::open(&compressed).unwrap()).unwrap(),
+        );
+        std::fs::remove_file(&uncompressed).unwrap();
+        std::fs::remove_file(&compressed).unwrap();
+        assert!(compressed_size < uncompressed_size);
+        assert_eq!(decoded.num_rows(), 100);
+        assert_eq!(decoded, expected);
+    }
+
+    /// An in-memory output which remains readable after its writer is dropped
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_written_to_any_writer_reads_back() {
+        let config = setup_small_volume().build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let buffer = SharedBuffer::default();
+        let writer = compressed_writer(buffer.clone(), OutputCompression::Uncompressed).unwrap();
+        let mut coord_to_index = CoordToIndex::try_with_writer(
+            &voxel_delta,
+            None,
+            writer,
+            0,
+            vec![0],
+            None,
+            &SerializedColumn::DEFAULT_ORDER,
+        )
+        .unwrap();
+        let corner =
+            |x: f32| ImageCoor::new(OrderedFloat(x), OrderedFloat(-0.5), OrderedFloat(0.0));
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        fb.add_to_render_queue(corner(-0.5), 0);
+        fb.add_to_render_queue(corner(0.5), 0);
+        let (channels, xs, ys, zs, values, lifetimes) =
+            coord_to_index.mapping.map_data_to_indices(fb);
+        let rb = coord_to_index
+            .mapping
+            .convert_vecs_to_recordbatch(channels, xs, ys, zs, values, lifetimes);
+        coord_to_index.serialize_to_stream(rb.clone()).unwrap();
+        coord_to_index.stream.finish().unwrap();
+        drop(coord_to_index);
+        let written = buffer.0.borrow().clone();
+        let decoded = read_single_batch(written.as_slice());
+        assert_eq!(decoded.num_rows(), 2);
+        assert_eq!(decoded, rb);
+    }
+
+    /// Serializes the frames using the given number of threads and returns
+    /// the written bytes
+    fn serialize_frames_with_threads(
+        name: &str,
+        frames: &[FrameBuffers],
+        threads: usize,
+    ) -> Vec<u8> {
+        let mut filename = std::env::temp_dir();
+        filename.push(name);
+        let config = setup_small_volume()
+            .with_filename(filename.to_str().unwrap().to_string())
+            .with_serialization_threads(threads)
+            .build();
+        let voxel_delta = VoxelDelta::<Coordinate>::from_config(&config);
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        for frame in frames {
+            sender.send(frame.clone()).unwrap();
+        }
+        drop(sender);
+        serialize_data(receiver, voxel_delta, None, config);
+        let path = filename.with_extension("arrow_stream");
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        written
+    }
+
+    #[test]
+    fn multithreaded_serialization_writes_the_same_bytes() {
+        let frames: Vec<FrameBuffers> = (0..9)
+            .map(|frame| {
+                let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+                for photon in 0..=frame {
+                    let x = -0.5 + photon as f32 / 9.0;
+                    let voxel =
+                        ImageCoor::new(OrderedFloat(x), OrderedFloat(-0.5), OrderedFloat(0.0));
+                    fb.add_to_render_queue(voxel, photon % 2);
+                }
+                fb
+            })
+            .collect();
+        let single = serialize_frames_with_threads("rpysight_single_threaded", &frames, 1);
+        let multi = serialize_frames_with_threads("rpysight_multi_threaded", &frames, 4);
+        assert!(!single.is_empty());
+        assert_eq!(single, multi);
+    }
+
+    #[test]
+    fn voxels_below_the_min_count_are_only_serialized() {
+        let mut fb =
+            FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 8).with_display_min_count(3);
+        for _ in 0..2 {
+            fb.add_to_render_queue(point_at(0.1), 0);
+        }
+        for _ in 0..3 {
+            fb.add_to_render_queue(point_at(0.2), 0);
+        }
+        fb.hide_low_count_voxels();
+        assert!(!fb.merged_channel().contains_key(&point_at(0.1)));
+        assert!(fb.merged_channel().contains_key(&point_at(0.2)));
+        assert!(fb.get_agg_channel(0).contains_key(&point_at(0.1)));
+    }
+
+    #[test]
+    fn auto_exposure_dims_brightening_frames_towards_the_target() {
+        let target = 0.3;
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 8)
+            .with_auto_exposure(Some(AutoExposure { target, rate: 0.5 }));
+        let render_frame = |fb: &mut FrameBuffers, photons: usize| {
+            for x in 0..4 {
+                for _ in 0..photons {
+                    fb.add_to_render_queue(point_at(x as f32 * 0.1), 0);
+                }
+            }
+            let mean = fb.mean_brightness().unwrap();
+            fb.adjust_exposure();
+            fb.merged_channel().clear();
+            fb.clear_merged_counts();
+            fb.clear_non_rendered_channels();
+            mean
+        };
+        let mut previous_increment = fb.color_increment();
+        for photons in [10, 20, 40] {
+            let first_mean = render_frame(&mut fb, photons);
+            assert!(first_mean > target);
+            for _ in 0..10 {
+                render_frame(&mut fb, photons);
+            }
+            assert_approx_eq!(render_frame(&mut fb, photons), target, 0.01);
+            assert!(fb.color_increment() < previous_increment);
+            previous_increment = fb.color_increment();
+        }
+    }
+
+    #[test]
+    fn display_gamma_brightens_half_intensity_voxels() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::DirectRGB, 1)
+            .with_color_levels(0.0, 0.5)
+            .with_display_gamma(2.0);
+        fb.add_to_render_queue(point(), 0);
+        assert_approx_eq!(fb.merged_channel()[&point()].x, 0.5);
+        fb.apply_display_gamma();
+        let color = fb.merged_channel()[&point()];
+        assert_approx_eq!(color.x, 0.5f32.sqrt());
+        assert_approx_eq!(color.y, 0.0);
+    }
+
+    #[test]
+    fn voxels_are_colored_by_the_colormap_of_their_plane() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::DirectRGB, 4)
+            .with_depth_color_mode(DepthColorMode::Viridis);
+        let plane = |z: f32| ImageCoor::new(OrderedFloat(0.0), OrderedFloat(0.0), OrderedFloat(z));
+        fb.add_to_render_queue(plane(-0.5), 0);
+        fb.add_to_render_queue(plane(0.0), 1);
+        fb.add_to_render_queue(plane(0.5), 2);
+        fb.add_to_render_queue(plane(0.375), 3);
+        let expected = [
+            (-0.5, [0.267, 0.005, 0.329]),
+            (0.0, [0.128, 0.567, 0.551]),
+            (0.5, [0.993, 0.906, 0.144]),
+            (0.375, [0.681, 0.8475, 0.2635]),
+        ];
+        for (z, color) in expected {
+            let rendered = fb.color_at(&plane(z));
+            for (comp, expected) in rendered.coords.iter().zip(color.iter()) {
+                assert_approx_eq!(comp, expected * GRAYSCALE_START);
+            }
+        }
+        let planar = FrameBuffers::from_config(
+            &AppConfigBuilder::default()
+                .with_planes(1)
+                .with_depth_color_mode(DepthColorMode::Viridis)
+                .build(),
+        );
+        assert_eq!(planar.depth_color(&plane(0.0)), None);
+    }
+
+    #[test]
+    fn points_beyond_render_cap_are_only_serialized() {
+        let mut fb =
+            FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 8).with_max_render_points(3);
+        for x in 0..5 {
+            fb.add_to_render_queue(point_at(x as f32 * 0.1), 0);
+        }
+        fb.add_to_render_queue(point_at(0.0), 0);
+        assert_eq!(fb.len(), 3);
+        assert_eq!(fb.get_agg_channel(0).len(), 5);
+        assert!(!fb.merged_channel().contains_key(&point_at(0.4)));
+        fb.merged_channel().clear();
+        fb.clear_merged_counts();
+        fb.add_to_render_queue(point_at(0.4), 0);
+        assert_eq!(fb.len(), 1);
+    }
+
+    #[test]
+    fn centroid_is_weighted_by_photon_counts() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 4);
+        assert_eq!(fb.centroid(), None);
+        let left = ImageCoor::new(OrderedFloat(-0.5), OrderedFloat(0.0), OrderedFloat(0.0));
+        let right = ImageCoor::new(OrderedFloat(0.5), OrderedFloat(0.25), OrderedFloat(0.0));
+        for _ in 0..2 {
+            fb.add_to_render_queue(left, 0);
+        }
+        for _ in 0..4 {
+            fb.add_to_render_queue(right, 1);
+        }
+        let left_count = fb.get_agg_channel(0)[&left].count as f32;
+        let right_count = fb.get_agg_channel(1)[&right].count as f32;
+        let total = left_count + right_count;
+        let centroid = fb.centroid().unwrap();
+        assert_approx_eq!(*centroid.x, (-0.5 * left_count + 0.5 * right_count) / total);
+        assert_approx_eq!(*centroid.y, 0.25 * right_count / total);
+        assert_approx_eq!(*centroid.z, 0.0);
+    }
+
+    #[test]
+    fn frame_image_matches_the_pixel_grid_of_the_aggregation() {
+        let config = AppConfigBuilder::default()
+            .with_rows(3)
+            .with_columns(4)
+            .with_planes(1)
+            .with_color_mode(ColorMode::DirectRGB)
+            .with_background_level(0.2)
+            .build();
+        let mut fb = FrameBuffers::from_config(&config);
+        let (rows, columns) = VoxelDelta::<Coordinate>::from_config(&config).map_coord_to_index();
+        let rows: Vec<_> = rows.keys().copied().collect();
+        let columns: Vec<_> = columns.keys().copied().collect();
+        let red = ImageCoor::new(rows[1], columns[2], OrderedFloat(0.0));
+        let green = ImageCoor::new(rows[2], columns[0], OrderedFloat(0.0));
+        fb.add_to_render_queue(red, 0);
+        fb.add_to_render_queue(red, 0);
+        fb.add_to_render_queue(green, 1);
+        let mut image = FrameImage::from_config(&config);
+        image.fill_from(fb.merged_channel());
+        let background = 51;
+        let mut expected = [background; 3 * 4 * 3];
+        let red_start = (4 + 2) * 3;
+        expected[red_start..red_start + 3].copy_from_slice(&[16, 0, 0]);
+        let green_start = (2 * 4) * 3;
+        expected[green_start..green_start + 3].copy_from_slice(&[0, 13, 0]);
+        assert_eq!((image.rows(), image.columns()), (3, 4));
+        assert_eq!(image.pixels(), &expected[..]);
+        image.clear();
+        assert!(image.pixels().iter().all(|pixel| *pixel == background));
+    }
+
+    #[test]
+    fn volume_projection_keeps_the_brightest_voxel_of_each_pixel() {
+        let config = AppConfigBuilder::default()
+            .with_rows(3)
+            .with_columns(4)
+            .with_planes(1)
+            .build();
+        let grid = VoxelDelta::<Coordinate>::from_config(&config);
+        let (rows, columns) = grid.map_coord_to_index();
+        let rows: Vec<_> = rows.keys().copied().collect();
+        let columns: Vec<_> = columns.keys().copied().collect();
+        let bottom = ImageCoor::new(rows[1], columns[2], OrderedFloat(-0.5));
+        let top = ImageCoor::new(rows[1], columns[2], OrderedFloat(0.5));
+        let single = ImageCoor::new(rows[2], columns[0], OrderedFloat(0.0));
+        let mut fb = FrameBuffers::from_config(&config);
+        fb.add_to_render_queue(bottom, 0);
+        fb.add_to_render_queue(bottom, 0);
+        fb.add_to_render_queue(top, 0);
+        fb.add_to_render_queue(top, 1);
+        fb.add_to_render_queue(top, 2);
+        fb.add_to_render_queue(single, 3);
+        let mut expected = vec![0u32; 3 * 4];
+        expected[4 + 2] = 3;
+        expected[2 * 4] = 1;
+        assert_eq!(fb.project_volume(&grid, 3, 4), expected);
+    }
+
+    #[test]
+    fn sibling_paths_are_named_after_the_data() {
+        let filename = "/data/session/run.arrow_stream";
+        let sibling = |name: &str| PathBuf::from(format!("/data/session/{}", name));
+        assert_eq!(frame_table_path(filename), sibling("run_frames.csv"));
+        assert_eq!(raw_events_path(filename), sibling("run_raw.arrow_stream"));
+        assert_eq!(mip_preview_path(filename, 12), sibling("run_mip_12.png"));
+        assert_eq!(thumbnails_path(filename), sibling("run_thumbnails.bin"));
+    }
+
+    #[test]
+    fn frame_counts_hold_only_the_photons_since_the_previous_frame() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        fb.add_to_render_queue(point_at(0.0), 0);
+        fb.add_to_render_queue(point_at(0.0), 1);
+        assert_eq!(fb.take_frame_counts()[&point_at(0.0)], 2);
+        fb.add_to_render_queue(point_at(0.0), 0);
+        fb.add_to_render_queue(point_at(0.5), 0);
+        let counts = fb.take_frame_counts();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&point_at(0.0)], 1);
+        assert_eq!(counts[&point_at(0.5)], 1);
+        assert!(fb.take_frame_counts().is_empty());
+        fb.clear_non_rendered_channels();
+        fb.add_to_render_queue(point_at(0.0), 0);
+        assert_eq!(fb.take_frame_counts()[&point_at(0.0)], 1);
+    }
+
+    #[test]
+    fn photons_replace_the_missing_data_color() {
+        let mut fb = FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1);
+        fb.add_to_render_queue(point_at(0.5), 0);
+        let color = fb.color_at(&point_at(0.5));
+        fb.mark_missing(point_at(0.5));
+        fb.mark_missing(point());
+        assert!(!fb.is_missing(&point_at(0.5)));
+        assert_eq!(fb.color_at(&point_at(0.5)), color);
+        assert!(fb.is_missing(&point()));
+        assert_eq!(fb.color_at(&point()), Point3::from(MISSING_DATA_COLOR));
+        fb.add_to_render_queue(point(), 0);
+        assert!(!fb.is_missing(&point()));
+        assert_eq!(fb.color_at(&point()), DISPLAY_COLORS[0]);
+    }
+
+    #[test]
+    fn finished_raw_recordings_end_their_stream() {
+        let mut path = std::env::temp_dir();
+        path.push("finished_raw_recordings_end_their_stream.arrow_stream");
+        let schema = Arc::new(Schema::new(vec![Field::new("type_", UInt8, false)]));
+        let column = Arc::new(UInt8Array::from_slice([0, 1]));
+        let batch = RecordBatch::try_new(schema, vec![column]).unwrap();
+        let mut recorder = RawEventRecorder::new(&path);
+        recorder.push(&batch).unwrap();
+        recorder.finish().unwrap();
+        let mut reader = File::open(&path).unwrap();
+        let meta = read_stream_metadata(&mut reader).unwrap();
+        let mut stream = StreamReader::new(reader, meta);
+        let recorded = stream.next();
+        let end = stream.next();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(recorded, Some(Ok(StreamState::Some(read))) if read == batch));
+        assert!(end.is_none());
+        assert!(stream.is_finished());
+    }
+
+    #[test]
+    fn missing_voxels_respect_the_render_cap() {
+        let mut fb =
+            FrameBuffers::new(1.25, ColorMode::PerChannelPalette, 1).with_max_render_points(1);
+        fb.add_to_render_queue(point(), 0);
+        fb.mark_missing(point_at(0.5));
+        assert!(!fb.is_missing(&point_at(0.5)));
+        assert_eq!(fb.merged_channel().len(), 1);
+    }
+
+    #[test]
+    fn sparse_clearing_resets_only_the_voxels_drawn_in_the_frame() {
+        let config = AppConfigBuilder::default()
+            .with_rows(3)
+            .with_columns(4)
+            .with_planes(1)
+            .with_sparse_image_clear(true)
+            .build();
+        let (rows, columns) = VoxelDelta::<Coordinate>::from_config(&config).map_coord_to_index();
+        let rows: Vec<_> = rows.keys().copied().collect();
+        let columns: Vec<_> = columns.keys().copied().collect();
+        let voxel = |row: usize, column: usize| {
+            ImageCoor::new(rows[row], columns[column], OrderedFloat(0.0))
+        };
+        let mut fb = FrameBuffers::from_config(&config);
+        fb.add_to_render_queue(voxel(1, 2), 0);
+        fb.add_to_render_queue(voxel(2, 0), 1);
+        let mut image = FrameImage::from_config(&config);
+        image.fill_from(fb.merged_channel());
+        let mut drawn = image.drawn.clone().unwrap();
+        drawn.sort_unstable();
+        assert_eq!(drawn, vec![(4 + 2) * 3, (2 * 4) * 3]);
+        image.clear();
+        assert!(image.drawn.as_ref().unwrap().is_empty());
+        assert_eq!(image, FrameImage::from_config(&config));
+    }
+
+    #[test]
+    fn downsampling_sums_the_pixels_of_each_bin() {
+        let image: Vec<u32> = (0..4 * 6).collect();
+        let binned = downsample(&image, 4, 6, 2);
+        assert_eq!(binned, vec![24, 42, 96, 114]);
+        assert_eq!(binned.iter().sum::<u32>(), image.iter().sum::<u32>());
+    }
+}
@@ -5,15 +5,20 @@
 extern crate log;
 use std::collections::BTreeMap;
 use std::f32::consts::PI;
+use std::fs::{read_to_string, write};
 use std::ops::Index;
+use std::path::Path;
 
+use anyhow::Result;
 use itertools_num::linspace;
 use nalgebra::DVector;
 use num_traits::{FromPrimitive, ToPrimitive};
 use ordered_float::{Float, OrderedFloat};
 use serde::{Deserialize, Serialize};
 
-use crate::configuration::{AppConfig, Bidirectionality, Period};
+use crate::configuration::{
+    AppConfig, Bidirectionality, FieldMask, LineShift, LineTiming, Period, PlaneRoi,
+};
 use crate::point_cloud_renderer::{ImageCoor, ProcessedEvent};
 
 /// The image bounds as the renderer requires - start, center and end
@@ -31,7 +36,7 @@ pub type Picosecond = i64;
 pub type Coordinate = OrderedFloat<f32>;
 
 /// Pixelization of the rendered volume
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 struct VolumeSize {
     rows: u32,
     columns: u32,
@@ -57,14 +62,15 @@ impl ImageDelta for Picosecond {}
 
 /// Data regarding the step size, either in image space or in picoseconds, that
 /// is needed to construct the 'snake' data vector of [`TimeToCoord`].
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct VoxelDelta<T: ImageDelta> {
     column: T,
+    line: T,
     row: T,
     plane: T,
     frame: T,
-    volsize: VolumeSize,
     num_planes: u32,
+    volsize: VolumeSize,
 }
 
 impl VoxelDelta<Coordinate> {
@@ -81,6 +87,7 @@ impl VoxelDelta<Coordinate> {
 
         VoxelDelta {
             column: jump_between_columns,
+            line: RENDERING_SPAN,
             row: jump_between_rows,
             plane: jump_between_planes,
             frame: OrderedFloat::nan(),
@@ -111,6 +118,57 @@ impl VoxelDelta<Coordinate> {
         (coord_to_index_rows, coord_to_index_cols)
     }
 
+    /// Snaps the row and column of the point to the nearest voxel of the
+    /// rendered grid.
+    ///
+    /// The snapped values are identical to the ones used by
+    /// [`VoxelDelta::map_coord_to_index`], so tiny floating point differences
+    /// between coordinates of the same voxel don't split it. Planes are
+    /// already taken from a fixed list of values and are left as they are.
+    pub(crate) fn snap_to_grid(&self, point: ImageCoor) -> ImageCoor {
+        ImageCoor::new(
+            VoxelDelta::snap_single_coord(point.x, self.row, self.volsize.rows),
+            VoxelDelta::snap_single_coord(point.y, self.column, self.volsize.columns),
+            point.z,
+        )
+    }
+
+    /// The row and column indices of the voxel nearest to the point, or `None`
+    /// if the point was masked
+    pub(crate) fn voxel_index(&self, point: &ImageCoor) -> Option<(u32, u32)> {
+        Some((
+            VoxelDelta::single_coord_index(point.x, self.row, self.volsize.rows)?,
+            VoxelDelta::single_coord_index(point.y, self.column, self.volsize.columns)?,
+        ))
+    }
+
+    /// The index of the nearest of `num` steps to a single coordinate
+    fn single_coord_index(coord: Coordinate, step: Coordinate, num: u32) -> Option<u32> {
+        if coord.is_nan() {
+            return None;
+        }
+        if num < 2 {
+            return Some(0);
+        }
+        let idx = ((coord - RENDERING_BOUNDS.0) / step)
+            .into_inner()
+            .round()
+            .clamp(0.0, (num - 1) as f32);
+        Some(idx as u32)
+    }
+
+    /// Snaps a single coordinate to the nearest of `num` steps
+    fn snap_single_coord(coord: Coordinate, step: Coordinate, num: u32) -> Coordinate {
+        if coord.is_nan() || num < 2 {
+            return coord;
+        }
+        let idx = ((coord - RENDERING_BOUNDS.0) / step)
+            .into_inner()
+            .round()
+            .clamp(0.0, (num - 1) as f32);
+        RENDERING_BOUNDS.0 + (OrderedFloat(idx) * step)
+    }
+
     /// Create a single mapping between a coordinate vector and its index
     fn create_single_coord_idx_mapping(
         num: u32,
@@ -127,14 +185,28 @@ impl VoxelDelta<Coordinate> {
     }
 }
 
+/// The time since the start of the line at which column `index` ends.
+///
+/// Each end time is rounded independently from the exact fraction of the line
+/// period, so the rounding error never accumulates: consecutive columns differ
+/// by at most a single picosecond and the last column ends exactly at the end
+/// of the line.
+fn column_end_time(line_period: Picosecond, num_columns: usize, index: usize) -> Picosecond {
+    let num_columns = num_columns as i128;
+    let numerator = 2 * (index as i128 + 1) * line_period as i128;
+    ((numerator + num_columns) / (2 * num_columns)) as Picosecond
+}
+
 impl VoxelDelta<Picosecond> {
     pub(crate) fn from_config(config: &AppConfig) -> VoxelDelta<Picosecond> {
         let time_between_columns = VoxelDelta::calc_time_between_columns(&config);
+        let effective_line_period = VoxelDelta::calc_effective_line_period(config);
         let time_between_rows = VoxelDelta::calc_time_between_rows(&config);
         let time_between_planes = VoxelDelta::calc_time_between_planes(&config);
         let time_between_frames = config.frame_dead_time;
         VoxelDelta {
             column: time_between_columns,
+            line: effective_line_period,
             row: time_between_rows,
             plane: time_between_planes,
             frame: time_between_frames,
@@ -146,15 +218,13 @@ impl VoxelDelta<Picosecond> {
     /// Number of picosecond between consecutive voxels in a single 2D line,
     /// barring any TAG-related scanning
     fn calc_time_between_columns(config: &AppConfig) -> Picosecond {
-        let effective_line_period = VoxelDelta::calc_effective_line_period(&config);
-        effective_line_period / (config.columns as Picosecond)
+        *config.pixel_dwell_time()
     }
 
     /// The time the scanner is effectively inside the image space. This time
     /// is different than the scan period due to the fill fraction
     fn calc_effective_line_period(config: &AppConfig) -> Picosecond {
-        ((*config.scan_period / 2) as f64 * (config.fill_fraction / 100.0) as f64).round()
-            as Picosecond
+        config.calc_effective_line_period()
     }
 
     /// Number of Picoseconds between consecutive Z-planes
@@ -168,8 +238,12 @@ impl VoxelDelta<Picosecond> {
     /// the mirror to slow down, turn and accelerate back to the next line in
     /// the opposite direction. If the scan is unidirectional then the method
     /// factors in the time it takes the mirror to move to its starting
-    /// position in the opposite side of the image.
+    /// position in the opposite side of the image. Sawtooth scanners spend
+    /// their retrace fraction of the scan period flying back.
     fn calc_time_between_rows(config: &AppConfig) -> Picosecond {
+        if config.is_sawtooth() {
+            return *config.scan_period - VoxelDelta::calc_effective_line_period(config);
+        }
         let full_time_per_line = *config.scan_period / 2;
         let effective_line_period = VoxelDelta::calc_effective_line_period(&config);
         let deadtime_during_rotation = full_time_per_line - effective_line_period;
@@ -185,18 +259,208 @@ impl VoxelDelta<Picosecond> {
 /// The mapping\pairing between a time in ps since the start of the experiment
 /// and the image-space coordinate that this time corresponds to for the
 /// current rendered volume.
+///
+/// When requested, the pair also carries the array indices of its voxel, so
+/// that its photons can be serialized without mapping their coordinates back
+/// to indices.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TimeCoordPair {
     pub end_time: Picosecond,
     pub coord: ImageCoor,
+    pub index: Option<VoxelIndex>,
 }
 
 impl TimeCoordPair {
     pub fn new(end_time: Picosecond, coord: ImageCoor) -> TimeCoordPair {
-        TimeCoordPair { end_time, coord }
+        TimeCoordPair {
+            end_time,
+            coord,
+            index: None,
+        }
     }
 }
 
+/// The array indices of a voxel, as written to the serialized table
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoxelIndex {
+    pub row: u32,
+    pub column: u32,
+    pub plane: u32,
+}
+
+/// Assigns each cell of a planar snake the indices of its voxel. Cells which
+/// aren't inside the image, e.g. masked cells or the ones of the mirror
+/// rotation, are left without indices.
+fn index_snake(data: &mut [TimeCoordPair], voxel_delta: &VoxelDelta<Coordinate>) {
+    for pair in data.iter_mut() {
+        pair.index = voxel_delta
+            .voxel_index(&pair.coord)
+            .map(|(row, column)| VoxelIndex {
+                row,
+                column,
+                plane: 0,
+            });
+    }
+}
+
+/// The coordinate of cells outside of the field mask. Like the cells of the
+/// mirror rotation it's a NaN, but its sign bit is set so that the two can be
+/// told apart.
+const MASKED_COORD: f32 = -f32::NAN;
+
+/// Whether the coordinate belongs to a cell outside of the field mask
+pub(crate) fn is_masked(coord: &ImageCoor) -> bool {
+    coord.x.is_nan() && coord.x.is_sign_negative()
+}
+
+/// The coordinate of cells outside of the field mask
+fn masked_coord() -> ImageCoor {
+    ImageCoor::new(
+        OrderedFloat(MASKED_COORD),
+        OrderedFloat(MASKED_COORD),
+        OrderedFloat(MASKED_COORD),
+    )
+}
+
+/// The row and column of the coordinate normalized to [-1.0, 1.0], with the
+/// center of the field of view at 0
+fn normalized_row_column(coord: &ImageCoor) -> (f32, f32) {
+    let half_span = *RENDERING_SPAN / 2.0;
+    (
+        (*coord.x - *RENDERING_BOUNDS.1) / half_span,
+        (*coord.y - *RENDERING_BOUNDS.1) / half_span,
+    )
+}
+
+/// Replaces the coordinates of the cells outside of the field mask with NaNs.
+///
+/// Photons arriving at these cells are then discarded, just like those
+/// arriving during the mirror rotation.
+fn apply_field_mask(data: &mut [TimeCoordPair], mask: &FieldMask) {
+    for pair in data.iter_mut() {
+        let (row, column) = normalized_row_column(&pair.coord);
+        if !row.is_nan() && !column.is_nan() && !mask.contains(row, column) {
+            pair.coord = masked_coord();
+        }
+    }
+}
+
+/// Describes the divergence of a generated snake's length from the expected
+/// one, if it's larger than the given tolerance.
+///
+/// The description contains the configuration values which determine the
+/// length, so that the misconfiguration behind it can be tracked down.
+fn snake_length_mismatch(
+    actual: usize,
+    expected: usize,
+    tolerance: usize,
+    config: &AppConfig,
+) -> Option<String> {
+    if actual.abs_diff(expected) <= tolerance {
+        return None;
+    }
+    Some(format!(
+        "The snake has {} cells instead of the expected {}, so the image may be wrong. \
+        Rows: {}, columns: {}, planes: {}, scan direction: {:?}, line timing: {:?}",
+        actual,
+        expected,
+        config.rows,
+        config.columns,
+        config.planes,
+        config.bidir,
+        config.line_timing
+    ))
+}
+
+/// The length of a snake covering a single plane, with a cell before the frame
+/// and one ending each row
+fn planar_snake_length(config: &AppConfig) -> usize {
+    ((config.columns + 1) * config.rows) as usize + 1
+}
+
+/// Verifies that a snake has the length expected from the image dimensions
+/// if the configuration asks for this check, logging a warning and returning
+/// the description of the mismatch if it doesn't.
+fn verify_snake_length(actual: usize, expected: usize, config: &AppConfig) -> Option<String> {
+    let tolerance = config.snake_length_tolerance?;
+    let mismatch = snake_length_mismatch(actual, expected, tolerance, config)?;
+    warn!("{}", mismatch);
+    Some(mismatch)
+}
+
+/// Verifies the length of a freshly generated snake, see
+/// [`verify_snake_length`].
+///
+/// Generated snakes only diverge from the expected length when their
+/// construction is broken, which debug builds assert.
+fn verify_generated_snake_length(actual: usize, expected: usize, config: &AppConfig) {
+    debug_assert_eq!(actual, expected, "Generated snake has an unexpected length");
+    verify_snake_length(actual, expected, config);
+}
+
+/// The clock edges of a scan, as they would appear on an oscilloscope
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaveformEvent {
+    FrameStart,
+    LineStart,
+}
+
+impl WaveformEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WaveformEvent::FrameStart => "frame_start",
+            WaveformEvent::LineStart => "line_start",
+        }
+    }
+}
+
+/// The start times of the frame and of each of its lines, derived from the end
+/// times of the cells preceding them: the cell capturing photons arriving
+/// between frames, and the cells of the mirror rotation at the end of each row.
+fn timing_waveform_of(
+    data: &[TimeCoordPair],
+    volsize: VolumeSize,
+) -> Vec<(Picosecond, WaveformEvent)> {
+    let cells_per_row = volsize.columns as usize + 1;
+    let mut waveform = Vec::with_capacity(volsize.rows as usize + 1);
+    if let Some(first) = data.first() {
+        waveform.push((first.end_time, WaveformEvent::FrameStart));
+    }
+    waveform.extend(
+        data.iter()
+            .step_by(cells_per_row)
+            .take(volsize.rows as usize)
+            .map(|pair| (pair.end_time, WaveformEvent::LineStart)),
+    );
+    waveform
+}
+
+/// The coordinates visited by the scanner, in the order of the snake's cells.
+///
+/// Cells without a coordinate, like those of the mirror rotation or outside
+/// of the field mask, are skipped, so that a line connecting consecutive
+/// coordinates traces the sweep of each row and the flyback to the next one.
+fn scan_path_of(data: &[TimeCoordPair]) -> Vec<ImageCoor> {
+    data.iter()
+        .map(|pair| pair.coord)
+        .filter(|coord| !coord.x.is_nan() && !coord.y.is_nan())
+        .collect()
+}
+
+/// The coordinates of the cells between the ones of the two times, including
+/// the cell of the earlier time but not that of the later one
+fn coords_between_of(data: &[TimeCoordPair], start: Picosecond, end: Picosecond) -> Vec<ImageCoor> {
+    let first = rewound_index(data, start);
+    let last = rewound_index(data, end).max(first);
+    scan_path_of(&data[first..last])
+}
+
+/// The index of the first cell of the snake which ends at or after the given
+/// time, from which the search for its coordinate should start
+fn rewound_index(data: &[TimeCoordPair], time: Picosecond) -> usize {
+    data.partition_point(|pair| pair.end_time < time)
+}
+
 /// Connect each timestamp to its coordinate.
 ///
 /// This struct matches between the Picosecond-based partitioning of the planes
@@ -250,8 +514,9 @@ impl Index<Picosecond> for IntervalToCoordMap {
 pub trait Snake {
     /// Returns the value assigned to the snake's capacity
     ///
-    /// For 2D imaging it's num_rows * (num_columns + 1), and for 3D we add
-    /// in the number of planes.
+    /// It's num_rows * (num_columns + 1) for both 2D and 3D imaging, since
+    /// the plane of each photon in 3D is determined by the phase of the TAG
+    /// lens and not by the snake.
     ///
     /// These numbers take into account a cell before each frame which captures
     /// photons arriving between frames, and a cell we remove from the last row
@@ -267,6 +532,9 @@ pub trait Snake {
 
     fn get_earliest_frame_time(&self) -> Picosecond;
 
+    /// The time at which the current frame ends
+    fn get_max_frame_time(&self) -> Picosecond;
+
     /// Initialize the time -> coordinate mapping assuming that we're starting
     /// the imaging at time `offset` of the experiment.
     ///
@@ -298,7 +566,7 @@ pub trait Snake {
         // during mirror rotation. Their coordinate will contain a NaN value,
         // which means that it will not be rendered.
         let column_deltas_ps = DVector::<Picosecond>::from_fn(num_columns, |i, _| {
-            (i as Picosecond) * voxel_delta_ps.column + voxel_delta_ps.column
+            column_end_time(voxel_delta_ps.line, num_columns, i)
         });
         // Manually add the cell corresponding to events arriving during mirror
         // rotation
@@ -386,8 +654,21 @@ pub trait Snake {
     /// a single step, or perhaps two. This should, in theory, be faster than
     /// other options for this algorithm (which are currently unexplored), such
     /// as binary search, hashmap or an interval tree.
+    ///
+    /// Events with identical time tags, e.g. due to the finite resolution of
+    /// the TimeTagger, are all placed in the same cell, regardless of their
+    /// channel. The search only advances up to the cell it found and never
+    /// past it, so a tied event never skips ahead to the next cell.
     fn time_to_coord_linear(&mut self, time: Picosecond, ch: usize) -> ProcessedEvent;
 
+    /// Moves the search for the coordinates of the following time tags back
+    /// to the cell of the given time.
+    ///
+    /// The linear search only moves forward, so time tags which predate the
+    /// previous one, e.g. those of a batch which arrived out of order, must
+    /// rewind it first. Later times leave the search where it is.
+    fn rewind_to(&mut self, time: Picosecond);
+
     /// Return the Z coordinate of a timetag.
     ///
     /// In the 2D case this method should be left unimplemented.
@@ -404,6 +685,12 @@ pub trait Snake {
         ProcessedEvent::NoOp
     }
 
+    /// Handles a line signal arriving in the middle of the frame.
+    ///
+    /// Snakes following the measured line timing move the row starting with
+    /// this line to its actual time, while others ignore it.
+    fn new_line_event(&mut self, _time: Picosecond) {}
+
     fn new_laser_event(&self, _time: Picosecond) -> ProcessedEvent {
         ProcessedEvent::NoOp
     }
@@ -413,6 +700,24 @@ pub trait Snake {
     }
 
     fn get_voxel_delta_im(&self) -> VoxelDelta<Coordinate>;
+
+    /// The expected frame and line clock edges of the current frame, which
+    /// may be overlaid on an oscilloscope capture of these signals
+    fn timing_waveform(&self) -> Vec<(Picosecond, WaveformEvent)>;
+
+    /// The ordered coordinates of the scan path, for visualizing the scan
+    /// geometry
+    fn scan_path(&self) -> Vec<ImageCoor>;
+
+    /// The coordinates the scanner swept between the two times, up to the
+    /// voxel of the later time, skipping cells without a coordinate
+    fn coords_between(&self, start: Picosecond, end: Picosecond) -> Vec<ImageCoor>;
+
+    /// The voxel indices carried by the cell of the last photon, if the snake
+    /// carries them
+    fn last_voxel_index(&self) -> Option<VoxelIndex> {
+        None
+    }
 }
 
 /// Data and logic for finding the image-space coordinates for the given
@@ -468,6 +773,26 @@ pub struct TwoDimensionalSnake {
     earliest_frame_time: Picosecond,
     /// Time between the end of one frame and the start of the next
     frame_dead_time: Picosecond,
+    /// The end times of the cells relative to the start of the frame, used to
+    /// align the rows with their measured line signals. Empty when the
+    /// nominal line timing is used.
+    nominal_end_times: Vec<Picosecond>,
+    /// The nominal (relative to the start of the frame) and measured start
+    /// times of the last row that was aligned with its line signal
+    last_line: (Picosecond, Picosecond),
+}
+
+/// The state of a [`TwoDimensionalSnake`] which is written to disk, from
+/// which the snake can be reconstituted without its configuration
+#[derive(Serialize, Deserialize)]
+struct SerializedSnake {
+    max_frame_time: Picosecond,
+    earliest_frame_time: Picosecond,
+    frame_dead_time: Picosecond,
+    nominal_end_times: Vec<Picosecond>,
+    voxel_delta_ps: VoxelDelta<Picosecond>,
+    voxel_delta_im: VoxelDelta<Coordinate>,
+    data: Vec<TimeCoordPair>,
 }
 
 pub struct ThreeDimensionalSnake {
@@ -494,6 +819,15 @@ pub struct ThreeDimensionalSnake {
     earliest_frame_time: Picosecond,
     /// Time between the end of one frame and the start of the next
     frame_dead_time: Picosecond,
+    /// The line shift of each plane relative to that of the first plane, which
+    /// was used to build the snake. Empty for unidirectional scans.
+    plane_line_shift_deltas: Vec<Picosecond>,
+    /// The region of interest of each plane, by plane index. Planes without
+    /// one aren't cropped
+    plane_rois: Vec<PlaneRoi>,
+    /// Whether photons of the reverse rows are moved to the row of their
+    /// paired forward row
+    collapse_bidir: bool,
 }
 
 impl TwoDimensionalSnake {
@@ -519,9 +853,55 @@ impl TwoDimensionalSnake {
             max_frame_time: 0,
             earliest_frame_time: 0,
             frame_dead_time: 0,
+            nominal_end_times: Vec::new(),
+            last_line: (0, 0),
         }
     }
 
+    /// Writes the cells of the snake and its voxel deltas to the given path as
+    /// TOML, so that the snake can be inspected or reloaded with
+    /// [`TwoDimensionalSnake::from_serialized`]
+    pub fn dump_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let serialized = SerializedSnake {
+            max_frame_time: self.max_frame_time,
+            earliest_frame_time: self.earliest_frame_time,
+            frame_dead_time: self.frame_dead_time,
+            nominal_end_times: self.nominal_end_times.clone(),
+            voxel_delta_ps: self.voxel_delta_ps,
+            voxel_delta_im: self.voxel_delta_im,
+            data: self.data.clone(),
+        };
+        write(path, toml::to_string(&serialized)?)?;
+        Ok(())
+    }
+
+    /// Compares the length of the snake to the one expected from the image
+    /// dimensions of the configuration, see [`verify_snake_length`]. Loaded
+    /// snakes may have been dumped with other dimensions.
+    pub fn verify_length(&self, config: &AppConfig) -> Option<String> {
+        verify_snake_length(self.data.len() + 1, planar_snake_length(config), config)
+    }
+
+    /// Reconstitutes a snake written by [`TwoDimensionalSnake::dump_to`].
+    ///
+    /// The reloaded snake maps times to coordinates exactly as the dumped one
+    /// did, which separates bugs in the construction of snakes from those of
+    /// the rest of the acquisition.
+    pub fn from_serialized<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let serialized: SerializedSnake = toml::from_str(&read_to_string(path)?)?;
+        Ok(Self {
+            data: serialized.data,
+            last_accessed_idx: 0,
+            max_frame_time: serialized.max_frame_time,
+            voxel_delta_ps: serialized.voxel_delta_ps,
+            voxel_delta_im: serialized.voxel_delta_im,
+            earliest_frame_time: serialized.earliest_frame_time,
+            frame_dead_time: serialized.frame_dead_time,
+            nominal_end_times: serialized.nominal_end_times,
+            last_line: (0, 0),
+        })
+    }
+
     /// Constructs the 1D vector mapping the time of arrival to image-space
     /// coordinates.
     ///
@@ -547,9 +927,14 @@ impl TwoDimensionalSnake {
         // Add the cell capturing all photons arriving between frames
         let deadtime_during_rotation = column_deltas_ps[column_deltas_ps.len() - 1];
         let mut line_offset: Picosecond = offset;
-        let column_deltas_imagespace_rev = self.reverse_row_imagespace(column_deltas_imagespace);
+        let column_deltas_imagespace_rev = if config.mirror_reverse_rows {
+            self.reverse_row_imagespace(column_deltas_imagespace)
+        } else {
+            column_deltas_imagespace.clone()
+        };
         let column_deltas_ps_bidir =
-            self.reverse_row_picosecond(column_deltas_ps, config.line_shift);
+            self.reverse_row_picosecond(column_deltas_ps, config.reverse_line_shift().for_plane(0));
+        let column_deltas_ps_forward = column_deltas_ps.add_scalar(config.forward_line_shift);
         let mut row_coord: Coordinate;
         for row in (0..config.rows).step_by(2) {
             // Start with the unidir row
@@ -557,14 +942,21 @@ impl TwoDimensionalSnake {
             TwoDimensionalSnake::push_pair_unidir(
                 &mut self.data,
                 &column_deltas_imagespace,
-                &column_deltas_ps,
+                &column_deltas_ps_forward,
                 row_coord,
                 line_offset,
             );
             line_offset += deadtime_during_rotation;
-            // Now the bidir row
-            row_coord =
-                (OrderedFloat((row + 1) as f32) * self.voxel_delta_im.row) + RENDERING_BOUNDS.0;
+            // An odd number of rows ends with a forward row
+            if row + 1 == config.rows {
+                break;
+            }
+            // Now the bidir row, which shares the coordinate of the forward
+            // row when the two are collapsed
+            if !config.collapse_bidir {
+                row_coord =
+                    (OrderedFloat((row + 1) as f32) * self.voxel_delta_im.row) + RENDERING_BOUNDS.0;
+            }
             TwoDimensionalSnake::push_pair_unidir(
                 &mut self.data,
                 &column_deltas_imagespace_rev,
@@ -586,6 +978,8 @@ impl TwoDimensionalSnake {
             voxel_delta_im: self.voxel_delta_im,
             earliest_frame_time: offset,
             frame_dead_time: config.frame_dead_time,
+            nominal_end_times: Vec::new(),
+            last_line: (0, offset),
         }
     }
 
@@ -647,6 +1041,8 @@ impl TwoDimensionalSnake {
             voxel_delta_im: self.voxel_delta_im,
             earliest_frame_time: offset,
             frame_dead_time: config.frame_dead_time,
+            nominal_end_times: Vec::new(),
+            last_line: (0, offset),
         }
     }
 }
@@ -676,6 +1072,111 @@ impl ThreeDimensionalSnake {
             max_frame_time: 0,
             earliest_frame_time: 0,
             frame_dead_time: 0,
+            plane_line_shift_deltas: Vec::new(),
+            plane_rois: Vec::new(),
+            collapse_bidir: false,
+        }
+    }
+
+    /// Generate the Picosecond snakes of the bidirectional rows of each plane.
+    ///
+    /// Every plane gets its own line shift, so that plane-dependent timing
+    /// changes induced by the TAG lens can be corrected.
+    fn reverse_row_picosecond_per_plane(
+        &self,
+        column_deltas_ps: &DVector<Picosecond>,
+        line_shift: &LineShift,
+        planes: usize,
+    ) -> Vec<DVector<Picosecond>> {
+        (0..planes.max(1))
+            .map(|plane| self.reverse_row_picosecond(column_deltas_ps, line_shift.for_plane(plane)))
+            .collect()
+    }
+
+    /// Moves a photon of a bidirectional row to the cell matching the line
+    /// shift of its plane.
+    ///
+    /// The snake is built with the line shift of the first plane, so photons
+    /// of other planes are looked up again with their time corrected by the
+    /// difference between the two shifts. The second lookup is confined to
+    /// the row of the photon, and photons whose corrected time falls outside
+    /// of that row were scanned during the mirror rotation of their plane,
+    /// so they're discarded.
+    fn apply_plane_line_shift(&self, coord: ImageCoor, time: Picosecond) -> ImageCoor {
+        if self.plane_line_shift_deltas.is_empty() || coord.x.is_nan() || coord.z.is_nan() {
+            return coord;
+        }
+        let row = ((coord.x - RENDERING_BOUNDS.0) / self.voxel_delta_im.row).round();
+        // The bidirectional rows are the odd ones
+        if row.to_usize().unwrap_or(0) % 2 != 1 {
+            return coord;
+        }
+        let plane = ((coord.z - RENDERING_BOUNDS.0) / self.voxel_delta_im.plane).round();
+        let plane = plane.to_usize().unwrap_or(0);
+        let last_plane = self.plane_line_shift_deltas.len() - 1;
+        let delta = self.plane_line_shift_deltas[plane.min(last_plane)];
+        if delta == 0 {
+            return coord;
+        }
+        let corrected_time = time - delta;
+        // The first cell of the snake starts the frame, and each row ends
+        // with the cell of the mirror rotation
+        let columns = self.voxel_delta_ps.volsize.columns as usize;
+        let row_start = (self.last_accessed_idx - 1) / (columns + 1) * (columns + 1) + 1;
+        let row_end = (row_start + columns).min(self.data.len());
+        let row_cells = &self.data[row_start..row_end];
+        if corrected_time <= self.data[row_start - 1].end_time
+            || corrected_time > row_cells[row_cells.len() - 1].end_time
+        {
+            return ImageCoor::new(
+                OrderedFloat(f32::NAN),
+                OrderedFloat(f32::NAN),
+                OrderedFloat(f32::NAN),
+            );
+        }
+        let idx = row_cells.partition_point(|pair| pair.end_time < corrected_time);
+        let shifted = row_cells[idx].coord;
+        if shifted.x.is_nan() {
+            return shifted;
+        }
+        ImageCoor::new(shifted.x, shifted.y, coord.z)
+    }
+
+    /// Moves a photon of a reverse row to the row of its paired forward row.
+    ///
+    /// Unlike the planar snake, the rows are only collapsed after the lookup,
+    /// since the per-plane line shift tells the reverse rows apart by their
+    /// row coordinate.
+    fn collapse_reverse_row(&self, coord: ImageCoor) -> ImageCoor {
+        if !self.collapse_bidir || coord.x.is_nan() {
+            return coord;
+        }
+        let row = ((coord.x - RENDERING_BOUNDS.0) / self.voxel_delta_im.row).round();
+        if row.to_usize().unwrap_or(0) % 2 != 1 {
+            return coord;
+        }
+        ImageCoor::new(coord.x - self.voxel_delta_im.row, coord.y, coord.z)
+    }
+
+    /// Masks a photon which arrived outside of the region of interest of its
+    /// plane.
+    ///
+    /// Unlike the field mask, the plane of a photon is only known once it
+    /// arrives, so the ROIs can't be applied to the snake in advance.
+    fn apply_plane_roi(&self, coord: ImageCoor) -> ImageCoor {
+        if self.plane_rois.is_empty() || coord.x.is_nan() || coord.z.is_nan() {
+            return coord;
+        }
+        let plane = ((coord.z - RENDERING_BOUNDS.0) / self.voxel_delta_im.plane).round();
+        let roi = match self.plane_rois.get(plane.to_usize().unwrap_or(0)) {
+            Some(roi) => roi,
+            None => return coord,
+        };
+        let (row, column) = normalized_row_column(&coord);
+        if roi.contains(row, column) {
+            coord
+        } else {
+            masked_coord()
         }
     }
 
@@ -700,55 +1201,32 @@ impl ThreeDimensionalSnake {
 
     /// Create a Z-planes coordinate vector.
     ///
-    /// This method assigns the coordinates to each plane of the volume by
-    /// dividing the Z axis into three parts, in accordance with a sine curve:
-    /// The rising part (up to pi/2), the decending part (pi/2, 3pi/2) and the
-    // last rise (3pi/2, 2pi).
+    /// The TAG lens sweeps the Z axis along a sine curve, passing through each
+    /// plane during its rising part (up to pi/2), its decending part (pi/2,
+    /// 3pi/2) and its last rise (3pi/2, 2pi). The extreme planes are only
+    /// visited once, in the decending part, and so is the central plane of
+    /// volumes with an odd number of planes. Any number of planes, from 2, is
+    /// supported.
     fn create_planes_snake_imagespace(&self, planes: usize) -> DVector<Coordinate> {
-        let half_planes = planes / 2 - 1;
-        let phase_limits_1_to_m1 = DVector::<Coordinate>::from_iterator(
-            planes,
-            linspace::<Coordinate>(RENDERING_BOUNDS.2, RENDERING_BOUNDS.0, planes),
-        );
-        let phase_limits_0_to_1 = &mut phase_limits_1_to_m1.rows(1, half_planes).clone_owned();
-        phase_limits_0_to_1.as_mut_slice().reverse();
-        let phase_limits_0_to_1 = DVector::<Coordinate>::from_iterator(
-            half_planes,
-            phase_limits_0_to_1.into_iter().map(|x| *x),
+        let decending: Vec<Coordinate> =
+            linspace::<Coordinate>(RENDERING_BOUNDS.2, RENDERING_BOUNDS.0, planes).collect();
+        let first_rise = decending[1..planes / 2].iter().rev();
+        let last_rise = decending[planes - planes / 2..planes - 1].iter().rev();
+        let all_phases = DVector::<Coordinate>::from_iterator(
+            planes + 2 * (planes / 2 - 1),
+            first_rise.chain(decending.iter()).chain(last_rise).copied(),
         );
-        let phase_limits_m1_to_0 = &mut phase_limits_1_to_m1
-            .rows(half_planes + 1, half_planes)
-            .clone_owned();
-        phase_limits_m1_to_0.as_mut_slice().reverse();
-        let phase_limits_m1_to_0 = DVector::<Coordinate>::from_iterator(
-            half_planes,
-            phase_limits_m1_to_0.into_iter().map(|x| *x),
-        );
-
-        let mut all_phases =
-            DVector::<Coordinate>::repeat(half_planes + half_planes + planes, OrderedFloat(0.0f32));
-        all_phases
-            .rows_mut(0, phase_limits_0_to_1.len())
-            .set_column(0, &phase_limits_0_to_1);
-        all_phases
-            .rows_mut(phase_limits_0_to_1.len(), phase_limits_1_to_m1.len())
-            .set_column(0, &phase_limits_1_to_m1);
-        all_phases
-            .rows_mut(
-                phase_limits_0_to_1.len() + phase_limits_1_to_m1.len(),
-                phase_limits_m1_to_0.len(),
-            )
-            .set_column(0, &phase_limits_m1_to_0);
         info!("The phases vector we made is: {:#?}", all_phases);
         all_phases
     }
 
     /// Create a Z-planes Picosecond vector.
     ///
-    /// This method assigns the Picosecond value to each plane of the volume by
-    /// dividing the Z axis into three parts, in accordance with a sine curve:
-    /// The rising part (up to pi/2), the decending part (pi/2, 3pi/2) and the
-    /// last rise (3pi/2, 2pi).
+    /// This method assigns the Picosecond value to each entry of the planes
+    /// imagespace vector by inverting the sine curve of the TAG lens where
+    /// that entry lies: Entries before the top plane belong to the rising part
+    /// (up to pi/2), those up to the bottom plane to the decending part (pi/2,
+    /// 3pi/2) and the rest to the last rise (3pi/2, 2pi).
     ///
     /// The Planes imagespace vector is multiplied by 2 before the computation
     /// because the way this arcsine function works is with the planes
@@ -759,34 +1237,22 @@ impl ThreeDimensionalSnake {
         period: Picosecond,
     ) -> DVector<Picosecond> {
         let quarter_period = OrderedFloat::from_i64(period / 4).unwrap();
-        let num_planes = planes.len();
-        let half = num_planes / 2 + 1;
-        let firstq = half / 2 - 1;
-        let lastq = firstq + half;
-        let mut asin = planes
-            .map(|x| x * OrderedFloat(2.0))
-            .map(|x| x.asin() / (PI / 2.0));
-        let mut sine_ps = DVector::<Coordinate>::repeat(num_planes, quarter_period);
-        // First quarter of phase
-        sine_ps
-            .rows_mut(0, firstq)
-            .component_mul_assign(&asin.rows(0, firstq));
-        // Middle two quarters
-        sine_ps
-            .rows_mut(firstq, half)
-            .component_mul_assign(&asin.rows_mut(firstq, half).map(|x| OrderedFloat(1.0) - x));
-        sine_ps
-            .rows_mut(firstq, half)
-            .add_scalar_mut(quarter_period);
-        // Last quarter
-        sine_ps
-            .rows_mut(lastq, firstq)
-            .component_mul_assign(&asin.rows_mut(lastq, firstq).map(|x| OrderedFloat(1.0) + x));
-        sine_ps
-            .rows_mut(lastq, firstq)
-            .add_scalar_mut(OrderedFloat(3.0) * quarter_period);
-
-        let sine_ps = sine_ps.map(|x| x.to_i64().unwrap());
+        let top = planes.iter().enumerate().max_by_key(|x| x.1).unwrap().0;
+        let bottom = planes.iter().enumerate().min_by_key(|x| x.1).unwrap().0;
+        let sine_ps = DVector::<Picosecond>::from_iterator(
+            planes.len(),
+            planes.iter().enumerate().map(|(idx, x)| {
+                let asin = (*x * OrderedFloat(2.0)).asin() / (PI / 2.0);
+                let ps = if idx < top {
+                    quarter_period * asin
+                } else if idx <= bottom {
+                    quarter_period * (OrderedFloat(1.0) - asin) + quarter_period
+                } else {
+                    quarter_period * (OrderedFloat(1.0) + asin) + OrderedFloat(3.0) * quarter_period
+                };
+                ps.to_i64().unwrap()
+            }),
+        );
         info!("The PS snake we made: {:#?}", sine_ps);
         sine_ps
     }
@@ -816,9 +1282,22 @@ impl ThreeDimensionalSnake {
         // Add the cell capturing all photons arriving between frames
         let deadtime_during_rotation = column_deltas_ps[column_deltas_ps.len() - 1];
         let mut line_offset: Picosecond = offset;
-        let column_deltas_imagespace_rev = self.reverse_row_imagespace(column_deltas_imagespace);
-        let column_deltas_ps_bidir =
-            self.reverse_row_picosecond(column_deltas_ps, config.line_shift);
+        let column_deltas_imagespace_rev = if config.mirror_reverse_rows {
+            self.reverse_row_imagespace(column_deltas_imagespace)
+        } else {
+            column_deltas_imagespace.clone()
+        };
+        let column_deltas_ps_per_plane = self.reverse_row_picosecond_per_plane(
+            column_deltas_ps,
+            &config.reverse_line_shift(),
+            config.planes as usize,
+        );
+        let column_deltas_ps_bidir = column_deltas_ps_per_plane[0].clone();
+        let plane_line_shift_deltas = column_deltas_ps_per_plane
+            .iter()
+            .map(|row| row[0] - column_deltas_ps_bidir[0])
+            .collect();
+        let column_deltas_ps_forward = column_deltas_ps.add_scalar(config.forward_line_shift);
         let mut row_coord: Coordinate;
         for row in (0..config.rows).step_by(2) {
             // Start with the unidir row
@@ -826,11 +1305,15 @@ impl ThreeDimensionalSnake {
             ThreeDimensionalSnake::push_pair_unidir(
                 &mut self.data,
                 &column_deltas_imagespace,
-                &column_deltas_ps,
+                &column_deltas_ps_forward,
                 row_coord,
                 line_offset,
             );
             line_offset += deadtime_during_rotation;
+            // An odd number of rows ends with a forward row
+            if row + 1 == config.rows {
+                break;
+            }
             // Now the bidir row
             row_coord =
                 (OrderedFloat((row + 1) as f32) * self.voxel_delta_im.row) + RENDERING_BOUNDS.0;
@@ -859,6 +1342,9 @@ impl ThreeDimensionalSnake {
             voxel_delta_im: self.voxel_delta_im,
             earliest_frame_time: offset,
             frame_dead_time: config.frame_dead_time,
+            plane_line_shift_deltas,
+            plane_rois: Vec::new(),
+            collapse_bidir: false,
         }
     }
 
@@ -900,6 +1386,9 @@ impl ThreeDimensionalSnake {
             frame_dead_time: config.frame_dead_time,
             last_taglens_time: 0,
             tag_deltas_to_coord,
+            plane_line_shift_deltas: Vec::new(),
+            plane_rois: Vec::new(),
+            collapse_bidir: false,
         }
     }
 
@@ -933,7 +1422,7 @@ impl Snake for TwoDimensionalSnake {
             twod_snake.construct_row_ps_snake(num_columns, &twod_snake.voxel_delta_ps);
         let column_deltas_imagespace =
             twod_snake.construct_row_im_snake(num_columns, &twod_snake.voxel_delta_im);
-        match config.bidir {
+        let mut twod_snake = match config.scan_direction() {
             Bidirectionality::Bidir => twod_snake.update_naive_with_parameters_bidir(
                 &config,
                 &mut column_deltas_ps,
@@ -946,7 +1435,26 @@ impl Snake for TwoDimensionalSnake {
                 &column_deltas_imagespace,
                 offset,
             ),
+        };
+        verify_generated_snake_length(
+            twod_snake.data.len() + 1,
+            planar_snake_length(config),
+            config,
+        );
+        if let Some(mask) = &config.field_mask {
+            apply_field_mask(&mut twod_snake.data, mask);
+        }
+        if config.carry_voxel_indices {
+            index_snake(&mut twod_snake.data, &twod_snake.voxel_delta_im);
         }
+        if config.line_timing == LineTiming::Measured {
+            twod_snake.nominal_end_times = twod_snake
+                .data
+                .iter()
+                .map(|pair| pair.end_time - offset)
+                .collect();
+        }
+        twod_snake
     }
 
     fn get_voxel_delta_im(&self) -> VoxelDelta<Coordinate> {
@@ -957,17 +1465,21 @@ impl Snake for TwoDimensionalSnake {
         self.earliest_frame_time
     }
 
+    fn get_max_frame_time(&self) -> Picosecond {
+        self.max_frame_time
+    }
+
     /// Returns the value assigned to the snake's capacity
     ///
-    /// For 2D imaging it's num_rows * (num_columns + 1)
+    /// For 2D imaging it's num_rows * (num_columns + 1), regardless of the
+    /// scan direction or whether the number of rows is odd
     ///
     /// These numbers take into account a cell before each frame which captures
     /// photons arriving between frames, and a cell we remove from the last row
     /// which is not needed and a cell that is added so that we don't over-
     /// allocate..
     fn calc_snake_length(&self, config: &AppConfig) -> usize {
-        let baseline_count = ((config.columns + 1) * config.rows) as usize;
-        baseline_count + 1
+        planar_snake_length(config)
     }
 
     /// Handle a time tag by finding its corresponding coordinate in image
@@ -1036,9 +1548,17 @@ impl Snake for TwoDimensionalSnake {
             return;
         }
         self.last_accessed_idx = 0;
-        let offset = next_frame_at - self.earliest_frame_time;
-        for pair in self.data.iter_mut() {
-            pair.end_time += offset;
+        if self.nominal_end_times.is_empty() {
+            let offset = next_frame_at - self.earliest_frame_time;
+            for pair in self.data.iter_mut() {
+                pair.end_time += offset;
+            }
+        } else {
+            // Rows realigned during the previous frame are reset
+            for (pair, nominal) in self.data.iter_mut().zip(&self.nominal_end_times) {
+                pair.end_time = next_frame_at + nominal;
+            }
+            self.last_line = (0, next_frame_at);
         }
         self.max_frame_time = self.data[self.data.len() - 1].end_time;
         self.earliest_frame_time = next_frame_at;
@@ -1048,9 +1568,86 @@ impl Snake for TwoDimensionalSnake {
         );
     }
 
+    /// Moves the row starting with this line to the line's arrival time.
+    ///
+    /// The row is identified by the nominal start time closest to the line.
+    /// Its cells are also stretched or squeezed by the ratio between the
+    /// measured and nominal durations of the preceding lines, since the
+    /// duration of the current line is only known once it's over. The rows
+    /// since the previous line are squeezed or stretched to end with this
+    /// one, and the cells following the moved row never end before it, so
+    /// that the snake stays ordered.
+    fn new_line_event(&mut self, time: Picosecond) {
+        if self.nominal_end_times.is_empty() {
+            return;
+        }
+        let cells_per_row = self.voxel_delta_ps.volsize.columns as usize + 1;
+        let line_period = self.nominal_end_times[cells_per_row];
+        let row = ((time - self.earliest_frame_time) as f64 / line_period as f64).round() as usize;
+        if row == 0 || row >= self.voxel_delta_ps.volsize.rows as usize {
+            debug!("A line at {} doesn't start any row of the frame", time);
+            return;
+        }
+        let first_cell = row * cells_per_row + 1;
+        let nominal_start = self.nominal_end_times[first_cell - 1];
+        let (last_nominal_start, last_start) = self.last_line;
+        if nominal_start <= last_nominal_start || time <= last_start {
+            return;
+        }
+        let scale = (time - last_start) as f64 / (nominal_start - last_nominal_start) as f64;
+        let previous_cell = self
+            .nominal_end_times
+            .partition_point(|&nominal| nominal <= last_nominal_start);
+        let last_cell = (first_cell + cells_per_row).min(self.data.len());
+        for (idx, (pair, nominal)) in self.data[..last_cell]
+            .iter_mut()
+            .zip(&self.nominal_end_times[..last_cell])
+            .enumerate()
+            .skip(previous_cell)
+        {
+            pair.end_time = if idx < first_cell {
+                last_start + ((nominal - last_nominal_start) as f64 * scale).round() as Picosecond
+            } else {
+                time + ((nominal - nominal_start) as f64 * scale).round() as Picosecond
+            };
+        }
+        let row_end = self.data[last_cell - 1].end_time;
+        for pair in self.data[last_cell..].iter_mut() {
+            if pair.end_time >= row_end {
+                break;
+            }
+            pair.end_time = row_end;
+        }
+        self.last_accessed_idx = self.last_accessed_idx.min(previous_cell);
+        self.max_frame_time = self.data[self.data.len() - 1].end_time;
+        self.last_line = (nominal_start, time);
+    }
+
     fn get_z_imagespace_planes(&self) -> Option<DVector<Coordinate>> {
         None
     }
+
+    fn timing_waveform(&self) -> Vec<(Picosecond, WaveformEvent)> {
+        timing_waveform_of(&self.data, self.voxel_delta_ps.volsize)
+    }
+
+    fn scan_path(&self) -> Vec<ImageCoor> {
+        scan_path_of(&self.data)
+    }
+
+    fn coords_between(&self, start: Picosecond, end: Picosecond) -> Vec<ImageCoor> {
+        coords_between_of(&self.data, start, end)
+    }
+
+    fn rewind_to(&mut self, time: Picosecond) {
+        self.last_accessed_idx = self.last_accessed_idx.min(rewound_index(&self.data, time));
+    }
+
+    fn last_voxel_index(&self) -> Option<VoxelIndex> {
+        self.data
+            .get(self.last_accessed_idx)
+            .and_then(|pair| pair.index)
+    }
 }
 
 /// A three-dimensional volume rendered in a snake
@@ -1071,7 +1668,7 @@ impl Snake for ThreeDimensionalSnake {
             threed_snake.construct_row_ps_snake(num_columns, &threed_snake.voxel_delta_ps);
         let column_deltas_imagespace =
             threed_snake.construct_row_im_snake(num_columns, &threed_snake.voxel_delta_im);
-        match config.bidir {
+        let mut threed_snake = match config.scan_direction() {
             Bidirectionality::Bidir => threed_snake.update_naive_with_parameters_bidir(
                 &config,
                 &mut column_deltas_ps,
@@ -1084,16 +1681,33 @@ impl Snake for ThreeDimensionalSnake {
                 &column_deltas_imagespace,
                 offset,
             ),
+        };
+        verify_generated_snake_length(
+            threed_snake.data.len() + 1,
+            threed_snake.calc_snake_length(config),
+            config,
+        );
+        if let Some(mask) = &config.field_mask {
+            apply_field_mask(&mut threed_snake.data, mask);
+        }
+        threed_snake.plane_rois = config.plane_rois.clone().unwrap_or_default();
+        threed_snake.collapse_bidir =
+            config.collapse_bidir && config.scan_direction() == Bidirectionality::Bidir;
+        if config.line_timing == LineTiming::Measured {
+            warn!("Measured line timing isn't supported in 3D, using the nominal one");
         }
+        threed_snake
     }
 
     fn get_voxel_delta_im(&self) -> VoxelDelta<Coordinate> {
         self.voxel_delta_im.clone()
     }
 
+    /// The TAG lens phase determines the plane of each photon, so the snake
+    /// itself only covers a single plane, with the rows of both directions of
+    /// bidirectional scans and an odd number of rows just like in 2D
     fn calc_snake_length(&self, config: &AppConfig) -> usize {
-        let baseline_count = ((config.columns + 1) * config.rows) as usize;
-        baseline_count * config.planes as usize + 1
+        planar_snake_length(config)
     }
 
     fn time_to_coord_linear(&mut self, time: i64, ch: usize) -> ProcessedEvent {
@@ -1118,6 +1732,8 @@ impl Snake for ThreeDimensionalSnake {
         // Makes sure that we indeed captured some cell. This can be avoided in
         // principle but I'm still not confident enough in this implementation.
         if let Some(coord) = coord {
+            let coord = self.apply_plane_line_shift(coord, time);
+            let coord = self.apply_plane_roi(self.collapse_reverse_row(coord));
             trace!("Found a point on the snake! Time: {}; Additional steps taken: {}; Channel: {}. The coord we're sending is: {:?}", time, additional_steps_taken, ch, coord);
             ProcessedEvent::Displayed(coord, ch)
         } else {
@@ -1153,6 +1769,10 @@ impl Snake for ThreeDimensionalSnake {
         self.earliest_frame_time
     }
 
+    fn get_max_frame_time(&self) -> Picosecond {
+        self.max_frame_time
+    }
+
     fn new_taglens_period(&mut self, time: Picosecond) -> ProcessedEvent {
         self.last_taglens_time = time;
         ProcessedEvent::NoOp
@@ -1161,6 +1781,25 @@ impl Snake for ThreeDimensionalSnake {
     fn get_z_imagespace_planes(&self) -> Option<DVector<Coordinate>> {
         Some(self.tag_deltas_to_coord.get_im_vec())
     }
+
+    fn timing_waveform(&self) -> Vec<(Picosecond, WaveformEvent)> {
+        timing_waveform_of(&self.data, self.voxel_delta_ps.volsize)
+    }
+
+    fn scan_path(&self) -> Vec<ImageCoor> {
+        scan_path_of(&self.data)
+    }
+
+    fn coords_between(&self, start: Picosecond, end: Picosecond) -> Vec<ImageCoor> {
+        coords_between_of(&self.data, start, end)
+            .into_iter()
+            .map(|coord| self.collapse_reverse_row(coord))
+            .collect()
+    }
+
+    fn rewind_to(&mut self, time: Picosecond) {
+        self.last_accessed_idx = self.last_accessed_idx.min(rewound_index(&self.data, time));
+    }
 }
 
 #[cfg(test)]
@@ -1168,7 +1807,46 @@ mod tests {
     use assert_approx_eq::assert_approx_eq;
 
     use super::*;
-    use crate::configuration::{AppConfigBuilder, InputChannel, Period};
+    use crate::configuration::{AppConfigBuilder, FillFraction, InputChannel, Period, XScanner};
+    use std::sync::{Mutex, Once};
+
+    /// The warnings logged by all tests, once [`record_warnings`] was called
+    static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct WarningRecorder;
+
+    impl log::Log for WarningRecorder {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Starts recording the logged warnings, so that tests can check that
+    /// theirs was logged
+    fn record_warnings() {
+        static RECORDER: WarningRecorder = WarningRecorder;
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&RECORDER).unwrap();
+            log::set_max_level(log::LevelFilter::Warn);
+        });
+    }
+
+    fn was_warned(message: &str) -> bool {
+        WARNINGS
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|warning| warning == message)
+    }
 
     /// Helper method to test config-dependent things without actually caring
     /// about the different config values
@@ -1181,7 +1859,7 @@ mod tests {
             .with_scan_period(Period::from_freq(7926.17))
             .with_tag_period(Period::from_freq(189800))
             .with_bidir(Bidirectionality::Bidir)
-            .with_fill_fraction(71.3)
+            .with_fill_fraction(FillFraction::from_percent(71.3).unwrap())
             .with_frame_dead_time(8 * *Period::from_freq(7926.17))
             .with_pmt1_ch(InputChannel::new(-1, 0.0))
             .with_pmt2_ch(InputChannel::new(0, 0.0))
@@ -1204,7 +1882,7 @@ mod tests {
             .with_scan_period(Period::from_freq(1_000_000_000))
             .with_tag_period(Period::from_freq(189800))
             .with_bidir(Bidirectionality::Bidir)
-            .with_fill_fraction(50i16)
+            .with_fill_fraction(FillFraction::from_percent(50.0).unwrap())
             .with_frame_dead_time(1 * *Period::from_freq(1_000_000_000))
             .with_pmt1_ch(InputChannel::new(-1, 0.0))
             .with_pmt2_ch(InputChannel::new(0, 0.0))
@@ -1262,6 +1940,24 @@ mod tests {
         assert_eq!(VoxelDelta::calc_effective_line_period(&config), 44_977_590);
     }
 
+    #[test]
+    fn column_deltas_sum_to_the_effective_line_period() {
+        let config = setup_default_config().build();
+        let num_columns = config.columns as usize;
+        let effective_line_period = VoxelDelta::calc_effective_line_period(&config);
+        assert_ne!(effective_line_period % num_columns as Picosecond, 0);
+        let twod_snake = naive_init_2d(&config);
+        let column_deltas_ps =
+            twod_snake.construct_row_ps_snake(num_columns, &twod_snake.voxel_delta_ps);
+        let mut column_ends = vec![0];
+        column_ends.extend(column_deltas_ps.rows(0, num_columns).iter());
+        let deltas: Vec<Picosecond> = column_ends.windows(2).map(|w| w[1] - w[0]).collect();
+        assert_eq!(deltas.iter().sum::<Picosecond>(), effective_line_period);
+        let shortest = deltas.iter().min().unwrap();
+        let longest = deltas.iter().max().unwrap();
+        assert!(longest - shortest <= 1);
+    }
+
     #[test]
     fn voxel_delta_between_planes() {
         let config = setup_default_config().build();
@@ -1274,6 +1970,7 @@ mod tests {
         let voxel_delta = VoxelDelta {
             row: 18_104_579,
             column: 175_693,
+            line: 44_977_590,
             plane: 263_435,
             frame: 1_009_314_712,
             volsize: VolumeSize::from_config(&config),
@@ -1290,6 +1987,24 @@ mod tests {
         assert_eq!(VoxelDelta::calc_time_between_rows(&config), 18_104_579);
     }
 
+    #[test]
+    fn sawtooth_rows_are_separated_by_the_retrace() {
+        let config = setup_default_config()
+            .with_bidir(Bidirectionality::Bidir)
+            .with_x_scanner(XScanner::Sawtooth {
+                retrace_fraction: 0.2,
+            })
+            .build();
+        let scan_period = *config.scan_period;
+        let retrace = (scan_period as f64 * 0.2).round() as Picosecond;
+        assert_eq!(VoxelDelta::calc_time_between_rows(&config), retrace);
+        assert_eq!(
+            VoxelDelta::calc_effective_line_period(&config),
+            scan_period - retrace
+        );
+        assert_eq!(*config.line_period(), scan_period);
+    }
+
     #[test]
     fn voxel_delta_imcoord_config() {
         let config = setup_default_config()
@@ -1419,6 +2134,340 @@ mod tests {
         assert_eq!(snake.data.len() + 1, snake.data.capacity());
     }
 
+    #[test]
+    fn collapsed_bidir_rows_share_the_voxels_of_their_forward_rows() {
+        let photon_coords = |collapse_bidir| {
+            let config = setup_image_scanning_config()
+                .with_collapse_bidir(collapse_bidir)
+                .build();
+            let mut snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+            let row_len = config.columns as usize + 1;
+            let column = 2;
+            let mirrored_column = config.columns as usize - 1 - column;
+            let forward = snake.data[1 + column].end_time;
+            let reverse = snake.data[1 + row_len + mirrored_column].end_time;
+            [forward, reverse].map(|time| match snake.time_to_coord_linear(time, 0) {
+                ProcessedEvent::Displayed(coord, _) => coord,
+                other => panic!("Photon wasn't displayed: {:?}", other),
+            })
+        };
+        let [forward, reverse] = photon_coords(true);
+        assert_eq!(forward, reverse);
+        let [forward, reverse] = photon_coords(false);
+        assert_eq!(forward.y, reverse.y);
+        assert_ne!(forward.x, reverse.x);
+    }
+
+    #[test]
+    fn odd_rows_bidir_end_with_a_forward_row() {
+        let config = setup_image_scanning_config().with_rows(5).build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let row_len = config.columns as usize + 1;
+        assert_eq!(snake.data.len(), 5 * row_len);
+        assert_eq!(snake.data.len() + 1, snake.data.capacity());
+        let row_delta = RENDERING_SPAN / OrderedFloat(4.0f32);
+        for row in 0..5 {
+            let first = snake.data[1 + row * row_len].coord;
+            assert_eq!(
+                first.x,
+                RENDERING_BOUNDS.0 + OrderedFloat(row as f32) * row_delta
+            );
+            let first_column = if row % 2 == 1 {
+                RENDERING_BOUNDS.2
+            } else {
+                RENDERING_BOUNDS.0
+            };
+            assert_eq!(first.y, first_column);
+        }
+        let last = snake.data[snake.data.len() - 1];
+        assert_eq!(
+            last.coord,
+            ImageCoor::new(RENDERING_BOUNDS.2, RENDERING_BOUNDS.2, RENDERING_BOUNDS.1)
+        );
+        assert_eq!(snake.max_frame_time, last.end_time);
+        assert!(snake
+            .data
+            .windows(2)
+            .all(|pair| pair[0].end_time < pair[1].end_time));
+    }
+
+    #[test]
+    fn odd_rows_bidir_volume_covers_each_row_once() {
+        let config = setup_image_scanning_config()
+            .with_rows(5)
+            .with_planes(4)
+            .build();
+        let snake = ThreeDimensionalSnake::from_acq_params(&config, 0);
+        let row_len = config.columns as usize + 1;
+        assert_eq!(snake.data.len() + 1, snake.calc_snake_length(&config));
+        assert_eq!(snake.data.len() + 1, snake.data.capacity());
+        let row_delta = RENDERING_SPAN / OrderedFloat(4.0f32);
+        for row in 0..5 {
+            let row_coord = RENDERING_BOUNDS.0 + OrderedFloat(row as f32) * row_delta;
+            let cells = snake
+                .data
+                .iter()
+                .filter(|pair| pair.coord.x == row_coord && !pair.coord.y.is_nan())
+                .count();
+            assert_eq!(cells, config.columns as usize);
+            let first = snake.data[1 + row * row_len].coord;
+            assert_eq!(first.x, row_coord);
+        }
+        assert_eq!(snake.data.len(), 5 * row_len);
+        assert!(snake
+            .data
+            .windows(2)
+            .all(|pair| pair[0].end_time < pair[1].end_time));
+    }
+
+    #[test]
+    fn waveform_lines_start_every_line_period() {
+        let config = setup_image_scanning_config().build();
+        let offset = 1_000;
+        let snake = TwoDimensionalSnake::from_acq_params(&config, offset);
+        let waveform = snake.timing_waveform();
+        assert_eq!(waveform.len(), config.rows as usize + 1);
+        assert_eq!(waveform[0], (offset, WaveformEvent::FrameStart));
+        let line_period = VoxelDelta::calc_effective_line_period(&config)
+            + VoxelDelta::calc_time_between_rows(&config);
+        for (row, (time, event)) in waveform[1..].iter().enumerate() {
+            assert_eq!(*event, WaveformEvent::LineStart);
+            assert_eq!(*time, offset + row as Picosecond * line_period);
+        }
+    }
+
+    #[test]
+    fn forward_and_reverse_rows_are_shifted_separately() {
+        let baseline =
+            TwoDimensionalSnake::from_acq_params(&setup_image_scanning_config().build(), 0);
+        let config = setup_image_scanning_config()
+            .with_line_shift(300)
+            .with_forward_line_shift(100)
+            .build();
+        let shifted = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let row_len = config.columns as usize + 1;
+        for row in 0..config.rows as usize {
+            let expected_shift = if row % 2 == 1 { 300 } else { 100 };
+            for cell in 1 + row * row_len..(row + 1) * row_len {
+                assert_eq!(
+                    shifted.data[cell].end_time - baseline.data[cell].end_time,
+                    expected_shift
+                );
+                assert_eq!(shifted.data[cell].coord, baseline.data[cell].coord);
+            }
+        }
+    }
+
+    #[test]
+    fn reverse_rows_can_be_mirrored_without_shifting() {
+        let baseline =
+            TwoDimensionalSnake::from_acq_params(&setup_image_scanning_config().build(), 0);
+        let config = setup_image_scanning_config()
+            .with_line_shift(300)
+            .with_shift_reverse_rows(false)
+            .build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let row_len = config.columns as usize + 1;
+        let forward_row = &snake.data[1..row_len];
+        let reverse_row = &snake.data[1 + row_len..2 * row_len];
+        for cell in 1..snake.data.len() {
+            assert_eq!(snake.data[cell].end_time, baseline.data[cell].end_time);
+        }
+        let last_column = config.columns as usize - 1;
+        assert_eq!(reverse_row[0].coord.y, forward_row[last_column].coord.y);
+        assert_eq!(reverse_row[last_column].coord.y, forward_row[0].coord.y);
+    }
+
+    #[test]
+    fn tied_time_tags_share_cell_but_keep_channel() {
+        let config = setup_image_scanning_config().build();
+        let mut snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let expected = snake.data[12].coord;
+        for ch in 0..crate::SUPPORTED_SPECTRAL_CHANNELS {
+            assert_eq!(
+                snake.time_to_coord_linear(525, ch),
+                ProcessedEvent::Displayed(expected, ch)
+            );
+        }
+        assert_eq!(snake.last_accessed_idx, 12);
+        assert_eq!(
+            snake.time_to_coord_linear(526, 0),
+            ProcessedEvent::Displayed(snake.data[13].coord, 0)
+        );
+    }
+
+    #[test]
+    fn reloaded_snake_maps_times_like_the_dumped_one() {
+        let config = setup_image_scanning_config().build();
+        let mut snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let mut path = std::env::temp_dir();
+        path.push("rpysight_dumped_snake.toml");
+        snake.dump_to(&path).unwrap();
+        let mut reloaded = TwoDimensionalSnake::from_serialized(&path).unwrap();
+        assert_eq!(reloaded.data, snake.data);
+        for time in (0..2 * snake.max_frame_time).step_by(37) {
+            assert_eq!(
+                reloaded.time_to_coord_linear(time, 0),
+                snake.time_to_coord_linear(time, 0)
+            );
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn snakes_loaded_for_other_dimensions_are_reported() {
+        record_warnings();
+        let config = setup_image_scanning_config().build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let mut path = std::env::temp_dir();
+        path.push("rpysight_mismatched_snake.toml");
+        snake.dump_to(&path).unwrap();
+        let reloaded = TwoDimensionalSnake::from_serialized(&path).unwrap();
+        let _ = std::fs::remove_file(path);
+        let checked = setup_image_scanning_config()
+            .with_snake_length_tolerance(Some(0))
+            .build();
+        assert_eq!(reloaded.verify_length(&checked), None);
+        let fewer_rows = setup_image_scanning_config()
+            .with_rows(5)
+            .with_snake_length_tolerance(Some(0))
+            .build();
+        let mismatch = reloaded
+            .verify_length(&fewer_rows)
+            .expect("The loaded snake's length wasn't checked");
+        assert!(mismatch.contains(&format!("The snake has {} cells", snake.data.len() + 1)));
+        assert!(mismatch.contains("Rows: 5"));
+        assert!(was_warned(&mismatch));
+        let unchecked = setup_image_scanning_config().with_rows(5).build();
+        assert_eq!(reloaded.verify_length(&unchecked), None);
+    }
+
+    #[test]
+    fn per_plane_line_shift_offsets_reverse_rows() {
+        let config = setup_default_config()
+            .with_planes(4)
+            .with_line_shifts(LineShift::PerPlane(vec![0, 100, 250, 250]))
+            .build();
+        let snake = naive_init_3d(&config);
+        let column_deltas_ps = DVector::from_vec(vec![10i64, 20, 30]);
+        let rows = snake.reverse_row_picosecond_per_plane(&column_deltas_ps, &config.line_shift, 4);
+        assert_eq!(rows[0], DVector::from_vec(vec![10i64, 20, 30]));
+        assert_eq!(rows[1], DVector::from_vec(vec![110i64, 120, 130]));
+        assert_eq!(rows[2], DVector::from_vec(vec![260i64, 270, 280]));
+        let snake = ThreeDimensionalSnake::from_acq_params(&config, 0);
+        assert_eq!(snake.plane_line_shift_deltas, vec![0, 100, 250, 250]);
+    }
+
+    #[test]
+    fn plane_line_shift_moves_photons_within_their_row() {
+        let config = setup_default_config()
+            .with_planes(2)
+            .with_line_shifts(LineShift::PerPlane(vec![0, 1]))
+            .build();
+        let mut snake = ThreeDimensionalSnake::from_acq_params(&config, 0);
+        let row_len = config.columns as usize + 1;
+        let second_plane = RENDERING_BOUNDS.2;
+        let in_second_plane = |coord: ImageCoor| ImageCoor::new(coord.x, coord.y, second_plane);
+        // The second column of the first reverse row, which is found one
+        // picosecond into the cell when using the shift of the first plane
+        let idx = row_len + 2;
+        snake.last_accessed_idx = idx;
+        let time = snake.data[idx - 1].end_time + 1;
+        assert_eq!(
+            snake.apply_plane_line_shift(in_second_plane(snake.data[idx].coord), time),
+            in_second_plane(snake.data[idx - 1].coord)
+        );
+        // The first column of the row is shifted into the mirror rotation
+        let idx = row_len + 1;
+        snake.last_accessed_idx = idx;
+        let time = snake.data[idx - 1].end_time + 1;
+        assert!(snake
+            .apply_plane_line_shift(in_second_plane(snake.data[idx].coord), time)
+            .x
+            .is_nan());
+    }
+
+    #[test]
+    fn collapsed_reverse_rows_of_volumes_share_their_forward_rows() {
+        let config = setup_default_config()
+            .with_planes(2)
+            .with_collapse_bidir(true)
+            .build();
+        let snake = ThreeDimensionalSnake::from_acq_params(&config, 0);
+        let row_len = config.columns as usize + 1;
+        let (column, mirrored_column) = (2, config.columns as usize - 1 - 2);
+        let second_plane = RENDERING_BOUNDS.2;
+        let in_second_plane = |coord: ImageCoor| ImageCoor::new(coord.x, coord.y, second_plane);
+        let forward = in_second_plane(snake.data[1 + column].coord);
+        let reverse = in_second_plane(snake.data[1 + row_len + mirrored_column].coord);
+        assert_ne!(forward, reverse);
+        assert_eq!(snake.collapse_reverse_row(reverse), forward);
+        assert_eq!(snake.collapse_reverse_row(forward), forward);
+        // The swept voxels of missed events are collapsed just the same
+        let swept = snake.coords_between(snake.data[1].end_time, snake.data[2 * row_len].end_time);
+        assert!(swept.iter().all(|coord| coord.x == forward.x));
+    }
+
+    #[test]
+    fn photons_are_masked_by_the_roi_of_their_plane() {
+        let left_half = PlaneRoi {
+            row_start: -1.0,
+            row_end: 1.0,
+            column_start: -1.0,
+            column_end: 0.0,
+        };
+        let right_half = PlaneRoi {
+            column_start: 0.0,
+            column_end: 1.0,
+            ..left_half
+        };
+        let config = setup_default_config()
+            .with_planes(2)
+            .with_plane_rois(Some(vec![left_half, right_half]))
+            .build();
+        let snake = ThreeDimensionalSnake::from_acq_params(&config, 0);
+        let left = |z| ImageCoor::new(OrderedFloat(0.0), OrderedFloat(-0.25), z);
+        let right = |z| ImageCoor::new(OrderedFloat(0.0), OrderedFloat(0.25), z);
+        let (first_plane, second_plane) = (RENDERING_BOUNDS.0, RENDERING_BOUNDS.2);
+        assert_eq!(snake.apply_plane_roi(left(first_plane)), left(first_plane));
+        assert!(is_masked(&snake.apply_plane_roi(right(first_plane))));
+        assert!(is_masked(&snake.apply_plane_roi(left(second_plane))));
+        assert_eq!(
+            snake.apply_plane_roi(right(second_plane)),
+            right(second_plane)
+        );
+    }
+
+    #[test]
+    fn global_line_shift_is_broadcast_to_all_planes() {
+        let config = setup_default_config()
+            .with_planes(4)
+            .with_line_shift(100)
+            .build();
+        let snake = ThreeDimensionalSnake::from_acq_params(&config, 0);
+        assert_eq!(snake.plane_line_shift_deltas, vec![0, 0, 0, 0]);
+        assert_eq!(config.line_shift.for_plane(3), 100);
+    }
+
+    #[test]
+    fn circular_field_mask_excludes_corners() {
+        let config = setup_image_scanning_config()
+            .with_field_mask(Some(FieldMask::Circle { radius: 1.0 }))
+            .build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let corner = ImageCoor::new(RENDERING_BOUNDS.0, RENDERING_BOUNDS.0, RENDERING_BOUNDS.1);
+        assert!(snake.data.iter().all(|pair| pair.coord != corner));
+        let center_row = RENDERING_BOUNDS.0 + OrderedFloat(4.0) * snake.voxel_delta_im.row;
+        let center_column = RENDERING_BOUNDS.0 + OrderedFloat(4.0) * snake.voxel_delta_im.column;
+        let center = ImageCoor::new(center_row, center_column, RENDERING_BOUNDS.1);
+        assert!(snake.data.iter().any(|pair| pair.coord == center));
+        let unmasked =
+            TwoDimensionalSnake::from_acq_params(&setup_image_scanning_config().build(), 0);
+        assert!(unmasked.data.iter().any(|pair| pair.coord == corner));
+        assert!(snake.data.iter().any(|pair| is_masked(&pair.coord)));
+        assert!(!unmasked.data.iter().any(|pair| is_masked(&pair.coord)));
+    }
+
     #[test]
     fn time_to_coord_snake_2d_unidir() {
         let config = setup_image_scanning_config()
@@ -1500,6 +2549,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn measured_lines_realign_their_rows() {
+        let config = setup_image_scanning_config()
+            .with_bidir(false)
+            .with_line_timing(LineTiming::Measured)
+            .build();
+        let mut snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let cells_per_row = config.columns as usize + 1;
+        let line_period = snake.data[cells_per_row].end_time;
+        let column = snake.voxel_delta_ps.column;
+        let coord_at =
+            |row: usize, column: usize| snake.data[row * cells_per_row + 1 + column].coord;
+        let (first_column_of_row_1, last_column_of_row_2) = (coord_at(1, 0), coord_at(2, 9));
+        // The second line is late by four columns, the third one follows it
+        // after a full period
+        let second_line = line_period + 4 * column;
+        snake.new_line_event(second_line);
+        match snake.time_to_coord_linear(second_line + column / 2, 0) {
+            ProcessedEvent::Displayed(coord, _) => assert_eq!(coord, first_column_of_row_1),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        let third_line = second_line + line_period;
+        snake.new_line_event(third_line);
+        match snake.time_to_coord_linear(third_line + 9 * column + column / 2, 0) {
+            ProcessedEvent::Displayed(coord, _) => assert_eq!(coord, last_column_of_row_2),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        // The next frame starts with the nominal timing again
+        snake.update_snake_for_next_frame(10 * line_period);
+        assert_eq!(snake.data[cells_per_row].end_time, 11 * line_period);
+    }
+
+    #[test]
+    fn early_measured_lines_keep_the_snake_ordered() {
+        let config = setup_image_scanning_config()
+            .with_bidir(false)
+            .with_line_timing(LineTiming::Measured)
+            .build();
+        let mut snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let cells_per_row = config.columns as usize + 1;
+        let line_period = snake.data[cells_per_row].end_time;
+        let column = snake.voxel_delta_ps.column;
+        let coord_at =
+            |row: usize, column: usize| snake.data[row * cells_per_row + 1 + column].coord;
+        let (last_column_of_row_0, first_column_of_row_1) = (coord_at(0, 9), coord_at(1, 0));
+        // The second line is early by four columns, so the first row is cut
+        // short and its flyback ends with the line
+        let second_line = line_period - 4 * column;
+        snake.new_line_event(second_line);
+        assert!(snake
+            .data
+            .windows(2)
+            .all(|pair| pair[0].end_time <= pair[1].end_time));
+        assert_eq!(snake.data[cells_per_row].end_time, second_line);
+        match snake.time_to_coord_linear(snake.data[cells_per_row - 1].end_time - 1, 0) {
+            ProcessedEvent::Displayed(coord, _) => assert_eq!(coord, last_column_of_row_0),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        match snake.time_to_coord_linear(second_line + column / 2, 0) {
+            ProcessedEvent::Displayed(coord, _) => assert_eq!(coord, first_column_of_row_1),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn late_measured_lines_keep_the_snake_ordered() {
+        let config = setup_image_scanning_config()
+            .with_bidir(false)
+            .with_line_timing(LineTiming::Measured)
+            .build();
+        let mut snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let line_period = snake.data[config.columns as usize + 1].end_time;
+        snake.new_line_event(line_period + 8 * snake.voxel_delta_ps.column);
+        assert!(snake
+            .data
+            .windows(2)
+            .all(|pair| pair[0].end_time <= pair[1].end_time));
+    }
+
+    #[test]
+    fn diverging_snake_length_is_described() {
+        let config = setup_image_scanning_config()
+            .with_rows(5)
+            .with_snake_length_tolerance(Some(1))
+            .build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let expected = planar_snake_length(&config);
+        assert_eq!(
+            snake_length_mismatch(snake.data.len() + 1, expected, 1, &config),
+            None
+        );
+        assert_eq!(
+            snake_length_mismatch(expected + 1, expected, 1, &config),
+            None
+        );
+        let mismatch = snake_length_mismatch(expected - 2, expected, 1, &config)
+            .expect("Divergence beyond the tolerance wasn't reported");
+        assert!(mismatch.contains(&format!("instead of the expected {}", expected)));
+        assert!(mismatch.contains("Rows: 5"));
+    }
+
+    #[test]
+    fn scan_path_skips_cells_without_coordinates() {
+        let config = setup_image_scanning_config().build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let path = snake.scan_path();
+        assert_eq!(path.len(), (config.rows * config.columns) as usize);
+        assert!(path.iter().all(|coord| !coord.x.is_nan()));
+        assert_eq!(path[0], snake.data[1].coord);
+        // The second row starts after the rotation cell ending the first one
+        assert_eq!(path[10], snake.data[12].coord);
+        assert_eq!(path[path.len() - 1], snake.data[snake.data.len() - 1].coord);
+    }
+
+    #[test]
+    fn coords_between_follow_the_scan_up_to_the_later_cell() {
+        let config = setup_image_scanning_config().build();
+        let snake = TwoDimensionalSnake::from_acq_params(&config, 0);
+        let (start, end) = (snake.data[3].end_time, snake.data[15].end_time);
+        let coords = snake.coords_between(start, end);
+        // The rotation cell ending the first row has no coordinate
+        let expected: Vec<_> = snake.data[3..15]
+            .iter()
+            .map(|pair| pair.coord)
+            .filter(|coord| !coord.x.is_nan() && !coord.y.is_nan())
+            .collect();
+        assert_eq!(coords.len(), 11);
+        assert_eq!(coords, expected);
+        assert!(snake.coords_between(end, start).is_empty());
+    }
+
     #[test]
     fn build_snake_2d() {
         let config = setup_image_scanning_config().build();
@@ -1513,7 +2693,7 @@ mod tests {
         let config = setup_image_scanning_config().with_planes(10).build();
         let threed_snake = naive_init_3d(&config);
         let snake = threed_snake.allocate_snake(&config);
-        assert_eq!(snake.capacity(), 1101);
+        assert_eq!(snake.capacity(), 111);
     }
 
     #[test]
@@ -1555,10 +2735,38 @@ mod tests {
             .collect();
     }
 
-    #[should_panic]
     #[test]
+    fn create_sine_imagespace_two_planes() {
+        let config = setup_image_scanning_config().with_planes(2).build();
+        let snake = ThreeDimensionalSnake::naive_init(&config);
+        let sine = snake.create_planes_snake_imagespace(config.planes as usize);
+        let truth = [0.5f32, -0.5];
+        assert_eq!(sine.len(), truth.len());
+        let _: Vec<_> = sine
+            .iter()
+            .zip(truth.iter())
+            .map(|x| assert_approx_eq!(x.0.into_inner(), x.1))
+            .collect();
+    }
+
+    #[test]
+    /// The truth vector is given by the same numpy code as above, with
+    /// q3_coords = np.flip(q2_coords[planes - elems:-1]), so that the
+    /// central plane is only visited once
     fn create_sine_imagespace_uneven_planes() {
-        let _ = setup_image_scanning_config().with_planes(13).build();
+        let config = setup_image_scanning_config().with_planes(11).build();
+        let snake = ThreeDimensionalSnake::naive_init(&config);
+        let sine = snake.create_planes_snake_imagespace(config.planes as usize);
+        let truth = [
+            0.1f32, 0.2, 0.3, 0.4, 0.5, 0.4, 0.3, 0.2, 0.1, 0.0, -0.1, -0.2, -0.3, -0.4, -0.5,
+            -0.4, -0.3, -0.2, -0.1,
+        ];
+        assert_eq!(sine.len(), truth.len());
+        let _: Vec<_> = sine
+            .iter()
+            .zip(truth.iter())
+            .map(|x| assert_approx_eq!(x.0.into_inner(), x.1))
+            .collect();
     }
 
     #[test]
@@ -1592,6 +2800,59 @@ mod tests {
         assert_eq!(c, sine_ps.len());
     }
 
+    /// Compares the PS snake of a volume with the given number of planes
+    /// and a period of 1000 PS to the truth vector, created by the numpy
+    /// code above with the q3_coords of create_sine_imagespace_uneven_planes
+    fn assert_sine_ps_matches(planes: u32, truth: Vec<i64>) {
+        let config = setup_image_scanning_config().with_planes(planes).build();
+        let snake = ThreeDimensionalSnake::naive_init(&config);
+        let sine = snake.create_planes_snake_imagespace(planes as usize);
+        let sine_ps = snake.create_planes_snake_ps(&sine, 1000);
+        assert_eq!(sine_ps, DVector::from_vec(truth));
+    }
+
+    #[test]
+    fn create_sine_ps_two_planes() {
+        assert_sine_ps_matches(2, vec![250, 750]);
+    }
+
+    #[test]
+    fn create_sine_ps_uneven_planes() {
+        assert_sine_ps_matches(
+            11,
+            vec![
+                32, 65, 102, 147, 250, 352, 397, 434, 467, 500, 532, 565, 602, 647, 750, 852, 897,
+                934, 967,
+            ],
+        );
+    }
+
+    #[test]
+    fn create_sine_ps_sixteen_planes() {
+        assert_sine_ps_matches(
+            16,
+            vec![
+                10, 32, 54, 77, 102, 131, 166, 250, 333, 368, 397, 422, 445, 467, 489, 510, 532,
+                554, 577, 602, 631, 666, 750, 833, 868, 897, 922, 945, 967, 989,
+            ],
+        );
+    }
+
+    #[test]
+    fn create_sine_ps_fifty_planes() {
+        assert_sine_ps_matches(
+            50,
+            vec![
+                3, 9, 16, 22, 29, 36, 42, 49, 56, 63, 70, 77, 85, 92, 100, 109, 117, 126, 136, 146,
+                157, 170, 185, 204, 250, 295, 314, 329, 342, 353, 363, 373, 382, 390, 399, 407,
+                414, 422, 429, 436, 443, 450, 457, 463, 470, 477, 483, 490, 496, 503, 509, 516,
+                522, 529, 536, 542, 549, 556, 563, 570, 577, 585, 592, 600, 609, 617, 626, 636,
+                646, 657, 670, 685, 704, 750, 795, 814, 829, 842, 853, 863, 873, 882, 890, 899,
+                907, 914, 922, 929, 936, 943, 950, 957, 963, 970, 977, 983, 990, 996,
+            ],
+        );
+    }
+
     #[test]
     #[should_panic]
     fn setup_interval_coord_map_incorrectly() {
@@ -1,11 +1,15 @@
 extern crate log;
 use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
 
+use arrow2::array::{Array, Int32Array, Int64Array, UInt16Array, UInt8Array};
 use arrow2::datatypes::{DataType, Field, Schema};
 use arrow2::io::csv::read::{
     deserialize_batch, deserialize_column, read_rows, Reader, ReaderBuilder,
 };
 use arrow2::io::ipc::write::StreamWriter;
+use arrow2::record_batch::RecordBatch;
 use log::*;
 use nalgebra::Point3;
 use ron::de::from_reader;
@@ -99,6 +103,29 @@ fn test_file_to_stream() {
     }
 }
 
+/// Writes a stream with a single batch of the given (channel, time) events
+fn write_events_to_stream(stream: &Path, events: &[(i32, i64)]) {
+    let schema = Schema::new(vec![
+        Field::new("type_", DataType::UInt8, false),
+        Field::new("missed_events", DataType::UInt16, false),
+        Field::new("channel", DataType::Int32, false),
+        Field::new("time", DataType::Int64, false),
+    ]);
+    let columns: Vec<Arc<dyn Array>> = vec![
+        Arc::new(UInt8Array::from_slice(vec![0; events.len()])),
+        Arc::new(UInt16Array::from_slice(vec![0; events.len()])),
+        Arc::new(Int32Array::from_slice(
+            events.iter().map(|e| e.0).collect::<Vec<_>>(),
+        )),
+        Arc::new(Int64Array::from_slice(
+            events.iter().map(|e| e.1).collect::<Vec<_>>(),
+        )),
+    ];
+    let batch = RecordBatch::try_new(Arc::new(schema.clone()), columns).unwrap();
+    let mut stream_writer = StreamWriter::try_new(File::create(stream).unwrap(), &schema).unwrap();
+    stream_writer.write(&batch).unwrap();
+}
+
 fn generate_mock_channels() -> Channels<PointLogger> {
     let mut plvec = Vec::new();
     for _ in 0..5 {
@@ -194,6 +221,43 @@ fn stepwise_short_unidir_single_frame() {
     assert_eq!(app.channels[ChannelNames::ChannelMerge], original);
 }
 
+#[test]
+fn live_loop_runs_until_stopped() {
+    let stream = std::env::temp_dir().join("live_loop_runs_until_stopped.dat");
+    let events: Vec<(i32, i64)> = (1..=3)
+        .flat_map(|frame| {
+            let start = 1_000_000_000 * frame;
+            vec![(4, start), (-1, start + 1_000)]
+        })
+        .collect();
+    write_events_to_stream(&stream, &events);
+    let output = std::env::temp_dir().join("live_loop_runs_until_stopped.arrow_stream");
+    let cfg: AppConfig = AppConfigBuilder::default()
+        .with_filename(output.to_string_lossy().into_owned())
+        .with_columns(10)
+        .with_rows(10)
+        .with_planes(1)
+        .with_pmt1_ch(InputChannel::new(-1, 0.0))
+        .with_frame_ch(InputChannel::new(4, 0.0))
+        .with_line_ch(InputChannel::new(0, 0.0))
+        .build();
+    let mut app = AppState::new(
+        generate_mock_channels(),
+        stream.to_string_lossy().into_owned(),
+        cfg.clone(),
+    );
+    app.acquire_filehandle().unwrap();
+    let mut checks = 0;
+    app.run_acq_loop(cfg, |_| {
+        checks += 1;
+        checks > 2
+    })
+    .unwrap();
+    assert_eq!(checks, 3);
+    let rendered = &app.channels[ChannelNames::ChannelMerge].rendered_events_loc;
+    assert_eq!(rendered.len(), 2);
+}
+
 // #[test]
 // fn stepwise_short_two_frames_bidir() {
 //     let cfg: AppConfig = AppConfigBuilder::default()